@@ -5,19 +5,77 @@ use std::sync::Arc;
 pub struct AudioAsset {
     pub id: u32,
     pub path: String,
-    pub data: Arc<Vec<f32>>, // Mono or Interleaved Stereo? Let's assume Mono for now or handle channels
+    pub data: Arc<Vec<f32>>, // Interleaved, `channels` wide, at `sample_rate`
     pub channels: u16,
     pub sample_rate: u32,
+    // Imported/recorded audio before rate conversion (kept for re-conversion)
+    pub original_data: Arc<Vec<f32>>,
+    pub original_sample_rate: u32,
     pub duration_seconds: f64,
     pub original_bpm: Option<f32>, // Metadata for stretching
 }
 
+impl AudioAsset {
+    pub fn new(id: u32, path: String, data: Vec<f32>, channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        let data = Arc::new(data);
+        Self {
+            id,
+            path,
+            duration_seconds: data.len() as f64 / (channels as f64 * sample_rate as f64),
+            original_data: data.clone(),
+            data,
+            channels,
+            sample_rate,
+            original_sample_rate: sample_rate,
+            original_bpm: None,
+        }
+    }
+
+    /// True once `data` is at the given rate (playback must skip it until then).
+    pub fn is_at_rate(&self, sample_rate: u32) -> bool {
+        self.sample_rate == sample_rate
+    }
+
+    /// Converts the original to `target_rate`. Slow: call off the audio thread.
+    pub fn convert_to(&self, target_rate: u32) -> Result<Vec<f32>, anyhow::Error> {
+        if target_rate == self.original_sample_rate {
+            return Ok((*self.original_data).clone());
+        }
+        let ratio = self.original_sample_rate as f64 / target_rate as f64;
+        crate::resampler::OmniResampler::resample_interleaved(&self.original_data, self.channels as usize, ratio)
+    }
+
+    /// Length in sample frames (one sample per channel).
+    pub fn frames(&self) -> usize {
+        self.data.len() / self.channels.max(1) as usize
+    }
+
+    /// One frame folded to stereo. Mono is duplicated, stereo is passed through,
+    /// wider layouts sum even channels to the left and odd channels to the right.
+    #[inline]
+    pub fn frame(&self, index: usize) -> (f32, f32) {
+        let channels = self.channels.max(1) as usize;
+        let base = index * channels;
+        match channels {
+            1 => (self.data[base], self.data[base]),
+            2 => (self.data[base], self.data[base + 1]),
+            _ => {
+                let frame = &self.data[base..base + channels];
+                let left: f32 = frame.iter().step_by(2).sum();
+                let right: f32 = frame.iter().skip(1).step_by(2).sum();
+                (left / channels.div_ceil(2) as f32, right / (channels / 2) as f32)
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AudioPool {
     assets: HashMap<u32, AudioAsset>,
     next_id: u32,
     path_cache: HashMap<String, u32>, // Path -> ID mapping to avoid duplicates
-    stretched_cache: HashMap<(u32, u32), u32>, // (Source ID, Ratio * 1000) -> Stretched ID
+    variant_cache: HashMap<(u32, u64), u32>, // (Source ID, Render Key) -> Stretched/Processed ID
 }
 
 impl AudioPool {
@@ -26,7 +84,7 @@ impl AudioPool {
             assets: HashMap::new(),
             next_id: 1, // Start from 1, 0 is reserved/null
             path_cache: HashMap::new(),
-            stretched_cache: HashMap::new(),
+            variant_cache: HashMap::new(),
         }
     }
 
@@ -61,20 +119,13 @@ impl AudioPool {
             }
         };
 
-        let duration = raw_samples.len() as f64 / (channels as f64 * sample_rate as f64);
-        
         let id = self.next_id;
         self.next_id += 1;
 
-        let asset = AudioAsset {
-            id,
-            path: path.to_string(),
-            data: Arc::new(raw_samples),
-            channels,
-            sample_rate,
-            duration_seconds: duration,
-            original_bpm: None, // TODO: Read from WAV metadata/fmt chuck if possible, or user input
-        };
+        // Stored at the file rate; the converter brings it to the engine rate.
+        // TODO: Read original_bpm from WAV metadata/fmt chuck if possible, or user input
+        let asset = AudioAsset::new(id, path.to_string(), raw_samples, channels, sample_rate);
+        let duration = asset.duration_seconds;
 
         self.assets.insert(id, asset);
         self.path_cache.insert(path.to_string(), id);
@@ -88,65 +139,71 @@ impl AudioPool {
         self.assets.get(&id)
     }
 
-    pub fn get_or_create_stretched(&mut self, source_id: u32, ratio: f32) -> Result<u32, anyhow::Error> {
-        let ratio_key = (ratio * 1000.0) as u32;
-        
-        // 1. Check Cache
-        if let Some(&id) = self.stretched_cache.get(&(source_id, ratio_key)) {
-            return Ok(id);
-        }
-
-        // 2. Get Source Data
-        let (source_data, channels, sr, path) = {
-            let asset = self.assets.get(&source_id).ok_or_else(|| anyhow::anyhow!("Asset not found"))?;
-            (asset.data.clone(), asset.channels, asset.sample_rate, asset.path.clone())
-        };
+    /// Processed variant of `source_id` rendered with `key`, if already in the pool.
+    pub fn variant(&self, source_id: u32, key: u64) -> Option<u32> {
+        self.variant_cache.get(&(source_id, key)).copied()
+    }
 
-        // 3. Resample
-        let stretched_data = crate::resampler::OmniResampler::resample(&source_data, ratio as f64)?;
-        
-        // 4. Create New Asset
+    /// Adds a processed variant (stretched clip, ...) rendered on the converter thread.
+    pub fn insert_variant(&mut self, source_id: u32, key: u64, mut asset: AudioAsset) -> u32 {
         let id = self.next_id;
         self.next_id += 1;
-        
-        let duration = stretched_data.len() as f64 / (channels as f64 * sr as f64);
-        
-        let new_asset = AudioAsset {
-            id,
-            path: format!("{} [Stretched {:.2}x]", path, ratio),
-            data: Arc::new(stretched_data),
-            channels,
-            sample_rate: sr,
-            duration_seconds: duration,
-            original_bpm: None, 
-        };
+        asset.id = id;
+        self.assets.insert(id, asset);
+        self.variant_cache.insert((source_id, key), id);
+        id
+    }
 
-        self.assets.insert(id, new_asset);
-        self.stretched_cache.insert((source_id, ratio_key), id);
-        
-        Ok(id)
+    /// Drops a processed variant no clip plays anymore (superseded by a newer render).
+    pub fn remove_variant(&mut self, id: u32) {
+        self.variant_cache.retain(|_, variant| *variant != id);
+        self.assets.remove(&id);
+    }
+
+    /// Number of assets, processed variants included.
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
     }
     
-    /// Create an AudioAsset from raw sample data (used for recording).
-    pub fn add_asset_from_data(&mut self, data: Vec<f32>, sample_rate: f32) -> u32 {
+    /// Create an AudioAsset from raw interleaved sample data (used for recording).
+    pub fn add_asset_from_data(&mut self, data: Vec<f32>, channels: u16, sample_rate: f32) -> u32 {
         let id = self.next_id;
         self.next_id += 1;
         
-        let duration = data.len() as f64 / sample_rate as f64;
-        
-        let asset = AudioAsset {
-            id,
-            path: format!("[Recorded {}]", id),
-            data: Arc::new(data),
-            channels: 1, // Mono recording
-            sample_rate: sample_rate as u32,
-            duration_seconds: duration,
-            original_bpm: None,
-        };
-        
+        let asset = AudioAsset::new(id, format!("[Recorded {}]", id), data, channels, sample_rate as u32);
+        eprintln!("[AudioPool] Created recorded asset {}: {} ch, {}s", id, asset.channels, asset.duration_seconds);
         self.assets.insert(id, asset);
-        eprintln!("[AudioPool] Created recorded asset {}: {}s", id, duration);
         
         id
     }
+
+    /// Assets whose playback data is not at `sample_rate` yet.
+    pub fn pending_conversions(&self, sample_rate: u32) -> Vec<u32> {
+        self.assets.values().filter(|a| !a.is_at_rate(sample_rate)).map(|a| a.id).collect()
+    }
+
+    /// Installs converted playback data. The original is left untouched.
+    pub fn set_converted(&mut self, id: u32, data: Arc<Vec<f32>>, sample_rate: u32) {
+        if let Some(asset) = self.assets.get_mut(&id) {
+            asset.duration_seconds = data.len() as f64 / (asset.channels as f64 * sample_rate as f64);
+            asset.data = data;
+            asset.sample_rate = sample_rate;
+        }
+    }
+
+    /// Converts every asset in place (blocking). For offline rendering; live engines use the converter thread.
+    pub fn convert_all(&mut self, sample_rate: u32) {
+        for id in self.pending_conversions(sample_rate) {
+            let converted = self.assets.get(&id).map(|a| a.convert_to(sample_rate));
+            match converted {
+                Some(Ok(data)) => self.set_converted(id, Arc::new(data), sample_rate),
+                Some(Err(e)) => eprintln!("[AudioPool] Failed to convert asset {}: {}", id, e),
+                None => {}
+            }
+        }
+    }
 }
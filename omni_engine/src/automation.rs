@@ -0,0 +1,136 @@
+//! Arrangement automation playback.
+//! Volume/pan lanes become per-frame gain ramps for the mixer; plugin
+//! parameter lanes become `ParameterEvent`s at a fixed frame interval, for the
+//! instrument or tagged with the position of the insert effect they drive.
+
+use crate::mixer::AutomationRamps;
+use omni_shared::project::{AutomationLane, AutomationTarget};
+use omni_shared::{ParameterEvent, MAX_PARAM_EVENTS};
+
+/// Frames between parameter events while a value is moving.
+pub const PARAM_EVENT_INTERVAL: usize = 64;
+
+/// Renders `lanes` for a block starting at `start_beat`.
+pub fn render_lanes(
+    lanes: &[AutomationLane],
+    start_beat: f64,
+    beats_per_frame: f64,
+    frames: usize,
+    ramps: &mut AutomationRamps,
+    param_events: &mut Vec<ParameterEvent>,
+    device_param_events: &mut Vec<(usize, ParameterEvent)>,
+) {
+    let beat_at = |frame: usize| start_beat + frame as f64 * beats_per_frame;
+
+    for lane in lanes {
+        if !lane.enabled || lane.points.is_empty() {
+            continue;
+        }
+        match lane.target {
+            AutomationTarget::Volume => fill_ramp(&mut ramps.vol, lane, frames, beat_at),
+            AutomationTarget::Pan => fill_ramp(&mut ramps.pan, lane, frames, beat_at),
+            AutomationTarget::Param { device, id } => {
+                // Block start always, then only while the value moves
+                let mut last = f32::NAN;
+                for frame in (0..frames).step_by(PARAM_EVENT_INTERVAL) {
+                    let Some(value) = lane.value_at(beat_at(frame)) else { break };
+                    if value == last {
+                        continue;
+                    }
+                    let event = ParameterEvent {
+                        param_id: id,
+                        value: value as f64,
+                        sample_offset: frame as u32,
+                    };
+                    match device {
+                        None if param_events.len() < MAX_PARAM_EVENTS => param_events.push(event),
+                        Some(device) if device_param_events.len() < MAX_PARAM_EVENTS => {
+                            device_param_events.push((device, event))
+                        }
+                        _ => continue,
+                    }
+                    last = value;
+                }
+            }
+        }
+    }
+}
+
+fn fill_ramp(ramp: &mut Vec<f32>, lane: &AutomationLane, frames: usize, beat_at: impl Fn(usize) -> f64) {
+    ramp.clear();
+    ramp.extend((0..frames).map(|frame| lane.value_at(beat_at(frame)).unwrap_or(0.0)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omni_shared::project::AutomationPoint;
+
+    fn lane(target: AutomationTarget, points: &[(f64, f32)]) -> AutomationLane {
+        let mut lane = AutomationLane::new(target);
+        for &(beat, value) in points {
+            lane.insert_point(AutomationPoint { beat, value, curve: 0.0 });
+        }
+        lane
+    }
+
+    #[test]
+    fn test_volume_ramp_and_param_events() {
+        let lanes = vec![
+            lane(AutomationTarget::Volume, &[(0.0, 0.0), (1.0, 1.0)]),
+            lane(AutomationTarget::Param { device: None, id: 7 }, &[(2.0, 0.25), (3.0, 0.75)]),
+            lane(AutomationTarget::Param { device: Some(1), id: 3 }, &[(0.0, 0.5)]),
+        ];
+        let mut ramps = AutomationRamps::default();
+        let mut events = Vec::new();
+        let mut device_events = Vec::new();
+
+        // 256 frames covering beats 0.0 .. 1.0
+        render_lanes(&lanes, 0.0, 1.0 / 256.0, 256, &mut ramps, &mut events, &mut device_events);
+        assert_eq!(ramps.vol.len(), 256);
+        assert!(ramps.pan.is_empty(), "pan not automated");
+        assert!((ramps.vol[128] - 0.5).abs() < 1e-6);
+        // Param holds its first value before the first point: one event only
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].param_id, events[0].value, events[0].sample_offset), (7, 0.25, 0));
+        // Effect lanes are tagged with their device instead
+        assert_eq!(device_events.len(), 1);
+        assert_eq!((device_events[0].0, device_events[0].1.param_id), (1, 3));
+
+        // Beats 2.0 .. 3.0: the parameter moves, one event per interval
+        events.clear();
+        render_lanes(&lanes, 2.0, 1.0 / 256.0, 256, &mut ramps, &mut events, &mut device_events);
+        assert_eq!(events.len(), 256 / PARAM_EVENT_INTERVAL);
+        assert_eq!(events[2].sample_offset, 128);
+        assert!((events[2].value - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_volume_ramp_spans_blocks() {
+        // 0.1 beats = 2400 frames at 120 BPM and 48 kHz, rendered in 1000-frame blocks
+        let lanes = vec![lane(AutomationTarget::Volume, &[(0.0, 0.0), (0.1, 1.0)])];
+        let beats_per_frame = 1.0 / 24000.0;
+        let mut ramps = AutomationRamps::default();
+        let mut gain = Vec::new();
+        for block in 0..3 {
+            let start_beat = (block * 1000) as f64 * beats_per_frame;
+            render_lanes(&lanes, start_beat, beats_per_frame, 1000, &mut ramps, &mut Vec::new(), &mut Vec::new());
+            gain.extend_from_slice(&ramps.vol);
+        }
+        assert_eq!(gain[0], 0.0, "starts silent");
+        assert!((gain[600] - 0.25).abs() < 1e-4, "linear, sample-accurate");
+        assert!((gain[1200] - 0.5).abs() < 1e-4, "halfway up the ramp");
+        assert!((gain[1000] - gain[999] - 1.0 / 2400.0).abs() < 1e-5, "no step at the block boundary");
+        assert_eq!(gain[2999], 1.0);
+    }
+
+    #[test]
+    fn test_curved_segment() {
+        let mut l = lane(AutomationTarget::Pan, &[(0.0, 0.0), (1.0, 1.0)]);
+        l.points[0].curve = 1.0;
+        assert!(l.value_at(0.5).unwrap() > 0.5, "convex rises early");
+        l.points[0].curve = -1.0;
+        assert!(l.value_at(0.5).unwrap() < 0.5, "concave rises late");
+        assert_eq!(l.value_at(5.0), Some(1.0), "holds after the last point");
+    }
+}
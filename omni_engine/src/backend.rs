@@ -0,0 +1,602 @@
+//! Audio Backends
+//!
+//! A backend owns the "clock" that pulls blocks out of the `EngineProcessor`:
+//! a cpal device stream, a dummy clock thread, or a WAV file sink.
+//! The processor is shared behind a mutex so a backend can be stopped and
+//! restarted without losing the project, graph or plugin processes.
+
+use crate::processor::EngineProcessor;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use omni_shared::AudioSettings;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Processor handle shared between the engine and the active backend.
+pub type SharedProcessor = Arc<Mutex<EngineProcessor>>;
+
+pub trait AudioBackend {
+    /// Short name for logs/UI (e.g. "cpal", "null", "file").
+    fn name(&self) -> &'static str;
+
+    /// Sample rate the backend will run at. Known before `start`.
+    fn sample_rate(&self) -> u32;
+
+    /// Number of interleaved output channels.
+    fn channels(&self) -> usize;
+
+    /// The settings actually in use (after defaults/fallbacks were resolved).
+    fn settings(&self) -> AudioSettings {
+        AudioSettings { sample_rate: Some(self.sample_rate()), ..Default::default() }
+    }
+
+    /// Start pulling audio from the processor.
+    fn start(&mut self, processor: SharedProcessor) -> Result<(), anyhow::Error>;
+
+    /// Stop pulling audio. The processor stays intact and can be handed to another backend.
+    fn stop(&mut self);
+}
+
+/// How clock-driven backends pace their blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMode {
+    /// One block per buffer period, like a sound card would.
+    RealTime,
+    /// As fast as possible (servers, batch rendering).
+    Freewheel,
+}
+
+// --- cpal ---
+
+/// Buffer size used when the user hasn't picked one.
+pub const DEFAULT_BUFFER_SIZE: u32 = 2048;
+
+/// Rates offered in device settings (filtered by what each device supports).
+const COMMON_SAMPLE_RATES: [u32; 6] = [44100, 48000, 88200, 96000, 176400, 192000];
+
+/// An output device as reported by cpal, for settings UIs.
+#[derive(Debug, Clone)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub sample_rates: Vec<u32>,
+    pub default_sample_rate: Option<u32>,
+    /// (min, max) frames, if the device reports a range
+    pub buffer_size_range: Option<(u32, u32)>,
+}
+
+/// Names of the audio hosts (APIs) available on this machine.
+pub fn list_hosts() -> Vec<String> {
+    cpal::available_hosts().iter().map(|id| id.name().to_string()).collect()
+}
+
+/// Output devices of a host (`None` = default host).
+pub fn list_output_devices(host_name: Option<&str>) -> Vec<AudioDeviceInfo> {
+    list_devices(host_name, false)
+}
+
+/// Input (capture) devices of a host (`None` = default host).
+pub fn list_input_devices(host_name: Option<&str>) -> Vec<AudioDeviceInfo> {
+    list_devices(host_name, true)
+}
+
+fn list_devices(host_name: Option<&str>, input: bool) -> Vec<AudioDeviceInfo> {
+    let Ok(host) = find_host(host_name) else {
+        return Vec::new();
+    };
+    let (default_device, devices) = if input {
+        (host.default_input_device(), host.input_devices().map(|d| d.collect::<Vec<_>>()))
+    } else {
+        (host.default_output_device(), host.output_devices().map(|d| d.collect::<Vec<_>>()))
+    };
+    let default_name = default_device.and_then(|d| device_name(&d));
+    let Ok(devices) = devices else {
+        return Vec::new();
+    };
+
+    devices
+        .into_iter()
+        .filter_map(|device| {
+            let name = device_name(&device)?;
+            let mut sample_rates = Vec::new();
+            let mut buffer_size_range: Option<(u32, u32)> = None;
+            let configs: Vec<_> = if input {
+                device.supported_input_configs().map(|c| c.collect()).unwrap_or_default()
+            } else {
+                device.supported_output_configs().map(|c| c.collect()).unwrap_or_default()
+            };
+            for range in configs.iter().filter(|c| c.sample_format() == cpal::SampleFormat::F32) {
+                for rate in COMMON_SAMPLE_RATES {
+                    if (range.min_sample_rate()..=range.max_sample_rate()).contains(&rate) && !sample_rates.contains(&rate) {
+                        sample_rates.push(rate);
+                    }
+                }
+                if let cpal::SupportedBufferSize::Range { min, max } = range.buffer_size() {
+                    buffer_size_range = Some(match buffer_size_range {
+                        Some((lo, hi)) => (lo.min(*min), hi.max(*max)),
+                        None => (*min, *max),
+                    });
+                }
+            }
+            sample_rates.sort_unstable();
+            let default_config = if input { device.default_input_config() } else { device.default_output_config() };
+            let default_sample_rate = default_config.ok().map(|c| c.sample_rate());
+
+            Some(AudioDeviceInfo {
+                is_default: default_name.as_deref() == Some(name.as_str()),
+                name,
+                sample_rates,
+                default_sample_rate,
+                buffer_size_range,
+            })
+        })
+        .collect()
+}
+
+fn device_name(device: &cpal::Device) -> Option<String> {
+    device.description().ok().map(|d| d.name().to_string())
+}
+
+fn find_host(host_name: Option<&str>) -> Result<cpal::Host, anyhow::Error> {
+    match host_name {
+        None => Ok(cpal::default_host()),
+        Some(name) => {
+            let id = cpal::available_hosts()
+                .into_iter()
+                .find(|id| id.name() == name)
+                .ok_or(anyhow::anyhow!("Audio host '{}' not available", name))?;
+            Ok(cpal::host_from_id(id)?)
+        }
+    }
+}
+
+pub struct CpalBackend {
+    host_name: String,
+    device_name: Option<String>,
+    device: cpal::Device,
+    config: cpal::StreamConfig,
+    sample_format: cpal::SampleFormat,
+    stream: Option<cpal::Stream>,
+    // Capture side (optional: playback works without it)
+    input_device: Option<cpal::Device>,
+    input_device_name: Option<String>,
+    disable_input: bool,
+    input_stream: Option<cpal::Stream>,
+}
+
+impl CpalBackend {
+    /// Default output device of the default host.
+    pub fn new_default() -> Result<Self, anyhow::Error> {
+        Self::new(&AudioSettings::default())
+    }
+
+    /// Opens the requested host/device/rate/buffer. Unknown devices fall back to the
+    /// host default so stale preferences don't leave the app without audio.
+    pub fn new(settings: &AudioSettings) -> Result<Self, anyhow::Error> {
+        let host = find_host(settings.host.as_deref())?;
+
+        let requested = settings.device.as_deref().and_then(|wanted| {
+            let found = host
+                .output_devices()
+                .ok()?
+                .find(|d| device_name(d).as_deref() == Some(wanted));
+            if found.is_none() {
+                eprintln!("[AudioEngine] Device '{}' not found, using default", wanted);
+            }
+            found
+        });
+        let device = match requested {
+            Some(d) => d,
+            None => host.default_output_device().ok_or(anyhow::anyhow!("No output device available"))?,
+        };
+
+        let config = match settings.sample_rate {
+            Some(rate) => {
+                let mut ranges: Vec<_> = device
+                    .supported_output_configs()?
+                    .filter(|c| c.sample_format() == cpal::SampleFormat::F32)
+                    .collect();
+                // Prefer stereo ranges
+                ranges.sort_by_key(|c| c.channels() != 2);
+                ranges
+                    .into_iter()
+                    .find_map(|c| c.try_with_sample_rate(rate))
+                    .ok_or(anyhow::anyhow!("Sample rate {} Hz not supported by device", rate))?
+            }
+            None => device.default_output_config()?,
+        };
+
+        // Check buffer size capabilities (Informational)
+        let range = match config.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } => {
+                eprintln!("[AudioEngine] Device Buffer Range: {}-{}", min, max);
+                Some((*min, *max))
+            }
+            cpal::SupportedBufferSize::Unknown => None,
+        };
+
+        let sample_format = config.sample_format();
+        // Create StreamConfig and override buffer size
+        let mut stream_config: cpal::StreamConfig = config.into();
+        let mut frames = settings.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+        if let Some((min, max)) = range {
+            frames = frames.clamp(min, max);
+        }
+        stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+
+        let input_device = if settings.disable_input {
+            None
+        } else {
+            let requested = settings.input_device.as_deref().and_then(|wanted| {
+                let found = host
+                    .input_devices()
+                    .ok()?
+                    .find(|d| device_name(d).as_deref() == Some(wanted));
+                if found.is_none() {
+                    eprintln!("[AudioEngine] Input device '{}' not found, using default", wanted);
+                }
+                found
+            });
+            requested.or_else(|| host.default_input_device())
+        };
+
+        Ok(Self {
+            host_name: host.id().name().to_string(),
+            device_name: device_name(&device),
+            device,
+            config: stream_config,
+            sample_format,
+            stream: None,
+            input_device_name: input_device.as_ref().and_then(device_name),
+            input_device,
+            disable_input: settings.disable_input,
+            input_stream: None,
+        })
+    }
+
+    /// Opens the capture stream at the output rate and returns the consumer side
+    /// of its ring buffer plus the channel count.
+    fn open_input(&mut self) -> Result<(ringbuf::HeapCons<f32>, usize), anyhow::Error> {
+        use ringbuf::traits::{Producer, Split};
+
+        let device = self.input_device.as_ref().ok_or(anyhow::anyhow!("No input device"))?;
+        let rate = self.config.sample_rate;
+        let config = device
+            .supported_input_configs()?
+            .filter(|c| c.sample_format() == cpal::SampleFormat::F32)
+            .find_map(|c| c.try_with_sample_rate(rate))
+            .ok_or(anyhow::anyhow!("Input device does not support {} Hz (F32)", rate))?;
+        let mut stream_config: cpal::StreamConfig = config.into();
+        stream_config.buffer_size = self.config.buffer_size;
+        let channels = stream_config.channels as usize;
+
+        // ~0.5s of headroom between the capture and playback callbacks
+        let rb = ringbuf::HeapRb::<f32>::new(rate as usize / 2 * channels);
+        let (mut producer, consumer) = rb.split();
+
+        let stream = device.build_input_stream(
+            &stream_config,
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                // Overflow (output stalled) drops the newest samples
+                producer.push_slice(data);
+            },
+            |err| eprintln!("[AudioEngine] Input stream error: {}", err),
+            None,
+        )?;
+        stream.play()?;
+        self.input_stream = Some(stream);
+        eprintln!(
+            "[AudioEngine] Input: {} ({} ch @ {} Hz)",
+            self.input_device_name.as_deref().unwrap_or("?"),
+            channels,
+            rate
+        );
+        Ok((consumer, channels))
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn name(&self) -> &'static str {
+        "cpal"
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.config.channels as usize
+    }
+
+    fn settings(&self) -> AudioSettings {
+        let buffer_size = match self.config.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None,
+        };
+        AudioSettings {
+            host: Some(self.host_name.clone()),
+            device: self.device_name.clone(),
+            sample_rate: Some(self.config.sample_rate),
+            buffer_size,
+            input_device: self.input_device_name.clone(),
+            disable_input: self.disable_input,
+        }
+    }
+
+    fn start(&mut self, processor: SharedProcessor) -> Result<(), anyhow::Error> {
+        eprintln!("[AudioEngine] Using Config: {:?}", self.config);
+        let channels = self.channels();
+
+        // Input first, so the first output block already sees it
+        let input = if self.input_device.is_some() {
+            self.open_input()
+                .map_err(|e| eprintln!("[AudioEngine] Input disabled: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        processor
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Processor lock poisoned"))?
+            .set_audio_input(input);
+
+        let err_fn = |err: cpal::StreamError| {
+            let s = err.to_string();
+            // Suppress common buffer under/overrun messages to avoid console spam
+            if !s.contains("underrun") && !s.contains("overrun") {
+                eprintln!("an error occurred on stream: {}", s);
+            }
+        };
+
+        let stream = match self.sample_format {
+            cpal::SampleFormat::F32 => self.device.build_output_stream(
+                &self.config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    // Only contended while the engine swaps backends: output silence then.
+                    match processor.try_lock() {
+                        Ok(mut p) => p.process(data, channels),
+                        Err(_) => data.fill(0.0),
+                    }
+                },
+                err_fn,
+                None,
+            )?,
+            _ => return Err(anyhow::anyhow!("Unsupported sample format")),
+        };
+
+        stream.play()?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        // Dropping the stream joins the device callback
+        self.stream = None;
+        self.input_stream = None;
+    }
+}
+
+// --- Clock-driven (null / file) ---
+
+/// Runs the processor on a plain thread and hands every rendered block to `sink`.
+struct ClockThread {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ClockThread {
+    fn spawn(
+        processor: SharedProcessor,
+        sample_rate: u32,
+        channels: usize,
+        buffer_size: usize,
+        mode: ClockMode,
+        mut sink: impl FnMut(&[f32]) + Send + 'static,
+    ) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let running_thread = running.clone();
+
+        let handle = std::thread::spawn(move || {
+            let mut block = vec![0.0f32; buffer_size * channels];
+            let period = Duration::from_secs_f64(buffer_size as f64 / sample_rate as f64);
+            let mut next_tick = Instant::now();
+
+            while running_thread.load(Ordering::Relaxed) {
+                if let Ok(mut p) = processor.lock() {
+                    p.process(&mut block, channels);
+                }
+                sink(&block);
+
+                match mode {
+                    ClockMode::RealTime => {
+                        // Absolute deadlines so the clock doesn't drift with processing time
+                        next_tick += period;
+                        let now = Instant::now();
+                        if next_tick > now {
+                            std::thread::sleep(next_tick - now);
+                        } else {
+                            next_tick = now;
+                        }
+                    }
+                    ClockMode::Freewheel => std::thread::yield_now(),
+                }
+            }
+        });
+
+        Self { running, handle: Some(handle) }
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Discards the output. Drives the engine from a clock thread without any audio hardware.
+pub struct NullBackend {
+    sample_rate: u32,
+    channels: usize,
+    buffer_size: usize,
+    mode: ClockMode,
+    clock: Option<ClockThread>,
+}
+
+impl NullBackend {
+    pub fn new(sample_rate: u32, buffer_size: usize) -> Self {
+        Self { sample_rate, channels: 2, buffer_size, mode: ClockMode::RealTime, clock: None }
+    }
+
+    pub fn with_clock(mut self, mode: ClockMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn name(&self) -> &'static str {
+        "null"
+    }
+
+    fn settings(&self) -> AudioSettings {
+        AudioSettings {
+            sample_rate: Some(self.sample_rate),
+            buffer_size: Some(self.buffer_size as u32),
+            ..Default::default()
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn start(&mut self, processor: SharedProcessor) -> Result<(), anyhow::Error> {
+        self.stop();
+        self.clock = Some(ClockThread::spawn(
+            processor,
+            self.sample_rate,
+            self.channels,
+            self.buffer_size,
+            self.mode,
+            |_| {},
+        ));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut clock) = self.clock.take() {
+            clock.stop();
+        }
+    }
+}
+
+impl Drop for NullBackend {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Streams the master output into a 32-bit float WAV file.
+/// A new file is written on every `start`; it is finalized on `stop`.
+pub struct FileBackend {
+    path: PathBuf,
+    sample_rate: u32,
+    channels: usize,
+    buffer_size: usize,
+    mode: ClockMode,
+    clock: Option<ClockThread>,
+}
+
+impl FileBackend {
+    pub fn new(path: impl Into<PathBuf>, sample_rate: u32, buffer_size: usize) -> Self {
+        Self {
+            path: path.into(),
+            sample_rate,
+            channels: 2,
+            buffer_size,
+            mode: ClockMode::RealTime,
+            clock: None,
+        }
+    }
+
+    pub fn with_clock(mut self, mode: ClockMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl AudioBackend for FileBackend {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn settings(&self) -> AudioSettings {
+        AudioSettings {
+            sample_rate: Some(self.sample_rate),
+            buffer_size: Some(self.buffer_size as u32),
+            ..Default::default()
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn start(&mut self, processor: SharedProcessor) -> Result<(), anyhow::Error> {
+        self.stop();
+        let spec = hound::WavSpec {
+            channels: self.channels as u16,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        // The writer lives in the clock closure; it is finalized when the thread exits.
+        let mut writer = hound::WavWriter::create(&self.path, spec)?;
+        let path = self.path.clone();
+        let mut failed = false;
+
+        self.clock = Some(ClockThread::spawn(
+            processor,
+            self.sample_rate,
+            self.channels,
+            self.buffer_size,
+            self.mode,
+            move |block| {
+                if failed {
+                    return;
+                }
+                for &s in block {
+                    if let Err(e) = writer.write_sample(s) {
+                        eprintln!("[FileBackend] Write to {} failed: {}", path.display(), e);
+                        failed = true;
+                        return;
+                    }
+                }
+            },
+        ));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut clock) = self.clock.take() {
+            clock.stop();
+        }
+    }
+}
+
+impl Drop for FileBackend {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
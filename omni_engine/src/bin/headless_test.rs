@@ -1,4 +1,5 @@
 use omni_engine::{AudioEngine, EngineCommand};
+use omni_engine::backend::NullBackend;
 use crossbeam_channel::unbounded;
 use std::thread;
 use std::time::Duration;
@@ -8,8 +9,12 @@ fn main() -> Result<(), anyhow::Error> {
     let (tx, rx) = unbounded();
     let (drop_tx, _drop_rx) = unbounded();
     
-    // Initialize engine
-    let _engine = AudioEngine::new(rx, drop_tx)?;
+    // Initialize engine (`--null` runs without a sound card)
+    let _engine = if std::env::args().any(|a| a == "--null") {
+        AudioEngine::with_backend(Box::new(NullBackend::new(48000, 512)), rx, drop_tx)?
+    } else {
+        AudioEngine::new(rx, drop_tx)?
+    };
     
     println!("[Headless] Engine initialized. Sending PLAY command...");
     tx.send(EngineCommand::Play)?;
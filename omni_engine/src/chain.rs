@@ -0,0 +1,265 @@
+//! Per-track device chain: the instrument, then insert effects in signal order.
+//! The chain is the track's single graph node, so routing and PDC see one node
+//! per track. Bypassed effects are skipped and add no latency. Effects with
+//! an auxiliary input take a key fed in by the processor before each block;
+//! when the key comes from a slower path, the chain delays its own audio to meet it.
+
+use crate::delay::DelayLine;
+use crate::nodes::AudioNode;
+use crate::processor::MAX_BUFFER_SIZE;
+use omni_shared::{ParameterEvent, MAX_PARAM_EVENTS};
+
+/// Longest sidechain compensation, in interleaved samples (1s of stereo at 96k)
+const KEY_DELAY_SAMPLES: usize = 96000 * 2;
+
+struct Effect {
+    node: Box<dyn AudioNode>,
+    bypass: bool,
+    params: Vec<ParameterEvent>, // Queued until the effect next processes
+    key: Vec<f32>,
+    key_delay: Option<DelayLine>, // Effects with a sidechain input only
+    key_fed: bool, // `key` holds this block's key
+}
+
+pub struct DeviceChain {
+    instrument: Box<dyn AudioNode>,
+    effects: Vec<Effect>,
+    key_alignment: u32,               // Frames the audio waits for a late key
+    alignment_delay: Option<DelayLine>, // Created with the first keyed effect
+}
+
+impl DeviceChain {
+    pub fn new(instrument: Box<dyn AudioNode>) -> Self {
+        Self { instrument, effects: Vec::new(), key_alignment: 0, alignment_delay: None }
+    }
+
+    /// Wraps a plain node in a chain; chains pass through unchanged.
+    pub fn wrap(mut node: Box<dyn AudioNode>) -> Box<dyn AudioNode> {
+        if node.as_chain().is_some() {
+            node
+        } else {
+            Box::new(Self::new(node))
+        }
+    }
+
+    /// Appends an effect (building a chain from a saved project).
+    pub fn with_effect(mut self, node: Box<dyn AudioNode>, bypass: bool) -> Self {
+        self.insert(self.effects.len(), node, bypass);
+        self
+    }
+
+    /// Swaps the instrument, keeping the effects. Returns the old one.
+    pub fn replace_instrument(&mut self, node: Box<dyn AudioNode>) -> Box<dyn AudioNode> {
+        std::mem::replace(&mut self.instrument, node)
+    }
+
+    pub fn effect_count(&self) -> usize {
+        self.effects.len()
+    }
+
+    /// Inserts an effect before `position` (clamped to the end of the chain).
+    /// Key buffers are allocated here so feeding a key never allocates.
+    pub fn insert(&mut self, position: usize, node: Box<dyn AudioNode>, bypass: bool) {
+        let position = position.min(self.effects.len());
+        let keyed = node.has_sidechain();
+        if keyed && self.alignment_delay.is_none() {
+            self.alignment_delay = Some(DelayLine::new(KEY_DELAY_SAMPLES, 0.0));
+        }
+        self.effects.insert(position, Effect {
+            node,
+            bypass,
+            params: Vec::with_capacity(MAX_PARAM_EVENTS),
+            key: Vec::with_capacity(if keyed { MAX_BUFFER_SIZE } else { 0 }),
+            key_delay: keyed.then(|| DelayLine::new(KEY_DELAY_SAMPLES, 0.0)),
+            key_fed: false,
+        });
+    }
+
+    pub fn remove(&mut self, position: usize) -> Option<Box<dyn AudioNode>> {
+        (position < self.effects.len()).then(|| self.effects.remove(position).node)
+    }
+
+    /// Moves the effect at `from` so it ends up at `to`.
+    pub fn move_effect(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.effects.len() || to >= self.effects.len() {
+            return false;
+        }
+        let effect = self.effects.remove(from);
+        self.effects.insert(to, effect);
+        true
+    }
+
+    pub fn set_bypass(&mut self, position: usize, bypass: bool) {
+        if let Some(effect) = self.effects.get_mut(position) {
+            effect.bypass = bypass;
+        }
+    }
+
+    pub fn effect_mut(&mut self, position: usize) -> Option<&mut Box<dyn AudioNode>> {
+        self.effects.get_mut(position).map(|effect| &mut effect.node)
+    }
+
+    pub fn has_sidechain(&self, position: usize) -> bool {
+        self.effects.get(position).is_some_and(|effect| effect.node.has_sidechain())
+    }
+
+    /// Latency of the signal arriving at the effect at `position`.
+    pub fn latency_before(&self, position: usize) -> u32 {
+        self.instrument.get_latency()
+            + self.key_alignment
+            + self.effects.iter().take(position).filter(|e| !e.bypass).map(|e| e.node.get_latency()).sum::<u32>()
+    }
+
+    pub fn key_alignment(&self) -> u32 {
+        self.key_alignment
+    }
+
+    /// Delays the audio entering the effects by `frames`, so keys from slower
+    /// paths arrive in time. Counts as chain latency. Returns whether it changed.
+    pub fn set_key_alignment(&mut self, frames: u32) -> bool {
+        let frames = if self.alignment_delay.is_some() { frames.min((KEY_DELAY_SAMPLES / 2 - 1) as u32) } else { 0 };
+        let changed = frames != self.key_alignment;
+        self.key_alignment = frames;
+        changed
+    }
+
+    /// Key for the effect's next block, delayed by `delay` frames to line up
+    /// with the audio reaching the effect.
+    pub fn feed_sidechain(&mut self, position: usize, key: &[f32], delay: u32) {
+        let Some(Effect { key: buffer, key_delay: Some(key_delay), key_fed, .. }) = self.effects.get_mut(position) else {
+            return;
+        };
+        buffer.clear();
+        buffer.extend_from_slice(&key[..key.len().min(buffer.capacity())]);
+        key_delay.process_in_place(buffer, delay * 2); // Interleaved stereo
+        *key_fed = true;
+    }
+
+    /// Sets an effect parameter: cached on the node and sent with its next block,
+    /// `sample_offset` frames in (automation).
+    pub fn set_effect_param(&mut self, position: usize, id: u32, value: f32, sample_offset: u32) {
+        let Some(effect) = self.effects.get_mut(position) else { return };
+        effect.node.set_param(id, value);
+        if effect.params.len() < MAX_PARAM_EVENTS {
+            effect.params.push(ParameterEvent { param_id: id, value: value as f64, sample_offset });
+        }
+    }
+}
+
+impl AudioNode for DeviceChain {
+    fn process(&mut self, output: &mut [f32], sample_rate: f32, midi_events: &[omni_shared::MidiNoteEvent], param_events: &[ParameterEvent], expression_events: &[omni_shared::ExpressionEvent]) {
+        self.instrument.process(output, sample_rate, midi_events, param_events, expression_events);
+        if let Some(delay) = &mut self.alignment_delay {
+            delay.process_in_place(output, self.key_alignment * 2); // Interleaved stereo
+        }
+        for effect in &mut self.effects {
+            if !effect.bypass {
+                // Effects get audio only; notes and expressions are for the instrument
+                effect.node.set_sidechain(if effect.key_fed { &effect.key } else { &[] });
+                effect.node.process(output, sample_rate, &[], &effect.params, &[]);
+                effect.params.clear();
+            }
+            effect.key_fed = false;
+        }
+    }
+
+    // Track-level calls address the instrument
+    fn set_param(&mut self, id: u32, value: f32) {
+        self.instrument.set_param(id, value);
+    }
+
+    fn get_plugin_params(&mut self) -> Vec<omni_shared::ParamInfo> {
+        self.instrument.get_plugin_params()
+    }
+
+    fn simulate_crash(&mut self) {
+        self.instrument.simulate_crash();
+    }
+
+    fn open_editor(&mut self) {
+        self.instrument.open_editor();
+    }
+
+    fn get_note_names(&mut self) -> (String, Vec<omni_shared::NoteNameInfo>) {
+        self.instrument.get_note_names()
+    }
+
+    fn get_last_touched(&self) -> (u32, f32, u32) {
+        self.instrument.get_last_touched()
+    }
+
+    fn plugin_kind(&self) -> omni_shared::PluginKind {
+        self.instrument.plugin_kind()
+    }
+
+    fn get_latency(&self) -> u32 {
+        self.instrument.get_latency()
+            + self.key_alignment
+            + self.effects.iter().filter(|e| !e.bypass).map(|e| e.node.get_latency()).sum::<u32>()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.instrument.set_sample_rate(sample_rate);
+        for effect in &mut self.effects {
+            effect.node.set_sample_rate(sample_rate);
+        }
+    }
+
+    fn get_state(&mut self) -> Result<Vec<u8>, anyhow::Error> {
+        self.instrument.get_state()
+    }
+
+    fn set_state(&mut self, data: Vec<u8>) -> Result<(), anyhow::Error> {
+        self.instrument.set_state(data)
+    }
+
+    fn as_chain(&mut self) -> Option<&mut DeviceChain> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodes::GainNode;
+
+    /// Instrument stand-in: a constant level, reporting `latency`.
+    struct Level(f32, u32);
+
+    impl AudioNode for Level {
+        fn process(&mut self, output: &mut [f32], _sr: f32, _m: &[omni_shared::MidiNoteEvent], _p: &[ParameterEvent], _e: &[omni_shared::ExpressionEvent]) {
+            output.fill(self.0);
+        }
+
+        fn get_latency(&self) -> u32 {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_effects_run_in_order_and_bypass() {
+        let mut chain = DeviceChain::new(Box::new(Level(1.0, 10)))
+            .with_effect(Box::new(GainNode::new(0.5)), false)
+            .with_effect(Box::new(GainNode::new(0.0)), true)
+            .with_effect(Box::new(Level(0.25, 64)), false);
+        let mut out = [0.0f32; 8];
+        let mut run = |chain: &mut DeviceChain| {
+            chain.process(&mut out, 48000.0, &[], &[], &[]);
+            out[0]
+        };
+
+        assert_eq!(run(&mut chain), 0.25, "last effect wins");
+        assert_eq!(chain.get_latency(), 10 + 64, "bypassed effects add nothing");
+
+        // Level first, then the gain halves it
+        assert!(chain.move_effect(2, 0));
+        assert_eq!(run(&mut chain), 0.125);
+        chain.set_bypass(2, false);
+        assert_eq!(run(&mut chain), 0.0, "un-bypassed mute");
+
+        assert!(chain.remove(2).is_some());
+        assert!(chain.remove(2).is_none());
+        assert_eq!(chain.effect_count(), 2);
+        assert!(DeviceChain::wrap(Box::new(chain)).as_chain().is_some(), "chains aren't nested");
+    }
+}
@@ -37,11 +37,19 @@ pub enum EngineCommand {
         new_condition: omni_shared::project::NoteCondition,
     },
     SetMute { track_index: usize, muted: bool },
+    /// Exclusive solo clears every other track's solo; additive keeps them
+    SetSolo { track_index: usize, solo: bool, exclusive: bool },
+    /// Return/group stays audible while other tracks are soloed
+    SetSoloSafe { track_index: usize, safe: bool },
     SetBpm(f32),
+    /// Replaces the project's tempo changes (the tempo before the first is `SetBpm`)
+    SetTempoChanges(Vec<omni_shared::tempo::TempoEvent>),
     SetPluginParam { track_index: usize, id: u32, value: f32 },
     GetPluginParams { track_index: usize, response_tx: Sender<Vec<omni_shared::ParamInfo>> },
     SimulateCrash { track_index: usize },
-    TriggerClip { track_index: usize, clip_index: usize },
+    TriggerClip { track_index: usize, clip_index: usize }, // Waits for the launch quantization
+    LaunchScene { scene_index: usize }, // Clip `scene_index` on every track, in sync
+    SetLaunchQuantization(omni_shared::project::LaunchQuantization),
     SetTrackVolume { track_index: usize, volume: f32 },
     SetTrackPan { track_index: usize, pan: f32 },
     // State Management (No I/O)
@@ -53,7 +61,24 @@ pub enum EngineCommand {
     NewProject, 
     OpenPluginEditor { track_index: usize },
     SetClipLength { track_index: usize, clip_index: usize, length: f64 },
+    SetClipFollowActions { track_index: usize, clip_index: usize, follow_actions: omni_shared::project::FollowActions },
     AddTrackNode { node: Box<dyn crate::nodes::AudioNode>, name: String, plugin_path: Option<String> }, 
+    AddReturnTrack { node: Box<dyn crate::nodes::AudioNode>, name: String, plugin_path: Option<String> },
+    SetTrackSends { track_index: usize, sends: Vec<omni_shared::project::TrackSend> },
+    /// New group track summing `children` (track indices)
+    AddGroupTrack { node: Box<dyn crate::nodes::AudioNode>, name: String, children: Vec<usize> },
+    SetTrackParent { track_index: usize, parent: Option<usize> },
+    // Device chain: `position` indexes the track's effects (after the instrument)
+    InsertDevice { track_index: usize, position: usize, node: Box<dyn crate::nodes::AudioNode>, device: omni_shared::project::Device },
+    RemoveDevice { track_index: usize, position: usize },
+    MoveDevice { track_index: usize, from: usize, to: usize },
+    SetDeviceBypass { track_index: usize, position: usize, bypass: bool },
+    SetDeviceParam { track_index: usize, position: usize, id: u32, value: f32 },
+    /// Feeds another track into the device's auxiliary input (None disconnects)
+    SetDeviceSidechain { track_index: usize, position: usize, sidechain: Option<omni_shared::project::Sidechain> },
+    OpenDeviceEditor { track_index: usize, position: usize },
+    GetDeviceParams { track_index: usize, response_tx: Sender<Vec<omni_shared::DeviceInfo>> },
+    GetDeviceStates { track_index: usize, response_tx: Sender<Vec<Option<Vec<u8>>>> },
     ReplaceTrackNode { track_index: usize, node: Box<dyn crate::nodes::AudioNode>, name: String, plugin_path: String }, 
     UpdateClipSequencer {
         track_index: usize,
@@ -72,14 +97,24 @@ pub enum EngineCommand {
     
     // Asset Management
     // UI Loads file, sends raw data. Engine adds to pool.
-    AddAsset { name: String, data: Vec<f32>, source_sample_rate: f32, response_tx: Sender<Result<u32, String>> }, 
+    AddAsset { name: String, data: Vec<f32>, channels: u16, source_sample_rate: f32, response_tx: Sender<Result<u32, String>> }, 
     
     // View/Mode
     SetArrangementMode(bool),
     
     // Arrangement Editing
-    MoveClip { track_index: usize, clip_index: usize, new_start: u64 },
-    StretchClip { track_index: usize, clip_index: usize, original_bpm: f32 },
+    MoveClip { track_index: usize, clip_index: usize, new_start: f64 }, // Beats
+    StretchClip { track_index: usize, clip_index: usize, stretch: bool, original_bpm: f32 },
+    SetClipPitch { track_index: usize, clip_index: usize, semitones: i32, cents: f32 },
+    SetClipGain { track_index: usize, clip_index: usize, gain: f32 }, // Linear
+    SetClipFades { track_index: usize, clip_index: usize, fade_in: omni_shared::project::ClipFade, fade_out: omni_shared::project::ClipFade },
+    AddMidiClip { track_index: usize, clip: omni_shared::project::MidiArrangementClip },
+    MoveMidiClip { track_index: usize, clip_index: usize, new_start: f64 }, // Beats
+    SetMidiClipLoop { track_index: usize, clip_index: usize, looped: bool, length: f64 },
+    RemoveMidiClip { track_index: usize, clip_index: usize },
+    SetAutomationLane { track_index: usize, lane: omni_shared::project::AutomationLane }, // Replaces the lane with the same target
+    RemoveAutomationLane { track_index: usize, target: omni_shared::project::AutomationTarget },
+    SetWarpMarkers { track_index: usize, clip_index: usize, markers: Vec<omni_shared::project::WarpMarker> },
     
     // Recording Session to Arrangement
     StartRecording,
@@ -87,9 +122,19 @@ pub enum EngineCommand {
     
     // Sync recorded clips to engine project
     AddArrangementClips { clips: Vec<(usize, omni_shared::project::ArrangementClip)> },
+
+    // Live Input (hardware capture)
+    SetTrackInput { track_index: usize, input: omni_shared::project::TrackInput },
+    SetRecordArm { track_index: usize, armed: bool },
+    SetMonitorMode { track_index: usize, mode: omni_shared::project::MonitorMode },
     
     // Time Signature & Groove
     SetTimeSignature { numerator: u8, denominator: u8 },
+    /// Replaces the project's meter changes (the meter before the first is `SetTimeSignature`)
+    SetMeterChanges(Vec<omni_shared::meter::MeterChange>),
+    SetLoopRegion(omni_shared::project::TimeRange),
+    SetPunchRange(omni_shared::project::TimeRange),
+    SetMetronome(omni_shared::project::MetronomeSettings),
     SetGroove(omni_shared::project::GrooveTemplate),
     SetSwing(f32), // Shortcut: sets MPC-style swing 0.0-1.0
 }
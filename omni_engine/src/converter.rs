@@ -0,0 +1,174 @@
+//! Background processing of pool assets.
+//! Assets enter the pool at their source rate; this thread brings them to the
+//! engine rate and renders stretched/transposed clip variants, swapping results into the
+//! pool (RCU), so the audio thread never waits.
+
+use std::sync::Arc;
+use crossbeam_channel::{Receiver, Sender};
+use arc_swap::ArcSwap;
+use crate::assets::{AudioAsset, AudioPool};
+use crate::stretch::ClipRender;
+
+// Command messages for the Converter thread
+pub enum ConverterCommand {
+    /// An asset entered the pool: bring it to the engine rate
+    Convert { asset_id: u32 },
+    /// Imported sample data: insert it into the pool, reply with its id, then convert it
+    AddAsset { name: String, data: Vec<f32>, channels: u16, source_sample_rate: f32, response_tx: Sender<Result<u32, String>> },
+    /// Device rate changed: re-convert every asset from its original
+    SetSampleRate(u32),
+    /// Render (or reuse) the processed variant of an arrangement clip
+    /// `offset` and `length` are the clip's source frames at the engine rate
+    RenderClip { track_index: usize, clip_index: usize, source_id: u32, render: ClipRender, offset: u64, length: u64 },
+    /// A clip variant no clip plays anymore
+    EvictVariant { asset_id: u32 },
+}
+
+// Results sent back to the processor
+pub enum ConverterEvent {
+    /// `span` is the offset and length (frames) the clip plays of the variant
+    ClipRendered { track_index: usize, clip_index: usize, source_id: u32, asset_id: u32, span: (u64, u64) },
+}
+
+pub struct AssetConverter {
+    command_rx: Receiver<ConverterCommand>,
+    event_tx: Sender<ConverterEvent>,
+    audio_pool: Arc<ArcSwap<AudioPool>>,
+    sample_rate: u32,
+}
+
+impl AssetConverter {
+    pub fn new(command_rx: Receiver<ConverterCommand>, event_tx: Sender<ConverterEvent>, audio_pool: Arc<ArcSwap<AudioPool>>, sample_rate: u32) -> Self {
+        Self { command_rx, event_tx, audio_pool, sample_rate }
+    }
+
+    /// Blocks on the command channel until the engine goes away.
+    pub fn run(&mut self) {
+        while let Ok(first) = self.command_rx.recv() {
+            let mut batch = vec![first];
+            batch.extend(self.command_rx.try_iter());
+            self.handle_batch(batch);
+        }
+    }
+
+    /// Handles whatever is queued, without blocking (offline rendering).
+    pub fn process_pending(&mut self) {
+        let batch: Vec<_> = self.command_rx.try_iter().collect();
+        self.handle_batch(batch);
+    }
+
+    fn handle_batch(&mut self, batch: Vec<ConverterCommand>) {
+        for (i, cmd) in batch.iter().enumerate() {
+            match cmd {
+                ConverterCommand::Convert { asset_id } => self.convert(*asset_id),
+                ConverterCommand::AddAsset { name, data, channels, source_sample_rate, response_tx } => {
+                    let mut asset_id = 0;
+                    self.audio_pool.rcu(|pool| {
+                        let mut new_pool = (**pool).clone();
+                        asset_id = new_pool.add_asset_from_data(data.clone(), *channels, *source_sample_rate);
+                        new_pool
+                    });
+                    eprintln!("[Converter] Added asset '{}' (ID: {})", name, asset_id);
+                    let _ = response_tx.send(Ok(asset_id));
+                    self.convert(asset_id);
+                }
+                ConverterCommand::SetSampleRate(sample_rate) => {
+                    self.sample_rate = *sample_rate;
+                    let pending = self.audio_pool.load().pending_conversions(*sample_rate);
+                    for asset_id in pending {
+                        self.convert(asset_id);
+                    }
+                }
+                ConverterCommand::EvictVariant { asset_id } => {
+                    self.audio_pool.rcu(|pool| {
+                        let mut new_pool = (**pool).clone();
+                        new_pool.remove_variant(*asset_id);
+                        new_pool
+                    });
+                }
+                ConverterCommand::RenderClip { track_index, clip_index, source_id, render, offset, length } => {
+                    // BPM drags queue many renders per clip: only the latest one counts
+                    let superseded = batch[i + 1..].iter().any(|later| {
+                        matches!(later, ConverterCommand::RenderClip { track_index: t, clip_index: c, .. } if t == track_index && c == clip_index)
+                    });
+                    if !superseded {
+                        self.render_clip(*track_index, *clip_index, *source_id, render, (*offset, *length));
+                    }
+                }
+            }
+        }
+    }
+
+    fn convert(&self, asset_id: u32) {
+        let target = self.sample_rate;
+        let asset = match self.audio_pool.load().get_asset(asset_id) {
+            Some(asset) if !asset.is_at_rate(target) => asset.clone(),
+            _ => return,
+        };
+
+        match asset.convert_to(target) {
+            Ok(data) => {
+                let data = Arc::new(data);
+                // Recorder/engine may have swapped the pool meanwhile: re-apply on the latest one
+                self.audio_pool.rcu(|pool| {
+                    let mut new_pool = (**pool).clone();
+                    new_pool.set_converted(asset_id, data.clone(), target);
+                    new_pool
+                });
+                eprintln!(
+                    "[Converter] Asset {}: {} Hz -> {} Hz",
+                    asset_id, asset.original_sample_rate, target
+                );
+            }
+            Err(e) => eprintln!("[Converter] Failed to convert asset {}: {}", asset_id, e),
+        }
+    }
+
+    fn render_clip(&self, track_index: usize, clip_index: usize, source_id: u32, render: &ClipRender, (offset, length): (u64, u64)) {
+        let key = render.cache_key();
+        let Some(source) = self.audio_pool.load().get_asset(source_id).cloned() else {
+            return;
+        };
+        let span = render.span(offset, length, self.sample_rate, source.original_sample_rate);
+        let cached = self.audio_pool.load().variant(source_id, key);
+
+        let asset_id = match cached {
+            Some(id) => id,
+            None => {
+                // Rendered from the original, then brought to the engine rate in the same job
+                let (rendered, rendered_rate) = render.render(&source.original_data, source.channels as usize, source.original_sample_rate);
+                let variant = AudioAsset::new(
+                    0,
+                    format!("{} [{}]", source.path, render.label()),
+                    rendered,
+                    source.channels,
+                    rendered_rate,
+                );
+                let converted = match variant.convert_to(self.sample_rate) {
+                    Ok(data) => Arc::new(data),
+                    Err(e) => {
+                        eprintln!("[Converter] Failed to convert clip variant of asset {}: {}", source_id, e);
+                        return;
+                    }
+                };
+
+                let mut new_id = 0;
+                self.audio_pool.rcu(|pool| {
+                    let mut new_pool = (**pool).clone();
+                    new_id = new_pool.insert_variant(source_id, key, variant.clone());
+                    new_pool.set_converted(new_id, converted.clone(), self.sample_rate);
+                    new_pool
+                });
+                eprintln!(
+                    "[Converter] Rendered asset {} ({}, {} markers) -> Asset {}",
+                    source_id, render.label(), render.warp_markers.len(), new_id
+                );
+                new_id
+            }
+        };
+
+        self.event_tx
+            .send(ConverterEvent::ClipRendered { track_index, clip_index, source_id, asset_id, span })
+            .ok();
+    }
+}
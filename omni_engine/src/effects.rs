@@ -0,0 +1,633 @@
+//! Built-in effects: EQ, compressor/limiter, delay, reverb, saturator and filter.
+//! They run in-process but look like plugins to the rest of the engine:
+//! parameters (id = index) come from `get_plugin_params`, are set with
+//! `set_param` or sample-accurate `ParameterEvent`s, and save with `get_state`.
+//! Projects refer to them by a `plugin_path` of `omni:<id>`.
+
+use crate::nodes::AudioNode;
+use omni_shared::{ExpressionEvent, MidiNoteEvent, ParamInfo, ParameterEvent, PluginKind};
+use std::f32::consts::PI;
+
+pub const NATIVE_PREFIX: &str = "omni:";
+
+/// Built-in effects as (plugin path, display name)
+pub const NATIVE_EFFECTS: [(&str, &str); 6] = [
+    ("omni:eq", "EQ"),
+    ("omni:compressor", "Compressor"),
+    ("omni:delay", "Delay"),
+    ("omni:reverb", "Reverb"),
+    ("omni:saturator", "Saturator"),
+    ("omni:filter", "Filter"),
+];
+
+/// The built-in effect at `path`, or None for anything else (a CLAP path).
+pub fn create(path: &str, sample_rate: f32) -> Option<Box<dyn AudioNode>> {
+    let node: Box<dyn AudioNode> = match path.strip_prefix(NATIVE_PREFIX)? {
+        "eq" => Box::new(NativeEffect::<Eq>::new(sample_rate)),
+        "compressor" => Box::new(NativeEffect::<Compressor>::new(sample_rate)),
+        "delay" => Box::new(NativeEffect::<StereoDelay>::new(sample_rate)),
+        "reverb" => Box::new(NativeEffect::<Reverb>::new(sample_rate)),
+        "saturator" => Box::new(NativeEffect::<Saturator>::new(sample_rate)),
+        "filter" => Box::new(NativeEffect::<Filter>::new(sample_rate)),
+        _ => return None,
+    };
+    Some(node)
+}
+
+/// One parameter of a built-in effect, in plain units.
+struct Param {
+    name: &'static str,
+    min: f32,
+    max: f32,
+    default: f32,
+    stepped: bool,
+}
+
+const fn param(name: &'static str, min: f32, max: f32, default: f32) -> Param {
+    Param { name, min, max, default, stepped: false }
+}
+
+const fn stepped(name: &'static str, max: f32, default: f32) -> Param {
+    Param { name, min: 0.0, max, default, stepped: true }
+}
+
+/// CLAP_PARAM_IS_STEPPED, which the device view shows as a switch
+const PARAM_IS_STEPPED: u32 = 1;
+
+/// The signal processing of one effect; `NativeEffect` handles the rest.
+trait Dsp: Default + Send + Sync {
+    const PARAMS: &'static [Param];
+    const SIDECHAIN: bool = false;
+
+    /// Parameters or the sample rate changed
+    fn update(&mut self, values: &[f32], sample_rate: f32);
+
+    /// Processes interleaved stereo in place; `key` is the sidechain for the same frames
+    fn process(&mut self, audio: &mut [f32], key: Option<&[f32]>, values: &[f32]);
+
+    /// Clears delay lines and filter memory
+    fn reset(&mut self);
+}
+
+struct NativeEffect<D: Dsp> {
+    dsp: D,
+    values: Vec<f32>,
+    sample_rate: f32,
+    key: Vec<f32>,
+}
+
+impl<D: Dsp> NativeEffect<D> {
+    fn new(sample_rate: f32) -> Self {
+        let mut effect = Self {
+            dsp: D::default(),
+            values: D::PARAMS.iter().map(|p| p.default).collect(),
+            sample_rate,
+            key: Vec::new(),
+        };
+        effect.dsp.update(&effect.values, sample_rate);
+        effect
+    }
+
+    fn set_value(&mut self, id: u32, value: f32) {
+        let Some(param) = D::PARAMS.get(id as usize) else { return };
+        let value = value.clamp(param.min, param.max);
+        self.values[id as usize] = if param.stepped { value.round() } else { value };
+    }
+
+    fn run(&mut self, audio: &mut [f32], start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let key = self.key.get(start * 2..end * 2);
+        self.dsp.process(&mut audio[start * 2..end * 2], key, &self.values);
+    }
+}
+
+impl<D: Dsp> AudioNode for NativeEffect<D> {
+    fn process(&mut self, output: &mut [f32], sample_rate: f32, _midi_events: &[MidiNoteEvent], param_events: &[ParameterEvent], _expression_events: &[ExpressionEvent]) {
+        if sample_rate != self.sample_rate {
+            self.set_sample_rate(sample_rate);
+        }
+        // Split the block at each parameter change
+        let frames = output.len() / 2;
+        let mut start = 0;
+        for event in param_events {
+            let at = (event.sample_offset as usize).clamp(start, frames);
+            self.run(output, start, at);
+            start = at;
+            self.set_value(event.param_id, event.value as f32);
+            self.dsp.update(&self.values, self.sample_rate);
+        }
+        self.run(output, start, frames);
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        self.set_value(id, value);
+        self.dsp.update(&self.values, self.sample_rate);
+    }
+
+    fn get_plugin_params(&mut self) -> Vec<ParamInfo> {
+        D::PARAMS
+            .iter()
+            .enumerate()
+            .map(|(id, p)| ParamInfo {
+                id: id as u32,
+                name: p.name.to_string(),
+                min_value: p.min as f64,
+                max_value: p.max as f64,
+                default_value: p.default as f64,
+                flags: if p.stepped { PARAM_IS_STEPPED } else { 0 },
+            })
+            .collect()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.dsp.update(&self.values, sample_rate);
+        self.dsp.reset();
+    }
+
+    fn get_state(&mut self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(bincode::serialize(&self.values)?)
+    }
+
+    fn set_state(&mut self, data: Vec<u8>) -> Result<(), anyhow::Error> {
+        let values: Vec<f32> = bincode::deserialize(&data)?;
+        for (id, value) in values.into_iter().enumerate() {
+            self.set_value(id as u32, value);
+        }
+        self.dsp.update(&self.values, self.sample_rate);
+        Ok(())
+    }
+
+    fn plugin_kind(&self) -> PluginKind {
+        PluginKind::Effect
+    }
+
+    fn has_sidechain(&self) -> bool {
+        D::SIDECHAIN
+    }
+
+    fn set_sidechain(&mut self, key: &[f32]) {
+        self.key.clear();
+        self.key.extend_from_slice(key);
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// `freq` kept below Nyquist at `sample_rate`
+fn max_freq(freq: f32, sample_rate: f32) -> f32 {
+    freq.clamp(10.0, sample_rate * 0.49)
+}
+
+// ───────────────────────────── EQ ──────────────────────────────
+
+#[derive(Clone, Copy, PartialEq)]
+enum Shape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+/// RBJ cookbook biquad, transposed direct form II, one state per channel.
+#[derive(Default)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [[f32; 2]; 2],
+}
+
+impl Biquad {
+    fn set(&mut self, shape: Shape, freq: f32, q: f32, gain_db: f32, sample_rate: f32) {
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * max_freq(freq, sample_rate) / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let (b, a0, a1, a2) = match shape {
+            Shape::Peak => {
+                let alpha = sin / (2.0 * q.max(0.01));
+                ([1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a], 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+            }
+            Shape::LowShelf | Shape::HighShelf => {
+                // Shelf slope 1
+                let beta = a.sqrt() * sin * std::f32::consts::SQRT_2;
+                let sign = if shape == Shape::LowShelf { 1.0 } else { -1.0 };
+                let (p, m) = (a + 1.0, a - 1.0);
+                (
+                    [
+                        a * (p - sign * m * cos + beta),
+                        sign * 2.0 * a * (m - sign * p * cos),
+                        a * (p - sign * m * cos - beta),
+                    ],
+                    p + sign * m * cos + beta,
+                    -sign * 2.0 * (m + sign * p * cos),
+                    p + sign * m * cos - beta,
+                )
+            }
+        };
+        self.b = b.map(|x| x / a0);
+        self.a = [a1 / a0, a2 / a0];
+    }
+
+    fn run(&mut self, channel: usize, x: f32) -> f32 {
+        let z = &mut self.z[channel];
+        let y = self.b[0] * x + z[0];
+        z[0] = self.b[1] * x - self.a[0] * y + z[1];
+        z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Low shelf, parametric mid and high shelf.
+#[derive(Default)]
+struct Eq {
+    bands: [Biquad; 3],
+}
+
+impl Dsp for Eq {
+    const PARAMS: &'static [Param] = &[
+        param("Low Freq", 20.0, 1000.0, 100.0),
+        param("Low Gain", -18.0, 18.0, 0.0),
+        param("Mid Freq", 100.0, 10000.0, 1000.0),
+        param("Mid Gain", -18.0, 18.0, 0.0),
+        param("Mid Q", 0.1, 10.0, 0.7),
+        param("High Freq", 1000.0, 20000.0, 8000.0),
+        param("High Gain", -18.0, 18.0, 0.0),
+    ];
+
+    fn update(&mut self, v: &[f32], sample_rate: f32) {
+        self.bands[0].set(Shape::LowShelf, v[0], 0.7, v[1], sample_rate);
+        self.bands[1].set(Shape::Peak, v[2], v[4], v[3], sample_rate);
+        self.bands[2].set(Shape::HighShelf, v[5], 0.7, v[6], sample_rate);
+    }
+
+    fn process(&mut self, audio: &mut [f32], _key: Option<&[f32]>, _values: &[f32]) {
+        for frame in audio.chunks_exact_mut(2) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = self.bands.iter_mut().fold(*sample, |x, band| band.run(channel, x));
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for band in &mut self.bands {
+            band.z = Default::default();
+        }
+    }
+}
+
+// ───────────────────────────── Compressor ──────────────────────────────
+
+/// Stereo-linked peak compressor. "Limit" makes the ratio infinite with an
+/// instant attack, so peaks never pass the threshold. Keyed by the sidechain
+/// when one is routed in.
+#[derive(Default)]
+struct Compressor {
+    attack: f32,  // Envelope coefficients per sample
+    release: f32,
+    reduction_db: f32,
+}
+
+impl Dsp for Compressor {
+    const PARAMS: &'static [Param] = &[
+        param("Threshold", -60.0, 0.0, -18.0),
+        param("Ratio", 1.0, 20.0, 4.0),
+        param("Attack", 0.1, 100.0, 10.0), // ms
+        param("Release", 10.0, 1000.0, 100.0), // ms
+        param("Makeup", 0.0, 24.0, 0.0),
+        stepped("Limit", 1.0, 0.0),
+    ];
+    const SIDECHAIN: bool = true;
+
+    fn update(&mut self, v: &[f32], sample_rate: f32) {
+        let coefficient = |ms: f32| (-1.0 / (ms * 0.001 * sample_rate)).exp();
+        self.attack = coefficient(v[2]);
+        self.release = coefficient(v[3]);
+    }
+
+    fn process(&mut self, audio: &mut [f32], key: Option<&[f32]>, v: &[f32]) {
+        let (threshold, makeup, limit) = (v[0], v[4], v[5] > 0.5);
+        let slope = if limit { 1.0 } else { 1.0 - 1.0 / v[1] };
+        for i in (0..audio.len()).step_by(2) {
+            let detect = key.unwrap_or(audio);
+            let peak = detect[i].abs().max(detect[i + 1].abs());
+            let over = 20.0 * (peak + 1e-9).log10() - threshold;
+            let target = over.max(0.0) * slope;
+            self.reduction_db = if target > self.reduction_db {
+                if limit { target } else { target + (self.reduction_db - target) * self.attack }
+            } else {
+                target + (self.reduction_db - target) * self.release
+            };
+            let gain = db_to_gain(makeup - self.reduction_db);
+            audio[i] *= gain;
+            audio[i + 1] *= gain;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+    }
+}
+
+// ───────────────────────────── Delay ──────────────────────────────
+
+/// Longest delay time in seconds
+const MAX_DELAY_SECONDS: f32 = 4.0;
+
+/// Stereo feedback delay, free in ms or synced to the transport tempo in beats.
+/// Ping-pong feeds the mono input to the left and crosses the repeats.
+#[derive(Default)]
+struct StereoDelay {
+    lines: [Vec<f32>; 2],
+    write: usize,
+    sample_rate: f32,
+}
+
+impl Dsp for StereoDelay {
+    const PARAMS: &'static [Param] = &[
+        param("Time", 1.0, 2000.0, 375.0), // ms
+        stepped("Sync", 1.0, 0.0),
+        param("Beats", 0.0625, 4.0, 0.75),
+        param("Feedback", 0.0, 0.95, 0.35),
+        stepped("Ping-Pong", 1.0, 0.0),
+        param("Mix", 0.0, 1.0, 0.3),
+    ];
+
+    fn update(&mut self, _values: &[f32], sample_rate: f32) {
+        if sample_rate != self.sample_rate {
+            let len = (MAX_DELAY_SECONDS * sample_rate) as usize + 1;
+            self.lines = [vec![0.0; len], vec![0.0; len]];
+            self.write = 0;
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    fn process(&mut self, audio: &mut [f32], _key: Option<&[f32]>, v: &[f32]) {
+        let len = self.lines[0].len();
+        let seconds = if v[1] > 0.5 {
+            let tempo = crate::transport::get_transport().tempo;
+            let tempo = if tempo > 0.0 { tempo as f32 } else { 120.0 };
+            v[2] * 60.0 / tempo
+        } else {
+            v[0] * 0.001
+        };
+        let delay = ((seconds * self.sample_rate) as usize).clamp(1, len - 1);
+        let (feedback, ping_pong, mix) = (v[3], v[4] > 0.5, v[5]);
+
+        for frame in audio.chunks_exact_mut(2) {
+            let read = (self.write + len - delay) % len;
+            let (wet_l, wet_r) = (self.lines[0][read], self.lines[1][read]);
+            let (in_l, in_r) = if ping_pong {
+                ((frame[0] + frame[1]) * 0.5 + wet_r * feedback, wet_l * feedback)
+            } else {
+                (frame[0] + wet_l * feedback, frame[1] + wet_r * feedback)
+            };
+            self.lines[0][self.write] = in_l;
+            self.lines[1][self.write] = in_r;
+            self.write = (self.write + 1) % len;
+            frame[0] += (wet_l - frame[0]) * mix;
+            frame[1] += (wet_r - frame[1]) * mix;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lines.iter_mut().for_each(|line| line.fill(0.0));
+    }
+}
+
+// ───────────────────────────── Reverb ──────────────────────────────
+
+/// Freeverb delay lengths at 44.1 kHz; the right channel is `STEREO_SPREAD` longer
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+
+#[derive(Default)]
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    filter: f32, // Damping lowpass state
+}
+
+impl Comb {
+    fn run(&mut self, x: f32, feedback: f32, damp: f32) -> f32 {
+        let out = self.buffer[self.pos];
+        self.filter = out * (1.0 - damp) + self.filter * damp;
+        self.buffer[self.pos] = x + self.filter * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        out
+    }
+}
+
+#[derive(Default)]
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn run(&mut self, x: f32) -> f32 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = x + delayed * 0.5;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - x
+    }
+}
+
+/// Freeverb: parallel damped combs into series allpasses, per channel.
+#[derive(Default)]
+struct Reverb {
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    sample_rate: f32,
+}
+
+impl Dsp for Reverb {
+    const PARAMS: &'static [Param] = &[
+        param("Size", 0.0, 1.0, 0.5),
+        param("Damping", 0.0, 1.0, 0.5),
+        param("Width", 0.0, 1.0, 1.0),
+        param("Mix", 0.0, 1.0, 0.25),
+    ];
+
+    fn update(&mut self, _values: &[f32], sample_rate: f32) {
+        if sample_rate == self.sample_rate {
+            return;
+        }
+        let scale = |n: usize| ((n as f32 * sample_rate / 44100.0) as usize).max(1);
+        for (channel, spread) in [0, STEREO_SPREAD].into_iter().enumerate() {
+            self.combs[channel] = COMB_TUNING
+                .iter()
+                .map(|&n| Comb { buffer: vec![0.0; scale(n + spread)], ..Default::default() })
+                .collect();
+            self.allpasses[channel] = ALLPASS_TUNING
+                .iter()
+                .map(|&n| Allpass { buffer: vec![0.0; scale(n + spread)], pos: 0 })
+                .collect();
+        }
+        self.sample_rate = sample_rate;
+    }
+
+    fn process(&mut self, audio: &mut [f32], _key: Option<&[f32]>, v: &[f32]) {
+        let feedback = v[0] * 0.28 + 0.7;
+        let damp = v[1] * 0.4;
+        let wet = v[3] * 3.0; // Freeverb's wet scale
+        let wet_same = wet * (v[2] / 2.0 + 0.5);
+        let wet_cross = wet * (1.0 - v[2]) / 2.0;
+        let dry = 1.0 - v[3];
+
+        for frame in audio.chunks_exact_mut(2) {
+            let input = (frame[0] + frame[1]) * 0.015;
+            let mut out = [0.0f32; 2];
+            for (channel, out) in out.iter_mut().enumerate() {
+                let sum: f32 = self.combs[channel].iter_mut().map(|comb| comb.run(input, feedback, damp)).sum();
+                *out = self.allpasses[channel].iter_mut().fold(sum, |x, allpass| allpass.run(x));
+            }
+            frame[0] = frame[0] * dry + out[0] * wet_same + out[1] * wet_cross;
+            frame[1] = frame[1] * dry + out[1] * wet_same + out[0] * wet_cross;
+        }
+    }
+
+    fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.buffer.fill(0.0);
+            comb.filter = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.buffer.fill(0.0);
+        }
+    }
+}
+
+// ───────────────────────────── Saturator ──────────────────────────────
+
+/// Waveshaper: tanh, or a hard clip when "Hard" is on.
+#[derive(Default)]
+struct Saturator;
+
+impl Dsp for Saturator {
+    const PARAMS: &'static [Param] = &[
+        param("Drive", 0.0, 36.0, 6.0), // dB
+        stepped("Hard", 1.0, 0.0),
+        param("Output", -24.0, 12.0, 0.0), // dB
+        param("Mix", 0.0, 1.0, 1.0),
+    ];
+
+    fn update(&mut self, _values: &[f32], _sample_rate: f32) {}
+
+    fn process(&mut self, audio: &mut [f32], _key: Option<&[f32]>, v: &[f32]) {
+        let (drive, hard, output, mix) = (db_to_gain(v[0]), v[1] > 0.5, db_to_gain(v[2]), v[3]);
+        for sample in audio.iter_mut() {
+            let driven = *sample * drive;
+            let shaped = if hard { driven.clamp(-1.0, 1.0) } else { driven.tanh() };
+            *sample += (shaped * output - *sample) * mix;
+        }
+    }
+
+    fn reset(&mut self) {}
+}
+
+// ───────────────────────────── Filter ──────────────────────────────
+
+/// State-variable filter (trapezoidal, Cytomic): lowpass, highpass, bandpass or notch.
+#[derive(Default)]
+struct Filter {
+    k: f32, // 1 / Q
+    a: [f32; 3],
+    ic: [[f32; 2]; 2], // Integrator states per channel
+}
+
+impl Dsp for Filter {
+    const PARAMS: &'static [Param] = &[
+        stepped("Type", 3.0, 0.0), // Lowpass, highpass, bandpass, notch
+        param("Cutoff", 20.0, 20000.0, 2000.0), // Hz
+        param("Resonance", 0.0, 1.0, 0.1),
+    ];
+
+    fn update(&mut self, v: &[f32], sample_rate: f32) {
+        let q = 0.5 * 40.0f32.powf(v[2]); // 0.5 .. 20
+        let g = (PI * max_freq(v[1], sample_rate) / sample_rate).tan();
+        self.k = 1.0 / q;
+        let a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a = [a1, g * a1, g * g * a1];
+    }
+
+    fn process(&mut self, audio: &mut [f32], _key: Option<&[f32]>, v: &[f32]) {
+        let mode = v[0] as u32;
+        for frame in audio.chunks_exact_mut(2) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let [ic1, ic2] = &mut self.ic[channel];
+                let x = *sample;
+                let v3 = x - *ic2;
+                let band = self.a[0] * *ic1 + self.a[1] * v3;
+                let low = *ic2 + self.a[1] * *ic1 + self.a[2] * v3;
+                *ic1 = 2.0 * band - *ic1;
+                *ic2 = 2.0 * low - *ic2;
+                *sample = match mode {
+                    0 => low,
+                    1 => x - self.k * band - low,
+                    2 => band,
+                    _ => x - self.k * band,
+                };
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.ic = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak level of a sine after `node`, past its settling time.
+    fn sine_peak(node: &mut dyn AudioNode, hz: f32, amplitude: f32) -> f32 {
+        let sr = 48000.0;
+        let mut block = vec![0.0f32; 9600];
+        for (i, frame) in block.chunks_mut(2).enumerate() {
+            frame.fill(amplitude * (2.0 * PI * hz * i as f32 / sr).sin());
+        }
+        node.process(&mut block, sr, &[], &[], &[]);
+        block[4800..].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn test_params_and_state_round_trip() {
+        for (path, _) in NATIVE_EFFECTS {
+            let mut node = create(path, 48000.0).unwrap();
+            let params = node.get_plugin_params();
+            assert!(!params.is_empty(), "{} has parameters", path);
+            assert_eq!(node.plugin_kind(), PluginKind::Effect);
+
+            // Out-of-range values clamp; state carries every value
+            node.set_param(0, params[0].max_value as f32 + 100.0);
+            let state = node.get_state().unwrap();
+            let mut restored = create(path, 48000.0).unwrap();
+            restored.set_state(state.clone()).unwrap();
+            assert_eq!(restored.get_state().unwrap(), state, "{}", path);
+        }
+        assert!(create("omni:nope", 48000.0).is_none());
+        assert!(create("/plugins/Surge XT.clap", 48000.0).is_none());
+    }
+
+    #[test]
+    fn test_filter_and_limiter() {
+        // Lowpass at 1 kHz: 10 kHz is cut, 100 Hz passes
+        let mut filter = create("omni:filter", 48000.0).unwrap();
+        filter.set_param(1, 1000.0);
+        assert!(sine_peak(filter.as_mut(), 10000.0, 0.5) < 0.02);
+        filter.set_sample_rate(48000.0);
+        assert!((sine_peak(filter.as_mut(), 100.0, 0.5) - 0.5).abs() < 0.02);
+
+        // Limit at -12 dB: a full-scale sine comes out at the threshold
+        let mut limiter = create("omni:compressor", 48000.0).unwrap();
+        limiter.set_param(0, -12.0);
+        limiter.set_param(5, 1.0);
+        let peak = sine_peak(limiter.as_mut(), 1000.0, 1.0);
+        assert!(peak <= db_to_gain(-12.0) + 1e-4 && peak > db_to_gain(-13.0), "{}", peak);
+    }
+}
@@ -1,8 +1,21 @@
 //! Offline audio export/bounce module.
 //! Renders the arrangement to WAV files without real-time constraints.
 
+use crate::assets::AudioPool;
+use crate::commands::EngineCommand;
+use crate::converter::AssetConverter;
+use crate::nodes::AudioNode;
+use crate::processor::EngineProcessor;
+use crate::stretch::ClipRender;
+use arc_swap::ArcSwap;
+use crossbeam_channel::{Receiver, Sender};
 use hound::{WavSpec, WavWriter, SampleFormat};
+use omni_shared::project::Project;
 use std::path::Path;
+use std::sync::Arc;
+
+/// Frames per block when rendering offline (matches the plugin shmem block size).
+pub const OFFLINE_BLOCK_FRAMES: usize = omni_shared::BUFFER_SIZE;
 
 /// Export format options
 #[derive(Debug, Clone, Copy)]
@@ -21,6 +34,7 @@ pub struct ExportConfig {
     pub normalize: bool,
     pub dither: bool,          // Apply TPDF dither when converting to int
     pub tail_seconds: f64,     // Extra tail for reverb/delay
+    pub include_metronome: bool, // Bounce the click too (off: project setting ignored)
 }
 
 impl Default for ExportConfig {
@@ -32,10 +46,142 @@ impl Default for ExportConfig {
             normalize: false,
             dither: true,
             tail_seconds: 2.0,
+            include_metronome: false,
         }
     }
 }
 
+/// Drives the engine's block processor faster than real time.
+/// Uses the exact same pipeline as playback (graph, PDC, mixer, master bus),
+/// so a bounce matches what the user hears.
+pub struct OfflineRenderer {
+    processor: EngineProcessor,
+    converter: AssetConverter,
+    command_tx: Sender<EngineCommand>,
+    drop_rx: Receiver<Box<dyn AudioNode>>,
+}
+
+impl OfflineRenderer {
+    pub fn new(
+        mut project: Project,
+        nodes: Vec<Box<dyn AudioNode>>,
+        audio_pool: Arc<ArcSwap<AudioPool>>,
+        sample_rate: u32,
+    ) -> Self {
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let (drop_tx, drop_rx) = crossbeam_channel::unbounded();
+        // No recorder thread offline: the consumers are simply dropped.
+        let (recorder_tx, _) = crossbeam_channel::unbounded();
+        // The converter runs inline between blocks instead of on its own thread
+        let (converter_tx, converter_rx) = crossbeam_channel::unbounded();
+        let (converter_event_tx, converter_event_rx) = crossbeam_channel::unbounded();
+
+        // Offline there is time to wait: convert a private snapshot of the pool up front,
+        // leaving the live pool at the device rate.
+        let mut pool = (**audio_pool.load()).clone();
+        pool.convert_all(sample_rate);
+        let audio_pool = Arc::new(ArcSwap::from_pointee(pool));
+
+        let converter = AssetConverter::new(converter_rx, converter_event_tx, audio_pool.clone(), sample_rate);
+        let processor = EngineProcessor::new(
+            command_rx,
+            sample_rate,
+            audio_pool,
+            recorder_tx,
+            converter_tx,
+            converter_event_rx,
+            drop_tx,
+        );
+        // Offline renders play straight through: the loop brace is a playback aid
+        project.loop_region.enabled = false;
+        command_tx.send(EngineCommand::LoadProjectState(project, nodes)).ok();
+
+        Self { processor, converter, command_tx, drop_rx }
+    }
+
+    /// Commands sent here are applied before the next rendered block.
+    pub fn command_sender(&self) -> Sender<EngineCommand> {
+        self.command_tx.clone()
+    }
+
+    /// Renders `frames` frames from the current position, interleaved at `channels` width.
+    pub fn render(&mut self, frames: u64, channels: usize) -> Vec<f32> {
+        let channels = channels.max(1);
+        let mut out = vec![0.0f32; frames as usize * channels];
+        for block in out.chunks_mut(OFFLINE_BLOCK_FRAMES * channels) {
+            // Finish background work (stretched clips) before the block that needs it
+            self.processor.drain_commands();
+            self.converter.process_pending();
+            self.processor.process(block, channels);
+            // Nodes replaced during the render are dropped here instead of a GC thread
+            self.drop_rx.try_iter().for_each(drop);
+        }
+        out
+    }
+}
+
+/// Length of the project content in samples (without tail).
+/// Arrangement mode: end of the last arrangement clip (audio or MIDI).
+/// Session mode: the longest active clip, played once.
+pub fn project_length_samples(project: &Project, sample_rate: u32) -> u64 {
+    let tempo = project.tempo_map();
+    let sample_at = |beat: f64| tempo.sample_at(beat, sample_rate as f64) as u64;
+    if project.arrangement_mode {
+        let audio_end = project.tracks.iter()
+            .flat_map(|t| t.arrangement.clips.iter())
+            .map(|c| {
+                // Stretched clips last as long as their variant will (warp markers taken at the export rate)
+                let length = ClipRender::from_clip(c, project.bpm, &project.tempo_changes)
+                    .map_or(c.length.samples, |r| r.span(c.start_offset.samples, c.length.samples, sample_rate, sample_rate).1);
+                c.start_sample(&tempo, sample_rate as f64) + length
+            })
+            .max()
+            .unwrap_or(0);
+        let midi_end = project.tracks.iter()
+            .flat_map(|t| t.arrangement.midi_clips.iter())
+            .map(|c| sample_at(c.end()))
+            .max()
+            .unwrap_or(0);
+        audio_end.max(midi_end)
+    } else {
+        project.tracks.iter()
+            .filter_map(|t| t.active_clip_index.and_then(|i| t.clips.get(i)))
+            .map(|c| sample_at(c.length))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Renders a project offline and returns interleaved audio in `config.channels` width.
+/// `nodes` are the track nodes, one per track (as for `EngineCommand::LoadProjectState`).
+pub fn render_project(
+    mut project: Project,
+    nodes: Vec<Box<dyn AudioNode>>,
+    audio_pool: Arc<ArcSwap<AudioPool>>,
+    config: &ExportConfig,
+) -> Vec<f32> {
+    project.metronome.enabled &= config.include_metronome;
+    let length = project_length_samples(&project, config.sample_rate)
+        + (config.tail_seconds.max(0.0) * config.sample_rate as f64) as u64;
+
+    let mut renderer = OfflineRenderer::new(project, nodes, audio_pool, config.sample_rate);
+    renderer.command_sender().send(EngineCommand::Play).ok();
+    renderer.render(length, config.channels as usize)
+}
+
+/// Renders a project offline straight to a WAV file.
+pub fn render_project_to_wav(
+    path: &Path,
+    project: Project,
+    nodes: Vec<Box<dyn AudioNode>>,
+    audio_pool: Arc<ArcSwap<AudioPool>>,
+    config: &ExportConfig,
+) -> Result<(), anyhow::Error> {
+    let data = render_project(project, nodes, audio_pool, config);
+    eprintln!("[Export] Rendered {} frames to {}", data.len() / config.channels.max(1) as usize, path.display());
+    write_wav(path, &data, config)
+}
+
 /// Write interleaved f32 audio data to a WAV file.
 /// Handles bit-depth conversion, normalization, and dithering.
 pub fn write_wav(
@@ -145,3 +291,566 @@ pub fn write_stems(
 
     Ok(paths)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omni_shared::project::{ArrangementClip, Note, Track};
+
+    /// Arrangement clip playing pool asset `source_id` from beat `start` for `length` samples.
+    fn audio_clip(source_id: u32, start: f64, length: u64) -> ArrangementClip {
+        ArrangementClip::new("Test".to_string(), source_id, start, length)
+    }
+
+    /// Middle C at velocity 100, held `duration` beats from `start`.
+    fn note(start: f64, duration: f64) -> Note {
+        Note::new(start, duration, 60, 100)
+    }
+
+    #[test]
+    fn test_render_arrangement_clip() {
+        let sr = 48000;
+        let mut pool = AudioPool::new();
+        let id = pool.add_asset_from_data(vec![0.5; 4800], 1, sr as f32);
+
+        let mut track = Track::default();
+        track.arrangement.clips.push(audio_clip(id, 1000.0 / 24000.0, 4800)); // 120 BPM at 48 kHz
+        let project = Project {
+            tracks: vec![track],
+            arrangement_mode: true,
+            ..Default::default()
+        };
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.1, ..Default::default() };
+        let pool = Arc::new(ArcSwap::from_pointee(pool));
+        let out = render_project(project, vec![], pool, &config);
+
+        assert_eq!(out.len(), (5800 + 4800) * 2);
+        let peak = |range: std::ops::Range<usize>| out[range.start * 2..range.end * 2].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak(0..1000) < 1e-3, "silence before the clip");
+        assert!(peak(1000..5800) > 0.1, "clip is audible");
+        assert!(peak(5800..10600) < 1e-3, "silence after the clip");
+    }
+
+    #[test]
+    fn test_audio_clip_position_follows_tempo() {
+        let sr = 48000;
+        let mut pool = AudioPool::new();
+        let id = pool.add_asset_from_data(vec![0.5; 4800], 1, sr as f32);
+        let mut track = Track::default();
+        track.arrangement.clips.push(audio_clip(id, 1.0, 4800));
+        // Beat 1 lands at 48000 at 60 BPM (24000 at 120)
+        let project = Project { tracks: vec![track], arrangement_mode: true, bpm: 60.0, ..Default::default() };
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.0, ..Default::default() };
+        let out = render_project(project, vec![], Arc::new(ArcSwap::from_pointee(pool)), &config);
+        assert_eq!(out.len(), (48000 + 4800) * 2);
+        assert!(out[..48000 * 2].iter().all(|s| s.abs() < 1e-3), "silent before beat 1");
+        assert!(out[48000 * 2..].iter().all(|s| s.abs() > 0.1), "plays from beat 1");
+    }
+
+    #[test]
+    fn test_render_stereo_clip_keeps_channels() {
+        let sr = 48000;
+        let mut pool = AudioPool::new();
+        // Left-only interleaved stereo, 4800 frames
+        let data: Vec<f32> = (0..4800).flat_map(|_| [0.5, 0.0]).collect();
+        let id = pool.add_asset_from_data(data, 2, sr as f32);
+
+        let mut track = Track::default();
+        track.arrangement.clips.push(audio_clip(id, 0.0, 4800));
+        let project = Project {
+            tracks: vec![track],
+            arrangement_mode: true,
+            ..Default::default()
+        };
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.0, ..Default::default() };
+        let pool = Arc::new(ArcSwap::from_pointee(pool));
+        let out = render_project(project, vec![], pool, &config);
+
+        assert_eq!(out.len(), 4800 * 2, "length counts frames, not samples");
+        let left = out.iter().step_by(2).fold(0.0f32, |m, s| m.max(s.abs()));
+        let right = out.iter().skip(1).step_by(2).fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(left > 0.1, "left channel plays");
+        assert!(right < 1e-3, "right channel stays silent");
+    }
+
+    #[test]
+    fn test_render_converts_asset_rate() {
+        let sr = 48000;
+        let mut pool = AudioPool::new();
+        // 0.1s at 24 kHz must last 4800 frames at 48 kHz
+        let id = pool.add_asset_from_data(vec![0.5; 2400], 1, 24000.0);
+
+        let mut track = Track::default();
+        track.arrangement.clips.push(audio_clip(id, 0.0, 6000));
+        let project = Project {
+            tracks: vec![track],
+            arrangement_mode: true,
+            ..Default::default()
+        };
+
+        let pool = Arc::new(ArcSwap::from_pointee(pool));
+        let converted = {
+            let mut snapshot = (**pool.load()).clone();
+            snapshot.convert_all(sr);
+            snapshot.get_asset(id).map(|a| (a.frames(), a.original_data.len()))
+        };
+        assert_eq!(converted, Some((4800, 2400)), "converted at engine rate, original kept");
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.0, ..Default::default() };
+        let out = render_project(project, vec![], pool.clone(), &config);
+        assert!(out[..4000 * 2].iter().all(|s| s.abs() > 0.1), "plays for the converted length");
+        assert!(pool.load().get_asset(id).is_some_and(|a| a.sample_rate == 24000), "live pool untouched");
+    }
+
+    #[test]
+    fn test_stretched_clip_plays_all_its_material() {
+        let sr = 48000;
+        let mut pool = AudioPool::new();
+        let id = pool.add_asset_from_data(vec![0.5; 4800], 1, sr as f32);
+
+        // Recorded at 120 BPM, played at 60: 4800 frames last 9600 from beat 1 (48000)
+        let mut clip = audio_clip(id, 1.0, 4800);
+        clip.stretch = true;
+        clip.original_bpm = 120.0;
+        let mut track = Track::default();
+        track.arrangement.clips.push(clip);
+        let project = Project { tracks: vec![track], arrangement_mode: true, bpm: 60.0, ..Default::default() };
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.1, ..Default::default() };
+        let out = render_project(project, vec![], Arc::new(ArcSwap::from_pointee(pool)), &config);
+        assert_eq!(out.len(), (48000 + 9600 + 4800) * 2, "export covers the stretched length");
+        assert!(out[48000 * 2..57600 * 2].iter().all(|s| s.abs() > 0.1), "plays the whole stretched clip");
+        assert!(out[57600 * 2..].iter().all(|s| s.abs() < 1e-3), "silent after the clip");
+    }
+
+    /// Outputs a constant level while any note is held, so note timing shows in the render.
+    struct GateNode {
+        held: usize,
+    }
+
+    impl AudioNode for GateNode {
+        fn process(&mut self, output: &mut [f32], _sr: f32, midi_events: &[omni_shared::MidiNoteEvent], _p: &[omni_shared::ParameterEvent], _e: &[omni_shared::ExpressionEvent]) {
+            for (i, frame) in output.chunks_mut(2).enumerate() {
+                for ev in midi_events.iter().filter(|ev| ev.sample_offset as usize == i) {
+                    if ev.velocity > 0 {
+                        self.held += 1;
+                    } else {
+                        self.held = self.held.saturating_sub(1);
+                    }
+                }
+                let level = if self.held > 0 { 0.5 } else { 0.0 };
+                frame.fill(level);
+            }
+        }
+    }
+
+    /// Records the largest block it is asked to render.
+    struct BlockSizeProbe {
+        largest: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl AudioNode for BlockSizeProbe {
+        fn process(&mut self, output: &mut [f32], _sr: f32, _m: &[omni_shared::MidiNoteEvent], _p: &[omni_shared::ParameterEvent], _e: &[omni_shared::ExpressionEvent]) {
+            self.largest.fetch_max(output.len() / 2, std::sync::atomic::Ordering::Relaxed);
+            output.fill(0.25);
+        }
+    }
+
+    #[test]
+    fn test_device_buffers_above_max_block_are_split() {
+        let largest = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let probe = BlockSizeProbe { largest: largest.clone() };
+        let project = Project { tracks: vec![Track::default()], ..Default::default() };
+        let mut renderer = OfflineRenderer::new(project, vec![Box::new(probe)], Arc::new(ArcSwap::from_pointee(AudioPool::new())), 48000);
+
+        // One device buffer four times the largest block
+        let frames = crate::processor::MAX_BLOCK_FRAMES * 4;
+        let mut out = vec![0.0f32; frames * 2];
+        renderer.processor.process(&mut out, 2);
+
+        assert_eq!(largest.load(std::sync::atomic::Ordering::Relaxed), crate::processor::MAX_BLOCK_FRAMES);
+        assert!(out[(frames - 1) * 2].abs() > 0.1, "the whole buffer is rendered");
+    }
+
+    #[test]
+    fn test_render_looped_midi_part_cuts_notes() {
+        let sr = 48000; // 24000 samples per beat at 120 BPM
+        let clip = omni_shared::project::Clip {
+            notes: vec![note(0.5, 1.0)], // Longer than the loop: cut at the loop end
+            length: 1.0,
+            ..Default::default()
+        };
+        let mut part = omni_shared::project::MidiArrangementClip::from_clip(clip, 1.0);
+        part.length = 2.0;
+
+        let mut track = Track::default();
+        track.arrangement.midi_clips.push(part);
+        let project = Project {
+            tracks: vec![track],
+            arrangement_mode: true,
+            ..Default::default()
+        };
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.5, ..Default::default() };
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let out = render_project(project, vec![Box::new(GateNode { held: 0 })], pool, &config);
+
+        let gate = |frame: usize| out[frame * 2].abs() > 0.1;
+        assert!(!gate(35999) && gate(36000), "first note on at beat 1.5");
+        assert!(gate(47999) && !gate(48000), "cut at the loop boundary");
+        assert!(!gate(59999) && gate(60000), "second iteration");
+        assert!(gate(71999) && !gate(72000), "cut at the part end");
+        assert!(out[72000 * 2..].iter().all(|s| s.abs() < 1e-6), "nothing after the part");
+    }
+
+    #[test]
+    fn test_render_midi_part_follows_tempo_changes() {
+        let sr = 48000;
+        let clip = omni_shared::project::Clip {
+            notes: vec![note(0.0, 1.0)],
+            length: 1.0,
+            ..Default::default()
+        };
+        let mut track = Track::default();
+        track.arrangement.midi_clips.push(omni_shared::project::MidiArrangementClip::from_clip(clip, 4.0));
+        // 2 beats at 120 BPM (1s), then 240 BPM: beat 4 is at 1.5s, beat 5 at 1.75s
+        let project = Project {
+            tracks: vec![track],
+            arrangement_mode: true,
+            tempo_changes: vec![omni_shared::tempo::TempoEvent { beat: 2.0, bpm: 240.0, ramp: Default::default() }],
+            ..Default::default()
+        };
+        assert_eq!(project_length_samples(&project, sr), 84000);
+
+        let config = ExportConfig { sample_rate: sr, tail_seconds: 0.1, ..Default::default() };
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let out = render_project(project, vec![Box::new(GateNode { held: 0 })], pool, &config);
+
+        let gate = |frame: usize| out[frame * 2].abs() > 0.1;
+        assert!(!gate(71999) && gate(72000), "note on at beat 4");
+        assert!(gate(83999) && !gate(84000), "note off at beat 5");
+    }
+
+    #[test]
+    fn test_loop_wraps_and_ends_held_notes() {
+        let sr = 48000; // 24000 samples per beat at 120 BPM
+        let clip = omni_shared::project::Clip {
+            notes: vec![note(0.5, 2.0)], // Held across the loop end
+            length: 4.0,
+            ..Default::default()
+        };
+        let mut track = Track::default();
+        track.arrangement.midi_clips.push(omni_shared::project::MidiArrangementClip::from_clip(clip, 0.0));
+        let project = Project {
+            tracks: vec![track],
+            arrangement_mode: true,
+            ..Default::default()
+        };
+
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let mut renderer = OfflineRenderer::new(project, vec![Box::new(GateNode { held: 0 })], pool, sr);
+        let tx = renderer.command_sender();
+        tx.send(EngineCommand::SetLoopRegion(omni_shared::project::TimeRange { enabled: true, start: 0.0, end: 1.0 })).ok();
+        tx.send(EngineCommand::Play).ok();
+        let out = renderer.render(60000, 2);
+
+        let gate = |frame: usize| out[frame * 2].abs() > 0.1;
+        assert!(!gate(11999) && gate(12000), "note on at beat 0.5");
+        assert!(gate(23999) && !gate(24000), "held note ends at the wrap");
+        assert!(!gate(35999) && gate(36000), "second pass plays it again");
+        assert!(gate(47999) && !gate(48000), "and ends it at the next wrap");
+    }
+
+    #[test]
+    fn test_scene_launch_and_stop_wait_for_the_bar() {
+        let sr = 48000; // 24000 samples per beat at 120 BPM
+        let clip = omni_shared::project::Clip {
+            notes: vec![note(0.0, 0.5)],
+            length: 1.0,
+            ..Default::default()
+        };
+        let track = Track { clips: vec![Default::default(), clip], active_clip_index: None, ..Default::default() };
+        let project = Project { tracks: vec![track], ..Default::default() };
+
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let mut renderer = OfflineRenderer::new(project, vec![Box::new(GateNode { held: 0 })], pool, sr);
+        let tx = renderer.command_sender();
+        tx.send(EngineCommand::Play).ok();
+        let mut out = renderer.render(30000, 2);
+        // Launched during bar 0: starts on bar 1 (beat 4)
+        tx.send(EngineCommand::LaunchScene { scene_index: 1 }).ok();
+        out.extend(renderer.render(80000, 2));
+        assert_eq!(renderer.processor.handles().launch_state.playing(0), Some(1));
+        // Stopped during bar 1: notes on beats 5..7 still play
+        tx.send(EngineCommand::StopTrack { track_index: 0 }).ok();
+        out.extend(renderer.render(110000, 2));
+
+        let gate = |frame: usize| out[frame * 2].abs() > 0.1;
+        assert!(!(0..96000).any(gate), "nothing before the bar");
+        assert!(gate(96000) && gate(168000), "clip plays from beat 4");
+        assert!(!(186000..220000).any(gate), "no note on beat 8");
+        assert_eq!(renderer.processor.handles().launch_state.playing(0), None);
+    }
+
+    /// Delays its input (or its own gate) by a fixed latency and reports it, like a lookahead plugin.
+    struct LatentNode {
+        gate: Option<GateNode>,
+        line: std::collections::VecDeque<f32>,
+    }
+
+    impl LatentNode {
+        fn new(gate: Option<GateNode>, latency: usize) -> Self {
+            Self { gate, line: std::iter::repeat_n(0.0, latency * 2).collect() }
+        }
+    }
+
+    impl AudioNode for LatentNode {
+        fn process(&mut self, output: &mut [f32], sr: f32, m: &[omni_shared::MidiNoteEvent], p: &[omni_shared::ParameterEvent], e: &[omni_shared::ExpressionEvent]) {
+            if let Some(gate) = &mut self.gate {
+                gate.process(output, sr, m, p, e);
+            }
+            for sample in output.iter_mut() {
+                self.line.push_back(*sample);
+                *sample = self.line.pop_front().unwrap_or(0.0);
+            }
+        }
+
+        fn get_latency(&self) -> u32 {
+            (self.line.len() / 2) as u32
+        }
+    }
+
+    #[test]
+    fn test_send_to_latent_return_stays_aligned() {
+        use omni_shared::project::{Clip, TrackKind, TrackSend};
+        let sr = 48000;
+        let latency = 1000;
+        let clip = Clip {
+            notes: vec![note(0.0, 4.0)],
+            length: 4.0,
+            ..Default::default()
+        };
+        let source = Track {
+            clips: vec![clip],
+            active_clip_index: Some(0),
+            sends: vec![TrackSend { target: 1, level: 0.5, pre_fader: false }],
+            ..Default::default()
+        };
+        let ret = Track { kind: TrackKind::Return, ..Default::default() };
+        let project = Project { tracks: vec![source, ret], ..Default::default() };
+
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let nodes: Vec<Box<dyn AudioNode>> = vec![
+            Box::new(GateNode { held: 0 }),
+            Box::new(LatentNode::new(None, latency)),
+        ];
+        let mut renderer = OfflineRenderer::new(project, nodes, pool, sr);
+        renderer.command_sender().send(EngineCommand::Play).ok();
+        let out = renderer.render(24000, 2);
+
+        // Direct path waits for the return: the sum arrives in one step
+        let onset = out.chunks(2).position(|f| f[0].abs() > 1e-3).unwrap();
+        assert_eq!(onset, latency, "direct path delayed by the return latency");
+        let full = out[(onset + 4000) * 2];
+        assert!((out[onset * 2] - full).abs() < 1e-4, "no step between the two paths");
+    }
+
+    #[test]
+    fn test_nested_groups_align_every_path() {
+        use omni_shared::project::{Clip, TrackKind};
+        let sr = 48000;
+        let held_note = Clip {
+            notes: vec![note(0.0, 4.0)],
+            length: 4.0,
+            ..Default::default()
+        };
+        let playing = |parent: Option<usize>| Track {
+            clips: vec![held_note.clone()],
+            active_clip_index: Some(0),
+            parent,
+            ..Default::default()
+        };
+        let group = |parent: Option<usize>| Track { kind: TrackKind::Group, parent, ..Default::default() };
+        // 0 (500 late) -> group 3 -> group 4 (300 late) -> master; 1 -> group 4; 2 -> master
+        let project = Project {
+            tracks: vec![playing(Some(3)), playing(Some(4)), playing(None), group(Some(4)), group(None)],
+            ..Default::default()
+        };
+        let nodes: Vec<Box<dyn AudioNode>> = vec![
+            Box::new(LatentNode::new(Some(GateNode { held: 0 }), 500)),
+            Box::new(GateNode { held: 0 }),
+            Box::new(GateNode { held: 0 }),
+            Box::new(crate::nodes::GainNode::new(1.0)),
+            Box::new(LatentNode::new(None, 300)),
+        ];
+
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let mut renderer = OfflineRenderer::new(project, nodes, pool, sr);
+        renderer.command_sender().send(EngineCommand::Play).ok();
+        let out = renderer.render(24000, 2);
+
+        // All three sources arrive together behind the slowest path (500 + 300)
+        let onset = out.chunks(2).position(|f| f[0].abs() > 1e-3).unwrap();
+        assert_eq!(onset, 800);
+        let full = out[(onset + 4000) * 2];
+        assert!((out[onset * 2] - full).abs() < 1e-4, "no step between the paths");
+
+        // Group 3 already feeds group 4: routing 4 into 3 would feed back
+        let sender = renderer.command_sender();
+        sender.send(EngineCommand::SetTrackParent { track_index: 4, parent: Some(3) }).ok();
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        sender.send(EngineCommand::GetProjectState(tx)).ok();
+        renderer.render(512, 2);
+        assert_eq!(rx.try_recv().unwrap().tracks[4].parent, None);
+    }
+
+    #[test]
+    fn test_solo_silences_arrangement_audio() {
+        let sr = 48000;
+        let mut pool = AudioPool::new();
+        let loud = pool.add_asset_from_data(vec![0.1; 48000], 1, sr as f32);
+        let quiet = pool.add_asset_from_data(vec![0.04; 48000], 1, sr as f32);
+        let playing = |asset| {
+            let mut track = Track::default();
+            track.arrangement.clips.push(audio_clip(asset, 0.0, 48000));
+            track
+        };
+        let project = Project {
+            tracks: vec![playing(loud), playing(quiet)],
+            arrangement_mode: true,
+            ..Default::default()
+        };
+
+        let pool = Arc::new(ArcSwap::from_pointee(pool));
+        let mut renderer = OfflineRenderer::new(project, vec![], pool, sr);
+        let sender = renderer.command_sender();
+        sender.send(EngineCommand::Play).ok();
+        let all = *renderer.render(4800, 2).last().unwrap();
+        sender.send(EngineCommand::SetSolo { track_index: 0, solo: true, exclusive: true }).ok();
+        sender.send(EngineCommand::SetSolo { track_index: 1, solo: true, exclusive: true }).ok();
+        let soloed = *renderer.render(4800, 2).last().unwrap();
+        sender.send(EngineCommand::SetSolo { track_index: 0, solo: true, exclusive: false }).ok();
+        let both = *renderer.render(4800, 2).last().unwrap();
+
+        assert!(all > 0.05, "both clips play");
+        assert!((soloed / all - 0.04 / 0.14).abs() < 0.01, "exclusive solo plays one track's clip: {} of {}", soloed, all);
+        assert!((both - all).abs() < 1e-4, "additive solo brings the other back");
+    }
+
+    /// Effect with an auxiliary input that outputs its key.
+    struct KeyListener {
+        key: Vec<f32>,
+    }
+
+    impl AudioNode for KeyListener {
+        fn process(&mut self, output: &mut [f32], _sr: f32, _m: &[omni_shared::MidiNoteEvent], _p: &[omni_shared::ParameterEvent], _e: &[omni_shared::ExpressionEvent]) {
+            for (i, sample) in output.iter_mut().enumerate() {
+                *sample = self.key.get(i).copied().unwrap_or(0.0);
+            }
+        }
+
+        fn has_sidechain(&self) -> bool {
+            true
+        }
+
+        fn set_sidechain(&mut self, key: &[f32]) {
+            self.key.clear();
+            self.key.extend_from_slice(key);
+        }
+    }
+
+    #[test]
+    fn test_sidechain_key_is_compensated() {
+        use crate::chain::DeviceChain;
+        use omni_shared::project::{Clip, Device, Sidechain};
+        let sr = 48000;
+        let latency = 300;
+        let clip = Clip {
+            notes: vec![note(0.0, 4.0)],
+            length: 4.0,
+            ..Default::default()
+        };
+        // Track 0 hears only the key from track 1 (after a 300 frame instrument);
+        // track 1 is faded out, so the pre-fader key is all that reaches the master
+        let keyed = Track {
+            effects: vec![Device { sidechain: Some(Sidechain { source: 1, pre_fader: true }), ..Default::default() }],
+            ..Default::default()
+        };
+        let source = Track { clips: vec![clip], active_clip_index: Some(0), volume: 0.0, ..Default::default() };
+        let project = Project { tracks: vec![keyed, source], ..Default::default() };
+        let nodes: Vec<Box<dyn AudioNode>> = vec![
+            Box::new(DeviceChain::new(Box::new(LatentNode::new(None, latency))).with_effect(Box::new(KeyListener { key: Vec::new() }), false)),
+            Box::new(GateNode { held: 0 }),
+        ];
+
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let mut renderer = OfflineRenderer::new(project, nodes, pool, sr);
+        renderer.command_sender().send(EngineCommand::Play).ok();
+        let out = renderer.render(4800, 2);
+
+        // The key runs first (higher index, earlier stage) and waits for the instrument
+        let onset = out.chunks(2).position(|f| f[0].abs() > 1e-3).unwrap();
+        assert_eq!(onset, latency);
+
+        // Disconnecting silences it
+        renderer.command_sender().send(EngineCommand::SetDeviceSidechain { track_index: 0, position: 0, sidechain: None }).ok();
+        let out = renderer.render(4800, 2);
+        assert!(out.iter().skip(latency * 2).all(|s| s.abs() < 1e-6));
+    }
+    /// Effect with an auxiliary input that subtracts its key: silent while the two line up.
+    struct KeyCancel {
+        key: Vec<f32>,
+    }
+
+    impl AudioNode for KeyCancel {
+        fn process(&mut self, output: &mut [f32], _sr: f32, _m: &[omni_shared::MidiNoteEvent], _p: &[omni_shared::ParameterEvent], _e: &[omni_shared::ExpressionEvent]) {
+            for (sample, key) in output.iter_mut().zip(&self.key) {
+                *sample -= key;
+            }
+        }
+
+        fn has_sidechain(&self) -> bool {
+            true
+        }
+
+        fn set_sidechain(&mut self, key: &[f32]) {
+            self.key.clear();
+            self.key.extend_from_slice(key);
+        }
+    }
+
+    #[test]
+    fn test_latent_key_source_delays_the_keyed_track() {
+        use crate::chain::DeviceChain;
+        use omni_shared::project::{Clip, Device, Sidechain};
+        let sr = 48000;
+        let clip = Clip { notes: vec![note(0.0, 4.0)], length: 4.0, ..Default::default() };
+        // Both tracks play the same note; the key path is 300 frames late and faded out
+        let keyed = Track {
+            clips: vec![clip.clone()],
+            active_clip_index: Some(0),
+            effects: vec![Device { sidechain: Some(Sidechain { source: 1, pre_fader: true }), ..Default::default() }],
+            ..Default::default()
+        };
+        let source = Track { clips: vec![clip], active_clip_index: Some(0), volume: 0.0, ..Default::default() };
+        let project = Project { tracks: vec![keyed, source], ..Default::default() };
+        let nodes: Vec<Box<dyn AudioNode>> = vec![
+            Box::new(DeviceChain::new(Box::new(GateNode { held: 0 })).with_effect(Box::new(KeyCancel { key: Vec::new() }), false)),
+            Box::new(LatentNode::new(Some(GateNode { held: 0 }), 300)),
+        ];
+
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let mut renderer = OfflineRenderer::new(project, nodes, pool, sr);
+        renderer.command_sender().send(EngineCommand::Play).ok();
+        let out = renderer.render(4800, 2);
+        assert!(out.iter().all(|s| s.abs() < 1e-6), "the keyed track waits for its key");
+
+        // Without the key the track plays again
+        renderer.command_sender().send(EngineCommand::SetDeviceSidechain { track_index: 0, position: 0, sidechain: None }).ok();
+        let out = renderer.render(4800, 2);
+        assert!(out[4000 * 2].abs() > 0.1);
+    }
+}
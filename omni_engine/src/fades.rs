@@ -0,0 +1,115 @@
+//! Clip gain envelopes for arrangement playback.
+//! Combines the clip's own fades with automatic crossfades where clips overlap
+//! on one track.
+
+use omni_shared::project::{ArrangementClip, ClipFade, FadeCurve};
+use omni_shared::tempo::TempoMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipEnvelope {
+    pub gain: f32,
+    pub length: u64,
+    pub fade_in: ClipFade,
+    pub fade_out: ClipFade,
+}
+
+impl ClipEnvelope {
+    /// Envelope of `clips[index]`. A clip partially overlapped by a neighbour
+    /// fades across the whole overlap with an equal-power curve, unless its
+    /// own fade is longer. Clips nested inside another are left alone.
+    pub fn for_clip(clips: &[ArrangementClip], index: usize, tempo: &TempoMap, sample_rate: f64) -> Self {
+        let clip = &clips[index];
+        let start = clip.start_sample(tempo, sample_rate);
+        let end = start + clip.played_span().1;
+        let mut fade_in = clip.fade_in;
+        let mut fade_out = clip.fade_out;
+
+        for (i, other) in clips.iter().enumerate() {
+            if i == index {
+                continue;
+            }
+            let other_start = other.start_sample(tempo, sample_rate);
+            let other_end = other_start + other.played_span().1;
+
+            // Neighbour ends inside this clip: crossfade in
+            if other_start < start && other_end > start && other_end < end {
+                let overlap = other_end - start;
+                if overlap > fade_in.length {
+                    fade_in = ClipFade { length: overlap, curve: FadeCurve::EqualPower };
+                }
+            }
+            // Neighbour starts inside this clip: crossfade out
+            if other_start > start && other_start < end && other_end > end {
+                let overlap = end - other_start;
+                if overlap > fade_out.length {
+                    fade_out = ClipFade { length: overlap, curve: FadeCurve::EqualPower };
+                }
+            }
+        }
+
+        Self { gain: clip.gain, length: clip.played_span().1, fade_in, fade_out }
+    }
+
+    /// Gain at frame `pos` from the clip start.
+    pub fn gain_at(&self, pos: u64) -> f32 {
+        let mut gain = self.gain;
+        if pos < self.fade_in.length {
+            gain *= self.fade_in.curve.gain((pos as f32 + 0.5) / self.fade_in.length as f32);
+        }
+        let remaining = self.length.saturating_sub(pos);
+        if remaining <= self.fade_out.length {
+            gain *= self.fade_out.curve.gain((remaining as f32 - 0.5) / self.fade_out.length as f32);
+        }
+        gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f64 = 48000.0; // 24000 samples per beat at 120 BPM
+
+    fn clip(start: u64, length: u64) -> ArrangementClip {
+        ArrangementClip::new("Clip".to_string(), 0, start as f64 / 24000.0, length)
+    }
+
+    #[test]
+    fn test_overlap_crossfades_with_constant_power() {
+        let clips = vec![clip(0, 10000), clip(8000, 10000)];
+        let tempo = TempoMap::new(120.0, &[]);
+        let a = ClipEnvelope::for_clip(&clips, 0, &tempo, SR);
+        let b = ClipEnvelope::for_clip(&clips, 1, &tempo, SR);
+        assert_eq!(a.fade_out, ClipFade { length: 2000, curve: FadeCurve::EqualPower });
+        assert_eq!(b.fade_in, ClipFade { length: 2000, curve: FadeCurve::EqualPower });
+
+        for t in (8000..10000).step_by(100) {
+            let (ga, gb) = (a.gain_at(t), b.gain_at(t - 8000));
+            assert!((ga * ga + gb * gb - 1.0).abs() < 1e-3, "power dips at {}", t);
+        }
+        // Outside the overlap both play untouched
+        assert_eq!(a.gain_at(5000), 1.0);
+        assert_eq!(b.gain_at(5000), 1.0);
+
+        // Clip gain scales the crossfade, with no jump at either edge
+        let clips: Vec<_> = clips.into_iter().map(|c| ArrangementClip { gain: 0.5, ..c }).collect();
+        let a = ClipEnvelope::for_clip(&clips, 0, &tempo, SR);
+        let b = ClipEnvelope::for_clip(&clips, 1, &tempo, SR);
+        assert!((a.gain_at(7999) - a.gain_at(8000)).abs() < 1e-3, "no jump entering the overlap");
+        assert!((b.gain_at(1999) - b.gain_at(2000)).abs() < 1e-3, "no jump leaving the overlap");
+        let (ga, gb) = (a.gain_at(9000), b.gain_at(1000));
+        assert!((ga * ga + gb * gb - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_fades_and_gain() {
+        let mut c = clip(0, 1000);
+        c.gain = 0.5;
+        c.fade_in = ClipFade { length: 100, curve: FadeCurve::Linear };
+        let env = ClipEnvelope::for_clip(&[c], 0, &TempoMap::new(120.0, &[]), SR);
+        assert!(env.gain_at(0) < 0.01);
+        assert!((env.gain_at(50) - 0.25).abs() < 0.01);
+        assert_eq!(env.gain_at(500), 0.5);
+        assert_eq!(env.gain_at(999), 0.5, "no fade-out set");
+    }
+}
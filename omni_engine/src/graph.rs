@@ -1,19 +1,36 @@
 use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
 use crate::nodes::AudioNode;
 use omni_shared::MidiNoteEvent;
 use std::cell::UnsafeCell;
 
 /// Wrapper that allows parallel mutable access to distinct nodes in the graph.
 /// Safety: Caller MUST guarantee that NodeIndices are distinct across threads.
-struct UnsafeGraphCell(UnsafeCell<DiGraph<Box<dyn AudioNode>, ()>>);
+struct UnsafeGraphCell(UnsafeCell<DiGraph<Box<dyn AudioNode>, Route>>);
 unsafe impl Sync for UnsafeGraphCell {}
 
+/// What an edge carries. Both order the schedule; only audio counts toward
+/// the destination's input latency (a key is compensated on its own).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Audio,
+    Sidechain,
+}
+
 pub struct AudioGraph {
-    graph: DiGraph<Box<dyn AudioNode>, ()>,
-    // Processing Chains (Track paths)
-    chains: Vec<Vec<NodeIndex>>,
-    // Buffers for parallel processing
-    buffers: Vec<Vec<f32>>,
+    graph: DiGraph<Box<dyn AudioNode>, Route>,
+    // Processing stages in topological order: a node only reads nodes of earlier
+    // stages, so each stage runs in parallel (independent branches share a stage)
+    stages: Vec<Vec<NodeIndex>>,
+    stage_of: Vec<usize>, // By node index
+    scheduled: bool,
+}
+
+/// Latency along the slowest path into and out of a node, in frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathLatency {
+    pub input: u32,  // When the slowest input arrives
+    pub output: u32, // input + the node's own latency
 }
 
 use rayon::prelude::*;
@@ -22,79 +39,120 @@ impl AudioGraph {
     pub fn new() -> Self {
         Self {
             graph: DiGraph::new(),
-            chains: Vec::new(),
-            buffers: Vec::new(),
+            stages: Vec::new(),
+            stage_of: Vec::new(),
+            scheduled: false,
         }
     }
 
     pub fn add_node(&mut self, node: Box<dyn AudioNode>) -> NodeIndex {
         let idx = self.graph.add_node(node);
-        // Invalidate chains
-        self.chains.clear();
+        // Invalidate schedule
+        self.scheduled = false;
         idx
     }
 
-    pub fn add_edge(&mut self, from: NodeIndex, to: NodeIndex) {
-        self.graph.add_edge(from, to, ());
-        self.chains.clear();
+    /// Routes the output of `from` into `to`. Refuses edges that would close a
+    /// cycle (feedback) and returns whether the edge is in the graph.
+    pub fn add_edge(&mut self, from: NodeIndex, to: NodeIndex) -> bool {
+        self.add_route(from, to, Route::Audio)
     }
 
+    /// Schedules `from` before `to`, whose device reads it as a key.
+    pub fn add_sidechain_edge(&mut self, from: NodeIndex, to: NodeIndex) -> bool {
+        self.add_route(from, to, Route::Sidechain)
+    }
+
+    fn add_route(&mut self, from: NodeIndex, to: NodeIndex, route: Route) -> bool {
+        if from == to || petgraph::algo::has_path_connecting(&self.graph, to, from, None) {
+            return false;
+        }
+        if !self.graph.edges_connecting(from, to).any(|e| *e.weight() == route) {
+            self.graph.add_edge(from, to, route);
+            self.scheduled = false;
+        }
+        true
+    }
+
+    /// Whether `from` routes audio into `to`.
+    pub fn contains_edge(&self, from: NodeIndex, to: NodeIndex) -> bool {
+        self.graph.edges_connecting(from, to).any(|e| *e.weight() == Route::Audio)
+    }
+
+    pub fn clear_edges(&mut self) {
+        self.graph.clear_edges();
+        self.scheduled = false;
+    }
+
+    /// Levels the DAG: sources are stage 0, every other node runs one stage
+    /// after its latest input.
     pub fn update_schedule(&mut self) {
-        if !self.chains.is_empty() { return; }
+        if self.scheduled { return; }
 
-        // Simple Chain Discovery: 
-        // Find all nodes with 0 incoming edges (Sources)
-        // DFS/Walk until leaf or join.
-        // For this prototype, we assume independent linear chains (Tracks) that don't merge until Master (which we might handle separately or just sum buffers).
-        
-        let sources: Vec<NodeIndex> = self.graph.node_indices()
-            .filter(|&idx| self.graph.neighbors_directed(idx, petgraph::Direction::Incoming).count() == 0)
-            .collect();
-
-        self.chains = sources.into_iter().map(|start| {
-            let mut chain = Vec::new();
-            let mut current = Some(start);
-            while let Some(idx) = current {
-                chain.push(idx);
-                // Assume single output for now (Linear Chain)
-                let mut neighbors = self.graph.neighbors(idx);
-                current = neighbors.next();
-            }
-            chain
-        }).collect();
-        
-        // Resize buffers
-        // We need one buffer per chain
-        let buffer_size = omni_shared::BUFFER_SIZE * omni_shared::CHANNEL_COUNT; // 512 * 2 = 1024
-        self.buffers.resize(self.chains.len(), vec![0.0; buffer_size]);
-        
-        
-        println!("[AudioGraph] Schedule updated. Chains: {}", self.chains.len());
+        let order = petgraph::algo::toposort(&self.graph, None).unwrap_or_default(); // add_edge keeps it acyclic
+        self.stage_of.clear();
+        self.stage_of.resize(self.graph.node_count(), 0);
+        for &idx in &order {
+            let stage = self.graph.neighbors_directed(idx, petgraph::Direction::Incoming)
+                .map(|input| self.stage_of[input.index()] + 1)
+                .max()
+                .unwrap_or(0);
+            self.stage_of[idx.index()] = stage;
+        }
+
+        let stage_count = self.stage_of.iter().map(|&s| s + 1).max().unwrap_or(0);
+        self.stages = vec![Vec::new(); stage_count];
+        for &idx in &order {
+            self.stages[self.stage_of[idx.index()]].push(idx);
+        }
+        self.scheduled = true;
+
+        println!("[AudioGraph] Schedule updated. Stages: {}", self.stages.len());
     }
 
-    pub fn calculate_latencies(&self) -> (Vec<u32>, u32) {
-        let mut track_latencies = Vec::new();
-        let mut max_latency = 0;
+    pub fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
 
-        for chain in &self.chains {
-            let mut total = 0;
-            for &idx in chain {
-                if let Some(node) = self.graph.node_weight(idx) {
-                    total += node.get_latency();
-                }
-            }
-            track_latencies.push(total);
-            if total > max_latency {
-                max_latency = total;
-            }
+    pub fn stage_of(&self, idx: NodeIndex) -> Option<usize> {
+        self.stage_of.get(idx.index()).copied()
+    }
+
+    /// Per-path latencies by node index (`out` keeps its allocation).
+    pub fn calculate_latencies(&self, out: &mut Vec<PathLatency>) {
+        out.clear();
+        out.resize(self.graph.node_count(), PathLatency::default());
+        for &idx in self.stages.iter().flatten() {
+            let input = self.graph.edges_directed(idx, petgraph::Direction::Incoming)
+                .filter(|e| *e.weight() == Route::Audio)
+                .map(|e| out[e.source().index()].output)
+                .max()
+                .unwrap_or(0);
+            let own = self.graph.node_weight(idx).map_or(0, |node| node.get_latency());
+            out[idx.index()] = PathLatency { input, output: input + own };
         }
-        (track_latencies, max_latency)
+    }
+
+    /// Processes the nodes of one schedule stage. `nodes[i]` owns `buffers[i]`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_stage(&mut self, stage: usize, nodes: &[NodeIndex], buffers: &mut [Vec<f32>], events: &[Vec<MidiNoteEvent>], param_events: &[Vec<omni_shared::ParameterEvent>], expression_events: &[Vec<omni_shared::ExpressionEvent>], sample_rate: f32) {
+        let stage_of = std::mem::take(&mut self.stage_of);
+        self.process_overlay_where(nodes, buffers, events, param_events, expression_events, sample_rate, |i| {
+            stage_of.get(nodes[i].index()) == Some(&stage)
+        });
+        self.stage_of = stage_of;
     }
 
     /// Parallel processing of specific nodes with provided buffers and events.
     /// Safety: NodeIndices MUST be distinct — each rayon task accesses a different node.
     /// Uses UnsafeCell wrapper to allow parallel &mut to distinct graph nodes.
     pub fn process_overlay(&mut self, nodes: &[NodeIndex], buffers: &mut [Vec<f32>], events: &[Vec<MidiNoteEvent>], param_events: &[Vec<omni_shared::ParameterEvent>], expression_events: &[Vec<omni_shared::ExpressionEvent>], sample_rate: f32) {
+        self.process_overlay_where(nodes, buffers, events, param_events, expression_events, sample_rate, |_| true);
+    }
+
+    /// `process_overlay` for the indices `include` accepts (one stage of the mix).
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_overlay_where(&mut self, nodes: &[NodeIndex], buffers: &mut [Vec<f32>], events: &[Vec<MidiNoteEvent>], param_events: &[Vec<omni_shared::ParameterEvent>], expression_events: &[Vec<omni_shared::ExpressionEvent>], sample_rate: f32, include: impl Fn(usize) -> bool + Sync) {
         // Wrap graph in UnsafeCell for parallel mutable access to distinct nodes
         let cell = UnsafeGraphCell(UnsafeCell::new(std::mem::take(&mut self.graph)));
         let cell_ref = &cell;
@@ -102,7 +160,7 @@ impl AudioGraph {
         buffers.par_iter_mut()
             .enumerate()
             .for_each(|(i, buffer)| {
-                if i < nodes.len() && i < events.len() && i < param_events.len() && i < expression_events.len() {
+                if i < nodes.len() && include(i) && i < events.len() && i < param_events.len() && i < expression_events.len() {
                     let node_idx = nodes[i];
                     let event_slice = &events[i];
                     let param_event_slice = &param_events[i];
@@ -125,7 +183,7 @@ impl AudioGraph {
     
     pub fn reset(&mut self) {
         self.graph.clear();
-        self.chains.clear();
+        self.scheduled = false;
     }
 
     /// Removes a node from the graph.
@@ -141,8 +199,8 @@ impl AudioGraph {
         
         // Remove node and get weight
         if let Some(weight) = self.graph.remove_node(idx) {
-            // Invalidate chains
-            self.chains.clear();
+            // Invalidate schedule (the node's edges go with it)
+            self.scheduled = false;
             
             let mut swapped_idx = None;
             // Check if a swap occurred
@@ -0,0 +1,292 @@
+//! Session clip launching.
+//! Launches and stops wait for the next launch quantization boundary; a scene
+//! queues every track on the same boundary so the row starts in sync. Follow
+//! actions come due on the same grid and launch like any other clip.
+
+use omni_shared::meter::MeterMap;
+use omni_shared::project::{FollowAction, FollowTime, LaunchQuantization, Track};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const EPSILON: f64 = 1e-6;
+const NONE: u32 = u32::MAX;
+const STOP: u32 = u32::MAX - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launch {
+    Clip(usize),
+    Stop,
+}
+
+/// First launch boundary at or after `beat`.
+pub fn next_boundary(quantization: LaunchQuantization, beat: f64, meter: &MeterMap) -> f64 {
+    let bars = match quantization {
+        LaunchQuantization::None => return beat,
+        LaunchQuantization::Sixteenth => return (beat / 0.25 - EPSILON).ceil() * 0.25,
+        LaunchQuantization::Beat => {
+            let bar = meter.position(beat);
+            let unit = bar.beat_unit();
+            let next = bar.bar_start_beat + ((beat - bar.bar_start_beat) / unit - EPSILON).ceil() * unit;
+            // Odd meters (5/4 as quarters in a 4.5 beat bar) never overshoot the barline
+            return next.min(meter.bar_start(bar.bar.max(0) as u32 + 1));
+        }
+        LaunchQuantization::Bar => 1,
+        LaunchQuantization::Bars(n) => n.max(1) as u32,
+    };
+    let bar = meter.position(beat);
+    let mut next_bar = bar.bar.max(0) as u32;
+    if beat > bar.bar_start_beat + EPSILON {
+        next_bar += 1;
+    }
+    meter.bar_start(next_bar.div_ceil(bars) * bars)
+}
+
+/// Playing and pending clip per track, written by the processor for the UI.
+pub struct LaunchState {
+    playing: Vec<AtomicU32>,
+    pending: Vec<AtomicU32>,
+}
+
+impl LaunchState {
+    pub fn new(max_tracks: usize) -> Self {
+        Self {
+            playing: (0..max_tracks).map(|_| AtomicU32::new(NONE)).collect(),
+            pending: (0..max_tracks).map(|_| AtomicU32::new(NONE)).collect(),
+        }
+    }
+
+    pub fn playing(&self, track: usize) -> Option<usize> {
+        let value = self.playing.get(track)?.load(Ordering::Relaxed);
+        (value != NONE).then_some(value as usize)
+    }
+
+    /// Launch waiting for its boundary (UI blinks it).
+    pub fn pending(&self, track: usize) -> Option<Launch> {
+        match self.pending.get(track)?.load(Ordering::Relaxed) {
+            NONE => None,
+            STOP => Some(Launch::Stop),
+            clip => Some(Launch::Clip(clip as usize)),
+        }
+    }
+}
+
+/// Where launches land: follow actions come due on the same grid.
+#[derive(Clone, Copy)]
+pub struct LaunchGrid<'a> {
+    pub meter: &'a MeterMap,
+    pub quantization: LaunchQuantization,
+}
+
+/// Launches queued on the audio thread: (what, timeline beat) per track, plus
+/// the beat the playing clip's follow actions come due.
+pub struct Launcher {
+    pending: Vec<Option<(Launch, f64)>>,
+    follow: Vec<Option<f64>>,
+}
+
+impl Launcher {
+    pub fn new(max_tracks: usize) -> Self {
+        Self { pending: vec![None; max_tracks], follow: vec![None; max_tracks] }
+    }
+
+    /// Replaces whatever the track had queued.
+    pub fn queue(&mut self, track: usize, launch: Launch, beat: f64) {
+        if let Some(slot) = self.pending.get_mut(track) {
+            *slot = Some((launch, beat));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.iter_mut().for_each(|slot| *slot = None);
+        self.follow.iter_mut().for_each(|slot| *slot = None);
+    }
+
+    /// Applies `launch` at `beat` (dropping anything queued) and schedules the
+    /// new clip's follow actions from there.
+    pub fn start(&mut self, track_index: usize, tracks: &mut [Track], launch: Launch, beat: f64, grid: LaunchGrid) {
+        let Some(track) = tracks.get_mut(track_index) else { return };
+        if let Some(slot) = self.pending.get_mut(track_index) {
+            *slot = None;
+        }
+        apply(track, launch);
+        self.schedule_follow(track_index, track, beat, grid);
+    }
+
+    /// (Re)starts the follow time of the track's playing clip at `beat`.
+    pub fn schedule_follow(&mut self, track_index: usize, track: &Track, beat: f64, grid: LaunchGrid) {
+        if let Some(slot) = self.follow.get_mut(track_index) {
+            *slot = follow_due(track, beat, grid);
+        }
+    }
+
+    /// Earliest queued launch or follow action.
+    pub fn next_beat(&self) -> Option<f64> {
+        self.pending.iter().flatten().map(|&(_, beat)| beat)
+            .chain(self.follow.iter().flatten().copied())
+            .reduce(f64::min)
+    }
+
+    /// Applies every launch and follow action due by `beat`. A queued launch
+    /// beats a follow action due at the same time.
+    pub fn fire_until(&mut self, beat: f64, tracks: &mut [Track], grid: LaunchGrid) {
+        for track_index in 0..self.pending.len().min(tracks.len()) {
+            if let Some((launch, at)) = self.pending[track_index]
+                && at <= beat
+            {
+                self.start(track_index, tracks, launch, at, grid);
+            } else if let Some(at) = self.follow[track_index]
+                && at <= beat
+            {
+                match follow_launch(&tracks[track_index]) {
+                    Some(launch) => self.start(track_index, tracks, launch, at, grid),
+                    // Missed the chance: try again after another follow time
+                    None => self.schedule_follow(track_index, &tracks[track_index], at, grid),
+                }
+            }
+        }
+    }
+
+    /// The transport jumped to `beat` (stop, loop wrap): queued launches apply
+    /// there and follow times restart from it.
+    pub fn restart(&mut self, beat: f64, tracks: &mut [Track], grid: LaunchGrid) {
+        for track_index in 0..self.pending.len().min(tracks.len()) {
+            match self.pending[track_index] {
+                Some((launch, _)) => self.start(track_index, tracks, launch, beat, grid),
+                None => self.schedule_follow(track_index, &tracks[track_index], beat, grid),
+            }
+        }
+    }
+
+    pub fn publish(&self, tracks: &[Track], state: &LaunchState) {
+        for (track_idx, playing) in state.playing.iter().enumerate() {
+            let clip = tracks.get(track_idx).and_then(|t| t.active_clip_index);
+            playing.store(clip.map_or(NONE, |c| c as u32), Ordering::Relaxed);
+        }
+        for (slot, pending) in self.pending.iter().zip(&state.pending) {
+            let value = match slot {
+                Some((Launch::Clip(clip), _)) => *clip as u32,
+                Some((Launch::Stop, _)) => STOP,
+                None => NONE,
+            };
+            pending.store(value, Ordering::Relaxed);
+        }
+    }
+}
+
+fn apply(track: &mut Track, launch: Launch) {
+    track.active_clip_index = match launch {
+        Launch::Clip(clip) if clip < track.clips.len() => Some(clip),
+        _ => None,
+    };
+}
+
+/// Beat the playing clip's follow actions come due when it started at `start`.
+fn follow_due(track: &Track, start: f64, grid: LaunchGrid) -> Option<f64> {
+    let clip = track.clips.get(track.active_clip_index?)?;
+    let follow = &clip.follow_actions;
+    if !follow.enabled {
+        return None;
+    }
+    let end = match follow.time {
+        FollowTime::Bars(bars) => {
+            let bar = grid.meter.position(start);
+            let target = bar.bar.max(0) as u32 + bars.max(1) as u32;
+            grid.meter.bar_start(target) + (start - bar.bar_start_beat)
+        }
+        FollowTime::Loops(loops) => start + clip.length.max(0.0625) * loops.max(1) as f64,
+    };
+    Some(next_boundary(grid.quantization, end, grid.meter))
+}
+
+/// Rolls the follow actions of the playing clip. None: keep looping.
+fn follow_launch(track: &Track) -> Option<Launch> {
+    let current = track.active_clip_index?;
+    let follow = track.clips.get(current)?.follow_actions;
+    let total = follow.weight_a as u32 + follow.weight_b as u32;
+    if total == 0 || fastrand::f32() >= follow.chance {
+        return None;
+    }
+    let action = if fastrand::u32(0..total) < follow.weight_a as u32 { follow.action_a } else { follow.action_b };
+
+    // Targets are slots with content, wrapping around the column
+    let clips = &track.clips;
+    let n = clips.len();
+    let playable = |i: &usize| clips[*i].has_content();
+    let random = |exclude: Option<usize>| {
+        let count = (0..n).filter(playable).filter(|&i| Some(i) != exclude).count();
+        if count == 0 {
+            return current;
+        }
+        let pick = fastrand::usize(0..count);
+        (0..n).filter(playable).filter(|&i| Some(i) != exclude).nth(pick).unwrap_or(current)
+    };
+    let target = match action {
+        FollowAction::None => return None,
+        FollowAction::Stop => return Some(Launch::Stop),
+        FollowAction::Again => current,
+        FollowAction::Next => (1..n).map(|k| (current + k) % n).find(|i| playable(i)).unwrap_or(current),
+        FollowAction::Previous => (1..n).map(|k| (current + n - k) % n).find(|i| playable(i)).unwrap_or(current),
+        FollowAction::First => (0..n).find(playable).unwrap_or(current),
+        FollowAction::Last => (0..n).rev().find(playable).unwrap_or(current),
+        FollowAction::Any => random(None),
+        FollowAction::Other => random(Some(current)),
+    };
+    Some(Launch::Clip(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omni_shared::meter::MeterChange;
+    use omni_shared::project::{Clip, FollowActions, Note, TimeSignature};
+
+    #[test]
+    fn test_boundaries_follow_the_meter() {
+        // 4/4 for two bars, then 7/8 (3.5 beats per bar)
+        let meter = MeterMap::new(
+            TimeSignature::default(),
+            &[MeterChange { bar: 2, signature: TimeSignature { numerator: 7, denominator: 8 } }],
+        );
+        let q = |quantization, beat| next_boundary(quantization, beat, &meter);
+
+        assert_eq!(q(LaunchQuantization::None, 1.3), 1.3);
+        assert_eq!(q(LaunchQuantization::Sixteenth, 1.3), 1.5);
+        assert_eq!(q(LaunchQuantization::Beat, 1.3), 2.0);
+        assert_eq!(q(LaunchQuantization::Beat, 8.6), 9.0, "eighths in 7/8");
+        assert_eq!(q(LaunchQuantization::Bar, 4.0), 4.0, "on a barline fires there");
+        assert_eq!(q(LaunchQuantization::Bar, 8.1), 11.5);
+        assert_eq!(q(LaunchQuantization::Bars(2), 0.5), 8.0);
+        assert_eq!(q(LaunchQuantization::Bars(2), 8.5), 15.0, "bar 4 after two 7/8 bars");
+    }
+
+    #[test]
+    fn test_follow_actions_chain_clips() {
+        let meter = MeterMap::new(TimeSignature::default(), &[]);
+        let grid = LaunchGrid { meter: &meter, quantization: LaunchQuantization::Bar };
+        let clip = |time: FollowTime, action: FollowAction| Clip {
+            notes: vec![Note::new(0.0, 0.25, 60, 100)],
+            length: 1.0,
+            follow_actions: FollowActions { enabled: true, time, action_a: action, ..Default::default() },
+            ..Default::default()
+        };
+        // A -> next non-empty slot B -> stop
+        let mut tracks = vec![Track {
+            clips: vec![clip(FollowTime::Loops(2), FollowAction::Next), Clip::default(), clip(FollowTime::Bars(1), FollowAction::Stop)],
+            ..Default::default()
+        }];
+        let mut launcher = Launcher::new(1);
+        launcher.start(0, &mut tracks, Launch::Clip(0), 0.0, grid);
+
+        // Two loops end on beat 2, the follow action waits for the bar
+        assert_eq!(launcher.next_beat(), Some(4.0));
+        launcher.fire_until(3.9, &mut tracks, grid);
+        assert_eq!(tracks[0].active_clip_index, Some(0));
+        launcher.fire_until(4.0, &mut tracks, grid);
+        assert_eq!(tracks[0].active_clip_index, Some(2), "skips the empty slot");
+
+        // B stops after one bar
+        assert_eq!(launcher.next_beat(), Some(8.0));
+        launcher.fire_until(8.0, &mut tracks, grid);
+        assert_eq!(tracks[0].active_clip_index, None);
+        assert_eq!(launcher.next_beat(), None);
+    }
+}
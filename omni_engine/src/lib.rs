@@ -1,17 +1,31 @@
 pub mod graph;
 pub mod nodes;
+pub mod effects; // Built-in EQ, compressor, delay, reverb, saturator, filter
+pub mod sampler; // Drum rack / sampler instrument playing pool assets
+pub mod chain; // Instrument + insert effects per track
 pub mod plugin_node;
 pub mod sequencer;
 pub mod transport;
 pub mod assets;
+pub mod converter; // Background sample-rate conversion of assets
 pub mod delay;
 pub mod resampler;
+pub mod stretch; // WSOLA time stretching for audio clips
+pub mod fades; // Clip fades, gain and crossfades
+pub mod automation; // Arrangement automation lanes
+pub mod metronome; // Click and count-in
+pub mod launcher; // Quantized session clip launching
 pub mod mixer;
 pub mod commands;
 pub mod engine; // AudioEngine lives here
+pub mod processor; // Block processing shared by live and offline rendering
+pub mod backend; // cpal / null / file-sink drivers for the processor
 pub mod export; // Offline export/bounce
 
 // Re-exports
 pub use commands::EngineCommand;
 pub use engine::AudioEngine;
 pub mod recorder;
+
+#[cfg(test)]
+mod tests_recording;
@@ -0,0 +1,157 @@
+//! Metronome click, accented on downbeats and following tempo and meter changes.
+//! Mixed after the master bus, so it stays out of the master meters.
+
+use crate::assets::AudioAsset;
+use omni_shared::meter::MeterMap;
+use omni_shared::project::{ClickSound, MetronomeSettings};
+use omni_shared::tempo::TempoMap;
+
+/// Synth click length in seconds
+const SYNTH_CLICK_SECONDS: f32 = 0.05;
+const ACCENT_HZ: f32 = 1760.0;
+const BEAT_HZ: f32 = 1320.0;
+/// Gain of an unaccented click relative to the downbeat
+const BEAT_GAIN: f32 = 0.6;
+
+pub struct Metronome {
+    clicks: Vec<(usize, bool)>, // (frame in block, accent), in order
+    voice: Option<(usize, bool)>, // (frames since the click, accent)
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self { clicks: Vec::with_capacity(64), voice: None }
+    }
+}
+
+impl Metronome {
+    /// Clicks on every signature beat (eighths in 7/8) inside the block.
+    pub fn schedule(&mut self, tempo: &TempoMap, meter: &MeterMap, start_sample: u64, frames: usize, sample_rate: f64) {
+        self.clicks.clear();
+        let end_sample = start_sample + frames as u64;
+        let start_beat = tempo.beat_at_sample(start_sample as f64, sample_rate);
+        let end_beat = tempo.beat_at_sample(end_sample as f64, sample_rate);
+
+        let mut bar = meter.position(start_beat);
+        loop {
+            let unit = bar.beat_unit();
+            let next_bar_beat = meter.bar_start(bar.bar.max(0) as u32 + 1);
+            let first = ((start_beat - bar.bar_start_beat) / unit).floor().max(0.0) as u32;
+            for k in first.. {
+                let beat = bar.bar_start_beat + k as f64 * unit;
+                if beat >= next_bar_beat - 1e-9 {
+                    break;
+                }
+                // Decide in samples so a click on a block boundary lands in exactly one block
+                let sample = tempo.sample_at(beat, sample_rate).round() as u64;
+                if sample >= end_sample {
+                    break;
+                }
+                if sample >= start_sample && self.clicks.len() < self.clicks.capacity() {
+                    self.clicks.push(((sample - start_sample) as usize, k == 0));
+                }
+            }
+            if next_bar_beat > end_beat + 1e-9 {
+                break;
+            }
+            bar = meter.position(next_bar_beat);
+        }
+    }
+
+    /// Count-in clicks: `elapsed` frames into it, one click per `unit_frames`,
+    /// accented every `units_per_bar`.
+    pub fn schedule_count_in(&mut self, elapsed: u64, frames: usize, unit_frames: f64, units_per_bar: u32) {
+        self.clicks.clear();
+        let unit_frames = unit_frames.max(1.0);
+        let mut k = (elapsed as f64 / unit_frames).ceil() as u64;
+        loop {
+            let sample = (k as f64 * unit_frames).round() as u64;
+            if sample >= elapsed + frames as u64 || self.clicks.len() == self.clicks.capacity() {
+                break;
+            }
+            if sample >= elapsed {
+                self.clicks.push(((sample - elapsed) as usize, k.is_multiple_of(units_per_bar.max(1) as u64)));
+            }
+            k += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.clicks.clear();
+        self.voice = None;
+    }
+
+    /// Adds the scheduled clicks (and any still ringing) to interleaved stereo `out`.
+    /// `sample` is the click asset for `ClickSound::Sample`, already at the engine rate.
+    pub fn render(
+        &mut self,
+        out: &mut [f32],
+        frames: usize,
+        settings: &MetronomeSettings,
+        sample: Option<&AudioAsset>,
+        sample_rate: f32,
+    ) {
+        let mut next = 0;
+        for i in 0..frames {
+            while next < self.clicks.len() && self.clicks[next].0 <= i {
+                self.voice = Some((0, self.clicks[next].1));
+                next += 1;
+            }
+            let Some((pos, accent)) = self.voice else { continue };
+            let gain = settings.volume * if accent { 1.0 } else { BEAT_GAIN };
+
+            let (l, r, done) = match (settings.sound, sample) {
+                (ClickSound::Sample(_), Some(asset)) => {
+                    if pos < asset.frames() {
+                        let (l, r) = asset.frame(pos);
+                        (l, r, false)
+                    } else {
+                        (0.0, 0.0, true)
+                    }
+                }
+                _ => {
+                    let t = pos as f32 / sample_rate;
+                    let hz = if accent { ACCENT_HZ } else { BEAT_HZ };
+                    let s = (2.0 * std::f32::consts::PI * hz * t).sin() * (-t * 80.0).exp();
+                    (s, s, t >= SYNTH_CLICK_SECONDS)
+                }
+            };
+            // Post soft-clip, so keep the sum in range here
+            out[i * 2] = (out[i * 2] + l * gain).clamp(-1.0, 1.0);
+            out[i * 2 + 1] = (out[i * 2 + 1] + r * gain).clamp(-1.0, 1.0);
+            self.voice = if done { None } else { Some((pos + 1, accent)) };
+        }
+        self.clicks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omni_shared::meter::MeterChange;
+    use omni_shared::project::TimeSignature;
+
+    #[test]
+    fn test_accents_follow_meter_changes() {
+        // 4/4 for one bar, then 7/8: clicks every beat, then every eighth
+        let tempo = TempoMap::constant(120.0); // 24000 samples per beat at 48k
+        let meter = MeterMap::new(
+            TimeSignature::default(),
+            &[MeterChange { bar: 1, signature: TimeSignature { numerator: 7, denominator: 8 } }],
+        );
+        let mut metronome = Metronome::default();
+        let mut clicks = Vec::new();
+        // ~12 beats in blocks that straddle beat boundaries
+        for block in 0..(24000 * 12 / 512) {
+            let start = block as u64 * 512;
+            metronome.schedule(&tempo, &meter, start, 512, 48000.0);
+            clicks.extend(metronome.clicks.iter().map(|&(offset, accent)| (start + offset as u64, accent)));
+        }
+
+        let beats: Vec<(f64, bool)> = clicks.iter().map(|&(s, a)| (s as f64 / 24000.0, a)).collect();
+        assert_eq!(&beats[..5], &[(0.0, true), (1.0, false), (2.0, false), (3.0, false), (4.0, true)]);
+        assert_eq!(beats[5], (4.5, false), "eighths in 7/8");
+        assert_eq!(beats[11], (7.5, true), "7/8 bar is 3.5 beats");
+        assert_eq!(beats.len(), 4 + 7 + 7 + 2, "each click exactly once");
+    }
+}
@@ -1,11 +1,44 @@
 use ringbuf::HeapProd;
 use omni_shared::{MidiNoteEvent, ExpressionEvent, ParameterEvent, MAX_EXPRESSION_EVENTS, MAX_PARAM_EVENTS};
+use crate::delay::DelayLine;
+use omni_shared::project::{Track, TrackKind, TrackSend};
 use std::sync::atomic::{AtomicU32, Ordering};
 
 // ───────────────────────────── Constants ──────────────────────────────
 /// TPDF dither amplitude for 24-bit output (±0.5 LSB at 24-bit)
 const DITHER_SCALE_24: f32 = 1.0 / (1 << 23) as f32;
 
+// ───────────────────────────── Solo ──────────────────────────────
+/// Tracks silenced by solo (`out[t]`). With any track soloed, a track plays
+/// only if it is soloed, sits inside a soloed group, is a group a soloed track
+/// plays through, or is a solo-safe return/group.
+pub fn solo_silenced(tracks: &[Track], parents: &[Option<usize>], out: &mut Vec<bool>) {
+    out.clear();
+    if !tracks.iter().any(|t| t.solo) {
+        out.resize(tracks.len(), false);
+        return;
+    }
+    // Parent chains are acyclic; the bound only guards a malformed project
+    let ancestors = |t_idx: usize| {
+        std::iter::successors(parents.get(t_idx).copied().flatten(), |&g| parents.get(g).copied().flatten())
+            .take(tracks.len())
+    };
+    out.resize(tracks.len(), true);
+    for (t_idx, track) in tracks.iter().enumerate() {
+        let safe = track.solo_safe && track.kind != TrackKind::Regular;
+        if track.solo || safe || ancestors(t_idx).any(|g| tracks.get(g).is_some_and(|g| g.solo)) {
+            out[t_idx] = false;
+        }
+        if track.solo {
+            for group in ancestors(t_idx) {
+                if let Some(silenced) = out.get_mut(group) {
+                    *silenced = false;
+                }
+            }
+        }
+    }
+}
+
 // ───────────────────────────── Metering ──────────────────────────────
 /// Per-track peak meter values shared with UI thread (atomic f32 as bits)
 pub struct PeakMeters {
@@ -112,11 +145,44 @@ fn lcg_next(state: &mut u32) -> f32 {
     (*state as f32) / (u32::MAX as f32) * 2.0 - 1.0
 }
 
+/// Per-frame volume/pan from arrangement automation.
+/// Empty vectors mean the static track values apply.
+#[derive(Default)]
+pub struct AutomationRamps {
+    pub vol: Vec<f32>,
+    pub pan: Vec<f32>,
+}
+
+impl AutomationRamps {
+    pub fn with_capacity(frames: usize) -> Self {
+        Self { vol: Vec::with_capacity(frames), pan: Vec::with_capacity(frames) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vol.is_empty() && self.pan.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.vol.clear();
+        self.pan.clear();
+    }
+
+    /// (volume, pan) at `frame`, falling back to the static values.
+    #[inline]
+    pub fn at(&self, frame: usize, vol: f32, pan: f32) -> (f32, f32) {
+        (
+            self.vol.get(frame).copied().unwrap_or(vol),
+            self.pan.get(frame).copied().unwrap_or(pan),
+        )
+    }
+}
+
 pub struct AudioBuffers {
     pub track_bufs: Vec<Vec<f32>>,
     pub track_vols: Vec<f32>,
     pub track_pans: Vec<f32>,
     pub track_trims: Vec<f32>,
+    pub track_ramps: Vec<AutomationRamps>,
     pub track_events: Vec<Vec<MidiNoteEvent>>,
     pub track_expression_events: Vec<Vec<ExpressionEvent>>,
     pub track_param_events: Vec<Vec<ParameterEvent>>,
@@ -130,6 +196,8 @@ pub struct AudioBuffers {
     // Dither RNG state (per-channel to avoid correlation)
     pub dither_state_l: u32,
     pub dither_state_r: u32,
+    // Delayed copy of a track output for a send
+    pub send_buf: Vec<f32>,
 }
 
 impl AudioBuffers {
@@ -139,6 +207,7 @@ impl AudioBuffers {
             track_vols: vec![1.0; max_tracks],
             track_pans: vec![0.0; max_tracks],
             track_trims: vec![1.0; max_tracks],
+            track_ramps: (0..max_tracks).map(|_| AutomationRamps::with_capacity(buffer_size)).collect(),
             track_events: vec![Vec::with_capacity(128); max_tracks],
             track_expression_events: vec![Vec::with_capacity(MAX_EXPRESSION_EVENTS); max_tracks],
             track_param_events: vec![Vec::with_capacity(MAX_PARAM_EVENTS); max_tracks],
@@ -148,6 +217,7 @@ impl AudioBuffers {
             pitch_buf: vec![0; 16],
             dither_state_l: 0x12345678,
             dither_state_r: 0x87654321,
+            send_buf: Vec::with_capacity(buffer_size),
         }
     }
 
@@ -185,8 +255,13 @@ impl AudioBuffers {
             self.track_bufs.resize(track_count, vec![0.0; max_buffer_size]);
         }
 
+        if self.track_ramps.len() < track_count {
+            self.track_ramps.resize_with(track_count, || AutomationRamps::with_capacity(max_buffer_size));
+        }
+
         // Clear Events and Fill Audio
         for i in 0..track_count {
+            self.track_ramps[i].clear();
             self.track_events[i].clear();
             self.track_expression_events[i].clear();
             self.track_param_events[i].clear();
@@ -205,40 +280,31 @@ impl AudioBuffers {
     /// 2. Track trim (pre-fader gain staging)
     /// 3. Peak metering per track (atomic, RT→UI)
     /// 4. Summation to master bus
+    ///
+    /// Tracks routed into a group are summed there instead (`mix_into_group`).
     pub fn mix_to_master(
         track_bufs: &[Vec<f32>], 
         master_mix: &mut [f32], 
         track_vols: &[f32], 
         track_pans: &[f32], 
         track_trims: &[f32],
+        track_ramps: &[AutomationRamps],
+        track_parents: &[Option<usize>],
         frames: usize, 
         track_count: usize,
         meters: Option<&PeakMeters>,
     ) {
         for (t_idx, track_buf) in track_bufs.iter().take(track_count).enumerate() {
-             let vol = track_vols[t_idx];
-             let trim = track_trims[t_idx];
-             let pan = track_pans[t_idx];
-             
-             // Equal-power pan law (professional DAW standard)
-             let (l_pan, r_pan) = equal_power_pan(pan);
-             let l_gain = vol * trim * l_pan;
-             let r_gain = vol * trim * r_pan;
-             
-             let mut peak_l: f32 = 0.0;
-             let mut peak_r: f32 = 0.0;
-             
-             for i in 0..frames {
-                 let left = track_buf[i * 2] * l_gain;
-                 let right = track_buf[i * 2 + 1] * r_gain;
-                 
-                 master_mix[i*2] += left;
-                 master_mix[i*2+1] += right;
-                 
-                 // Track peak metering
-                 peak_l = peak_l.max(left.abs());
-                 peak_r = peak_r.max(right.abs());
+             if track_parents.get(t_idx).copied().flatten().is_some() {
+                 continue;
              }
+             let (peak_l, peak_r) = mix_track(
+                 track_buf,
+                 master_mix,
+                 (track_vols[t_idx], track_pans[t_idx], track_trims[t_idx]),
+                 &track_ramps[t_idx],
+                 frames,
+             );
              
              // Store peak atomically for UI
              if let Some(m) = meters {
@@ -247,6 +313,59 @@ impl AudioBuffers {
          }
     }
 
+    /// Sums track `src` through its fader into the input of group track `group`.
+    pub fn mix_into_group(&mut self, src: usize, group: usize, frames: usize, meters: Option<&PeakMeters>) {
+        let (src_buf, dst_buf) = pair_mut(&mut self.track_bufs, src, group);
+        let (peak_l, peak_r) = mix_track(
+            src_buf,
+            dst_buf,
+            (self.track_vols[src], self.track_pans[src], self.track_trims[src]),
+            &self.track_ramps[src],
+            frames,
+        );
+        if let Some(m) = meters {
+            m.store_track_peak(src, peak_l, peak_r);
+        }
+    }
+
+    /// Adds a send of track `src` into the input of its return track, `delay`
+    /// frames late (PDC). Post-fader sends follow the track volume and its
+    /// automation; pan stays on the direct path.
+    pub fn mix_send(&mut self, src: usize, send: &TrackSend, delay_line: &mut DelayLine, delay: u32, frames: usize) {
+        // Delay a copy: the track's own output goes on to its group or the master
+        self.send_buf.clear();
+        self.send_buf.extend_from_slice(&self.track_bufs[src][..frames * 2]);
+        delay_line.process_in_place(&mut self.send_buf, delay * 2); // Interleaved stereo
+
+        let dst_buf = &mut self.track_bufs[send.target];
+        let (vol, trim, ramps) = (self.track_vols[src], self.track_trims[src], &self.track_ramps[src]);
+        for i in 0..frames {
+            let gain = if send.pre_fader {
+                send.level
+            } else {
+                send.level * ramps.at(i, vol, 0.0).0 * trim
+            };
+            dst_buf[i * 2] += self.send_buf[i * 2] * gain;
+            dst_buf[i * 2 + 1] += self.send_buf[i * 2 + 1] * gain;
+        }
+    }
+
+    /// Key signal of track `src` for a sidechain (in `send_buf`): pre-fader is the
+    /// raw output, post-fader follows volume (and its automation), trim and mute.
+    pub fn sidechain_key(&mut self, src: usize, pre_fader: bool, muted: bool, frames: usize) -> &[f32] {
+        self.send_buf.clear();
+        self.send_buf.extend_from_slice(&self.track_bufs[src][..frames * 2]);
+        if !pre_fader {
+            let (vol, trim, ramps) = (self.track_vols[src], self.track_trims[src], &self.track_ramps[src]);
+            for i in 0..frames {
+                let gain = if muted { 0.0 } else { ramps.at(i, vol, 0.0).0 * trim };
+                self.send_buf[i * 2] *= gain;
+                self.send_buf[i * 2 + 1] *= gain;
+            }
+        }
+        &self.send_buf
+    }
+
     /// Apply master bus processing: soft-clip + dither + metering.
     /// Called after mix_to_master, before writing to output buffer.
     pub fn master_finalize(
@@ -288,3 +407,86 @@ impl AudioBuffers {
         }
     }
 }
+
+/// Adds `src` into `dst` through a fader: (volume, pan, trim), automation
+/// ramps overriding volume and pan per frame. Returns the (left, right) peaks.
+fn mix_track(src: &[f32], dst: &mut [f32], (vol, pan, trim): (f32, f32, f32), ramps: &AutomationRamps, frames: usize) -> (f32, f32) {
+    // Equal-power pan law (professional DAW standard)
+    let (l_pan, r_pan) = equal_power_pan(pan);
+    let mut l_gain = vol * trim * l_pan;
+    let mut r_gain = vol * trim * r_pan;
+
+    let mut peak_l: f32 = 0.0;
+    let mut peak_r: f32 = 0.0;
+
+    for i in 0..frames {
+        // Automated: gain ramps per frame
+        if !ramps.is_empty() {
+            let (vol, pan) = ramps.at(i, vol, pan);
+            let (l_pan, r_pan) = equal_power_pan(pan);
+            l_gain = vol * trim * l_pan;
+            r_gain = vol * trim * r_pan;
+        }
+        let left = src[i * 2] * l_gain;
+        let right = src[i * 2 + 1] * r_gain;
+
+        dst[i * 2] += left;
+        dst[i * 2 + 1] += right;
+
+        // Track peak metering
+        peak_l = peak_l.max(left.abs());
+        peak_r = peak_r.max(right.abs());
+    }
+    (peak_l, peak_r)
+}
+
+/// Borrows track buffer `src` and a different track buffer `dst` at once.
+fn pair_mut(bufs: &mut [Vec<f32>], src: usize, dst: usize) -> (&[f32], &mut [f32]) {
+    if src < dst {
+        let (head, tail) = bufs.split_at_mut(dst);
+        (&head[src], &mut tail[0])
+    } else {
+        let (head, tail) = bufs.split_at_mut(src);
+        (&tail[0], &mut head[dst])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solo_follows_groups_and_solo_safe() {
+        // 0 and 1 regular, 2 a return, 3 a group holding 4
+        let mut tracks = vec![
+            Track::default(),
+            Track::default(),
+            Track { kind: TrackKind::Return, solo_safe: true, ..Default::default() },
+            Track { kind: TrackKind::Group, ..Default::default() },
+            Track::default(),
+        ];
+        let parents = [None, None, None, None, Some(3)];
+        let mut silenced = Vec::new();
+
+        solo_silenced(&tracks, &parents, &mut silenced);
+        assert_eq!(silenced, [false; 5], "nothing soloed");
+
+        tracks[0].solo = true;
+        solo_silenced(&tracks, &parents, &mut silenced);
+        assert_eq!(silenced, [false, true, false, true, true], "soloed track and the solo-safe return");
+
+        tracks[3].solo = true;
+        solo_silenced(&tracks, &parents, &mut silenced);
+        assert_eq!(silenced, [false, true, false, false, false], "a soloed group plays its children");
+
+        tracks[3].solo = false;
+        tracks[4].solo = true;
+        tracks[2].solo_safe = false;
+        solo_silenced(&tracks, &parents, &mut silenced);
+        assert_eq!(silenced, [false, true, true, false, false], "a soloed child plays through its group");
+
+        tracks[1].solo_safe = true;
+        solo_silenced(&tracks, &parents, &mut silenced);
+        assert!(silenced[1], "solo-safe only applies to returns and groups");
+    }
+}
@@ -33,11 +33,26 @@ pub trait AudioNode: Send + Sync {
     /// Get latency in samples
     fn get_latency(&self) -> u32 { 0 }
 
+    /// Device sample rate changed (stream restarted). Plugins re-activate here.
+    fn set_sample_rate(&mut self, _sample_rate: f32) {}
+
     /// Get plugin state (for plugins)
     fn get_state(&mut self) -> Result<Vec<u8>, anyhow::Error> { Err(anyhow::anyhow!("Not supported")) }
     
     /// Set plugin state (for plugins)
     fn set_state(&mut self, _data: Vec<u8>) -> Result<(), anyhow::Error> { Err(anyhow::anyhow!("Not supported")) }
+
+    /// Instrument or audio effect (from the CLAP descriptor for plugins)
+    fn plugin_kind(&self) -> omni_shared::PluginKind { omni_shared::PluginKind::Instrument }
+
+    /// Has an auxiliary (key) input for sidechaining
+    fn has_sidechain(&self) -> bool { false }
+
+    /// Key audio (interleaved stereo) for the next `process` call
+    fn set_sidechain(&mut self, _key: &[f32]) {}
+
+    /// The track's device chain, if this node is one
+    fn as_chain(&mut self) -> Option<&mut crate::chain::DeviceChain> { None }
 }
 
 pub struct SineNode {
@@ -90,4 +105,8 @@ impl AudioNode for GainNode {
          // Assuming id 0 is gain
          self.gain = value;
     }
+
+    fn plugin_kind(&self) -> omni_shared::PluginKind {
+        omni_shared::PluginKind::Effect
+    }
 }
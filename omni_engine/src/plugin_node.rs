@@ -1,5 +1,5 @@
 use crate::nodes::AudioNode;
-use omni_shared::{HostCommand, OmniShmemHeader, PluginEvent, OMNI_MAGIC};
+use omni_shared::{HostCommand, OmniShmemHeader, PluginEvent, PluginKind, OMNI_MAGIC};
 use shared_memory::ShmemConf;
 use std::process::{Child, Command, Stdio};
 use std::io::{Write, BufReader, BufRead};
@@ -16,6 +16,9 @@ pub struct PluginNode {
     shmem_config: omni_shared::ShmemConfig,
     param_cache: std::collections::HashMap<u32, f32>,
     sample_rate: f64,
+    kind: PluginKind,
+    has_sidechain: bool,
+    sidechain: Vec<f32>, // Key for the next block (empty: none)
 }
 
 unsafe impl Sync for PluginNode {}
@@ -124,7 +127,13 @@ impl PluginNode {
         // Wait for PluginLoaded
         let mut line = String::new();
         reader.read_line(&mut line)?;
-        let _decoded = BASE64.decode(line.trim())?;
+        let decoded = BASE64.decode(line.trim())?;
+        let (kind, has_sidechain) = match bincode::deserialize(&decoded)? {
+            PluginEvent::PluginLoaded { kind, sidechain } => (kind, sidechain),
+            PluginEvent::Error(e) => return Err(anyhow::anyhow!("Plugin failed to load: {}", e)),
+            _ => (PluginKind::default(), false),
+        };
+        eprintln!("[PluginNode] Loaded {} as {:?} (sidechain: {})", plugin_path, kind, has_sidechain);
         Ok(Self {
             process: child,
             shmem,
@@ -134,6 +143,9 @@ impl PluginNode {
             shmem_config: shmem_config,
             param_cache: std::collections::HashMap::new(),
             sample_rate,
+            kind,
+            has_sidechain,
+            sidechain: Vec::with_capacity(crate::processor::MAX_BUFFER_SIZE),
         })
     }
 
@@ -262,6 +274,18 @@ impl AudioNode for PluginNode {
                 let expr_ptr = (ptr as *mut u8).add(expr_offset_bytes) as *mut omni_shared::ExpressionEvent;
                 std::ptr::copy_nonoverlapping(expression_events.as_ptr(), expr_ptr, header.expression_event_count as usize);
             }
+
+            // Sidechain key audio, when it fits the region
+            let sidechain_offset_bytes = expr_offset_bytes + (omni_shared::MAX_EXPRESSION_EVENTS * std::mem::size_of::<omni_shared::ExpressionEvent>());
+            let key_len = self.sidechain.len().min(output.len());
+            let fits = sidechain_offset_bytes + key_len * std::mem::size_of::<f32>() <= self.shmem_config.size;
+            header.sidechain_offset = sidechain_offset_bytes as u32;
+            header.sidechain_sample_count = if fits { key_len as u32 } else { 0 };
+            if fits && key_len > 0 {
+                let key_ptr = ptr.add(sidechain_offset_bytes) as *mut f32;
+                std::ptr::copy_nonoverlapping(self.sidechain.as_ptr(), key_ptr, key_len);
+            }
+            self.sidechain.clear();
             
             // NEW: Write Transport State from global
             let transport = crate::transport::get_transport();
@@ -272,6 +296,9 @@ impl AudioNode for PluginNode {
             header.transport_bar_number = transport.bar_number;
             header.transport_time_sig_num = transport.time_sig_num;
             header.transport_time_sig_denom = transport.time_sig_denom;
+            header.transport_loop_active = transport.loop_active as u32;
+            header.transport_loop_start_beats = transport.loop_start_beats;
+            header.transport_loop_end_beats = transport.loop_end_beats;
             
             // 3. Signal Process
             // std::sync::atomic::fence(Ordering::Release); // Ensure data is visible?
@@ -349,6 +376,27 @@ impl AudioNode for PluginNode {
         self.get_latency_impl()
     }
 
+    fn plugin_kind(&self) -> PluginKind {
+        self.kind
+    }
+
+    fn has_sidechain(&self) -> bool {
+        self.has_sidechain
+    }
+
+    fn set_sidechain(&mut self, key: &[f32]) {
+        self.sidechain.clear();
+        if self.has_sidechain {
+            self.sidechain.extend_from_slice(key);
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        if let Err(e) = self.reactivate_impl(sample_rate as f64) {
+            eprintln!("[PluginNode] Reactivate failed: {}", e);
+        }
+    }
+
     fn get_state(&mut self) -> Result<Vec<u8>, anyhow::Error> {
         self.get_state_impl()
     }
@@ -443,6 +491,32 @@ impl PluginNode {
         Err(anyhow::anyhow!("Failed to get plugin state"))
     }
     
+    pub fn reactivate_impl(&mut self, sample_rate: f64) -> Result<(), anyhow::Error> {
+        // Also used when resurrecting
+        self.sample_rate = sample_rate;
+
+        if let Some(stdin) = &mut self.stdin {
+            let cmd = HostCommand::Reactivate { sample_rate };
+            let serialized = bincode::serialize(&cmd)?;
+            writeln!(stdin, "{}", BASE64.encode(serialized))?;
+            stdin.flush()?;
+        }
+
+        if let Some(reader) = &mut self.reader {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let decoded = BASE64.decode(line.trim())?;
+            let event: PluginEvent = bincode::deserialize(&decoded)?;
+            return match event {
+                PluginEvent::Reactivated => Ok(()),
+                PluginEvent::Error(e) => Err(anyhow::anyhow!("Plugin Error: {}", e)),
+                _ => Err(anyhow::anyhow!("Unexpected reply to Reactivate")),
+            };
+        }
+
+        Err(anyhow::anyhow!("No active plugin connection"))
+    }
+
     pub fn set_state_impl(&mut self, data: Vec<u8>) -> Result<(), anyhow::Error> {
         if let Some(stdin) = &mut self.stdin {
             let cmd = HostCommand::SetState { data };
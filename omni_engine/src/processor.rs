@@ -0,0 +1,2900 @@
+//! Engine Processor
+//!
+//! The audio-thread side of the engine: owns the graph, project and mixer
+//! buffers and renders one block at a time. `AudioEngine` drives it from the
+//! device callback, the offline renderer in `export` drives it directly.
+
+use crate::assets::AudioPool;
+use crate::commands::EngineCommand;
+use crate::converter::{ConverterCommand, ConverterEvent};
+use crate::stretch::ClipRender;
+use crate::chain::DeviceChain;
+use crate::graph::{AudioGraph, PathLatency};
+use petgraph::graph::NodeIndex;
+use crate::launcher::{Launch, LaunchGrid, LaunchState, Launcher};
+use crate::mixer::{AudioBuffers, PeakMeters};
+use crate::nodes::{AudioNode, GainNode};
+use crate::recorder::RecorderCommand;
+use crate::sequencer::{Sequencer, StepGenerator};
+use arc_swap::ArcSwap;
+use crossbeam_channel::{Receiver, Sender};
+use omni_shared::{MidiNoteEvent, ParameterEvent, MAX_PARAM_EVENTS};
+use omni_shared::project::{ClickSound, CountIn, GrooveTemplate, LaunchQuantization, Project, StepSequencerData, Track, TrackInput, TrackKind};
+use omni_shared::meter::MeterMap;
+use omni_shared::tempo::TempoMap;
+use ringbuf::{HeapCons, HeapRb};
+use ringbuf::traits::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+pub const MAX_TRACKS: usize = 32;
+pub const MAX_BUFFER_SIZE: usize = 2048 * 2;
+/// Largest block rendered in one go; device buffers above it are split.
+/// Scratch buffers and the plugin shared memory are sized for it.
+pub const MAX_BLOCK_FRAMES: usize = MAX_BUFFER_SIZE / 2;
+
+/// Atomics shared between the processor and the outside world (UI, AudioEngine).
+#[derive(Clone)]
+pub struct ProcessorHandles {
+    pub is_playing: Arc<AtomicBool>,
+    pub is_recording: Arc<AtomicBool>,
+    pub sample_position: Arc<AtomicU64>,
+    pub recording_start_sample: Arc<AtomicU64>,
+    pub master_gain: Arc<AtomicU32>,
+    pub current_step: Arc<AtomicU32>,
+    pub peak_meters: Arc<PeakMeters>,
+    pub launch_state: Arc<LaunchState>,
+    /// Channel count of the open input device (0 = no input)
+    pub input_channels: Arc<AtomicU32>,
+}
+
+pub struct EngineProcessor {
+    command_rx: Receiver<EngineCommand>,
+    sample_rate: u32,
+
+    // Shared Atomic State
+    is_playing: Arc<AtomicBool>,
+    is_recording: Arc<AtomicBool>, // Recording to Arrangement
+    sample_position: Arc<AtomicU64>,
+    recording_start_sample: Arc<AtomicU64>,
+    master_gain: Arc<AtomicU32>,
+    current_step: Arc<AtomicU32>,
+    peak_meters: Arc<PeakMeters>,
+    launch_state: Arc<LaunchState>,
+    audio_pool: Arc<ArcSwap<AudioPool>>,
+    recorder_tx: Sender<RecorderCommand>,
+    converter_tx: Sender<ConverterCommand>,
+    converter_events: Receiver<ConverterEvent>,
+    drop_tx: Sender<Box<dyn AudioNode>>, // Off-thread dropping
+
+    // Owned State for Audio Thread
+    graph: AudioGraph,
+    project: Project,
+    sequencer: Sequencer,
+    launcher: Launcher,
+    tempo: TempoMap, // Built from project.bpm + project.tempo_changes
+    meter: MeterMap, // Built from project.time_signature + project.meter_changes
+    track_node_indices: Vec<petgraph::graph::NodeIndex>,
+    // Track active notes for Note Offs: TrackIndex -> Vec<(Note, RemainingSamples)>
+    active_notes: Vec<Vec<(u8, u64)>>,
+    audio_buffers: AudioBuffers,
+    max_buffer_size: usize,
+    // PDC Delays: each track's output path and each of its sends
+    track_delays: Vec<crate::delay::DelayLine>,
+    send_delays: Vec<Vec<crate::delay::DelayLine>>,
+    path_latencies: Vec<PathLatency>, // By graph node index
+    // Group each track is summed into, as routed in the graph (None: master)
+    track_parents: Vec<Option<usize>>,
+    solo_silenced: Vec<bool>, // Per track, refreshed every block
+    device_param_events: Vec<(usize, ParameterEvent)>, // Effect automation of one track, this block
+    crossfade: f32, // 0.0 = Session, 1.0 = Arrangement
+    // Throttle counters for debug logging
+    rec_log_throttle: u64,
+    rec_debug_throttle: u64,
+    // Recording pass: something captured yet / loop wrapped after capturing
+    rec_captured: bool,
+    rec_pass_done: bool,
+    rec_from: u64, // Pre-roll: capture starts here
+    // Metronome and count-in (transport waits while `count_in_remaining` > 0)
+    metronome: crate::metronome::Metronome,
+    count_in_remaining: u64,
+    count_in_elapsed: u64,
+    count_in_unit: f64, // Frames per click
+    count_in_units_per_bar: u32,
+    // Local buffer for parameter events to persist across command loop
+    // (Since audio_buffers.prepare_buffers clears the main event vector)
+    local_param_events: Vec<Vec<omni_shared::ParameterEvent>>,
+    // Live Input (filled by the backend's capture stream, interleaved)
+    input_consumer: Option<HeapCons<f32>>,
+    input_channels: Arc<AtomicU32>,
+    input_buf: Vec<f32>,
+}
+
+impl EngineProcessor {
+    pub fn new(
+        command_rx: Receiver<EngineCommand>,
+        sample_rate: u32,
+        audio_pool: Arc<ArcSwap<AudioPool>>,
+        recorder_tx: Sender<RecorderCommand>,
+        converter_tx: Sender<ConverterCommand>,
+        converter_events: Receiver<ConverterEvent>,
+        drop_tx: Sender<Box<dyn AudioNode>>,
+    ) -> Self {
+        // ZERO-ALLOCATION BUFFERS
+        let mut audio_buffers = AudioBuffers::new(MAX_TRACKS, MAX_BUFFER_SIZE);
+
+        // Initialize Recording Buffers (Zero-Allocation)
+        // One RingBuffer per track. Producer -> Audio Thread, Consumer -> Recorder Thread.
+        for i in 0..MAX_TRACKS {
+            let rb = HeapRb::<f32>::new(131072); // ~1.4s of stereo at 48k
+            let (prod, cons) = rb.split();
+            audio_buffers.recording_producers[i] = Some(prod);
+            recorder_tx
+                .send(RecorderCommand::AddTrack {
+                    track_index: i,
+                    consumer: cons,
+                })
+                .ok();
+        }
+
+        Self {
+            command_rx,
+            sample_rate,
+            is_playing: Arc::new(AtomicBool::new(false)),
+            is_recording: Arc::new(AtomicBool::new(false)),
+            sample_position: Arc::new(AtomicU64::new(0)),
+            recording_start_sample: Arc::new(AtomicU64::new(0)),
+            master_gain: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            current_step: Arc::new(AtomicU32::new(0)),
+            peak_meters: Arc::new(PeakMeters::new(MAX_TRACKS)),
+            launch_state: Arc::new(LaunchState::new(MAX_TRACKS)),
+            audio_pool,
+            recorder_tx,
+            converter_tx,
+            converter_events,
+            drop_tx,
+            graph: AudioGraph::new(),
+            project: Project::default(),
+            sequencer: Sequencer::new(120.0),
+            launcher: Launcher::new(MAX_TRACKS),
+            tempo: TempoMap::constant(120.0),
+            meter: MeterMap::constant(Default::default()),
+            track_node_indices: Vec::new(),
+            active_notes: vec![vec![]; MAX_TRACKS],
+            audio_buffers,
+            max_buffer_size: MAX_BUFFER_SIZE,
+            track_delays: Vec::new(),
+            send_delays: Vec::new(),
+            path_latencies: Vec::new(),
+            track_parents: Vec::new(),
+            solo_silenced: Vec::new(),
+            device_param_events: Vec::with_capacity(MAX_PARAM_EVENTS),
+            crossfade: 0.0,
+            rec_log_throttle: 0,
+            rec_captured: false,
+            rec_pass_done: false,
+            rec_from: 0,
+            metronome: Default::default(),
+            count_in_remaining: 0,
+            count_in_elapsed: 0,
+            count_in_unit: 1.0,
+            count_in_units_per_bar: 4,
+            rec_debug_throttle: 0,
+            local_param_events: vec![vec![]; MAX_TRACKS],
+            input_consumer: None,
+            input_channels: Arc::new(AtomicU32::new(0)),
+            input_buf: Vec::new(),
+        }
+    }
+
+    pub fn handles(&self) -> ProcessorHandles {
+        ProcessorHandles {
+            is_playing: self.is_playing.clone(),
+            is_recording: self.is_recording.clone(),
+            sample_position: self.sample_position.clone(),
+            recording_start_sample: self.recording_start_sample.clone(),
+            master_gain: self.master_gain.clone(),
+            current_step: self.current_step.clone(),
+            peak_meters: self.peak_meters.clone(),
+            launch_state: self.launch_state.clone(),
+            input_channels: self.input_channels.clone(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn project(&self) -> &Project {
+        &self.project
+    }
+
+    /// Switches the processor to a new device rate.
+    /// Only call while no backend is pulling audio: plugins re-activate synchronously.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate == self.sample_rate || sample_rate == 0 {
+            return;
+        }
+        let old_rate = self.sample_rate;
+        self.sample_rate = sample_rate;
+
+        // Keep the playhead at the same time position
+        let pos = self.sample_position.load(Ordering::Relaxed);
+        let scaled = (pos as f64 * sample_rate as f64 / old_rate as f64) as u64;
+        self.sample_position.store(scaled, Ordering::Relaxed);
+
+        // PDC lines are sized in samples: rebuilt on the next block
+        self.track_delays.clear();
+        self.send_delays.clear();
+
+        for &node_idx in &self.track_node_indices {
+            if let Some(node) = self.graph.node_mut(node_idx) {
+                node.set_sample_rate(sample_rate as f32);
+            }
+        }
+
+        self.recorder_tx
+            .send(RecorderCommand::SetSampleRate(sample_rate as f32))
+            .ok();
+        // Assets are re-converted from their originals; until then they stay silent
+        self.converter_tx
+            .send(ConverterCommand::SetSampleRate(sample_rate))
+            .ok();
+        eprintln!("[Engine] Sample rate changed: {} -> {} Hz", old_rate, sample_rate);
+    }
+
+    /// Attaches (or detaches) the capture stream feeding track inputs.
+    /// The ring buffer carries interleaved samples, `channels` wide.
+    pub fn set_audio_input(&mut self, input: Option<(HeapCons<f32>, usize)>) {
+        match input {
+            Some((consumer, channels)) => {
+                self.input_buf = vec![0.0; self.max_buffer_size * channels];
+                self.input_consumer = Some(consumer);
+                self.input_channels
+                    .store(channels as u32, Ordering::Relaxed);
+            }
+            None => {
+                self.input_consumer = None;
+                self.input_channels.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pulls one block of input into `input_buf`. Underruns are zero-filled.
+    /// Returns false when no input stream is attached.
+    fn read_input(&mut self, frames: usize) -> bool {
+        let channels = self.input_channels.load(Ordering::Relaxed) as usize;
+        let Some(consumer) = self.input_consumer.as_mut() else {
+            return false;
+        };
+        if channels == 0 {
+            return false;
+        }
+        let needed = frames * channels;
+        if self.input_buf.len() < needed {
+            self.input_buf.resize(needed, 0.0);
+        }
+
+        // Input and output clocks drift apart: drop the backlog to keep latency bounded
+        let occupied = consumer.occupied_len();
+        if occupied > needed * 4 {
+            let excess = occupied - needed;
+            consumer.skip(excess - excess % channels);
+        }
+
+        let got = consumer.pop_slice(&mut self.input_buf[..needed]);
+        self.input_buf[got..needed].fill(0.0);
+        true
+    }
+
+    /// Applies queued engine commands and finished background renders.
+    pub fn drain_commands(&mut self) {
+        while let Ok(cmd) = self.command_rx.try_recv() {
+            self.handle_command(cmd);
+        }
+        while let Ok(event) = self.converter_events.try_recv() {
+            match event {
+                ConverterEvent::ClipRendered { track_index, clip_index, source_id, asset_id, span } => {
+                    // Indices may be stale if clips changed meanwhile: match the source too
+                    let current = self
+                        .project
+                        .tracks
+                        .get(track_index)
+                        .and_then(|t| t.arrangement.clips.get(clip_index))
+                        .filter(|clip| clip.source_id == source_id)
+                        .filter(|clip| ClipRender::is_needed(clip, self.project.bpm, &self.project.tempo_changes));
+                    if current.is_none() {
+                        continue;
+                    }
+                    if self.audio_pool.load().get_asset(asset_id).is_some() {
+                        self.replace_clip_variant(track_index, clip_index, Some((asset_id, span)));
+                    } else {
+                        // Evicted while the result was on its way: render it again
+                        self.request_clip_render(track_index, clip_index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Queues the stretched/transposed variant of a clip on the converter thread.
+    /// The clip keeps playing its previous variant (or the source) until the new one is ready.
+    fn request_clip_render(&mut self, track_index: usize, clip_index: usize) {
+        let Some(clip) = self
+            .project
+            .tracks
+            .get_mut(track_index)
+            .and_then(|t| t.arrangement.clips.get_mut(clip_index))
+        else {
+            return;
+        };
+        match ClipRender::from_clip(clip, self.project.bpm, &self.project.tempo_changes) {
+            Some(render) => {
+                clip.stretch_ratio = render.speed() as f32;
+                self.converter_tx
+                    .send(ConverterCommand::RenderClip {
+                        track_index,
+                        clip_index,
+                        source_id: clip.source_id,
+                        render,
+                        offset: clip.start_offset.samples,
+                        length: clip.length.samples,
+                    })
+                    .ok();
+            }
+            None => {
+                clip.stretch_ratio = 1.0;
+                self.replace_clip_variant(track_index, clip_index, None);
+            }
+        }
+    }
+
+    /// Points a clip at a new variant and the span it plays of it (`None`: its source).
+    /// The variant it played before is evicted from the pool once no other clip plays it.
+    fn replace_clip_variant(&mut self, track_index: usize, clip_index: usize, variant: Option<(u32, (u64, u64))>) {
+        let Some(clip) = self
+            .project
+            .tracks
+            .get_mut(track_index)
+            .and_then(|t| t.arrangement.clips.get_mut(clip_index))
+        else {
+            return;
+        };
+        clip.cached_span = variant.map(|(_, span)| span);
+        let Some(previous) = std::mem::replace(&mut clip.cached_id, variant.map(|(id, _)| id)) else {
+            return;
+        };
+        let still_played = self
+            .project
+            .tracks
+            .iter()
+            .flat_map(|t| &t.arrangement.clips)
+            .any(|clip| clip.cached_id == Some(previous));
+        if !still_played {
+            self.converter_tx
+                .send(ConverterCommand::EvictVariant { asset_id: previous })
+                .ok();
+        }
+    }
+
+    fn request_all_clip_renders(&mut self) {
+        for t_idx in 0..self.project.tracks.len() {
+            for c_idx in 0..self.project.tracks[t_idx].arrangement.clips.len() {
+                let clip = &self.project.tracks[t_idx].arrangement.clips[c_idx];
+                if clip.cached_id.is_some()
+                    || ClipRender::is_needed(clip, self.project.bpm, &self.project.tempo_changes)
+                {
+                    self.request_clip_render(t_idx, c_idx);
+                }
+            }
+        }
+    }
+
+    /// Drains pending commands, renders one block and writes it interleaved
+    /// into `data` (`channels` wide).
+    /// An active loop brace splits the block at the loop end; blocks longer
+    /// than `MAX_BLOCK_FRAMES` are rendered in several chunks.
+    pub fn process(&mut self, data: &mut [f32], channels: usize) {
+        self.drain_commands();
+        let total = data.len() / channels;
+        let mut done = 0;
+        while done < total {
+            let playing = self.is_playing.load(Ordering::Relaxed);
+            let pos = self.sample_position.load(Ordering::Relaxed);
+            if playing {
+                self.fire_due_launches(pos);
+            }
+            let loop_range = self.loop_range().filter(|&(_, end)| playing && pos < end);
+            let mut frames = match loop_range {
+                _ if self.count_in_remaining > 0 => (self.count_in_remaining as usize).min(total - done),
+                Some((_, end)) => ((end - pos) as usize).min(total - done),
+                None => total - done,
+            }
+            .min(MAX_BLOCK_FRAMES);
+            // Split at the next launch so the new clip starts on its boundary
+            if playing && let Some(fire) = self.next_launch_sample() {
+                frames = frames.min((fire - pos) as usize);
+            }
+            self.render_block(frames);
+
+            if self.count_in_remaining > 0 {
+                self.count_in_remaining -= frames as u64;
+                self.count_in_elapsed += frames as u64;
+                if self.count_in_remaining == 0 {
+                    self.is_playing.store(true, Ordering::Relaxed);
+                }
+            }
+
+            for i in 0..frames {
+                let left = self.audio_buffers.master_mix[i * 2];
+                let right = self.audio_buffers.master_mix[i * 2 + 1];
+                let frame = &mut data[(done + i) * channels..(done + i + 1) * channels];
+                if channels == 1 {
+                    frame[0] = (left + right) * 0.5;
+                } else {
+                    frame[0] = left;
+                    frame[1] = right;
+                    for extra in frame.iter_mut().skip(2) {
+                        *extra = 0.0;
+                    }
+                }
+            }
+            done += frames;
+
+            if let Some((start, end)) = loop_range
+                && self.sample_position.load(Ordering::Relaxed) >= end
+            {
+                self.wrap_loop(start);
+            }
+        }
+        self.launcher.publish(&self.project.tracks, &self.launch_state);
+    }
+
+    /// Loop brace in samples, when active (arrangement only).
+    fn loop_range(&self) -> Option<(u64, u64)> {
+        self.timeline_range(self.project.loop_region)
+            .filter(|_| self.project.arrangement_mode)
+    }
+
+    fn timeline_range(&self, range: omni_shared::project::TimeRange) -> Option<(u64, u64)> {
+        if !range.is_active() {
+            return None;
+        }
+        let sr = self.sample_rate as f64;
+        let start = self.tempo.sample_at(range.start, sr).round() as u64;
+        let end = self.tempo.sample_at(range.end, sr).round() as u64;
+        (end > start).then_some((start, end))
+    }
+
+    /// Boundary a launch made now waits for (None: apply immediately).
+    fn launch_beat(&self) -> Option<f64> {
+        let quantization = self.project.launch_quantization;
+        if !self.is_playing.load(Ordering::Relaxed) || quantization == LaunchQuantization::None {
+            return None;
+        }
+        let pos = self.sample_position.load(Ordering::Relaxed);
+        let beat = self.tempo.beat_at_sample(pos as f64, self.sample_rate as f64);
+        Some(crate::launcher::next_boundary(quantization, beat, &self.meter))
+    }
+
+    fn queue_launch(&mut self, track_index: usize, launch: Launch, at: Option<f64>) {
+        match at {
+            Some(beat) => self.launcher.queue(track_index, launch, beat),
+            None => {
+                let beat = self.position_beat();
+                let grid = LaunchGrid { meter: &self.meter, quantization: self.project.launch_quantization };
+                self.launcher.start(track_index, &mut self.project.tracks, launch, beat, grid);
+            }
+        }
+    }
+
+    /// The transport jumped: apply queued launches and restart follow times here.
+    fn restart_launcher(&mut self) {
+        let beat = self.position_beat();
+        let grid = LaunchGrid { meter: &self.meter, quantization: self.project.launch_quantization };
+        self.launcher.restart(beat, &mut self.project.tracks, grid);
+    }
+
+    fn chain_mut(&mut self, track_index: usize) -> Option<&mut DeviceChain> {
+        let &node_idx = self.track_node_indices.get(track_index)?;
+        self.graph.node_mut(node_idx)?.as_chain()
+    }
+
+    /// Delays keyed tracks whose sidechain source has more latency than the
+    /// audio reaching the keyed effect, then refreshes the path latencies.
+    fn align_sidechain_keys(&mut self, track_count: usize) {
+        // A keyed track can key another: each pass settles at least one more
+        for _ in 0..track_count {
+            let mut changed = false;
+            for (c_idx, consumer) in self.project.tracks.iter().enumerate().take(track_count) {
+                let consumer_node = self.track_node_indices[c_idx];
+                let input = self.path_latencies.get(consumer_node.index()).map_or(0, |l| l.input);
+                let mut needed = 0;
+                for (position, device) in consumer.effects.iter().enumerate() {
+                    let Some(key) = device.sidechain else { continue };
+                    let Some(&source_node) = self.track_node_indices.get(key.source) else { continue };
+                    if key.source >= track_count || self.graph.stage_of(source_node) >= self.graph.stage_of(consumer_node) {
+                        continue; // Refused as feedback, like the key feed
+                    }
+                    let source_latency = self.path_latencies.get(source_node.index()).map_or(0, |l| l.output);
+                    let Some(chain) = self.graph.node_mut(consumer_node).and_then(|node| node.as_chain()) else { break };
+                    if chain.has_sidechain(position) {
+                        let arrival = input + chain.latency_before(position) - chain.key_alignment();
+                        needed = needed.max(source_latency.saturating_sub(arrival));
+                    }
+                }
+                if let Some(chain) = self.graph.node_mut(consumer_node).and_then(|node| node.as_chain()) {
+                    changed |= chain.set_key_alignment(needed);
+                }
+            }
+            if !changed {
+                break;
+            }
+            self.graph.calculate_latencies(&mut self.path_latencies);
+        }
+    }
+
+    /// Re-derives the graph edges from group membership and sends. Routes that
+    /// would close a loop are dropped (group membership wins over sends).
+    fn rebuild_routing(&mut self) {
+        self.graph.clear_edges();
+        self.track_parents.clear();
+        let tracks = &self.project.tracks;
+        let node_of = |t_idx: usize| self.track_node_indices.get(t_idx).copied();
+
+        for (t_idx, track) in tracks.iter().enumerate() {
+            let parent = track.parent.filter(|&group| {
+                tracks.get(group).is_some_and(|g| g.kind == TrackKind::Group)
+                    && match (node_of(t_idx), node_of(group)) {
+                        (Some(from), Some(to)) => self.graph.add_edge(from, to),
+                        _ => false,
+                    }
+            });
+            if parent != track.parent {
+                eprintln!("[Engine] Track {} can't route into {:?}, using master", t_idx, track.parent);
+            }
+            self.track_parents.push(parent);
+        }
+
+        for (t_idx, track) in tracks.iter().enumerate() {
+            if track.kind == TrackKind::Return {
+                continue; // Returns don't send
+            }
+            for send in &track.sends {
+                let is_return = tracks.get(send.target).is_some_and(|t| t.kind == TrackKind::Return);
+                if let (true, Some(from), Some(to)) = (is_return, node_of(t_idx), node_of(send.target))
+                    && !self.graph.add_edge(from, to)
+                {
+                    eprintln!("[Engine] Dropped send {} -> {} (feedback)", t_idx, send.target);
+                }
+            }
+        }
+
+        // Sidechains only order the schedule: the key source runs first
+        for (t_idx, track) in tracks.iter().enumerate() {
+            for key in track.effects.iter().filter_map(|device| device.sidechain) {
+                if let (Some(from), Some(to)) = (node_of(key.source), node_of(t_idx))
+                    && !self.graph.add_sidechain_edge(from, to)
+                {
+                    eprintln!("[Engine] Dropped sidechain {} -> {} (feedback)", key.source, t_idx);
+                }
+            }
+        }
+    }
+
+    fn position_beat(&self) -> f64 {
+        let pos = self.sample_position.load(Ordering::Relaxed);
+        self.tempo.beat_at_sample(pos as f64, self.sample_rate as f64)
+    }
+
+    fn next_launch_sample(&self) -> Option<u64> {
+        let beat = self.launcher.next_beat()?;
+        Some(self.tempo.sample_at(beat, self.sample_rate as f64).round() as u64)
+    }
+
+    fn fire_due_launches(&mut self, pos: u64) {
+        while let Some(fire) = self.next_launch_sample()
+            && fire <= pos
+        {
+            let beat = self.launcher.next_beat().unwrap_or(0.0);
+            let grid = LaunchGrid { meter: &self.meter, quantization: self.project.launch_quantization };
+            self.launcher.fire_until(beat, &mut self.project.tracks, grid);
+        }
+    }
+
+    /// Count-in or pre-roll when recording starts from stop.
+    fn start_count_in(&mut self) {
+        let settings = self.project.metronome;
+        if self.is_playing.load(Ordering::Relaxed) || settings.count_in == CountIn::Off {
+            return;
+        }
+        let sr = self.sample_rate as f64;
+        let pos = self.sample_position.load(Ordering::Relaxed);
+        let beat = self.tempo.beat_at_sample(pos as f64, sr);
+        let bar = self.meter.position(beat);
+        let count_in_beats = settings.count_in_bars.clamp(1, 2) as f64 * bar.signature.beats_per_bar();
+
+        match settings.count_in {
+            CountIn::CountIn => {
+                // Clicks in the meter and tempo at the record position
+                self.count_in_unit = bar.beat_unit() * 60.0 / self.tempo.bpm_at(beat) * sr;
+                self.count_in_units_per_bar = bar.signature.numerator.max(1) as u32;
+                self.count_in_elapsed = 0;
+                self.count_in_remaining = (count_in_beats / bar.beat_unit() * self.count_in_unit).round() as u64;
+            }
+            CountIn::PreRoll => {
+                let from = self.tempo.sample_at((beat - count_in_beats).max(0.0), sr).round() as u64;
+                self.rec_from = pos;
+                self.sample_position.store(from, Ordering::Relaxed);
+                self.is_playing.store(true, Ordering::Relaxed);
+            }
+            CountIn::Off => {}
+        }
+    }
+
+    /// Count-in clicks, or the metronome while playing. Added after the master
+    /// bus: not metered and independent of the master volume.
+    fn render_click(&mut self, block_start: u64, frames: usize, playing: bool) {
+        let settings = self.project.metronome;
+        if self.count_in_remaining > 0 {
+            self.metronome.schedule_count_in(
+                self.count_in_elapsed,
+                frames,
+                self.count_in_unit,
+                self.count_in_units_per_bar,
+            );
+        } else if playing && settings.enabled {
+            self.metronome
+                .schedule(&self.tempo, &self.meter, block_start, frames, self.sample_rate as f64);
+        }
+        let pool = self.audio_pool.load();
+        let sample = match settings.sound {
+            ClickSound::Sample(id) => pool.get_asset(id).filter(|a| a.is_at_rate(self.sample_rate)),
+            ClickSound::Synth => None,
+        };
+        self.metronome.render(
+            &mut self.audio_buffers.master_mix,
+            frames,
+            &settings,
+            sample,
+            self.sample_rate as f32,
+        );
+    }
+
+    /// Jumps back to the loop start. Held notes end at the wrap; audio clips
+    /// are placed from the position every block and simply resume there.
+    fn wrap_loop(&mut self, start: u64) {
+        self.sample_position.store(start, Ordering::Relaxed);
+        // Boundaries past the loop end would never be reached
+        self.restart_launcher();
+        for notes in &mut self.active_notes {
+            for (_, remaining) in notes.iter_mut() {
+                *remaining = 0;
+            }
+        }
+        // Recording keeps the first pass only
+        if self.is_recording.load(Ordering::Relaxed) && self.rec_captured {
+            self.rec_pass_done = true;
+        }
+    }
+
+    fn handle_command(&mut self, cmd: EngineCommand) {
+        match cmd {
+            EngineCommand::Play => self.is_playing.store(true, Ordering::Relaxed),
+            EngineCommand::Pause => {
+                self.is_playing.store(false, Ordering::Relaxed);
+                self.count_in_remaining = 0;
+                self.restart_launcher();
+            }
+            EngineCommand::Stop => {
+                self.is_playing.store(false, Ordering::Relaxed);
+                self.count_in_remaining = 0;
+                self.sample_position.store(0, Ordering::Relaxed);
+                self.restart_launcher();
+                self.sequencer.reset();
+            }
+            EngineCommand::SetVolume(v) => {
+                self.master_gain.store(v.to_bits(), Ordering::Relaxed);
+            }
+            EngineCommand::SetBpm(bpm) => {
+                self.sequencer.bpm = bpm;
+                self.project.bpm = bpm;
+                self.tempo = self.project.tempo_map();
+                // Stretched clips follow the tempo (re-rendered in the background)
+                self.request_all_clip_renders();
+            }
+            EngineCommand::SetTempoChanges(changes) => {
+                self.project.tempo_changes = changes;
+                self.tempo = self.project.tempo_map();
+                self.request_all_clip_renders();
+            }
+            EngineCommand::SetArrangementMode(mode) => {
+                self.project.arrangement_mode = mode;
+                eprintln!("[Engine] Arrangement Mode: {}", mode);
+            }
+            EngineCommand::MoveClip {
+                track_index,
+                clip_index,
+                new_start,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.clips.get_mut(clip_index) {
+                        clip.start = new_start;
+                    }
+                // Under tempo changes a stretched clip's render depends on its position
+                if !self.tempo.is_constant() {
+                    self.request_clip_render(track_index, clip_index);
+                }
+            }
+            EngineCommand::StretchClip {
+                track_index,
+                clip_index,
+                stretch,
+                original_bpm,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.clips.get_mut(clip_index) {
+                        clip.stretch = stretch;
+                        if original_bpm > 0.0 {
+                            clip.original_bpm = original_bpm;
+                        }
+                    }
+                self.request_clip_render(track_index, clip_index);
+            }
+            EngineCommand::SetClipPitch {
+                track_index,
+                clip_index,
+                semitones,
+                cents,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.clips.get_mut(clip_index) {
+                        clip.pitch_semitones = semitones;
+                        clip.pitch_cents = cents;
+                    }
+                self.request_clip_render(track_index, clip_index);
+            }
+            EngineCommand::SetClipGain {
+                track_index,
+                clip_index,
+                gain,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.clips.get_mut(clip_index) {
+                        clip.gain = gain.max(0.0);
+                    }
+            }
+            EngineCommand::SetClipFades {
+                track_index,
+                clip_index,
+                fade_in,
+                fade_out,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.clips.get_mut(clip_index) {
+                        clip.fade_in = fade_in;
+                        clip.fade_out = fade_out;
+                    }
+            }
+            EngineCommand::AddMidiClip { track_index, clip } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    track.arrangement.midi_clips.push(clip);
+                }
+            }
+            EngineCommand::MoveMidiClip {
+                track_index,
+                clip_index,
+                new_start,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.midi_clips.get_mut(clip_index) {
+                        clip.start = new_start.max(0.0);
+                    }
+            }
+            EngineCommand::SetMidiClipLoop {
+                track_index,
+                clip_index,
+                looped,
+                length,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.midi_clips.get_mut(clip_index) {
+                        clip.looped = looped;
+                        if length > 0.0 {
+                            clip.length = length;
+                        }
+                    }
+            }
+            EngineCommand::RemoveMidiClip {
+                track_index,
+                clip_index,
+            } => {
+                // Hanging notes still get their note-offs from active_notes
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && clip_index < track.arrangement.midi_clips.len() {
+                        track.arrangement.midi_clips.remove(clip_index);
+                    }
+            }
+            EngineCommand::SetAutomationLane { track_index, lane } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    let lanes = &mut track.arrangement.automation;
+                    match lanes.iter_mut().find(|l| l.target == lane.target) {
+                        Some(existing) => *existing = lane,
+                        None => lanes.push(lane),
+                    }
+                }
+            }
+            EngineCommand::RemoveAutomationLane { track_index, target } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    track.arrangement.automation.retain(|l| l.target != target);
+                }
+            }
+            EngineCommand::SetWarpMarkers {
+                track_index,
+                clip_index,
+                markers,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.arrangement.clips.get_mut(clip_index) {
+                        clip.warp_markers = markers;
+                    }
+                self.request_clip_render(track_index, clip_index);
+            }
+            EngineCommand::SetClipLength {
+                track_index,
+                clip_index,
+                length,
+            } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.clips.get_mut(clip_index) {
+                        clip.length = length;
+                        eprintln!(
+                            "[Engine] Updated Clip {}:{} Length to {} beats. Seq pattern_length remains {}",
+                            track_index, clip_index, length, self.sequencer.pattern_length
+                        );
+                    }
+            }
+            EngineCommand::SetClipFollowActions {
+                track_index,
+                clip_index,
+                follow_actions,
+            } => {
+                let beat = self.position_beat();
+                if let Some(track) = self.project.tracks.get_mut(track_index)
+                    && let Some(clip) = track.clips.get_mut(clip_index) {
+                        clip.follow_actions = follow_actions;
+                        // Editing the playing clip restarts its follow time
+                        if track.active_clip_index == Some(clip_index) {
+                            let grid = LaunchGrid { meter: &self.meter, quantization: self.project.launch_quantization };
+                            self.launcher.schedule_follow(track_index, track, beat, grid);
+                        }
+                    }
+            }
+            EngineCommand::StartRecording => {
+                self.is_recording.store(true, Ordering::Relaxed);
+                self.rec_captured = false;
+                self.rec_pass_done = false;
+                self.rec_from = 0;
+                self.start_count_in();
+                let start_val = self.sample_position.load(Ordering::Relaxed);
+                self.recording_start_sample
+                    .store(start_val, Ordering::Relaxed);
+
+                // Real-Time Safety: Send command to Recorder Thread
+                self.recorder_tx.send(RecorderCommand::Clear).ok();
+                self.recorder_tx.send(RecorderCommand::Start).ok();
+            }
+            EngineCommand::StopRecording { response_tx } => {
+                self.is_recording.store(false, Ordering::Relaxed);
+                self.count_in_remaining = 0;
+                let start_val = self.recording_start_sample.load(Ordering::Relaxed);
+                let rec_start_beat = self.tempo.beat_at_sample(start_val as f64, self.sample_rate as f64);
+                // Real-Time Safety: Delegate to Recorder Thread
+                self.recorder_tx
+                    .send(RecorderCommand::Stop {
+                        response_tx,
+                        rec_start_beat,
+                    })
+                    .ok();
+            }
+            EngineCommand::AddArrangementClips { clips } => {
+                for (track_idx, clip) in clips {
+                    if track_idx < self.project.tracks.len() {
+                        self.project.tracks[track_idx].arrangement.clips.push(clip);
+                    }
+                }
+                eprintln!("[Engine] Added arrangement clips to project");
+            }
+            EngineCommand::SetTimeSignature {
+                numerator,
+                denominator,
+            } => {
+                self.project.time_signature = omni_shared::project::TimeSignature {
+                    numerator,
+                    denominator,
+                };
+                self.meter = self.project.meter_map();
+                eprintln!("[Engine] Time Signature: {}/{}", numerator, denominator);
+            }
+            EngineCommand::SetMeterChanges(changes) => {
+                self.project.meter_changes = changes;
+                self.meter = self.project.meter_map();
+            }
+            EngineCommand::SetLoopRegion(range) => {
+                self.project.loop_region = range;
+            }
+            EngineCommand::SetPunchRange(range) => {
+                self.project.punch = range;
+            }
+            EngineCommand::SetMetronome(settings) => {
+                // A click sample loaded into the pool still needs the engine rate
+                if let ClickSound::Sample(asset_id) = settings.sound {
+                    self.converter_tx.send(ConverterCommand::Convert { asset_id }).ok();
+                }
+                self.project.metronome = settings;
+            }
+            EngineCommand::SetGroove(groove) => {
+                eprintln!("[Engine] Groove: {}", groove.name);
+                self.project.groove = groove;
+            }
+            EngineCommand::SetSwing(amount) => {
+                self.project.groove = omni_shared::project::GrooveTemplate::mpc_swing(amount);
+                eprintln!("[Engine] Swing: {:.0}%", amount * 100.0);
+            }
+            EngineCommand::SetPluginParam {
+                track_index,
+                id,
+                value,
+            } => {
+                // 1. Update Engine Node (Cache Only)
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        node.set_param(id, value);
+                    }
+
+                // 2. Queue Event for Audio Processing (Lock-Free)
+                if track_index < self.local_param_events.len() {
+                    self.local_param_events[track_index].push(omni_shared::ParameterEvent {
+                        param_id: id,
+                        value: value as f64,
+                        sample_offset: 0, // Apply at start of block
+                    });
+                }
+                // 2. Update Project State
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    track.parameters.insert(id, value);
+                }
+            }
+            EngineCommand::GetPluginParams {
+                track_index,
+                response_tx,
+            } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        let params = node.get_plugin_params();
+                        let _ = response_tx.send(params);
+                    }
+            }
+            EngineCommand::ToggleNote {
+                track_index,
+                clip_index,
+                start,
+                duration,
+                note,
+                velocity,
+                probability,
+                velocity_deviation,
+                condition,
+            } => {
+                if track_index < self.project.tracks.len()
+                    && clip_index < self.project.tracks[track_index].clips.len() {
+                        let notes = &mut self.project.tracks[track_index].clips[clip_index].notes;
+                        // Simple exact match removal or add
+                        if let Some(idx) = notes
+                            .iter()
+                            .position(|n| n.key == note && (n.start - start).abs() < 0.001)
+                        {
+                            notes.remove(idx);
+                        } else {
+                            notes.push(omni_shared::project::Note {
+                                start,
+                                duration,
+                                key: note,
+                                velocity,
+                                probability,
+                                velocity_deviation,
+                                condition,
+                                selected: false,
+                            });
+                        }
+                    }
+            }
+
+            EngineCommand::RemoveNote {
+                track_index,
+                clip_index,
+                start,
+                note,
+            } => {
+                let note_key = note;
+                if track_index < self.project.tracks.len() {
+                    let track = &mut self.project.tracks[track_index];
+                    if clip_index < track.clips.len() {
+                        let clip = &mut track.clips[clip_index];
+                        if let Some(idx) = clip
+                            .notes
+                            .iter()
+                            .position(|n| n.key == note_key && (n.start - start).abs() < 0.001)
+                        {
+                            clip.notes.remove(idx);
+                        }
+                    }
+                }
+            }
+            EngineCommand::UpdateNote {
+                track_index,
+                clip_index,
+                old_start,
+                old_note,
+                new_start,
+                new_duration,
+                new_note,
+                new_velocity,
+                new_probability,
+                new_velocity_deviation,
+                new_condition,
+            } => {
+                if track_index < self.project.tracks.len() {
+                    let track = &mut self.project.tracks[track_index];
+                    if clip_index < track.clips.len() {
+                        let clip = &mut track.clips[clip_index];
+                        if let Some(idx) = clip
+                            .notes
+                            .iter()
+                            .position(|n| n.key == old_note && (n.start - old_start).abs() < 0.001)
+                        {
+                            let note = &mut clip.notes[idx];
+                            note.start = new_start;
+                            note.duration = new_duration;
+                            note.key = new_note;
+                            note.velocity = new_velocity;
+                            note.probability = new_probability;
+                            note.velocity_deviation = new_velocity_deviation;
+                            note.condition = new_condition;
+                        }
+                    }
+                }
+            }
+            EngineCommand::SetMute { track_index, muted } => {
+                if track_index < self.project.tracks.len() {
+                    self.project.tracks[track_index].mute = muted;
+                }
+            }
+            EngineCommand::SetSolo { track_index, solo, exclusive } => {
+                if track_index < self.project.tracks.len() {
+                    if solo && exclusive {
+                        for track in &mut self.project.tracks {
+                            track.solo = false;
+                        }
+                    }
+                    self.project.tracks[track_index].solo = solo;
+                }
+            }
+            EngineCommand::SetSoloSafe { track_index, safe } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    track.solo_safe = safe;
+                }
+            }
+            EngineCommand::SimulateCrash { track_index } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        node.simulate_crash();
+                    }
+            }
+            EngineCommand::TriggerClip {
+                track_index,
+                clip_index,
+            } => {
+                let at = self.launch_beat();
+                self.queue_launch(track_index, Launch::Clip(clip_index), at);
+            }
+            EngineCommand::LaunchScene { scene_index } => {
+                // One boundary for the whole row; tracks without the slot stop
+                let at = self.launch_beat();
+                for track_index in 0..self.project.tracks.len() {
+                    self.queue_launch(track_index, Launch::Clip(scene_index), at);
+                }
+            }
+            EngineCommand::StopTrack { track_index } => {
+                let at = self.launch_beat();
+                self.queue_launch(track_index, Launch::Stop, at);
+            }
+            EngineCommand::SetLaunchQuantization(quantization) => {
+                self.project.launch_quantization = quantization;
+            }
+            EngineCommand::OpenPluginEditor { track_index } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        // Explicitly cast or handle return to satisfy type inference if needed, but here it returns ()
+                        node.open_editor();
+                    }
+            }
+            EngineCommand::SetTrackVolume {
+                track_index,
+                volume,
+            } => {
+                if track_index < self.project.tracks.len() {
+                    self.project.tracks[track_index].volume = volume;
+                }
+            }
+            EngineCommand::SetTrackPan { track_index, pan } => {
+                if track_index < self.project.tracks.len() {
+                    self.project.tracks[track_index].pan = pan;
+                }
+            }
+            EngineCommand::SetTrackInput { track_index, input } => {
+                if track_index < self.project.tracks.len() {
+                    self.project.tracks[track_index].input = input;
+                }
+            }
+            EngineCommand::SetRecordArm { track_index, armed } => {
+                if track_index < self.project.tracks.len() {
+                    self.project.tracks[track_index].record_arm = armed;
+                }
+            }
+            EngineCommand::SetMonitorMode { track_index, mode } => {
+                if track_index < self.project.tracks.len() {
+                    self.project.tracks[track_index].monitor = mode;
+                }
+            }
+            EngineCommand::GetProjectState(response_tx) => {
+                let _ = response_tx.send(self.project.clone());
+            }
+            EngineCommand::LoadProjectState(new_proj, nodes) => {
+                // 1 Reset Graph
+                self.graph = AudioGraph::new();
+                self.track_node_indices.clear();
+                self.track_parents.clear();
+
+                // 2 Load Project
+                self.project = new_proj;
+                self.launcher.clear();
+                self.sequencer.bpm = self.project.bpm;
+                self.tempo = self.project.tempo_map();
+                self.meter = self.project.meter_map();
+                self.restart_launcher(); // Follow actions of the loaded clips
+                // Jump straight to the loaded view instead of fading from the old one
+                self.crossfade = if self.project.arrangement_mode { 1.0 } else { 0.0 };
+                // Assets loaded straight into the pool (load_asset) get converted by a sweep
+                self.converter_tx
+                    .send(ConverterCommand::SetSampleRate(self.sample_rate))
+                    .ok();
+                // Processed variants are runtime-only (cached_id is not saved)
+                self.request_all_clip_renders();
+
+                // 3 Rebuild Graph from Project & Provided Nodes
+                // We expect nodes to match tracks 1:1, but handle mismatches safely
+                let mut nodes_iter = nodes.into_iter();
+
+                for track in &self.project.tracks {
+                    // Use provided node or fallback to GainNode
+                    let mut node = DeviceChain::wrap(
+                        nodes_iter
+                            .next()
+                            .unwrap_or_else(|| Box::new(GainNode::new(1.0))),
+                    );
+                    // Effects that didn't come with the node pass audio through
+                    if let Some(chain) = node.as_chain() {
+                        for effect in track.effects.iter().skip(chain.effect_count()) {
+                            chain.insert(usize::MAX, Box::new(GainNode::new(1.0)), effect.bypass);
+                        }
+                    }
+
+                    let node_idx = self.graph.add_node(node);
+                    self.track_node_indices.push(node_idx);
+                }
+                self.rebuild_routing();
+
+                // 4. Restore Plugin State (Blob)
+                for (t_idx, track) in self.project.tracks.iter().enumerate() {
+                    if let Some(state_data) = &track.plugin_state
+                        && let Some(&node_idx) = self.track_node_indices.get(t_idx)
+                            && let Some(node) = self.graph.node_mut(node_idx) {
+                                let _ = node.set_state(state_data.clone());
+                            }
+                }
+
+                // 5. Restore Plugin Parameters (Individual)
+                // Resize self.local_param_events to match new track count
+                if self.local_param_events.len() < self.project.tracks.len() {
+                    self.local_param_events
+                        .resize(self.project.tracks.len(), Vec::new());
+                }
+
+                for (t_idx, track) in self.project.tracks.iter().enumerate() {
+                    if !track.parameters.is_empty()
+                        && let Some(&node_idx) = self.track_node_indices.get(t_idx)
+                            && let Some(node) = self.graph.node_mut(node_idx) {
+                                for (&id, &val) in &track.parameters {
+                                    // 1. Update Node Cache (for resurrection)
+                                    node.set_param(id, val);
+
+                                    // 2. Queue for Immediate Processing
+                                    if t_idx < self.local_param_events.len() {
+                                        self.local_param_events[t_idx].push(
+                                            omni_shared::ParameterEvent {
+                                                param_id: id,
+                                                value: val as f64,
+                                                sample_offset: 0,
+                                            },
+                                        );
+                                    }
+                                }
+                                // eprintln!("[Engine] Restored {} params for track {}", track.parameters.len(), t_idx);
+                            }
+                }
+
+                // 6. Restore Effect State and Parameters
+                for (t_idx, track) in self.project.tracks.iter().enumerate() {
+                    let Some(chain) = self
+                        .track_node_indices
+                        .get(t_idx)
+                        .and_then(|&node_idx| self.graph.node_mut(node_idx))
+                        .and_then(|node| node.as_chain())
+                    else {
+                        continue;
+                    };
+                    for (position, effect) in track.effects.iter().enumerate() {
+                        chain.set_bypass(position, effect.bypass);
+                        if let Some(state_data) = &effect.state
+                            && let Some(node) = chain.effect_mut(position)
+                        {
+                            let _ = node.set_state(state_data.clone());
+                        }
+                        for (&id, &val) in &effect.parameters {
+                            chain.set_effect_param(position, id, val, 0);
+                        }
+                    }
+                }
+                eprintln!("[Engine] Loaded project state (Non-Blocking Swap)");
+            }
+            EngineCommand::ResetGraph => {
+                self.graph = AudioGraph::new();
+                self.track_node_indices.clear();
+                self.track_parents.clear();
+                eprintln!("[Engine] Audio Graph Reset");
+            }
+            EngineCommand::NewProject => {
+                // 1. Stop Playback
+                self.is_playing.store(false, Ordering::Relaxed);
+                self.sample_position.store(0, Ordering::Relaxed);
+
+                // 2. Reset Engine State
+                self.graph = AudioGraph::new();
+                self.track_node_indices.clear();
+                self.track_parents.clear();
+                self.project = Project::default();
+                self.launcher.clear();
+                self.tempo = self.project.tempo_map();
+                self.meter = self.project.meter_map();
+                self.sequencer.reset();
+                self.active_notes.iter_mut().for_each(|v| v.clear());
+
+                eprintln!("[Engine] New Project Created (Engine State Reset)");
+            }
+            EngineCommand::RemoveTrack { track_index } => {
+                if track_index < self.track_node_indices.len() {
+                    let node_idx_to_remove = self.track_node_indices[track_index];
+
+                    // 1. Remove Track Metadata
+                    if track_index < self.project.tracks.len() {
+                        let removed = self.project.tracks.remove(track_index);
+                        for track in self.project.tracks.iter_mut() {
+                            omni_shared::project::reindex_sends(&mut track.sends, track_index);
+                            omni_shared::project::reindex_sidechains(&mut track.effects, track_index);
+                            track.parent = omni_shared::project::reindex_parent(track.parent, track_index, removed.parent);
+                        }
+                        self.launcher.clear(); // Queued launches are per track index
+                        self.restart_launcher();
+                    }
+
+                    // 2. Remove from Graph (Prevent Memory Leak)
+                    // self.graph.remove_node performs a swap-remove, moving the last node to the removed index.
+                    // It returns the index of the node that was moved (if any).
+                    // AND it should return the removed node weight so we can drop it off-thread.
+
+                    if let Some((moved_node_old_idx, removed_node)) =
+                        self.graph.remove_node_with_return(node_idx_to_remove)
+                    {
+                        // Send removed node to drop thread
+                        if let Some(node) = removed_node {
+                            let _ = self.drop_tx.send(node);
+                        }
+
+                        if let Some(swapped_old_idx) = moved_node_old_idx {
+                            // Update index for swapped node
+                            for idx in self.track_node_indices.iter_mut() {
+                                if *idx == swapped_old_idx {
+                                    *idx = node_idx_to_remove;
+                                    break;
+                                }
+                            }
+                        }
+                    }
+
+                    // 3. Remove Node Index Mapping
+                    self.track_node_indices.remove(track_index);
+
+                    // 4. Clean up active notes
+                    self.active_notes.remove(track_index);
+
+                    // 5. PDC lines follow their tracks; routing edges went with the node
+                    if track_index < self.track_delays.len() {
+                        self.track_delays.remove(track_index);
+                        self.send_delays.remove(track_index);
+                    }
+                    self.rebuild_routing();
+
+                    eprintln!("[Engine] Removed Track {}", track_index);
+                }
+            }
+            EngineCommand::AddTrackNode {
+                node,
+                name,
+                plugin_path,
+            } => {
+                let node_idx = self.graph.add_node(DeviceChain::wrap(node));
+                let mut t = Track {
+                    name,
+                    ..Default::default()
+                };
+                if let Some(p) = plugin_path {
+                    t.plugin_path = p;
+                }
+
+                self.project.tracks.push(t);
+                self.track_node_indices.push(node_idx);
+                self.rebuild_routing();
+            }
+            EngineCommand::AddReturnTrack {
+                node,
+                name,
+                plugin_path,
+            } => {
+                let node_idx = self.graph.add_node(DeviceChain::wrap(node));
+                self.project.tracks.push(Track {
+                    name,
+                    kind: TrackKind::Return,
+                    plugin_path: plugin_path.unwrap_or_default(),
+                    solo_safe: true,
+                    ..Default::default()
+                });
+                self.track_node_indices.push(node_idx);
+                self.rebuild_routing();
+            }
+            EngineCommand::AddGroupTrack {
+                node,
+                name,
+                children,
+            } => {
+                let node_idx = self.graph.add_node(DeviceChain::wrap(node));
+                let group = self.project.tracks.len();
+                // The new group goes where its first child went
+                let parent = children.first().and_then(|&c| self.project.tracks.get(c)).and_then(|t| t.parent);
+                self.project.tracks.push(Track {
+                    name,
+                    kind: TrackKind::Group,
+                    parent,
+                    ..Default::default()
+                });
+                self.track_node_indices.push(node_idx);
+                for child in children {
+                    if let Some(track) = self.project.tracks.get_mut(child)
+                        && child != group
+                    {
+                        track.parent = Some(group);
+                    }
+                }
+                self.rebuild_routing();
+            }
+            EngineCommand::SetTrackParent { track_index, parent } => {
+                let tracks = &self.project.tracks;
+                let parents: Vec<Option<usize>> = tracks.iter().map(|t| t.parent).collect();
+                let kinds: Vec<TrackKind> = tracks.iter().map(|t| t.kind).collect();
+                let allowed = parent.is_none_or(|group| {
+                    omni_shared::project::can_route_to_group(&parents, &kinds, track_index, group)
+                });
+                if allowed && let Some(track) = self.project.tracks.get_mut(track_index) {
+                    track.parent = parent;
+                    self.rebuild_routing();
+                }
+            }
+            EngineCommand::SetTrackSends { track_index, sends } => {
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    // Returns don't send (no return-to-return feedback)
+                    if track.kind != TrackKind::Return {
+                        track.sends = sends;
+                        self.rebuild_routing();
+                    }
+                }
+            }
+            EngineCommand::ReplaceTrackNode {
+                track_index,
+                node,
+                name,
+                plugin_path,
+            } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(existing_node_ref) = self.graph.node_mut(node_idx) {
+                        // The instrument changes; the track's effects stay
+                        let old = match existing_node_ref.as_chain() {
+                            Some(chain) => chain.replace_instrument(node),
+                            None => std::mem::replace(existing_node_ref, DeviceChain::wrap(node)),
+                        };
+                        let _ = self.drop_tx.send(old);
+
+                        // Update Project
+                        if track_index < self.project.tracks.len() {
+                            self.project.tracks[track_index].name = name;
+                            self.project.tracks[track_index].plugin_path = plugin_path;
+                        }
+                    }
+            }
+            EngineCommand::UpdateClipSequencer {
+                track_index,
+                clip_index,
+                use_sequencer,
+                data,
+            } => {
+                if track_index < self.project.tracks.len()
+                    && clip_index < self.project.tracks[track_index].clips.len() {
+                        self.project.tracks[track_index].clips[clip_index].use_sequencer =
+                            use_sequencer;
+                        self.project.tracks[track_index].clips[clip_index].step_sequencer = data;
+                    }
+            }
+            EngineCommand::GetNoteNames {
+                track_index,
+                response_tx,
+            } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        let names = node.get_note_names();
+                        let _ = response_tx.send(names);
+                    }
+            }
+            EngineCommand::GetLastTouchedParam {
+                track_index,
+                response_tx,
+            } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        let touched = node.get_last_touched();
+                        // Filter empty (0,0,0) - assuming 0 param ID might be valid but generation 0 means never touched?
+                        // Generation starts at 0. But incremented on touch.
+                        // Let's pass it raw.
+                        let result = if touched.2 > 0 { Some(touched) } else { None };
+                        let _ = response_tx.send(result);
+                    }
+            }
+            EngineCommand::GetPluginState {
+                track_index,
+                response_tx,
+            } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index) {
+                    if let Some(node) = self.graph.node_mut(node_idx) {
+                        let state = node.get_state().ok();
+                        let _ = response_tx.send(state);
+                    } else {
+                        let _ = response_tx.send(None);
+                    }
+                } else {
+                    let _ = response_tx.send(None);
+                }
+            }
+            EngineCommand::SetPluginState { track_index, data } => {
+                if let Some(&node_idx) = self.track_node_indices.get(track_index)
+                    && let Some(node) = self.graph.node_mut(node_idx) {
+                        let _ = node.set_state(data);
+                    }
+            }
+            EngineCommand::InsertDevice {
+                track_index,
+                position,
+                node,
+                device,
+            } => {
+                let Some(chain) = self.chain_mut(track_index) else {
+                    let _ = self.drop_tx.send(node);
+                    return;
+                };
+                let position = position.min(chain.effect_count());
+                chain.insert(position, node, device.bypass);
+                if let Some(track) = self.project.tracks.get_mut(track_index) {
+                    eprintln!("[Engine] Track {}: inserted {} at {}", track_index, device.name, position);
+                    track.effects.insert(position.min(track.effects.len()), device);
+                    track.arrangement.remap_device_lanes(|p| Some(if p >= position { p + 1 } else { p }));
+                }
+                self.rebuild_routing();
+            }
+            EngineCommand::RemoveDevice { track_index, position } => {
+                if let Some(node) = self.chain_mut(track_index).and_then(|chain| chain.remove(position)) {
+                    let _ = self.drop_tx.send(node);
+                    if let Some(track) = self.project.tracks.get_mut(track_index)
+                        && position < track.effects.len()
+                    {
+                        track.effects.remove(position);
+                        track.arrangement.remap_device_lanes(|p| match p.cmp(&position) {
+                            std::cmp::Ordering::Less => Some(p),
+                            std::cmp::Ordering::Equal => None,
+                            std::cmp::Ordering::Greater => Some(p - 1),
+                        });
+                    }
+                    self.rebuild_routing();
+                }
+            }
+            EngineCommand::MoveDevice { track_index, from, to } => {
+                if self.chain_mut(track_index).is_some_and(|chain| chain.move_effect(from, to))
+                    && let Some(track) = self.project.tracks.get_mut(track_index)
+                    && from < track.effects.len()
+                    && to < track.effects.len()
+                {
+                    let device = track.effects.remove(from);
+                    track.effects.insert(to, device);
+                    track.arrangement.remap_device_lanes(|p| Some(moved_position(p, from, to)));
+                }
+            }
+            EngineCommand::SetDeviceSidechain { track_index, position, sidechain } => {
+                if let Some(device) = self.project.tracks.get_mut(track_index).and_then(|t| t.effects.get_mut(position)) {
+                    device.sidechain = sidechain;
+                    self.rebuild_routing();
+                }
+            }
+            EngineCommand::SetDeviceBypass { track_index, position, bypass } => {
+                if let Some(chain) = self.chain_mut(track_index) {
+                    chain.set_bypass(position, bypass);
+                }
+                if let Some(device) = self.project.tracks.get_mut(track_index).and_then(|t| t.effects.get_mut(position)) {
+                    device.bypass = bypass;
+                }
+            }
+            EngineCommand::SetDeviceParam { track_index, position, id, value } => {
+                if let Some(chain) = self.chain_mut(track_index) {
+                    chain.set_effect_param(position, id, value, 0);
+                }
+                if let Some(device) = self.project.tracks.get_mut(track_index).and_then(|t| t.effects.get_mut(position)) {
+                    device.parameters.insert(id, value);
+                }
+            }
+            EngineCommand::OpenDeviceEditor { track_index, position } => {
+                if let Some(node) = self.chain_mut(track_index).and_then(|chain| chain.effect_mut(position)) {
+                    node.open_editor();
+                }
+            }
+            EngineCommand::GetDeviceParams { track_index, response_tx } => {
+                let devices = match self.chain_mut(track_index) {
+                    Some(chain) => (0..chain.effect_count())
+                        .map(|position| omni_shared::DeviceInfo {
+                            sidechain: chain.has_sidechain(position),
+                            params: chain.effect_mut(position).map(|n| n.get_plugin_params()).unwrap_or_default(),
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let _ = response_tx.send(devices);
+            }
+            EngineCommand::GetDeviceStates { track_index, response_tx } => {
+                let states = match self.chain_mut(track_index) {
+                    Some(chain) => (0..chain.effect_count())
+                        .map(|position| chain.effect_mut(position).and_then(|n| n.get_state().ok()))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let _ = response_tx.send(states);
+            }
+            EngineCommand::AddAsset {
+                name,
+                data,
+                channels,
+                source_sample_rate,
+                response_tx,
+            } => {
+                // Pool insertion allocates: the converter does it (RCU) and replies
+                self.converter_tx
+                    .send(ConverterCommand::AddAsset { name, data, channels, source_sample_rate, response_tx })
+                    .ok();
+            }
+        }
+    }
+
+    /// Schedules notes of arrangement MIDI parts overlapping this block into `track_events`.
+    /// Notes are cut at the part end and, for looped parts, at each loop boundary.
+    fn schedule_arrangement_midi(&mut self, current_sample: u64, frames: usize, track_count: usize) {
+        let sr = self.sample_rate as f64;
+        let tempo = &self.tempo;
+        let block_start = tempo.beat_at_sample(current_sample as f64, sr);
+        let block_end = tempo.beat_at_sample((current_sample + frames as u64) as f64, sr);
+        // Note lengths within the block use the tempo at its start
+        let samples_per_beat = (sr * 60.0 / tempo.bpm_at(block_start)) as f32;
+        // Timeline beat -> offset into this block (negative before it, may lie past it)
+        let signed_offset = |beat: f64| (tempo.sample_at(beat, sr) - current_sample as f64) as i64;
+        let block_offset = |beat: f64| signed_offset(beat).max(0) as u64;
+
+        for (t_idx, track) in self.project.tracks.iter().enumerate() {
+            if t_idx >= track_count || track.mute {
+                continue;
+            }
+
+            for part in &track.arrangement.midi_clips {
+                let part_end = part.end();
+                if part_end <= block_start || part.start >= block_end {
+                    continue;
+                }
+                let clip = &part.clip;
+                // Timeline beat of content beat 0
+                let origin = part.start - part.start_offset;
+                let from = block_start.max(part.start);
+                let to = block_end.min(part_end);
+                let note_limit = block_offset(part_end);
+
+                if clip.use_sequencer {
+                    let step_dur_beats = 0.25;
+                    let first_step = (((from - origin) / step_dur_beats).floor() as i64).max(0) as u64;
+                    let end_step = ((to - origin) / step_dur_beats).ceil().max(0.0) as u64;
+
+                    for step in first_step..end_step {
+                        if !part.looped && step as f64 * step_dur_beats >= clip.length {
+                            break;
+                        }
+                        let groove_offset =
+                            self.project.groove.get_offset(step as usize) as f64 * step_dur_beats;
+                        let step_beat_time = origin + step as f64 * step_dur_beats + groove_offset;
+                        // Steps before the part start are cut off
+                        if step_beat_time < part.start || step_beat_time >= part_end {
+                            continue;
+                        }
+                        let offset_beats = step_beat_time - block_start;
+                        let offset_samples_raw = signed_offset(step_beat_time);
+                        if offset_samples_raw >= frames as i64 {
+                            continue;
+                        }
+
+                        schedule_sequencer_step(
+                            &clip.step_sequencer,
+                            &self.project.groove,
+                            &StepTiming {
+                                step,
+                                beat: step_beat_time,
+                                offset_beats,
+                                offset_samples: offset_samples_raw.max(0) as u32,
+                                starts_in_buffer: offset_samples_raw >= 0,
+                                block_start_beat: block_start,
+                                step_dur_beats,
+                                samples_per_beat,
+                                frames,
+                                note_limit,
+                            },
+                            t_idx,
+                            &mut self.audio_buffers,
+                            &mut self.active_notes,
+                        );
+                    }
+                    continue;
+                }
+
+                let loop_len = clip.length;
+                for note in &clip.notes {
+                    // Each occurrence: (timeline beat, iteration, cut-off beat)
+                    let (mut iteration, last) = if part.looped {
+                        if loop_len <= 0.0 || note.start >= loop_len {
+                            continue;
+                        }
+                        let first = ((from - origin - note.start) / loop_len).floor().max(0.0) as u64;
+                        let last = ((to - origin - note.start) / loop_len).ceil().max(0.0) as u64;
+                        (first, last)
+                    } else {
+                        (0, 1)
+                    };
+
+                    while iteration < last {
+                        let content_start = origin + iteration as f64 * loop_len;
+                        let beat = content_start + note.start;
+                        iteration += 1;
+                        if beat < from || beat >= to {
+                            continue;
+                        }
+
+                        // Same stochastic rules as session clips
+                        if note.probability < 1.0 && fastrand::f64() > note.probability {
+                            continue;
+                        }
+                        if let omni_shared::project::NoteCondition::Iteration { expected, cycle } = note.condition {
+                            let current_cycle_idx = ((iteration - 1) % cycle.max(1) as u64) as u8 + 1;
+                            if current_cycle_idx != expected {
+                                continue;
+                            }
+                        }
+                        let mut velocity = note.velocity;
+                        if note.velocity_deviation != 0 {
+                            let dev = note.velocity_deviation as i32;
+                            velocity = (velocity as i32 + fastrand::i32(-dev.abs()..=dev.abs())).clamp(1, 127) as u8;
+                        }
+
+                        let mut end_beat = (beat + note.duration).min(part_end);
+                        if part.looped {
+                            end_beat = end_beat.min(content_start + loop_len);
+                        }
+                        let on = block_offset(beat);
+                        let off = block_offset(end_beat).max(on + 1);
+
+                        self.audio_buffers.track_events[t_idx].push(MidiNoteEvent {
+                            note: note.key,
+                            velocity,
+                            channel: 0,
+                            sample_offset: on as u32,
+                            detune: 0.0,
+                        });
+                        if off < frames as u64 {
+                            self.audio_buffers.track_events[t_idx].push(MidiNoteEvent {
+                                note: note.key,
+                                velocity: 0,
+                                channel: 0,
+                                sample_offset: off as u32,
+                                detune: 0.0,
+                            });
+                        } else if t_idx < self.active_notes.len() {
+                            self.active_notes[t_idx].push((note.key, off - frames as u64));
+                        }
+                    }
+                }
+            }
+
+            // Plugins expect events in time order (stable: a note-off stays ahead of a retrigger)
+            if !track.arrangement.midi_clips.is_empty() {
+                self.audio_buffers.track_events[t_idx].sort_by_key(|e| e.sample_offset);
+            }
+        }
+    }
+
+    /// Renders `frames` stereo frames into `audio_buffers.master_mix`.
+    fn render_block(&mut self, frames: usize) {
+        let playing = self.is_playing.load(Ordering::Relaxed);
+        let block_start_sample = self.sample_position.load(Ordering::Relaxed);
+        let sample_rate_val = self.sample_rate as f32;
+        let track_count = self.track_node_indices.len();
+
+        // Resize Buffers (Keep Capacity)
+        // Prepare Buffers (Resize & Clear)
+        self.audio_buffers
+            .prepare_buffers(frames, track_count, self.max_buffer_size);
+
+        // Resize self.local_param_events if needed
+        if self.local_param_events.len() < track_count {
+            self.local_param_events.resize(track_count, Vec::new());
+        }
+
+        // Copy Parameter Events from Command Loop to Audio Buffers
+        for (i, events) in self.local_param_events.iter_mut().enumerate() {
+            if i < self.audio_buffers.track_param_events.len() && !events.is_empty() {
+                self.audio_buffers.track_param_events[i].append(events);
+                // events is now empty after append (moves content) -> Actually Vec::append moves elements.
+                // But does it clear source? Yes, "The elements are moved... source becomes empty."
+            } else {
+                events.clear(); // Ensure clear if not appended (e.g. track removed)
+            }
+        }
+
+        // 1. Update Vol/Pan from Project (Always)
+        if self.active_notes.len() < track_count {
+            self.active_notes.resize(track_count, Vec::new());
+        }
+
+        for (t_idx, track) in self.project.tracks.iter().enumerate() {
+            if t_idx < track_count {
+                self.audio_buffers.track_vols[t_idx] = track.volume;
+                self.audio_buffers.track_pans[t_idx] = track.pan;
+            }
+        }
+
+        // 2a. Process Active Notes (Note Offs)
+        for (t_idx, notes) in self.active_notes.iter_mut().enumerate() {
+            if t_idx >= track_count {
+                continue;
+            }
+
+            // Optimize allocation: Use retain instead of drain/push
+            notes.retain_mut(|(note, remaining)| {
+                if *remaining >= frames as u64 {
+                    *remaining -= frames as u64;
+                    true // Keep note
+                } else {
+                    // Note Off (remaining = offset into this block)
+                    self.audio_buffers.track_events[t_idx].push(MidiNoteEvent {
+                        note: *note,
+                        velocity: 0,
+                        channel: 0,
+                        sample_offset: *remaining as u32,
+                        detune: 0.0,
+                    });
+                    false // Remove note
+                }
+            });
+        }
+
+        // 2b. If Playing: Generate Sequence Events (Note Ons) or Arrangement Audio
+        if playing {
+            let current_sample = self.sample_position.load(Ordering::Relaxed);
+            let frames_u64 = frames as u64;
+            let buffer_end_sample = current_sample + frames_u64;
+
+            // CALCULATE CROSSFADE
+            let target_crossfade = if self.project.arrangement_mode {
+                1.0
+            } else {
+                0.0
+            };
+            if (self.crossfade - target_crossfade).abs() > 0.001 {
+                let step = 0.1; // Fast fade (approx 10 blocks = 200ms at 2048)
+                if self.crossfade < target_crossfade {
+                    self.crossfade = (self.crossfade + step).min(target_crossfade);
+                } else {
+                    self.crossfade = (self.crossfade - step).max(target_crossfade);
+                }
+            } else {
+                self.crossfade = target_crossfade;
+            }
+
+            // --- ARRANGEMENT AUTOMATION (before anything reads vol/pan) ---
+            if self.project.arrangement_mode {
+                let sr = self.sample_rate as f64;
+                let start_beat = self.tempo.beat_at_sample(current_sample as f64, sr);
+                let end_beat = self.tempo.beat_at_sample((current_sample + frames as u64) as f64, sr);
+                let beats_per_frame = (end_beat - start_beat) / frames.max(1) as f64;
+                for (t_idx, track) in self.project.tracks.iter().enumerate().take(track_count) {
+                    self.device_param_events.clear();
+                    crate::automation::render_lanes(
+                        &track.arrangement.automation,
+                        start_beat,
+                        beats_per_frame,
+                        frames,
+                        &mut self.audio_buffers.track_ramps[t_idx],
+                        &mut self.audio_buffers.track_param_events[t_idx],
+                        &mut self.device_param_events,
+                    );
+                    // Effect lanes go to the insert at their position
+                    if let Some(chain) = self.graph.node_mut(self.track_node_indices[t_idx]).and_then(|node| node.as_chain()) {
+                        for &(position, event) in &self.device_param_events {
+                            chain.set_effect_param(position, event.param_id, event.value as f32, event.sample_offset);
+                        }
+                    }
+                }
+            }
+
+            // --- ARRANGEMENT MODE (Sample-Accurate Audio) ---
+            if self.crossfade > 0.001 {
+                for (t_idx, track) in self.project.tracks.iter().enumerate() {
+                    if t_idx >= track_count || track.mute {
+                        continue;
+                    }
+
+                    // Iterate Arrangement Clips
+                    for (c_idx, clip) in track.arrangement.clips.iter().enumerate() {
+                        // Check overlap with current buffer
+                        let clip_start = clip.start_sample(&self.tempo, self.sample_rate as f64);
+                        let (played_offset, played_length) = clip.played_span();
+                        let clip_end = clip_start + played_length;
+
+                        if clip_end > current_sample && clip_start < buffer_end_sample {
+                            // Calculate intersection
+                            let render_start = clip_start.max(current_sample);
+                            let render_end = clip_end.min(buffer_end_sample);
+
+                            if render_end > render_start {
+                                let buffer_offset = (render_start - current_sample) as usize;
+                                let length = (render_end - render_start) as usize;
+                                let source_offset = (render_start - clip_start + played_offset) as usize;
+
+                                // Get Audio Data (Lock-Free)
+                                let pool = self.audio_pool.load();
+                                // pool is Guard<Arc<AudioPool>>
+                                // Stretched/transposed variant once rendered
+                                let asset_id = clip.cached_id.unwrap_or(clip.source_id);
+                                // Assets not converted to the device rate yet are skipped
+                                if let Some(asset) = pool.get_asset(asset_id)
+                                    && asset.is_at_rate(self.sample_rate)
+                                {
+                                    // Span rounding may run a frame past the variant's end: clamp
+                                    let length = length.min(asset.frames().saturating_sub(source_offset));
+                                    if length > 0 {
+                                        // Into the track's buffer (+ self.crossfade): the track's chain,
+                                        // fader, solo, sends and PDC treat it like any other track audio
+                                        // Clip gain, fades and overlap crossfades (per frame)
+                                        let envelope = crate::fades::ClipEnvelope::for_clip(&track.arrangement.clips, c_idx, &self.tempo, self.sample_rate as f64);
+                                        let clip_pos = render_start - clip_start;
+                                        let track_buf = &mut self.audio_buffers.track_bufs[t_idx];
+
+                                        for i in 0..length {
+                                            let (left, right) = asset.frame(source_offset + i);
+                                            let gain = envelope.gain_at(clip_pos + i as u64) * self.crossfade;
+                                            let dst_idx = (buffer_offset + i) * 2;
+                                            track_buf[dst_idx] += left * gain;
+                                            track_buf[dst_idx + 1] += right * gain;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                self.schedule_arrangement_midi(current_sample, frames, track_count);
+
+                // Update UI step for visual feedback (even in Arrangement)
+                // Be precise
+                let current_beat = self.tempo.beat_at_sample(current_sample as f64, sample_rate_val as f64);
+                self.current_step
+                    .store((current_beat * 4.0) as u32, Ordering::Relaxed);
+            }
+
+            // --- SESSION MODE (Loop-based Sequencer) ---
+            if self.crossfade < 0.999 {
+                // Calculate time range for this buffer
+                // let current_sample = self.sample_position.load(Ordering::Relaxed); // Already loaded
+                let sr = sample_rate_val as f64;
+                let start_beat = self.tempo.beat_at_sample(current_sample as f64, sr);
+                let end_beat = self.tempo.beat_at_sample((current_sample + frames as u64) as f64, sr);
+                // Average tempo over the block, so offsets land exactly between the two ends
+                let samples_per_beat = (frames as f64 / (end_beat - start_beat).max(1e-9)) as f32;
+
+                // Update UI step (floored beat * 4 for 16th notes)
+                let current_16th = (start_beat * 4.0) as u32;
+                // Use dynamic pattern length from self.sequencer
+                let len_steps = self.sequencer.pattern_length.max(1);
+                self.current_step
+                    .store(current_16th % len_steps, Ordering::Relaxed);
+
+                for (t_idx, track) in self.project.tracks.iter().enumerate() {
+                    if t_idx < track_count && !track.mute
+                        && let Some(clip_idx) = track.active_clip_index
+                            && let Some(clip) = track.clips.get(clip_idx) {
+                                // Check notes in this clip
+                                if clip.use_sequencer {
+                                    // --- THESYS STEP SEQUENCER LOGIC ---
+                                    let seq = &clip.step_sequencer;
+
+                                    // Determine which global steps we are covering in this buffer
+                                    // 16th notes (0.25 beats)
+                                    let step_dur_beats = 0.25;
+                                    // Check steps that could have subdivisions in this buffer
+                                    // Look back 1 step to catch roll subdivisions from previous step
+                                    let start_step_idx =
+                                        ((start_beat / step_dur_beats).floor() as i64).max(0)
+                                            as u64;
+                                    let end_step_idx = (end_beat / step_dur_beats).ceil() as u64;
+
+                                    for global_step_counter in start_step_idx..end_step_idx {
+                                        // Apply groove/swing timing offset
+                                        let groove_offset = self
+                                            .project
+                                            .groove
+                                            .get_offset(global_step_counter as usize)
+                                            as f64
+                                            * step_dur_beats;
+                                        let step_beat_time = global_step_counter as f64
+                                            * step_dur_beats
+                                            + groove_offset;
+                                        let offset_beats = step_beat_time - start_beat;
+                                        let offset_samples_raw =
+                                            (offset_beats * samples_per_beat as f64) as i64;
+
+                                        // Determine if this step's base trigger is in this buffer
+                                        let step_starts_in_buffer = offset_samples_raw >= 0
+                                            && offset_samples_raw < frames as i64;
+                                        let offset_samples = offset_samples_raw.max(0) as u32;
+
+                                        // Skip if step start is past this buffer
+                                        if offset_samples >= frames as u32 {
+                                            continue;
+                                        }
+
+                                        schedule_sequencer_step(
+                                            seq,
+                                            &self.project.groove,
+                                            &StepTiming {
+                                                step: global_step_counter,
+                                                beat: step_beat_time,
+                                                offset_beats,
+                                                offset_samples,
+                                                starts_in_buffer: step_starts_in_buffer,
+                                                block_start_beat: start_beat,
+                                                step_dur_beats,
+                                                samples_per_beat,
+                                                frames,
+                                                note_limit: u64::MAX,
+                                            },
+                                            t_idx,
+                                            &mut self.audio_buffers,
+                                            &mut self.active_notes,
+                                        );
+                                    }
+                                } else {
+                                    // --- LEGACY PIANO ROLL LOGIC ---
+                                    // Ideally use a spatial map, but for <1000 notes linear scan is fine
+                                    let loop_len = clip.length;
+
+                                    for note in &clip.notes {
+                                        // Handle looping: Map note start to current window
+                                        // Simple approach: Check if note falls in [start_beat, end_beat) modulo loop
+
+                                        // Allow for notes to be triggered in this window
+                                        // Note start relative to loop start
+                                        let relative_start = note.start % loop_len;
+
+                                        // We need to check if 'relative_start' happens between 'start_beat' (mod loop) and 'end_beat' (mod loop)
+                                        // But wrapping makes this hard.
+                                        // Easier: Calculate absolute beats for potential occurrences in this window.
+
+                                        let loop_start_beat =
+                                            (start_beat / loop_len).floor() * loop_len;
+                                        let mut check_beat = loop_start_beat + relative_start;
+
+                                        // If we are just before the note in previous loop, check next
+                                        if check_beat < start_beat {
+                                            check_beat += loop_len;
+                                        }
+
+                                        if check_beat >= start_beat && check_beat < end_beat {
+                                            // STOCHASTIC CHECKS
+                                            // 1. Probability
+                                            if note.probability < 1.0
+                                                && fastrand::f64() > note.probability
+                                            {
+                                                continue;
+                                            }
+
+                                            // 2. Condition (Loop Iteration)
+                                            if let omni_shared::project::NoteCondition::Iteration { expected, cycle } = note.condition {
+                                                  let iteration = (check_beat / loop_len).floor() as u64;
+                                                  // 1-based index (1..cycle)
+                                                  let current_cycle_idx = (iteration % cycle as u64) as u8 + 1;
+                                                  if current_cycle_idx != expected {
+                                                      continue;
+                                                  }
+                                             }
+
+                                            // 3. Velocity Deviation
+                                            let mut velocity = note.velocity;
+                                            if note.velocity_deviation != 0 {
+                                                let dev = note.velocity_deviation as i32;
+                                                let rnd = fastrand::i32(-dev.abs()..=dev.abs());
+                                                velocity =
+                                                    (velocity as i32 + rnd).clamp(1, 127) as u8;
+                                            }
+
+                                            // Trigger!
+                                            let offset_beats = check_beat - start_beat;
+                                            let offset_samples =
+                                                (offset_beats * samples_per_beat as f64) as u32;
+
+                                            if offset_samples < frames as u32 {
+                                                self.audio_buffers.track_events[t_idx].push(
+                                                    MidiNoteEvent {
+                                                        note: note.key,
+                                                        velocity,
+                                                        channel: 0,
+                                                        sample_offset: offset_samples,
+                                                        detune: 0.0,
+                                                    },
+                                                );
+
+                                                if t_idx < self.active_notes.len() {
+                                                    // Calculate note duration in samples
+                                                    let dur_samples = (note.duration
+                                                        * samples_per_beat as f64)
+                                                        as u64;
+                                                    self.active_notes[t_idx]
+                                                        .push((note.key, dur_samples));
+                                                }
+                                            }
+                                        }
+                                    }
+                                } // End Else added by script
+                            }
+                }
+            } // End Session Else
+        } // End if playing
+
+        // 3. Update Global Transport for PluginNodes
+        {
+            let current_sample = self.sample_position.load(Ordering::Relaxed);
+            let song_pos_beats = self.tempo.beat_at_sample(current_sample as f64, sample_rate_val as f64);
+
+            // Bar and meter at the current position
+            let bar = self.meter.position(song_pos_beats);
+            let loop_region = self.project.loop_region;
+
+            crate::transport::update_transport(crate::transport::TransportState {
+                is_playing: playing,
+                tempo: self.tempo.bpm_at(song_pos_beats),
+                song_pos_beats,
+                bar_start_beats: bar.bar_start_beat,
+                bar_number: bar.bar,
+                time_sig_num: bar.signature.numerator as u16,
+                time_sig_denom: bar.signature.denominator as u16,
+                loop_active: self.loop_range().is_some(),
+                loop_start_beats: loop_region.start,
+                loop_end_beats: loop_region.end,
+            });
+        }
+
+        // 3b. Live Input Monitoring (pre-graph, so track effects process it)
+        let has_input = self.read_input(frames);
+        if has_input {
+            let channels = self.input_channels.load(Ordering::Relaxed) as usize;
+            for (t_idx, track) in self.project.tracks.iter().enumerate().take(track_count) {
+                if track.input == TrackInput::None
+                    || track.kind != TrackKind::Regular
+                    || track.mute
+                    || !track.monitor.is_monitoring(track.record_arm)
+                {
+                    continue;
+                }
+                let buf = &mut self.audio_buffers.track_bufs[t_idx];
+                for i in 0..frames {
+                    let (l, r) = input_frame(&self.input_buf, channels, track.input, i);
+                    buf[i * 2] += l;
+                    buf[i * 2 + 1] += r;
+                }
+            }
+        }
+
+        // 4. Process Graph, stage by stage: groups and returns run once everything
+        // routed into them has been summed (nodes within a stage run in parallel)
+        self.graph.update_schedule();
+        self.graph.calculate_latencies(&mut self.path_latencies);
+        self.align_sidechain_keys(track_count);
+
+        // 4a. PDC (Plugin Delay Compensation), per path: every route into a group,
+        // return or the master waits for the slowest route into the same place
+        if self.track_delays.len() < track_count {
+            let buffer_size_samples = self.sample_rate as usize * 2; // 2 seconds buffer
+            self.track_delays.resize_with(track_count, || {
+                crate::delay::DelayLine::new(buffer_size_samples, self.sample_rate as f32)
+            });
+            self.send_delays.resize_with(track_count, Vec::new);
+        }
+        let latency_of = |node_idx: NodeIndex| self.path_latencies.get(node_idx.index()).copied().unwrap_or_default();
+        let master_latency = (0..track_count)
+            .filter(|&t_idx| self.track_parents.get(t_idx).copied().flatten().is_none())
+            .map(|t_idx| latency_of(self.track_node_indices[t_idx]).output)
+            .max()
+            .unwrap_or(0);
+        crate::mixer::solo_silenced(&self.project.tracks, &self.track_parents, &mut self.solo_silenced);
+
+        for stage in 0..self.graph.stage_count() {
+            // PASS SLICES OF PRE_ALLOCATED BUFFERS
+            self.graph.process_stage(
+                stage,
+                &self.track_node_indices,
+                &mut self.audio_buffers.track_bufs[0..track_count],
+                &self.audio_buffers.track_events[0..track_count],
+                &self.audio_buffers.track_param_events[0..track_count],
+                &self.audio_buffers.track_expression_events[0..track_count],
+                sample_rate_val,
+            );
+
+            // Sidechain keys from this stage, taken before PDC delays the sources
+            // and lined up with the audio reaching the keyed device
+            for (c_idx, consumer) in self.project.tracks.iter().enumerate().take(track_count) {
+                let consumer_node = self.track_node_indices[c_idx];
+                for (position, device) in consumer.effects.iter().enumerate() {
+                    let Some(key) = device.sidechain else { continue };
+                    let Some(&source_node) = self.track_node_indices.get(key.source) else { continue };
+                    if key.source >= track_count
+                        || self.graph.stage_of(source_node) != Some(stage)
+                        || self.graph.stage_of(consumer_node) <= Some(stage)
+                    {
+                        continue; // Not from this stage, or refused as feedback
+                    }
+                    let source_latency = latency_of(source_node).output;
+                    let muted = self.project.tracks[key.source].mute;
+                    let signal = self.audio_buffers.sidechain_key(key.source, key.pre_fader, muted, frames);
+                    if let Some(chain) = self.graph.node_mut(consumer_node).and_then(|node| node.as_chain()) {
+                        let arrival = latency_of(consumer_node).input + chain.latency_before(position);
+                        chain.feed_sidechain(position, signal, arrival.saturating_sub(source_latency));
+                    }
+                }
+            }
+
+            for (t_idx, track) in self.project.tracks.iter().enumerate().take(track_count) {
+                let node_idx = self.track_node_indices[t_idx];
+                if self.graph.stage_of(node_idx) != Some(stage) {
+                    continue;
+                }
+                let output_latency = latency_of(node_idx).output;
+                // Solo silences after the chain, so keys and tails are unaffected
+                let silenced = self.solo_silenced.get(t_idx).copied().unwrap_or(false);
+
+                // Sends -> return tracks (a later stage)
+                if !track.mute && !silenced {
+                    if self.send_delays[t_idx].len() < track.sends.len() {
+                        let buffer_size_samples = self.sample_rate as usize * 2;
+                        self.send_delays[t_idx].resize_with(track.sends.len(), || {
+                            crate::delay::DelayLine::new(buffer_size_samples, self.sample_rate as f32)
+                        });
+                    }
+                    for (send, delay_line) in track.sends.iter().zip(self.send_delays[t_idx].iter_mut()) {
+                        let Some(&target_node) = self.track_node_indices.get(send.target) else { continue };
+                        if send.level <= 0.0 || !self.graph.contains_edge(node_idx, target_node) {
+                            continue;
+                        }
+                        let delay = latency_of(target_node).input.saturating_sub(output_latency);
+                        self.audio_buffers.mix_send(t_idx, send, delay_line, delay, frames);
+                    }
+                }
+
+                // Own output -> group or master
+                let parent = self.track_parents.get(t_idx).copied().flatten();
+                let destination_latency = match parent {
+                    Some(group) => latency_of(self.track_node_indices[group]).input,
+                    None => master_latency,
+                };
+                let track_buf = &mut self.audio_buffers.track_bufs[t_idx];
+                if silenced || (track.mute && track.kind == TrackKind::Group) {
+                    track_buf.fill(0.0); // Muting a group silences everything in it
+                }
+                // Ensure DelayLine is fed (0 delay still keeps the line fed)
+                self.track_delays[t_idx].process_in_place(track_buf, destination_latency.saturating_sub(output_latency) * 2); // Interleaved stereo
+                if let Some(group) = parent {
+                    self.audio_buffers.mix_into_group(t_idx, group, frames, Some(&self.peak_meters));
+                }
+            }
+        }
+
+        // 4b. Mix to Master (equal-power pan, trim, metering)
+        crate::mixer::AudioBuffers::mix_to_master(
+            &self.audio_buffers.track_bufs,
+            &mut self.audio_buffers.master_mix,
+            &self.audio_buffers.track_vols,
+            &self.audio_buffers.track_pans,
+            &self.audio_buffers.track_trims,
+            &self.audio_buffers.track_ramps,
+            &self.track_parents,
+            frames,
+            track_count,
+            Some(&self.peak_meters),
+        );
+
+        // 4c. Recording Capture
+        // Armed tracks with an input record the raw input (both modes);
+        // other tracks resample their output, in Session mode only.
+        let is_rec = self.is_recording.load(Ordering::Relaxed);
+        if is_rec && playing {
+            // Log once per second approx (processor-owned counter, no UB)
+            let current_pos = self.sample_position.load(Ordering::Relaxed);
+            if current_pos.saturating_sub(self.rec_log_throttle) > self.sample_rate as u64 {
+                eprintln!(
+                    "[Engine] Recording: capturing {} tracks, {} frames",
+                    track_count, frames
+                );
+                self.rec_log_throttle = current_pos;
+            }
+
+            // Frames to capture: the punch range, and nothing after a loop wrap
+            let (mut capture_from, mut capture_to) = (0, frames);
+            if let Some((punch_in, punch_out)) = self.timeline_range(self.project.punch) {
+                capture_from = (punch_in.saturating_sub(current_pos) as usize).min(frames);
+                capture_to = (punch_out.saturating_sub(current_pos) as usize).min(frames);
+            }
+            if self.rec_from > current_pos {
+                capture_from = capture_from.max(((self.rec_from - current_pos) as usize).min(frames));
+            }
+            if self.rec_pass_done {
+                capture_to = capture_from;
+            }
+            if capture_from < capture_to && !self.rec_captured {
+                // The clip starts at the first captured frame
+                self.rec_captured = true;
+                self.recording_start_sample
+                    .store(current_pos + capture_from as u64, Ordering::Relaxed);
+            }
+
+            let input_channels = self.input_channels.load(Ordering::Relaxed) as usize;
+            for t_idx in 0..track_count {
+                let track_input = self
+                    .project
+                    .tracks
+                    .get(t_idx)
+                    .filter(|t| t.record_arm && has_input)
+                    .map(|t| t.input)
+                    .unwrap_or_default();
+                // Returns and groups have no clip slots to record into
+                let is_bus = self.project.tracks.get(t_idx).is_some_and(|t| t.kind != TrackKind::Regular);
+                if track_input == TrackInput::None && (self.project.arrangement_mode || is_bus) {
+                    continue;
+                }
+                // Interleaved stereo into the recording buffer (RingBuffer Push)
+                if let Some(prod_opt) = &mut self.audio_buffers.recording_producers[t_idx] {
+                    let prod: &mut ringbuf::HeapProd<f32> = prod_opt;
+                    let mut dropped = 0;
+                    let mut signal = false;
+
+                    for i in capture_from..capture_to {
+                        let (l, r) = if track_input == TrackInput::None {
+                            (
+                                self.audio_buffers.track_bufs[t_idx][i * 2],
+                                self.audio_buffers.track_bufs[t_idx][i * 2 + 1],
+                            )
+                        } else {
+                            input_frame(&self.input_buf, input_channels, track_input, i)
+                        };
+                        if l.abs() > 0.001 || r.abs() > 0.001 {
+                            signal = true;
+                        }
+                        // Whole frames only, so channels never slip
+                        if prod.vacant_len() < 2 {
+                            dropped += 1;
+                            continue;
+                        }
+                        prod.push_slice(&[l, r]);
+                    }
+
+                    // Debug Log (Throttled)
+                    let pos = self.sample_position.load(Ordering::Relaxed);
+                    if pos.saturating_sub(self.rec_debug_throttle) > self.sample_rate as u64 * 2
+                        && (signal || dropped > 0)
+                    {
+                        eprintln!(
+                            "[Engine] Rec Track {}: Signal={}, Dropped={}, Frames={}",
+                            t_idx, signal, dropped, frames
+                        );
+                        self.rec_debug_throttle = pos;
+                    }
+                }
+            }
+        }
+        if playing {
+            self.sample_position
+                .fetch_add(frames as u64, Ordering::Relaxed);
+        }
+
+        let gain = f32::from_bits(self.master_gain.load(Ordering::Relaxed));
+
+        // Master bus processing: soft-clip → dither → hard-clip → metering
+        crate::mixer::AudioBuffers::master_finalize(
+            &mut self.audio_buffers.master_mix,
+            frames,
+            gain,
+            &mut self.audio_buffers.dither_state_l,
+            &mut self.audio_buffers.dither_state_r,
+            Some(&self.peak_meters),
+        );
+
+        // 5. Metronome / count-in
+        self.render_click(block_start_sample, frames, playing);
+    }
+}
+
+/// Position of the effect at `position` after the one at `from` moved to `to`.
+fn moved_position(position: usize, from: usize, to: usize) -> usize {
+    if position == from {
+        to
+    } else if from < to && (from + 1..=to).contains(&position) {
+        position - 1
+    } else if to < from && (to..from).contains(&position) {
+        position + 1
+    } else {
+        position
+    }
+}
+
+/// Where a step-sequencer step lands in the current block.
+struct StepTiming {
+    step: u64,             // Step counter driving the lanes
+    beat: f64,             // Step start (incl. groove), timeline beats
+    offset_beats: f64,     // Step start relative to the block
+    offset_samples: u32,
+    starts_in_buffer: bool,
+    block_start_beat: f64,
+    step_dur_beats: f64,
+    samples_per_beat: f32,
+    frames: usize,
+    note_limit: u64, // Block offset where notes are cut (clip end)
+}
+
+/// Generates the notes, bends and modulation of one step-sequencer step.
+fn schedule_sequencer_step(
+    seq: &StepSequencerData,
+    groove: &GrooveTemplate,
+    timing: &StepTiming,
+    t_idx: usize,
+    buffers: &mut AudioBuffers,
+    active_notes: &mut [Vec<(u8, u64)>],
+) {
+    let &StepTiming {
+        step: global_step_counter,
+        beat: step_beat_time,
+        offset_beats,
+        offset_samples,
+        starts_in_buffer: step_starts_in_buffer,
+        block_start_beat: start_beat,
+        step_dur_beats,
+        samples_per_beat,
+        frames,
+        note_limit,
+    } = timing;
+
+    // 1. Get Random/Performance Mask Early if active
+    let rnd_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.performance_random.direction,
+        seq.performance_random.loop_start,
+        seq.performance_random.loop_end,
+    );
+    let rnd_probability = seq
+        .performance_random
+        .steps
+        .get(rnd_idx)
+        .copied()
+        .unwrap_or(0);
+    let rnd_muted = seq.muted.get(rnd_idx).copied().unwrap_or(false);
+
+    let mut do_randomize = false;
+    let random_mask = seq.random_mask_global;
+
+    if !rnd_muted && rnd_probability > 0 && fastrand::u8(1..=100) <= rnd_probability {
+        do_randomize = true;
+    }
+
+    // 2. Get Pitch
+    let pitch_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.pitch.direction,
+        seq.pitch.loop_start,
+        seq.pitch.loop_end,
+    );
+    let mut raw_pitch = seq.pitch.steps.get(pitch_idx).copied().unwrap_or(60);
+    let pitch_muted = seq.muted.get(pitch_idx).copied().unwrap_or(false);
+
+    if do_randomize && (random_mask & 1) != 0 {
+        raw_pitch = fastrand::u8(0..=127);
+    }
+
+    // 3. Get Velocity
+    let vel_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.velocity.direction,
+        seq.velocity.loop_start,
+        seq.velocity.loop_end,
+    );
+    let mut velocity = seq.velocity.steps.get(vel_idx).copied().unwrap_or(100);
+    let vel_muted = seq.muted.get(vel_idx).copied().unwrap_or(false);
+
+    if do_randomize && (random_mask & 2) != 0 {
+        velocity = fastrand::u8(0..=127);
+    }
+
+    if velocity == 0 || vel_muted || pitch_muted {
+        return;
+    } // Muted step
+
+    // Apply groove velocity scaling
+    let groove_vel_scale = groove.get_velocity_scale(global_step_counter as usize);
+    velocity = ((velocity as f32 * groove_vel_scale)
+        .round()
+        .clamp(1.0, 127.0)) as u8;
+
+    // 4. Get Gate
+    let gate_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.gate.direction,
+        seq.gate.loop_start,
+        seq.gate.loop_end,
+    );
+    let mut gate_len = seq.gate.steps.get(gate_idx).copied().unwrap_or(0.5);
+    let gate_muted = seq.muted.get(gate_idx).copied().unwrap_or(false);
+
+    if do_randomize && (random_mask & 4) != 0 {
+        gate_len = fastrand::f32();
+    }
+
+    if gate_muted {
+        return;
+    }
+
+    // 5. Get Probability
+    // ... (Keep existing prob check) ...
+    let prob_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.probability.direction,
+        seq.probability.loop_start,
+        seq.probability.loop_end,
+    );
+    let probability = seq.probability.steps.get(prob_idx).copied().unwrap_or(100);
+    let prob_muted = seq.muted.get(prob_idx).copied().unwrap_or(false);
+
+    if prob_muted {
+        return;
+    }
+    if probability < 100 && fastrand::u8(1..=100) > probability {
+        return;
+    }
+
+    // --- PERFORMANCE: OCTAVE ---
+    let oct_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.performance_octave.direction,
+        seq.performance_octave.loop_start,
+        seq.performance_octave.loop_end,
+    );
+    let mut octave_shift = seq
+        .performance_octave
+        .steps
+        .get(oct_idx)
+        .copied()
+        .unwrap_or(0);
+    let oct_muted = seq.muted.get(oct_idx).copied().unwrap_or(false);
+
+    if !oct_muted {
+        if do_randomize && (random_mask & 8) != 0 {
+            octave_shift = fastrand::i8(-2..=2);
+        }
+
+        // Apple offset (saturating)
+        let shift_semis = (octave_shift as i32) * 12;
+        raw_pitch = (raw_pitch as i32 + shift_semis).clamp(0, 127) as u8;
+    }
+
+    // Quantize AFTER Octave shift or BEFORE?
+    // Usually before chord, but after raw pitch generation.
+    let quantized_pitch = omni_shared::scale::quantize(raw_pitch, seq.root_key, seq.scale);
+
+    // --- PERFORMANCE: CHORD ---
+    let chd_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.performance_chord.direction,
+        seq.performance_chord.loop_start,
+        seq.performance_chord.loop_end,
+    );
+    let mut chord_type_id = seq
+        .performance_chord
+        .steps
+        .get(chd_idx)
+        .copied()
+        .unwrap_or(0);
+    let chd_muted = seq.muted.get(chd_idx).copied().unwrap_or(false);
+
+    if do_randomize && (random_mask & 32) != 0 {
+        chord_type_id = fastrand::u8(0..=11); // Range of chords
+    }
+
+    // Resolve Chord Intervals (pre-allocated buffer, zero heap alloc)
+    buffers.pitch_buf.clear();
+    buffers.pitch_buf.push(quantized_pitch);
+
+    if !chd_muted
+        && chord_type_id > 0
+        && let Some(ctype) = omni_shared::scale::ChordType::from_index(chord_type_id as usize)
+    {
+        for &interval in ctype.get_intervals() {
+            if interval == 0 {
+                continue;
+            } // Skip root, added already
+            let p = (quantized_pitch as i32 + interval as i32).clamp(0, 127) as u8;
+            buffers.pitch_buf.push(p);
+        }
+    }
+
+    // --- PERFORMANCE: BEND ---
+    let bend_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.performance_bend.direction,
+        seq.performance_bend.loop_start,
+        seq.performance_bend.loop_end,
+    );
+    let bend_val = seq
+        .performance_bend
+        .steps
+        .get(bend_idx)
+        .copied()
+        .unwrap_or(0);
+    let bend_muted = seq.muted.get(bend_idx).copied().unwrap_or(false);
+
+    // --- PERFORMANCE: ROLL (PATTERNS) ---
+    let roll_idx = StepGenerator::get_step_index(
+        global_step_counter,
+        seq.performance_roll.direction,
+        seq.performance_roll.loop_start,
+        seq.performance_roll.loop_end,
+    );
+    let roll_type = seq
+        .performance_roll
+        .steps
+        .get(roll_idx)
+        .copied()
+        .unwrap_or(0);
+    let roll_muted = seq.muted.get(roll_idx).copied().unwrap_or(false);
+
+    // Determine if roll is active
+    let roll_active = !roll_muted && roll_type > 0;
+
+    // Skip entire step processing if step doesn't start in this buffer
+    // (except for Roll subdivisions which are handled below)
+    if !step_starts_in_buffer && !roll_active {
+        return;
+    }
+
+    if roll_active {
+        // ROLL ACTIVE: Apply pattern with subdivisions
+        let roll_pattern = omni_shared::performance::RollPattern::get(roll_type);
+        let num_subdivisions = 4;
+        let mut pitch_accumulator: i32 = 0; // Cumulative pitch offset
+
+        for sub_i in 0..num_subdivisions {
+            let sub_step = roll_pattern.steps[sub_i];
+
+            // Update pitch accumulator based on sub-step type
+            match sub_step {
+                omni_shared::performance::RollSubStep::PlayUp => {
+                    pitch_accumulator += 1;
+                }
+                omni_shared::performance::RollSubStep::PlayDown => {
+                    pitch_accumulator -= 1;
+                }
+                _ => {}
+            }
+
+            if sub_step == omni_shared::performance::RollSubStep::Rest {
+                continue;
+            }
+
+            // Calculate sub-offsets
+            let sub_offset_beats = (step_dur_beats / num_subdivisions as f64) * sub_i as f64;
+            let event_offset_beats = offset_beats + sub_offset_beats;
+
+            // Calculate sample offset - allow negative/future offsets
+            let event_offset_samples_raw = (event_offset_beats * samples_per_beat as f64) as i64;
+
+            // If this subdivision is in the future (past buffer end), skip for now
+            // It will be triggered when we reach that step in a future buffer
+            if event_offset_samples_raw >= frames as i64 {
+                continue;
+            }
+            if event_offset_samples_raw < 0 {
+                continue;
+            }
+            if event_offset_samples_raw as u64 >= note_limit {
+                continue;
+            }
+
+            let event_offset_samples = event_offset_samples_raw as u32;
+
+            // Effective Gate - use 80% of subdivision duration, clamped
+            let sub_dur_beats = step_dur_beats / num_subdivisions as f64;
+            let effective_gate = 0.8_f32; // 80% of subdivision
+
+            let dur_beats = effective_gate as f64 * sub_dur_beats;
+            let dur_samples = (dur_beats * samples_per_beat as f64) as u64;
+            // Cut at the clip end (arrangement)
+            let dur_samples =
+                dur_samples.min(note_limit.saturating_sub(event_offset_samples as u64));
+
+            for &base_p in buffers.pitch_buf.iter() {
+                // Apply cumulative pitch offset
+                let p = (base_p as i32 + pitch_accumulator).clamp(0, 127) as u8;
+
+                // Note On
+                buffers.track_events[t_idx].push(MidiNoteEvent {
+                    note: p,
+                    velocity,
+                    channel: 0,
+                    sample_offset: event_offset_samples,
+                    detune: 0.0,
+                });
+
+                // Handle Note Duration
+                let end_offset_abs = event_offset_samples as u64 + dur_samples;
+
+                if end_offset_abs < frames as u64 {
+                    buffers.track_events[t_idx].push(MidiNoteEvent {
+                        note: p,
+                        velocity: 0,
+                        channel: 0,
+                        sample_offset: end_offset_abs as u32,
+                        detune: 0.0,
+                    });
+                } else {
+                    if t_idx < active_notes.len() {
+                        let remaining = end_offset_abs - frames as u64;
+                        active_notes[t_idx].push((p, remaining));
+                    }
+                }
+
+                // --- BEND GENERATION ---
+                // Only generate if bend is enabled (value > 0) AND not muted
+                if !bend_muted && bend_val > 0 {
+                    let start_s = event_offset_samples;
+                    let end_s =
+                        (event_offset_samples as u64 + dur_samples).min(frames as u64) as u32;
+
+                    let mut s = start_s;
+                    // Generate expression events - less frequently to reduce overhead
+                    while s < end_s {
+                        let time_of_s_beats = start_beat + (s as f64 / samples_per_beat as f64);
+                        let time_in_step_beats = time_of_s_beats - step_beat_time;
+                        let phase = (time_in_step_beats / step_dur_beats) as f32;
+
+                        let detune_val =
+                            omni_shared::performance::BendShape::get_value(bend_val, phase);
+
+                        buffers.track_expression_events[t_idx].push(omni_shared::ExpressionEvent {
+                            key: p,
+                            channel: 0,
+                            expression_id: omni_shared::EXPRESSION_TUNING,
+                            value: detune_val as f64,
+                            sample_offset: s,
+                        });
+
+                        s += 128; // Larger granularity to reduce overhead
+                    }
+                }
+            }
+        }
+    } else {
+        // ROLL INACTIVE: Play single normal note
+        let event_offset_samples = offset_samples;
+        let dur_beats = gate_len as f64 * step_dur_beats;
+        let dur_samples = (dur_beats * samples_per_beat as f64) as u64;
+        let dur_samples = dur_samples.min(note_limit.saturating_sub(event_offset_samples as u64));
+
+        for &base_p in buffers.pitch_buf.iter() {
+            let p = base_p;
+
+            // Note On
+            buffers.track_events[t_idx].push(MidiNoteEvent {
+                note: p,
+                velocity,
+                channel: 0,
+                sample_offset: event_offset_samples,
+                detune: 0.0,
+            });
+
+            // Handle Note Duration
+            let end_offset_abs = event_offset_samples as u64 + dur_samples;
+
+            if end_offset_abs < frames as u64 {
+                buffers.track_events[t_idx].push(MidiNoteEvent {
+                    note: p,
+                    velocity: 0,
+                    channel: 0,
+                    sample_offset: end_offset_abs as u32,
+                    detune: 0.0,
+                });
+            } else {
+                if t_idx < active_notes.len() {
+                    let remaining = end_offset_abs - frames as u64;
+                    active_notes[t_idx].push((p, remaining));
+                }
+            }
+
+            // --- BEND GENERATION (for non-roll notes) ---
+            if !bend_muted && bend_val > 0 {
+                let start_s = event_offset_samples;
+                let end_s = (event_offset_samples as u64 + dur_samples).min(frames as u64) as u32;
+
+                let mut s = start_s;
+                while s < end_s {
+                    let time_of_s_beats = start_beat + (s as f64 / samples_per_beat as f64);
+                    let time_in_step_beats = time_of_s_beats - step_beat_time;
+                    let phase = (time_in_step_beats / step_dur_beats) as f32;
+
+                    let detune_val =
+                        omni_shared::performance::BendShape::get_value(bend_val, phase);
+
+                    buffers.track_expression_events[t_idx].push(omni_shared::ExpressionEvent {
+                        key: p,
+                        channel: 0,
+                        expression_id: omni_shared::EXPRESSION_TUNING,
+                        value: detune_val as f64,
+                        sample_offset: s,
+                    });
+
+                    s += 128;
+                }
+
+                // Reset pitch bend at note end to avoid stale tuning
+                if end_s > start_s {
+                    buffers.track_expression_events[t_idx].push(omni_shared::ExpressionEvent {
+                        key: p,
+                        channel: 0,
+                        expression_id: omni_shared::EXPRESSION_TUNING,
+                        value: 0.0, // Reset to neutral pitch
+                        sample_offset: end_s.saturating_sub(1),
+                    });
+                }
+            }
+        }
+    }
+
+    // --- MODULATION TARGETS ---
+    // Process each modulation target, get step value, generate ParameterEvent
+    for mod_target in &seq.modulation_targets {
+        // Get step index for this target's lane
+        let mod_idx = StepGenerator::get_step_index(
+            global_step_counter,
+            mod_target.lane.direction,
+            mod_target.lane.loop_start,
+            mod_target.lane.loop_end,
+        );
+
+        // Get the modulation value (0-127)
+        let mod_value = mod_target.lane.steps.get(mod_idx).copied().unwrap_or(0);
+
+        // Convert 0-127 to 0.0-1.0
+        let normalized_value = mod_value as f64 / 127.0;
+
+        // Push parameter event
+        buffers.track_param_events[t_idx].push(omni_shared::ParameterEvent {
+            param_id: mod_target.param_id,
+            value: normalized_value,
+            sample_offset: offset_samples,
+        });
+    }
+}
+
+/// One stereo frame of a track's selected input. Mono inputs are duplicated to both sides;
+/// channels the device does not have read as silence.
+#[inline]
+fn input_frame(buf: &[f32], channels: usize, input: TrackInput, frame: usize) -> (f32, f32) {
+    let base = frame * channels;
+    let sample = |ch: usize| if ch < channels { buf[base + ch] } else { 0.0 };
+    match input {
+        TrackInput::None => (0.0, 0.0),
+        TrackInput::Mono(ch) => {
+            let v = sample(ch as usize);
+            (v, v)
+        }
+        TrackInput::Stereo(ch) => (sample(ch as usize), sample(ch as usize + 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::AssetConverter;
+    use omni_shared::project::{ArrangementClip, Track};
+
+    #[test]
+    fn test_tempo_edits_replace_clip_variants() {
+        let mut pool = AudioPool::new();
+        let source = pool.add_asset_from_data(vec![0.5; 4800], 1, 48000.0);
+        let pool = Arc::new(ArcSwap::from_pointee(pool));
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let (recorder_tx, _recorder_rx) = crossbeam_channel::unbounded();
+        let (converter_tx, converter_rx) = crossbeam_channel::unbounded();
+        let (event_tx, event_rx) = crossbeam_channel::unbounded();
+        let (drop_tx, _drop_rx) = crossbeam_channel::unbounded();
+        let mut converter = AssetConverter::new(converter_rx, event_tx, pool.clone(), 48000);
+        let mut processor = EngineProcessor::new(command_rx, 48000, pool.clone(), recorder_tx, converter_tx, event_rx, drop_tx);
+
+        let mut track = Track::default();
+        let mut clip = ArrangementClip::new("Loop".to_string(), source, 0.0, 4800);
+        clip.stretch = true;
+        clip.original_bpm = 100.0;
+        track.arrangement.clips.push(clip);
+        let project = Project { tracks: vec![track], arrangement_mode: true, ..Default::default() };
+        command_tx.send(EngineCommand::LoadProjectState(project, vec![])).ok();
+
+        let mut block = vec![0.0f32; 256 * 2];
+        let mut sizes = Vec::new();
+        for bpm in [110.0, 120.0, 130.0, 140.0, 150.0] {
+            command_tx.send(EngineCommand::SetBpm(bpm)).ok();
+            processor.process(&mut block, 2); // Queues the render
+            converter.process_pending(); // Renders the new variant
+            processor.process(&mut block, 2); // Switches the clip over, releasing the old one
+            converter.process_pending(); // Evicts it
+            sizes.push(pool.load().len());
+        }
+        assert_eq!(sizes, [2; 5], "source plus the current variant");
+    }
+}
@@ -7,17 +7,22 @@ use ringbuf::HeapCons;
 use arc_swap::ArcSwap;
 use crate::assets::AudioPool;
 
+/// Recorded tracks arrive as interleaved stereo.
+pub const RECORD_CHANNELS: u16 = 2;
+
 // Command messages for the Recorder thread
 pub enum RecorderCommand {
     Start,
     Stop { 
         // Returns (track_idx, ArrangementClip) pairs
         response_tx: Sender<Vec<(usize, omni_shared::project::ArrangementClip)>>,
-        rec_start_sample: u64,
+        rec_start_beat: f64,
     },
     AddTrack { track_index: usize, consumer: HeapCons<f32> },
     RemoveTrack { track_index: usize },
     Clear,
+    /// Device rate changed: new recordings are tagged with this rate
+    SetSampleRate(f32),
 }
 
 pub struct AudioRecorder {
@@ -62,7 +67,7 @@ impl AudioRecorder {
     fn handle_cmd(&mut self, cmd: RecorderCommand) {
         match cmd {
              RecorderCommand::Start => { self.is_recording = true; eprintln!("[Recorder] Started"); },
-             RecorderCommand::Stop { response_tx, rec_start_sample } => {
+             RecorderCommand::Stop { response_tx, rec_start_beat } => {
                 self.is_recording = false;
                 self.drain_inputs();
                 let current_pool = self.audio_pool.load();
@@ -72,7 +77,7 @@ impl AudioRecorder {
                 for (track_idx, buf) in self.recording_buffers.iter_mut().enumerate() {
                     if !buf.is_empty() {
                         let asset_data = std::mem::take(buf);
-                        let asset_len = asset_data.len();
+                        let asset_len = asset_data.len() / RECORD_CHANNELS as usize; // frames
                         
                         // DEBUG: Analyze Content
                         let mut max_val = 0.0_f32;
@@ -84,21 +89,14 @@ impl AudioRecorder {
                         eprintln!("[Recorder] Track {} Stats: Len={}, MaxVal={:.4}, NonZeroSamples={}", track_idx, asset_len, max_val, non_zero);
                         
                         // Add to pool
-                        let asset_id = new_pool_map.add_asset_from_data(asset_data, self.sample_rate);
+                        let asset_id = new_pool_map.add_asset_from_data(asset_data, RECORD_CHANNELS, self.sample_rate);
                         pool_modified = true;
-                         let clip = omni_shared::project::ArrangementClip {
-                            source_id: asset_id,
-                            start_time: omni_shared::project::Timestamp { samples: rec_start_sample, fractional: 0.0 },
-                            start_offset: omni_shared::project::Timestamp::default(),
-                            length: omni_shared::project::Timestamp { samples: asset_len as u64, fractional: 0.0 },
-                            name: format!("Recorded_{}", asset_id),
-                            selected: false,
-                            warp_markers: Vec::new(),
-                            stretch: false,
-                            stretch_ratio: 1.0,
-                            original_bpm: 120.0,
-                            cached_id: None,
-                        };
+                         let clip = omni_shared::project::ArrangementClip::new(
+                            format!("Recorded_{}", asset_id),
+                            asset_id,
+                            rec_start_beat,
+                            asset_len as u64,
+                        );
                         created_clips.push((track_idx, clip));
                     }
                 }
@@ -118,6 +116,9 @@ impl AudioRecorder {
              RecorderCommand::Clear => {
                  for buf in &mut self.recording_buffers { buf.clear(); }
              },
+             RecorderCommand::SetSampleRate(sample_rate) => {
+                 self.sample_rate = sample_rate;
+             },
         }
     }
 
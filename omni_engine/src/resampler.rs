@@ -7,6 +7,11 @@ impl OmniResampler {
     /// Ratio > 1.0 means speed up (shorter duration).
     /// Ratio < 1.0 means slow down (longer duration).
     pub fn resample(input: &[f32], ratio: f64) -> Result<Vec<f32>, anyhow::Error> {
+        Self::resample_interleaved(input, 1, ratio)
+    }
+
+    /// Same as `resample` for interleaved data, `channels` wide. Output stays interleaved.
+    pub fn resample_interleaved(input: &[f32], channels: usize, ratio: f64) -> Result<Vec<f32>, anyhow::Error> {
         if input.is_empty() {
             return Ok(Vec::new());
         }
@@ -14,6 +19,7 @@ impl OmniResampler {
         if ratio <= 0.0 {
             return Err(anyhow::anyhow!("Ratio must be positive"));
         }
+        let channels = channels.max(1);
 
         // Calculate target sample rate relative to source
         let chunk_size = 1024;
@@ -28,7 +34,6 @@ impl OmniResampler {
             window: WindowFunction::BlackmanHarris2,
         };
         
-        let channels = 1;
         let mut resampler = SincFixedIn::<f32>::new(
             target_ratio,
             2.0, // Max ratio flexibility
@@ -37,31 +42,40 @@ impl OmniResampler {
             channels
         )?;
 
-        let mut output = Vec::with_capacity((input.len() as f64 * target_ratio) as usize + 1024);
+        let input_frames = input.len() / channels;
+        // The sinc filter delays its output: keep feeding silence until the tail is out, then trim
+        let delay = resampler.output_delay();
+        let expected_frames = (input_frames as f64 * target_ratio).ceil() as usize;
+        let mut output = Vec::with_capacity((expected_frames + delay + chunk_size * 2) * channels);
+        let mut waves = vec![vec![0.0_f32; chunk_size]; channels];
         let mut input_pos = 0;
-        let input_len = input.len();
         
-        while input_pos < input_len {
-            let end = (input_pos + chunk_size).min(input_len);
-            let chunk_len = end - input_pos;
+        while output.len() < (delay + expected_frames) * channels {
+            let end = (input_pos + chunk_size).min(input_frames);
             
-            let mut chunk = input[input_pos..end].to_vec();
-            if chunk_len < chunk_size {
-                 chunk.resize(chunk_size, 0.0);
+            // De-interleave (zero-padded last chunk)
+            for (ch, wave) in waves.iter_mut().enumerate() {
+                for (i, sample) in wave.iter_mut().enumerate() {
+                    let frame = input_pos + i;
+                    *sample = if frame < end { input[frame * channels + ch] } else { 0.0 };
+                }
             }
             
-            let waves = vec![chunk];
             let out_waves = resampler.process(&waves, None)?;
             
-            if let Some(chan_out) = out_waves.get(0) {
-                // If this is the last chunk, we should prevent garbage at the end?
-                // For now, appending is fine.
-                output.extend_from_slice(chan_out);
+            // Re-interleave
+            let out_frames = out_waves.first().map(|w| w.len()).unwrap_or(0);
+            for i in 0..out_frames {
+                for wave in &out_waves {
+                    output.push(wave[i]);
+                }
             }
             
             input_pos += chunk_size;
         }
         
+        output.drain(..delay * channels);
+        output.truncate(expected_frames * channels);
         Ok(output)
     }
 }
@@ -0,0 +1,291 @@
+//! Sampler / drum rack instrument: MIDI keys trigger pads playing AudioPool
+//! assets. Each note picks the pad layer for its velocity and runs its own
+//! voice with an ADSR; pads in a choke group cut each other off. Pad names
+//! are reported as note names, so the piano roll and step sequencer show
+//! one row per pad. Saved as the track's plugin state (the pad list).
+
+use crate::assets::{AudioAsset, AudioPool};
+use crate::nodes::AudioNode;
+use arc_swap::ArcSwap;
+use omni_shared::sampler::SamplerPad;
+use omni_shared::{ExpressionEvent, MidiNoteEvent, NoteNameInfo, ParameterEvent};
+use std::sync::Arc;
+
+pub const SAMPLER_PATH: &str = "omni:sampler";
+
+const MAX_VOICES: usize = 64;
+/// Fade applied to choked voices, in seconds
+const CHOKE_SECONDS: f32 = 0.005;
+
+/// Pad list as stored in the plugin state.
+pub fn encode_pads(pads: &[SamplerPad]) -> Vec<u8> {
+    serde_json::to_vec(pads).unwrap_or_default()
+}
+
+pub fn decode_pads(data: &[u8]) -> Result<Vec<SamplerPad>, anyhow::Error> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release(f32), // Level drop per sample
+}
+
+struct Voice {
+    key: u8,
+    pad: usize,
+    asset_id: u32,
+    pos: f64, // Frame in the asset's playback data
+    step: f64,
+    end: f64,
+    loop_region: Option<(f64, f64)>,
+    gain: f32,
+    stage: Stage,
+    level: f32,
+    done: bool,
+}
+
+impl Voice {
+    fn release(&mut self, seconds: f32, sample_rate: f32) {
+        if !matches!(self.stage, Stage::Release(_)) {
+            self.stage = Stage::Release(self.level / (seconds * sample_rate).max(1.0));
+        }
+    }
+}
+
+pub struct Sampler {
+    pool: Arc<ArcSwap<AudioPool>>,
+    pads: Vec<SamplerPad>,
+    voices: Vec<Voice>,
+}
+
+impl Sampler {
+    pub fn new(pool: Arc<ArcSwap<AudioPool>>) -> Self {
+        Self { pool, pads: Vec::new(), voices: Vec::with_capacity(MAX_VOICES) }
+    }
+
+    pub fn with_pads(mut self, pads: Vec<SamplerPad>) -> Self {
+        self.pads = pads;
+        self
+    }
+
+    fn note_on(&mut self, pool: &AudioPool, event: &MidiNoteEvent, sample_rate: f32) {
+        for (pad_idx, pad) in self.pads.iter().enumerate().filter(|(_, p)| p.key == event.note) {
+            if let Some(group) = pad.choke_group {
+                for voice in &mut self.voices {
+                    if self.pads.get(voice.pad).is_some_and(|p| p.choke_group == Some(group)) {
+                        voice.release(CHOKE_SECONDS, sample_rate);
+                    }
+                }
+            }
+            let Some(layer) = pad.layer_for(event.velocity) else { continue };
+            // Assets play once they're at the engine rate
+            let Some(asset) = pool.get_asset(layer.asset_id).filter(|a| a.is_at_rate(sample_rate as u32)) else {
+                continue;
+            };
+            // Regions are in frames of the original file
+            let scale = asset.sample_rate as f64 / asset.original_sample_rate.max(1) as f64;
+            let frames = asset.frames() as f64;
+            let end = if layer.end > 0 { (layer.end as f64 * scale).min(frames) } else { frames };
+            let loop_region = layer
+                .loop_region
+                .map(|(start, end)| (start as f64 * scale, (end as f64 * scale).min(frames)))
+                .filter(|(start, end)| end > start);
+
+            if self.voices.len() == MAX_VOICES {
+                self.voices.remove(0); // Steal the oldest
+            }
+            self.voices.push(Voice {
+                key: event.note,
+                pad: pad_idx,
+                asset_id: layer.asset_id,
+                pos: layer.start as f64 * scale,
+                step: 2.0f64.powf((pad.tune + event.detune) as f64 / 12.0),
+                end,
+                loop_region,
+                gain: pad.gain * event.velocity as f32 / 127.0,
+                stage: Stage::Attack,
+                level: 0.0,
+                done: false,
+            });
+        }
+    }
+
+    fn note_off(&mut self, key: u8, sample_rate: f32) {
+        for voice in self.voices.iter_mut().filter(|v| v.key == key) {
+            let Some(pad) = self.pads.get(voice.pad) else { continue };
+            if !pad.one_shot {
+                voice.release(pad.envelope.release, sample_rate);
+            }
+        }
+    }
+
+    /// Adds the voices for frames `start..end` to interleaved `output`.
+    fn render(&mut self, pool: &AudioPool, output: &mut [f32], start: usize, end: usize, sample_rate: f32) {
+        for voice in &mut self.voices {
+            let (Some(pad), Some(asset)) = (self.pads.get(voice.pad), pool.get_asset(voice.asset_id)) else {
+                voice.done = true;
+                continue;
+            };
+            let envelope = pad.envelope;
+            for frame in output[start * 2..end * 2].chunks_exact_mut(2) {
+                if let (Some((loop_start, loop_end)), false) = (voice.loop_region, matches!(voice.stage, Stage::Release(_))) {
+                    while voice.pos >= loop_end {
+                        voice.pos -= loop_end - loop_start;
+                    }
+                }
+                if voice.pos >= voice.end {
+                    voice.done = true;
+                    break;
+                }
+                match voice.stage {
+                    Stage::Attack => {
+                        voice.level += 1.0 / (envelope.attack * sample_rate).max(1.0);
+                        if voice.level >= 1.0 {
+                            voice.level = 1.0;
+                            voice.stage = Stage::Decay;
+                        }
+                    }
+                    Stage::Decay => {
+                        voice.level -= (1.0 - envelope.sustain) / (envelope.decay * sample_rate).max(1.0);
+                        if voice.level <= envelope.sustain {
+                            voice.level = envelope.sustain;
+                            voice.stage = Stage::Sustain;
+                        }
+                    }
+                    Stage::Sustain => {}
+                    Stage::Release(drop) => {
+                        voice.level -= drop;
+                        if voice.level <= 0.0 {
+                            voice.done = true;
+                            break;
+                        }
+                    }
+                }
+                let (l, r) = interpolate(asset, voice.pos);
+                let gain = voice.gain * voice.level;
+                frame[0] += l * gain;
+                frame[1] += r * gain;
+                voice.pos += voice.step;
+            }
+        }
+        self.voices.retain(|v| !v.done);
+    }
+}
+
+/// Linear interpolation between the frames around `pos`.
+fn interpolate(asset: &AudioAsset, pos: f64) -> (f32, f32) {
+    let index = pos as usize;
+    let frac = (pos - index as f64) as f32;
+    let (l0, r0) = asset.frame(index);
+    let (l1, r1) = asset.frame((index + 1).min(asset.frames() - 1));
+    (l0 + (l1 - l0) * frac, r0 + (r1 - r0) * frac)
+}
+
+impl AudioNode for Sampler {
+    fn process(&mut self, output: &mut [f32], sample_rate: f32, midi_events: &[MidiNoteEvent], _param_events: &[ParameterEvent], _expression_events: &[ExpressionEvent]) {
+        output.fill(0.0);
+        let pool = self.pool.load();
+        let frames = output.len() / 2;
+        let mut start = 0;
+        for event in midi_events {
+            let at = (event.sample_offset as usize).clamp(start, frames);
+            self.render(&pool, output, start, at, sample_rate);
+            start = at;
+            if event.velocity > 0 {
+                self.note_on(&pool, event, sample_rate);
+            } else {
+                self.note_off(event.note, sample_rate);
+            }
+        }
+        self.render(&pool, output, start, frames, sample_rate);
+    }
+
+    fn get_note_names(&mut self) -> (String, Vec<NoteNameInfo>) {
+        let mut names: Vec<NoteNameInfo> = self
+            .pads
+            .iter()
+            .map(|pad| NoteNameInfo { key: pad.key as i16, channel: -1, name: pad.name.clone() })
+            .collect();
+        names.sort_by_key(|n| n.key);
+        names.dedup_by_key(|n| n.key);
+        (SAMPLER_PATH.to_string(), names)
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: f32) {
+        self.voices.clear();
+    }
+
+    fn get_state(&mut self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(encode_pads(&self.pads))
+    }
+
+    fn set_state(&mut self, data: Vec<u8>) -> Result<(), anyhow::Error> {
+        self.pads = decode_pads(&data)?;
+        self.voices.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omni_shared::sampler::SampleLayer;
+
+    fn note(note: u8, velocity: u8, sample_offset: u32) -> MidiNoteEvent {
+        MidiNoteEvent { note, velocity, channel: 0, sample_offset, detune: 0.0 }
+    }
+
+    /// Left channel of `frames` frames after `events`.
+    fn play(sampler: &mut Sampler, events: &[MidiNoteEvent], frames: usize) -> Vec<f32> {
+        let mut out = vec![0.0f32; frames * 2];
+        sampler.process(&mut out, 48000.0, events, &[], &[]);
+        out.chunks(2).map(|f| f[0]).collect()
+    }
+
+    #[test]
+    fn test_layers_choke_and_note_names() {
+        let mut pool = AudioPool::new();
+        let soft = pool.add_asset_from_data(vec![0.25; 48000], 1, 48000.0);
+        let hard = pool.add_asset_from_data(vec![0.5; 48000], 1, 48000.0);
+        let hat = pool.add_asset_from_data(vec![1.0; 48000], 1, 48000.0);
+
+        let mut snare = SamplerPad::new("Snare".to_string(), 38, soft);
+        snare.layers[0].velocity_max = 63;
+        snare.layers.push(SampleLayer { velocity_min: 64, ..SampleLayer::new(hard) });
+        let mut open_hat = SamplerPad::new("Open Hat".to_string(), 46, hat);
+        let mut closed_hat = SamplerPad::new("Closed Hat".to_string(), 42, hat);
+        open_hat.choke_group = Some(1);
+        closed_hat.choke_group = Some(1);
+        closed_hat.layers[0].end = 100;
+
+        let pool = Arc::new(ArcSwap::from_pointee(pool));
+        let mut sampler = Sampler::new(pool).with_pads(vec![snare, open_hat, closed_hat]);
+
+        // Velocity picks the layer (past the 1ms attack)
+        assert!((play(&mut sampler, &[note(38, 40, 0)], 512)[100] - 0.25 * 40.0 / 127.0).abs() < 1e-6);
+        sampler.set_sample_rate(48000.0);
+        assert_eq!(play(&mut sampler, &[note(38, 127, 0)], 512)[100], 0.5);
+        sampler.set_sample_rate(48000.0);
+
+        // One-shot pads ignore note off; the closed hat chokes the open one
+        let out = play(&mut sampler, &[note(46, 127, 0), note(46, 0, 10), note(42, 127, 200)], 1024);
+        assert_eq!(out[150], 1.0);
+        let fade = (CHOKE_SECONDS * 48000.0) as usize;
+        assert!(out[200 + fade.max(100) + 10..].iter().all(|&s| s == 0.0), "both hats stopped");
+
+        let (id, names) = sampler.get_note_names();
+        assert_eq!(id, SAMPLER_PATH);
+        let keys: Vec<(i16, &str)> = names.iter().map(|n| (n.key, n.name.as_str())).collect();
+        assert_eq!(keys, [(38, "Snare"), (42, "Closed Hat"), (46, "Open Hat")]);
+
+        // The pad list round-trips through the plugin state
+        let state = sampler.get_state().unwrap();
+        let mut restored = Sampler::new(Arc::new(ArcSwap::from_pointee(AudioPool::new())));
+        restored.set_state(state).unwrap();
+        assert_eq!(restored.pads, sampler.pads);
+    }
+}
@@ -0,0 +1,363 @@
+//! Time stretching and pitch shifting for arrangement audio clips.
+//! WSOLA (waveform-similarity overlap-add) changes duration without changing pitch;
+//! pitch shifts stretch by the pitch factor and let the rate conversion resample it back.
+//! Runs on the converter thread, never on the audio thread.
+
+use omni_shared::project::{ArrangementClip, WarpMarker};
+use omni_shared::tempo::{TempoEvent, TempoMap};
+use std::hash::{Hash, Hasher};
+
+/// Analysis/synthesis frame length in seconds (~40ms suits most material).
+const FRAME_SECONDS: f64 = 0.04;
+
+/// Piecewise-linear map between clip beats and source frames, built from warp markers.
+/// Outside the markers (or without any) the source plays at its original tempo.
+pub struct WarpMap {
+    points: Vec<(f64, f64)>, // (beat, source frame), both strictly increasing
+    frames_per_beat: f64,    // Source frames per beat at the original tempo
+}
+
+impl WarpMap {
+    pub fn new(markers: &[WarpMarker], frames_per_beat: f64) -> Self {
+        let mut sorted: Vec<(f64, f64)> = markers
+            .iter()
+            .map(|m| (m.timeline_beat, m.source_sample as f64))
+            .collect();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Drop markers that would make the map run backwards
+        let mut points: Vec<(f64, f64)> = Vec::with_capacity(sorted.len());
+        for p in sorted {
+            if points.last().is_none_or(|last| p.0 > last.0 && p.1 > last.1) {
+                points.push(p);
+            }
+        }
+        Self { points, frames_per_beat }
+    }
+
+    /// Source frame heard at `beat` (beats from the clip start).
+    pub fn source_at(&self, beat: f64) -> f64 {
+        Self::interpolate(&self.points, beat, self.frames_per_beat, |&p| p)
+    }
+
+    /// Beat at which source frame `source` is heard (inverse of `source_at`).
+    pub fn beat_at(&self, source: f64) -> f64 {
+        Self::interpolate(&self.points, source, 1.0 / self.frames_per_beat, |&(b, s)| (s, b))
+    }
+
+    fn interpolate(points: &[(f64, f64)], x: f64, outer_slope: f64, axis: impl Fn(&(f64, f64)) -> (f64, f64)) -> f64 {
+        let (Some(first), Some(last)) = (points.first().map(&axis), points.last().map(&axis)) else {
+            return x * outer_slope;
+        };
+        if x <= first.0 {
+            return first.1 - (first.0 - x) * outer_slope;
+        }
+        if x >= last.0 {
+            return last.1 + (x - last.0) * outer_slope;
+        }
+        let i = points.partition_point(|p| axis(p).0 <= x);
+        let (x0, y0) = axis(&points[i - 1]);
+        let (x1, y1) = axis(&points[i]);
+        y0 + (x - x0) / (x1 - x0) * (y1 - y0)
+    }
+}
+
+/// What a processed clip variant is rendered from. Two clips with equal renders share one asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipRender {
+    pub project_bpm: f32,
+    pub original_bpm: f32, // Equal to project_bpm when the clip is not stretched
+    pub warp_markers: Vec<WarpMarker>,
+    pub pitch_semitones: i32,
+    pub pitch_cents: f32,
+    /// Tempo changes a stretched clip follows (empty when not stretched)
+    pub tempo_changes: Vec<TempoEvent>,
+    /// Timeline beat of the clip start; only matters with tempo changes
+    pub start_beat: f64,
+}
+
+impl ClipRender {
+    /// True when the clip plays something other than its source unchanged.
+    pub fn is_needed(clip: &ArrangementClip, project_bpm: f32, tempo_changes: &[TempoEvent]) -> bool {
+        let stretched = clip.stretch
+            && clip.original_bpm > 0.0
+            && project_bpm > 0.0
+            && (!clip.warp_markers.is_empty()
+                || !tempo_changes.is_empty()
+                || (clip.original_bpm - project_bpm).abs() >= 1e-3);
+        stretched || clip.pitch_semitones != 0 || clip.pitch_cents.abs() >= 0.01
+    }
+
+    /// `None` when the clip plays its source unchanged.
+    pub fn from_clip(clip: &ArrangementClip, project_bpm: f32, tempo_changes: &[TempoEvent]) -> Option<Self> {
+        if !Self::is_needed(clip, project_bpm, tempo_changes) {
+            return None;
+        }
+        let stretch = clip.stretch && clip.original_bpm > 0.0 && project_bpm > 0.0;
+        let follows_tempo = stretch && !tempo_changes.is_empty();
+        let start_beat = if follows_tempo { clip.start } else { 0.0 };
+        Some(Self {
+            project_bpm,
+            original_bpm: if stretch { clip.original_bpm } else { project_bpm },
+            warp_markers: if stretch { clip.warp_markers.clone() } else { Vec::new() },
+            pitch_semitones: clip.pitch_semitones,
+            pitch_cents: clip.pitch_cents,
+            tempo_changes: if follows_tempo { tempo_changes.to_vec() } else { Vec::new() },
+            start_beat,
+        })
+    }
+
+    fn tempo_map(&self) -> TempoMap {
+        TempoMap::new(self.project_bpm, &self.tempo_changes)
+    }
+
+    /// Playback speed at the clip start, outside warp markers (2.0 = twice as fast).
+    pub fn speed(&self) -> f64 {
+        self.tempo_map().bpm_at(self.start_beat) / self.original_bpm as f64
+    }
+
+    /// Frequency factor of the transposition (2.0 = one octave up).
+    pub fn pitch_factor(&self) -> f64 {
+        2f64.powf((self.pitch_semitones as f64 + self.pitch_cents as f64 / 100.0) / 12.0)
+    }
+
+    /// Key for the pool's variant cache.
+    pub fn cache_key(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.project_bpm.to_bits().hash(&mut hasher);
+        self.original_bpm.to_bits().hash(&mut hasher);
+        for marker in &self.warp_markers {
+            marker.source_sample.hash(&mut hasher);
+            marker.timeline_beat.to_bits().hash(&mut hasher);
+        }
+        self.pitch_semitones.hash(&mut hasher);
+        self.pitch_cents.to_bits().hash(&mut hasher);
+        // Constant-tempo renders don't depend on where the clip sits
+        if !self.tempo_changes.is_empty() {
+            self.start_beat.to_bits().hash(&mut hasher);
+            for event in &self.tempo_changes {
+                event.beat.to_bits().hash(&mut hasher);
+                event.bpm.to_bits().hash(&mut hasher);
+                (event.ramp as u8).hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Short description for asset names, e.g. "1.25x, +3st".
+    pub fn label(&self) -> String {
+        let mut label = format!("{:.2}x", self.speed());
+        if self.pitch_semitones != 0 || self.pitch_cents.abs() >= 0.01 {
+            label += &format!(", {:+}st {:+.0}ct", self.pitch_semitones, self.pitch_cents);
+        }
+        label
+    }
+
+    /// Where source frames `offset..offset + length` land in the render, as an offset and
+    /// a length in frames at `rate`. Clip frames are at `rate`; the source is at `source_rate`.
+    pub fn span(&self, offset: u64, length: u64, rate: u32, source_rate: u32) -> (u64, u64) {
+        let scale = source_rate as f64 / rate as f64;
+        let map = WarpMap::new(&self.warp_markers, source_rate as f64 * 60.0 / self.original_bpm as f64);
+        let tempo = self.tempo_map();
+        let start_seconds = tempo.seconds_at(self.start_beat);
+        let frame_at = |frame: u64| {
+            let beat = self.start_beat + map.beat_at(frame as f64 * scale);
+            ((tempo.seconds_at(beat) - start_seconds) * rate as f64).round().max(0.0) as u64
+        };
+        let start = frame_at(offset);
+        (start, frame_at(offset + length).saturating_sub(start))
+    }
+
+    /// Renders the clip from interleaved `input` at `sample_rate`.
+    /// Returns the data and the rate to declare it at: the pitch factor is folded into the rate,
+    /// so converting to the engine rate resamples pitch and rate in a single pass.
+    pub fn render(&self, input: &[f32], channels: usize, sample_rate: u32) -> (Vec<f32>, u32) {
+        let channels = channels.max(1);
+        let in_frames = input.len() / channels;
+        let pitch = self.pitch_factor();
+        let source_fpb = sample_rate as f64 * 60.0 / self.original_bpm as f64;
+        // Stretched `pitch` times longer, then played back `pitch` times faster
+        let out_rate = sample_rate as f64 * pitch;
+
+        // Output frames -> timeline beats (through the tempo map) -> source frames
+        let tempo = self.tempo_map();
+        let start_seconds = tempo.seconds_at(self.start_beat);
+        let map = WarpMap::new(&self.warp_markers, source_fpb);
+        let end_beat = self.start_beat + map.beat_at(in_frames as f64);
+        let out_frames = ((tempo.seconds_at(end_beat) - start_seconds) * out_rate).ceil().max(0.0) as usize;
+        let data = wsola(input, channels, sample_rate, out_frames, |frame| {
+            map.source_at(tempo.beat_at(start_seconds + frame / out_rate) - self.start_beat)
+        });
+        (data, (sample_rate as f64 * pitch).round() as u32)
+    }
+}
+
+/// WSOLA time-scale modification of interleaved audio.
+/// Produces `out_frames` frames; output frame `t` is taken from around source frame `source_at(t)`.
+/// Each grain is nudged (within a small tolerance) to the position that best continues the
+/// previous grain's waveform, which avoids the phasiness of plain overlap-add.
+pub fn wsola(input: &[f32], channels: usize, sample_rate: u32, out_frames: usize, source_at: impl Fn(f64) -> f64) -> Vec<f32> {
+    let channels = channels.max(1);
+    let in_frames = input.len() / channels;
+    let frame_len = ((sample_rate as f64 * FRAME_SECONDS) as usize / 2 * 2).max(64);
+    let hop = frame_len / 2;
+    let seek = frame_len / 4;
+
+    // Periodic Hann: sums to 1 at 50% overlap
+    let window: Vec<f32> = (0..frame_len)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / frame_len as f32).cos())
+        .collect();
+
+    // Mono guide signal for the similarity search
+    let guide: Vec<f32> = input
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    let guide_at = |frame: isize| -> f32 {
+        if frame >= 0 && (frame as usize) < in_frames { guide[frame as usize] } else { 0.0 }
+    };
+    // Similarity of the grain at `candidate` with the natural continuation at `natural`
+    let similarity = |candidate: isize, natural: isize, stride: usize| -> f32 {
+        (0..hop).step_by(stride).map(|i| guide_at(candidate + i as isize) * guide_at(natural + i as isize)).sum()
+    };
+
+    // Output starts `hop` frames into the buffer, so the first grain is centred on frame 0
+    let mut out = vec![0.0f32; (out_frames + hop + frame_len) * channels];
+    let mut weight = vec![0.0f32; out_frames + hop + frame_len];
+    let mut previous: Option<isize> = None;
+    let mut out_pos = 0usize;
+
+    while out_pos < out_frames + hop {
+        // Grain centre follows the time map (the search below keeps the waveform continuous)
+        let nominal = (source_at(out_pos as f64) - hop as f64).round() as isize;
+
+        let start = match previous {
+            None => nominal,
+            Some(prev) => {
+                let natural = prev + hop as isize;
+                // Coarse search, then refine around the best coarse match.
+                // Ties (steady or silent material) keep closest to the time map instead of drifting.
+                let best = |offsets: &mut dyn Iterator<Item = isize>, stride: usize| {
+                    offsets
+                        .map(|d| (similarity(nominal + d, natural, stride), d))
+                        .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.abs().cmp(&a.1.abs())))
+                        .map(|(_, d)| d)
+                        .unwrap_or(0)
+                };
+                let coarse = best(&mut (-(seek as isize)..=seek as isize).step_by(4), 4);
+                nominal + best(&mut (coarse - 3..=coarse + 3), 1)
+            }
+        };
+
+        for (i, &w) in window.iter().enumerate() {
+            let src = start + i as isize;
+            if src >= 0 && (src as usize) < in_frames {
+                let src_base = src as usize * channels;
+                let dst_base = (out_pos + i) * channels;
+                for ch in 0..channels {
+                    out[dst_base + ch] += input[src_base + ch] * w;
+                }
+                // Only material counts: grains hanging past either end don't fade it out
+                weight[out_pos + i] += w;
+            }
+        }
+
+        previous = Some(start);
+        out_pos += hop;
+    }
+
+    // Normalise the edges where fewer grains (or grain parts) overlap
+    for (frame, &w) in out.chunks_exact_mut(channels).zip(weight.iter()) {
+        if w > 1e-3 {
+            frame.iter_mut().for_each(|s| *s /= w);
+        }
+    }
+    out.drain(..hop * channels);
+    out.truncate(out_frames * channels);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zero_crossings(signal: &[f32]) -> usize {
+        signal.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn test_wsola_keeps_pitch() {
+        let sr = 48000;
+        let input: Vec<f32> = (0..sr as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / sr as f32).sin())
+            .collect();
+
+        // Half speed: twice as long, same frequency
+        let out = wsola(&input, 1, sr, input.len() * 2, |t| t * 0.5);
+        assert_eq!(out.len(), input.len() * 2);
+        let hz = zero_crossings(&out) as f32 / 2.0;
+        assert!((hz - 440.0).abs() < 5.0, "pitch drifted to {} Hz", hz);
+    }
+
+    #[test]
+    fn test_pitch_shift_keeps_length() {
+        let sr = 48000;
+        let input: Vec<f32> = (0..sr as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / sr as f32).sin())
+            .collect();
+        let render = ClipRender {
+            project_bpm: 120.0,
+            original_bpm: 120.0,
+            warp_markers: vec![],
+            pitch_semitones: 12,
+            pitch_cents: 0.0,
+            tempo_changes: vec![],
+            start_beat: 0.0,
+        };
+
+        let (data, rate) = render.render(&input, 1, sr);
+        assert_eq!(rate, sr * 2);
+        // Played back at `rate`, the octave-up tone lasts as long as the original
+        let out = crate::resampler::OmniResampler::resample(&data, rate as f64 / sr as f64).unwrap();
+        assert!((out.len() as i64 - input.len() as i64).abs() < 4);
+        // Skip the band-limited edges (ringing would add crossings)
+        let hz = zero_crossings(&out[4800..43200]) as f32 / 0.8;
+        assert!((hz - 880.0).abs() < 10.0, "expected 880 Hz, got {}", hz);
+    }
+
+    #[test]
+    fn test_render_follows_tempo_changes() {
+        // 4 beats at 120 BPM; the tempo doubles from beat 2 of the timeline
+        let sr = 48000;
+        let input = vec![0.0f32; 96000];
+        let mut render = ClipRender {
+            project_bpm: 120.0,
+            original_bpm: 120.0,
+            warp_markers: vec![],
+            pitch_semitones: 0,
+            pitch_cents: 0.0,
+            tempo_changes: vec![TempoEvent { beat: 2.0, bpm: 240.0, ramp: Default::default() }],
+            start_beat: 0.0,
+        };
+        let (data, _) = render.render(&input, 1, sr);
+        assert_eq!(data.len(), 48000 + 24000);
+
+        // Starting after the change the whole clip plays twice as fast
+        render.start_beat = 4.0;
+        assert_eq!(render.render(&input, 1, sr).0.len(), 48000);
+        assert_eq!(render.speed(), 2.0);
+    }
+
+    #[test]
+    fn test_warp_map_markers() {
+        // Source at 120 BPM / 48k: 24000 frames per beat
+        let markers = vec![
+            WarpMarker { source_sample: 0, timeline_beat: 0.0 },
+            WarpMarker { source_sample: 48000, timeline_beat: 4.0 }, // first 2 beats stretched over 4
+        ];
+        let map = WarpMap::new(&markers, 24000.0);
+        assert_eq!(map.source_at(2.0), 24000.0);
+        assert_eq!(map.source_at(5.0), 72000.0); // original tempo after the last marker
+        assert_eq!(map.beat_at(72000.0), 5.0);
+    }
+}
@@ -3,11 +3,11 @@
 #[cfg(test)]
 mod tests {
     use crate::{AudioEngine, EngineCommand};
-    use crossbeam_channel::{unbounded, bounded};
+    use crate::backend::NullBackend;
+    use crate::nodes::SineNode;
+    use crossbeam_channel::unbounded;
     use std::thread;
     use std::time::Duration;
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
 
     #[test]
     fn test_recording_integration() {
@@ -20,11 +20,18 @@ mod tests {
             for _ in drop_rx {}
         });
 
-        // Initialize Engine
-        // Note: AudioEngine::new spawns the audio thread and recorder thread.
-        let engine = AudioEngine::new(cmd_rx, drop_tx).expect("Failed to create engine");
-        
-        // 2. Simulate Audio Processing (Wait for init)
+        // Initialize Engine on the null backend (clock thread, no sound card needed)
+        // Note: with_backend spawns the clock thread and recorder thread.
+        let backend = NullBackend::new(48000, 512);
+        let engine = AudioEngine::with_backend(Box::new(backend), cmd_rx, drop_tx).expect("Failed to create engine");
+
+        // 2. One track producing signal, transport running
+        cmd_tx.send(EngineCommand::AddTrackNode {
+            node: Box::new(SineNode::new(440.0)),
+            name: "Sine".to_string(),
+            plugin_path: None,
+        }).unwrap();
+        cmd_tx.send(EngineCommand::Play).unwrap();
         thread::sleep(Duration::from_millis(100));
         
         println!("[Test] Engine Initialized.");
@@ -33,17 +40,7 @@ mod tests {
         cmd_tx.send(EngineCommand::StartRecording).unwrap();
         println!("[Test] Started Recording...");
         
-        // 4. Wait / Simulate Playback
-        // Since we can't easily inject audio into the input device in this test without more mocking,
-        // we rely on the fact that the engine *runs*.
-        // Ideally we would mock the `cpal` stream or use a "Dummy" host, but AudioEngine uses cpal directly.
-        // However, we can check if the recorder creates a clip even if silence.
-        
-        // But wait! The issue is "Silent/Empty".
-        // If we record silence, we expect a clip with Zeros.
-        // If we could inject signal -> we expect non-zeros.
-        // For now, let's just verify the PIPELINE (Start -> Stop -> Clip Created).
-        
+        // 4. Let the clock thread run for a while
         thread::sleep(Duration::from_millis(1000));
         
         // 5. Stop Recording
@@ -58,19 +55,8 @@ mod tests {
         let clips = result.unwrap();
         println!("[Test] Received {} clips", clips.len());
         
-        // We expect 1 clip per track (default 8 tracks? No, Engine init default is likely 0 or 8?)
-        // Mixer defaults to 32 tracks.
-        // The loop in StopRecording iterates `recording_buffers`.
-        // If buffer is not empty, it creates a clip.
-        // "Signal present" check is not in Recorder, it records whatever is pushed.
-        // Engine pushes zeros if no input?
-        // Actually engine inputs are from `AudioBuffers::track_bufs`.
-        // If no plugins, track bufs are zero.
-        
-        // So we expect clips with silence.
-        // But they should EXIST.
-        
-        assert!(!clips.is_empty(), "Should have created at least one clip (even if silence)");
+        // The sine track is captured from its track buffer
+        assert!(!clips.is_empty(), "Should have created at least one clip");
         
         // 7. Verify Data (Optional: Access AudioPool if possible)
         // engine.audio_pool is Arc<ArcSwap<AudioPool>>.
@@ -82,7 +68,8 @@ mod tests {
             assert!(asset.is_some(), "Asset should exist in pool");
             let data = &asset.unwrap().data;
             println!("[Test] Asset Data Len: {}", data.len());
-            assert!(data.len() > 0, "Asset data should not be empty");
+            assert!(!data.is_empty(), "Asset data should not be empty");
+            assert!(data.iter().any(|s| s.abs() > 0.1), "Asset should contain the recorded sine");
         }
     }
 }
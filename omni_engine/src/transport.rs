@@ -10,6 +10,9 @@ static TRANSPORT_SONG_POS_BITS: AtomicU64 = AtomicU64::new(0);
 static TRANSPORT_BAR_START_BITS: AtomicU64 = AtomicU64::new(0);
 static TRANSPORT_BAR_NUMBER: AtomicI32 = AtomicI32::new(0);
 static TRANSPORT_TIME_SIG: AtomicU32 = AtomicU32::new(0x0004_0004); // packed num|denom
+static TRANSPORT_LOOP_ACTIVE: AtomicU32 = AtomicU32::new(0);
+static TRANSPORT_LOOP_START_BITS: AtomicU64 = AtomicU64::new(0);
+static TRANSPORT_LOOP_END_BITS: AtomicU64 = AtomicU64::new(0);
 
 /// Transport state passed to plugins (value type, cheap to copy)
 #[derive(Clone, Copy, Debug)]
@@ -21,6 +24,9 @@ pub struct TransportState {
     pub bar_number: i32,
     pub time_sig_num: u16,
     pub time_sig_denom: u16,
+    pub loop_active: bool,
+    pub loop_start_beats: f64,
+    pub loop_end_beats: f64,
 }
 
 impl Default for TransportState {
@@ -33,6 +39,9 @@ impl Default for TransportState {
             bar_number: 0,
             time_sig_num: 4,
             time_sig_denom: 4,
+            loop_active: false,
+            loop_start_beats: 0.0,
+            loop_end_beats: 0.0,
         }
     }
 }
@@ -48,6 +57,9 @@ pub fn update_transport(state: TransportState) {
     TRANSPORT_BAR_NUMBER.store(state.bar_number, Ordering::Relaxed);
     let packed_sig = ((state.time_sig_num as u32) << 16) | (state.time_sig_denom as u32);
     TRANSPORT_TIME_SIG.store(packed_sig, Ordering::Relaxed);
+    TRANSPORT_LOOP_ACTIVE.store(state.loop_active as u32, Ordering::Relaxed);
+    TRANSPORT_LOOP_START_BITS.store(state.loop_start_beats.to_bits(), Ordering::Relaxed);
+    TRANSPORT_LOOP_END_BITS.store(state.loop_end_beats.to_bits(), Ordering::Relaxed);
 }
 
 /// Get the current transport state (called by PluginNode during process)
@@ -63,5 +75,8 @@ pub fn get_transport() -> TransportState {
         bar_number: TRANSPORT_BAR_NUMBER.load(Ordering::Relaxed),
         time_sig_num: (packed_sig >> 16) as u16,
         time_sig_denom: (packed_sig & 0xFFFF) as u16,
+        loop_active: TRANSPORT_LOOP_ACTIVE.load(Ordering::Relaxed) != 0,
+        loop_start_beats: f64::from_bits(TRANSPORT_LOOP_START_BITS.load(Ordering::Relaxed)),
+        loop_end_beats: f64::from_bits(TRANSPORT_LOOP_END_BITS.load(Ordering::Relaxed)),
     }
 }
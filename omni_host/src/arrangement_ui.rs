@@ -2,6 +2,16 @@ use eframe::egui;
 use omni_engine::EngineCommand;
 use crossbeam_channel::Sender;
 use std::collections::HashMap;
+use omni_shared::project::{AutomationLane, AutomationPoint, AutomationTarget, FadeCurve, TimeRange, TimeSignature};
+use omni_shared::tempo::{TempoEvent, TempoMap, TempoRamp};
+use omni_shared::meter::{MeterChange, MeterMap};
+
+/// Height of an expanded automation lane below its track
+const AUTOMATION_LANE_HEIGHT: f32 = 50.0;
+/// Height of the tempo lane under the ruler
+const TEMPO_LANE_HEIGHT: f32 = 36.0;
+/// Tempo range shown in the tempo lane (same as the BPM field)
+const TEMPO_RANGE: (f32, f32) = (40.0, 240.0);
 
 /// Cached waveform peaks for an asset at a specific resolution
 #[derive(Clone)]
@@ -22,6 +32,17 @@ pub struct ArrangementUI {
     
     // Interaction State
     pub drag_state: Option<DragState>,
+    pub midi_drag: Option<MidiDragState>,
+    /// Beat under the pointer when a lane context menu was opened
+    context_beat: f64,
+
+    // Automation: lane shown under each track, point being dragged (track, point)
+    pub automation_view: HashMap<usize, AutomationTarget>,
+    automation_drag: Option<(usize, usize)>,
+    // Tempo event being dragged
+    tempo_drag: Option<usize>,
+    // Beat where a loop brace drag on the ruler started
+    loop_drag_anchor: Option<f64>,
     
     // Waveform Cache: asset_id -> cached peaks
     waveform_cache: HashMap<u32, WaveformCache>,
@@ -31,7 +52,15 @@ pub struct ArrangementUI {
 pub struct DragState {
     pub track_index: usize,
     pub clip_index: usize,
-    pub original_start_samples: u64,
+    pub original_start_beat: f64,
+    pub start_mouse_x: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MidiDragState {
+    pub track_index: usize,
+    pub clip_index: usize,
+    pub original_start_beats: f64,
     pub start_mouse_x: f32,
 }
 
@@ -44,6 +73,12 @@ impl Default for ArrangementUI {
             zoom_y: 60.0, 
             header_width: 150.0,
             drag_state: None,
+            midi_drag: None,
+            context_beat: 0.0,
+            automation_view: HashMap::new(),
+            automation_drag: None,
+            tempo_drag: None,
+            loop_drag_anchor: None,
             waveform_cache: HashMap::new(),
         }
     }
@@ -54,16 +89,256 @@ impl ArrangementUI {
         Self::default()
     }
 
+    /// Breakpoint editor: double-click adds a point, drag moves it,
+    /// right-click edits the segment curve or deletes points.
+    #[allow(clippy::too_many_arguments)]
+    fn show_automation_lane(
+        &mut self,
+        ui: &mut egui::Ui,
+        painter: &egui::Painter,
+        rect: egui::Rect,
+        track_index: usize,
+        target: AutomationTarget,
+        (min, max): (f32, f32),
+        tracks: &mut [crate::TrackData],
+        sender: &Sender<EngineCommand>,
+    ) {
+        let lanes = &mut tracks[track_index].arrangement.automation;
+        let lane_idx = match lanes.iter().position(|l| l.target == target) {
+            Some(idx) => idx,
+            None => {
+                lanes.push(AutomationLane::new(target));
+                lanes.len() - 1
+            }
+        };
+        let lane = &mut lanes[lane_idx];
+
+        let x_of = |beat: f64| rect.min.x + beat as f32 * self.zoom_x - self.scroll_x;
+        let beat_of = |x: f32| ((x - rect.min.x + self.scroll_x) / self.zoom_x).max(0.0) as f64;
+        let y_of = |value: f32| rect.max.y - 3.0 - (value - min) / (max - min) * (rect.height() - 6.0);
+        let value_of = |y: f32| (min + (rect.max.y - 3.0 - y) / (rect.height() - 6.0) * (max - min)).clamp(min, max);
+
+        painter.rect_filled(rect, 0.0, crate::ui::theme::THEME.bg_dark);
+        painter.rect_stroke(rect, 0.0, (1.0, crate::ui::theme::THEME.grid_line), egui::StrokeKind::Middle);
+        painter.text(
+            rect.left_top() + egui::vec2(5.0, 8.0),
+            egui::Align2::LEFT_CENTER,
+            target_name(target),
+            egui::FontId::proportional(11.0),
+            crate::ui::theme::THEME.text_secondary,
+        );
+
+        let response = ui.interact(rect, ui.id().with(("automation", track_index)), egui::Sense::click_and_drag());
+        let pointer = response.interact_pointer_pos();
+        let hit = |lane: &AutomationLane, pos: egui::Pos2| {
+            lane.points.iter().position(|p| egui::pos2(x_of(p.beat), y_of(p.value)).distance(pos) < 6.0)
+        };
+        let mut changed = false;
+
+        if let Some(pos) = pointer {
+            if response.double_clicked() && hit(lane, pos).is_none() {
+                lane.insert_point(AutomationPoint { beat: beat_of(pos.x), value: value_of(pos.y), curve: 0.0 });
+                changed = true;
+            }
+            if response.drag_started() {
+                self.automation_drag = hit(lane, pos).map(|p| (track_index, p));
+            }
+            if response.secondary_clicked() {
+                self.context_beat = beat_of(pos.x);
+            }
+        }
+        if let Some((t, p_idx)) = self.automation_drag {
+            if t == track_index {
+                match pointer {
+                    Some(pos) if response.dragged() && p_idx < lane.points.len() => {
+                        // Stay between the neighbours so the order holds
+                        let lo = if p_idx > 0 { lane.points[p_idx - 1].beat } else { 0.0 };
+                        let hi = lane.points.get(p_idx + 1).map(|p| p.beat).unwrap_or(f64::MAX);
+                        let point = &mut lane.points[p_idx];
+                        point.beat = beat_of(pos.x).clamp(lo, hi);
+                        point.value = value_of(pos.y);
+                        changed = true;
+                    }
+                    _ => self.automation_drag = None,
+                }
+            }
+        }
+        let context_beat = self.context_beat;
+        response.context_menu(|ui| {
+            // Segment starting at the last point before the click
+            let seg = lane.points.partition_point(|p| p.beat <= context_beat).checked_sub(1);
+            if let Some(seg) = seg.filter(|&s| s + 1 < lane.points.len()) {
+                ui.horizontal(|ui| {
+                    ui.label("Curve:");
+                    changed |= ui.add(egui::Slider::new(&mut lane.points[seg].curve, -1.0..=1.0)).changed();
+                });
+            }
+            if let Some(seg) = seg {
+                if ui.button("Delete Point").clicked() {
+                    lane.points.remove(seg);
+                    changed = true;
+                    ui.close();
+                }
+            }
+            if ui.checkbox(&mut lane.enabled, "Enabled").changed() {
+                changed = true;
+            }
+            if ui.button("Clear Lane").clicked() {
+                lane.points.clear();
+                changed = true;
+                ui.close();
+            }
+        });
+
+        // Envelope
+        let points: Vec<egui::Pos2> = (0..=rect.width() as usize)
+            .step_by(2)
+            .filter_map(|px| {
+                let x = rect.min.x + px as f32;
+                lane.value_at(beat_of(x)).map(|v| egui::pos2(x, y_of(v)))
+            })
+            .collect();
+        let color = if lane.enabled { egui::Color32::from_rgb(240, 120, 80) } else { egui::Color32::GRAY };
+        painter.add(egui::Shape::line(points, (1.5, color)));
+        for p in &lane.points {
+            painter.circle_filled(egui::pos2(x_of(p.beat), y_of(p.value)), 3.5, color);
+        }
+
+        if changed {
+            let _ = sender.send(EngineCommand::SetAutomationLane { track_index, lane: lane.clone() });
+        }
+    }
+
+    /// Tempo events over the timeline: double-click adds one, drag moves it
+    /// (beat and tempo), right-click sets the tempo, ramp or deletes.
+    fn show_tempo_lane(
+        &mut self,
+        ui: &mut egui::Ui,
+        painter: &egui::Painter,
+        rect: egui::Rect,
+        tempo: &TempoMap,
+        tempo_changes: &mut Vec<TempoEvent>,
+        sender: &Sender<EngineCommand>,
+    ) {
+        let (min, max) = TEMPO_RANGE;
+        let x_of = |beat: f64| rect.min.x + beat as f32 * self.zoom_x - self.scroll_x;
+        let beat_of = |x: f32| ((x - rect.min.x + self.scroll_x) / self.zoom_x).max(0.0) as f64;
+        let y_of = |bpm: f32| rect.max.y - 3.0 - (bpm.clamp(min, max) - min) / (max - min) * (rect.height() - 6.0);
+        let bpm_of = |y: f32| (min + (rect.max.y - 3.0 - y) / (rect.height() - 6.0) * (max - min)).clamp(min, max);
+        // Tempo changes snap to 16ths
+        let snap = |beat: f64| ((beat * 4.0).round() / 4.0).max(0.25);
+
+        painter.rect_filled(rect, 0.0, crate::ui::theme::THEME.bg_dark);
+        painter.rect_stroke(rect, 0.0, (1.0, crate::ui::theme::THEME.grid_line), egui::StrokeKind::Middle);
+
+        let response = ui.interact(rect, ui.id().with("tempo_lane"), egui::Sense::click_and_drag());
+        let pointer = response.interact_pointer_pos();
+        let hit = |changes: &[TempoEvent], pos: egui::Pos2| {
+            changes.iter().position(|e| egui::pos2(x_of(e.beat), y_of(e.bpm)).distance(pos) < 6.0)
+        };
+        let mut changed = false;
+
+        if let Some(pos) = pointer {
+            if response.double_clicked() && hit(tempo_changes, pos).is_none() {
+                let beat = snap(beat_of(pos.x));
+                if !tempo_changes.iter().any(|e| e.beat == beat) {
+                    tempo_changes.push(TempoEvent { beat, bpm: bpm_of(pos.y).round(), ramp: TempoRamp::Instant });
+                    tempo_changes.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+                    changed = true;
+                }
+            }
+            if response.drag_started() {
+                self.tempo_drag = hit(tempo_changes, pos);
+            }
+            if response.secondary_clicked() {
+                self.context_beat = beat_of(pos.x);
+            }
+        }
+        if let Some(idx) = self.tempo_drag {
+            match pointer {
+                Some(pos) if response.dragged() && idx < tempo_changes.len() => {
+                    // Stay between the neighbours so the order holds
+                    let lo = if idx > 0 { tempo_changes[idx - 1].beat + 0.25 } else { 0.25 };
+                    let hi = tempo_changes.get(idx + 1).map(|e| e.beat - 0.25).unwrap_or(f64::MAX);
+                    let event = &mut tempo_changes[idx];
+                    event.beat = snap(beat_of(pos.x)).clamp(lo, hi.max(lo));
+                    event.bpm = bpm_of(pos.y).round();
+                    changed = true;
+                }
+                _ => self.tempo_drag = None,
+            }
+        }
+        let context_beat = self.context_beat;
+        response.context_menu(|ui| {
+            // Last change at or before the click
+            let Some(idx) = tempo_changes.partition_point(|e| e.beat <= context_beat).checked_sub(1) else {
+                ui.label("Double-click to add a tempo change");
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label("BPM:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut tempo_changes[idx].bpm).range(min..=max).speed(0.5))
+                    .changed();
+            });
+            let mut ramp = tempo_changes[idx].ramp == TempoRamp::Linear;
+            if ui.checkbox(&mut ramp, "Ramp from previous").changed() {
+                tempo_changes[idx].ramp = if ramp { TempoRamp::Linear } else { TempoRamp::Instant };
+                changed = true;
+            }
+            if ui.button("Delete").clicked() {
+                tempo_changes.remove(idx);
+                changed = true;
+                ui.close();
+            }
+        });
+
+        // Tempo curve (from the map as drawn this frame)
+        let points: Vec<egui::Pos2> = (0..=rect.width() as usize)
+            .step_by(2)
+            .map(|px| {
+                let x = rect.min.x + px as f32;
+                egui::pos2(x, y_of(tempo.bpm_at(beat_of(x)) as f32))
+            })
+            .collect();
+        let color = egui::Color32::from_rgb(120, 200, 240);
+        painter.add(egui::Shape::line(points, (1.5, color)));
+        for event in tempo_changes.iter() {
+            let pos = egui::pos2(x_of(event.beat), y_of(event.bpm));
+            painter.circle_filled(pos, 3.5, color);
+            painter.text(
+                pos + egui::vec2(5.0, -6.0),
+                egui::Align2::LEFT_CENTER,
+                format!("{:.1}", event.bpm),
+                egui::FontId::proportional(10.0),
+                crate::ui::theme::THEME.text_secondary,
+            );
+        }
+
+        if changed {
+            let _ = sender.send(EngineCommand::SetTempoChanges(tempo_changes.clone()));
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn show(
         &mut self,
         ui: &mut egui::Ui,
         tracks: &mut Vec<crate::TrackData>,
         bpm: f32,
+        tempo_changes: &mut Vec<TempoEvent>,
+        time_signature: &mut TimeSignature,
+        meter_changes: &mut Vec<MeterChange>,
+        loop_region: &mut TimeRange,
+        punch: &mut TimeRange,
         sender: &Sender<EngineCommand>,
         _current_step: u32, 
         playback_pos_samples: u64,
         sample_rate: f32,
         audio_pool: Option<&std::sync::Arc<arc_swap::ArcSwap<omni_engine::assets::AudioPool>>>,
+        selected_track: usize,
+        plugin_params: &[omni_shared::ParamInfo],
+        effect_devices: &[omni_shared::DeviceInfo],
     ) {
         let max_rect = ui.available_rect_before_wrap();
         // ui.set_clip_rect(max_rect); // Clip to available space
@@ -79,10 +354,12 @@ impl ArrangementUI {
         self.scroll_x = self.scroll_x.max(0.0);
         self.scroll_y = self.scroll_y.max(0.0);
         
+        let tempo = TempoMap::new(bpm, tempo_changes);
+
         // 2. Layout Dimensions
         let top_ruler_height = 30.0;
         let content_rect = egui::Rect::from_min_max(
-            max_rect.min + egui::vec2(0.0, top_ruler_height),
+            max_rect.min + egui::vec2(0.0, top_ruler_height + TEMPO_LANE_HEIGHT),
             max_rect.max
         );
         let ruler_rect = egui::Rect::from_min_max(
@@ -96,40 +373,211 @@ impl ArrangementUI {
         // Background
         painter.rect_filled(ruler_rect, 0.0, crate::ui::theme::THEME.bg_medium);
         
-        let visible_start_beat = (self.scroll_x / self.zoom_x).floor() as i64;
-        let visible_width_beats = (max_rect.width() / self.zoom_x).ceil() as i64 + 1;
+        let meter = MeterMap::new(*time_signature, meter_changes);
+        let grid_left = max_rect.min.x + self.header_width;
+        let view_start = (self.scroll_x / self.zoom_x) as f64;
+        let view_end = view_start + (max_rect.width() / self.zoom_x) as f64;
+        let x_of = |beat: f64| (beat as f32 * self.zoom_x) - self.scroll_x + grid_left;
         
-        for b in 0..visible_width_beats {
-            let beat = visible_start_beat + b;
-            if beat < 0 { continue; }
+        let mut bar = meter.position(view_start).bar.max(0) as u32;
+        loop {
+            let bar_beat = meter.bar_start(bar);
+            if bar_beat > view_end { break; }
+            let next_bar_beat = meter.bar_start(bar + 1);
+            let signature = meter.signature_at(bar_beat);
             
-            let x = (beat as f32 * self.zoom_x) - self.scroll_x + self.header_width + max_rect.min.x;
+            // Bar line, then one tick per signature beat (eighths in 7/8)
+            let unit = 4.0 / signature.denominator.max(1) as f64;
+            let draw_ticks = unit as f32 * self.zoom_x >= 4.0;
+            let mut tick = bar_beat;
+            while tick < next_bar_beat - 1e-9 {
+                let x = x_of(tick);
+                let is_bar = tick == bar_beat;
+                if x >= grid_left && (is_bar || draw_ticks) {
+                    let color = if is_bar { crate::ui::theme::THEME.text_primary } else { crate::ui::theme::THEME.text_secondary };
+                    let height = if is_bar { 15.0 } else { 8.0 };
+                    painter.line_segment(
+                        [egui::pos2(x, ruler_rect.bottom()), egui::pos2(x, ruler_rect.bottom() - height)],
+                        (1.0, color)
+                    );
+                }
+                tick += unit;
+            }
             
-            if x >= max_rect.min.x + self.header_width {
-                // Major Tick (Bar/Beat)
-                // Assuming 4/4 for now
-                let is_bar = beat % 4 == 0;
-                let color = if is_bar { crate::ui::theme::THEME.text_primary } else { crate::ui::theme::THEME.text_secondary };
-                let height = if is_bar { 15.0 } else { 8.0 };
-                
-                painter.line_segment(
-                    [egui::pos2(x, ruler_rect.bottom()), egui::pos2(x, ruler_rect.bottom() - height)],
-                    (1.0, color)
+            let x = x_of(bar_beat);
+            if x >= grid_left {
+                painter.text(
+                    egui::pos2(x + 5.0, ruler_rect.bottom() - 20.0),
+                    egui::Align2::LEFT_CENTER,
+                    format!("{}", bar + 1),
+                    egui::FontId::proportional(12.0),
+                    crate::ui::theme::THEME.text_primary,
                 );
-                
-                if is_bar {
-                    let text = format!("{}", (beat / 4) + 1);
+                // Meter at the start and wherever it changes
+                if bar == 0 || meter_changes.iter().any(|c| c.bar == bar) {
                     painter.text(
-                        egui::pos2(x + 5.0, ruler_rect.bottom() - 20.0),
+                        egui::pos2(x + 5.0, ruler_rect.top() + 6.0),
                         egui::Align2::LEFT_CENTER,
-                        text,
-                        egui::FontId::proportional(12.0),
-                        crate::ui::theme::THEME.text_primary,
+                        format!("{}/{}", signature.numerator, signature.denominator),
+                        egui::FontId::proportional(10.0),
+                        egui::Color32::from_rgb(120, 200, 240),
                     );
                 }
             }
+            bar += 1;
+        }
+        
+        // Loop brace (upper half) and punch range (bottom strip)
+        let ruler_grid = egui::Rect::from_min_max(egui::pos2(grid_left, ruler_rect.min.y), ruler_rect.max);
+        let range_rect = |range: &TimeRange, top: f32, bottom: f32| {
+            egui::Rect::from_x_y_ranges(
+                x_of(range.start).max(grid_left)..=x_of(range.end).max(grid_left),
+                top..=bottom,
+            )
+        };
+        if loop_region.end > loop_region.start {
+            let alpha = if loop_region.enabled { 90 } else { 30 };
+            painter.rect_filled(
+                range_rect(loop_region, ruler_rect.top(), ruler_rect.center().y),
+                0.0,
+                egui::Color32::from_rgba_unmultiplied(120, 200, 240, alpha),
+            );
+        }
+        if punch.end > punch.start {
+            let alpha = if punch.enabled { 160 } else { 50 };
+            painter.rect_filled(
+                range_rect(punch, ruler_rect.bottom() - 4.0, ruler_rect.bottom()),
+                0.0,
+                egui::Color32::from_rgba_unmultiplied(230, 60, 60, alpha),
+            );
+        }
+
+        // Drag on the ruler sets the loop brace; right-click a bar for meter, loop and punch
+        let ruler_response = ui.interact(ruler_grid, ui.id().with("ruler"), egui::Sense::click_and_drag());
+        let ruler_beat = |x: f32| ((x - grid_left + self.scroll_x) / self.zoom_x).max(0.0) as f64;
+        if let Some(pos) = ruler_response.interact_pointer_pos() {
+            if ruler_response.secondary_clicked() {
+                self.context_beat = ruler_beat(pos.x);
+            }
+            if ruler_response.drag_started_by(egui::PointerButton::Primary) {
+                self.loop_drag_anchor = Some(ruler_beat(pos.x).round());
+            }
+            if let Some(anchor) = self.loop_drag_anchor {
+                let beat = ruler_beat(pos.x).round();
+                if ruler_response.dragged() && beat != anchor {
+                    *loop_region = TimeRange { enabled: true, start: anchor.min(beat), end: anchor.max(beat) };
+                    let _ = sender.send(EngineCommand::SetLoopRegion(*loop_region));
+                }
+            }
+        }
+        if ruler_response.drag_stopped() {
+            self.loop_drag_anchor = None;
         }
+        let context_bar = meter.position(self.context_beat).bar.max(0) as u32;
+        let context_beat = self.context_beat.round();
+        ruler_response.context_menu(|ui| {
+            ui.label(format!("Bar {}", context_bar + 1));
+            ui.separator();
+            let mut loop_changed = false;
+            if ui.checkbox(&mut loop_region.enabled, "Loop").changed() {
+                if loop_region.end <= loop_region.start {
+                    // Nothing set yet: loop 4 bars from here
+                    loop_region.start = meter.bar_start(context_bar);
+                    loop_region.end = meter.bar_start(context_bar + 4);
+                }
+                loop_changed = true;
+            }
+            if ui.button("Set Loop Start Here").clicked() {
+                loop_region.start = context_beat;
+                loop_changed = true;
+                ui.close();
+            }
+            if ui.button("Set Loop End Here").clicked() {
+                loop_region.end = context_beat;
+                loop_changed = true;
+                ui.close();
+            }
+            if loop_changed {
+                let _ = sender.send(EngineCommand::SetLoopRegion(*loop_region));
+            }
+            ui.separator();
+            let mut punch_changed = ui.checkbox(&mut punch.enabled, "Punch In/Out").changed();
+            if ui.button("Set Punch In Here").clicked() {
+                punch.start = context_beat;
+                punch_changed = true;
+                ui.close();
+            }
+            if ui.button("Set Punch Out Here").clicked() {
+                punch.end = context_beat;
+                punch_changed = true;
+                ui.close();
+            }
+            if punch_changed {
+                let _ = sender.send(EngineCommand::SetPunchRange(*punch));
+            }
+            ui.separator();
+            let existing = meter_changes.iter().position(|c| c.bar == context_bar);
+            if context_bar == 0 || existing.is_some() {
+                let mut signature = match existing {
+                    Some(idx) => meter_changes[idx].signature,
+                    None => *time_signature,
+                };
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    changed |= ui.add(egui::DragValue::new(&mut signature.numerator).range(1..=32)).changed();
+                    ui.label("/");
+                    for denominator in [2u8, 4, 8, 16] {
+                        changed |= ui.selectable_value(&mut signature.denominator, denominator, denominator.to_string()).changed();
+                    }
+                });
+                if changed {
+                    match existing {
+                        Some(idx) => {
+                            meter_changes[idx].signature = signature;
+                            let _ = sender.send(EngineCommand::SetMeterChanges(meter_changes.clone()));
+                        }
+                        None => {
+                            *time_signature = signature;
+                            let _ = sender.send(EngineCommand::SetTimeSignature {
+                                numerator: signature.numerator,
+                                denominator: signature.denominator,
+                            });
+                        }
+                    }
+                }
+                if let Some(idx) = existing {
+                    if ui.button("Remove Meter Change").clicked() {
+                        meter_changes.remove(idx);
+                        let _ = sender.send(EngineCommand::SetMeterChanges(meter_changes.clone()));
+                        ui.close();
+                    }
+                }
+            } else if ui.button("Add Meter Change").clicked() {
+                meter_changes.push(MeterChange { bar: context_bar, signature: meter.signature_at(meter.bar_start(context_bar)) });
+                meter_changes.sort_by_key(|c| c.bar);
+                let _ = sender.send(EngineCommand::SetMeterChanges(meter_changes.clone()));
+            }
+        });
         
+        // --- TEMPO LANE ---
+        let tempo_header = egui::Rect::from_min_size(
+            egui::pos2(max_rect.min.x, ruler_rect.bottom()),
+            egui::vec2(self.header_width, TEMPO_LANE_HEIGHT),
+        );
+        painter.rect_filled(tempo_header, 0.0, crate::ui::theme::THEME.bg_medium);
+        painter.text(
+            tempo_header.left_center() + egui::vec2(10.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            format!("Tempo {:.1}", tempo.bpm_at(self.scroll_x as f64 / self.zoom_x as f64)),
+            egui::FontId::proportional(12.0),
+            crate::ui::theme::THEME.text_secondary,
+        );
+        let tempo_rect = egui::Rect::from_min_max(
+            egui::pos2(tempo_header.max.x, tempo_header.min.y),
+            egui::pos2(max_rect.max.x, tempo_header.max.y),
+        );
+        self.show_tempo_lane(ui, &painter.with_clip_rect(tempo_rect), tempo_rect, &tempo, tempo_changes, sender);
+
         // --- DRAW TRACKS ---
         let _visible_start_track = (self.scroll_y / self.zoom_y).floor() as usize;
         let _visible_count = (content_rect.height() / self.zoom_y).ceil() as usize + 1;
@@ -150,11 +598,15 @@ impl ArrangementUI {
         // Clip Rect for Grid
         ui.set_clip_rect(grid_rect);
 
+        let mut row_top = content_rect.min.y - self.scroll_y;
         for i in 0..tracks.len() {
-             let screen_y = content_rect.min.y + (i as f32 * self.zoom_y) - self.scroll_y;
+             let screen_y = row_top;
+             let lane_target = self.automation_view.get(&i).copied();
+             let row_height = self.zoom_y + if lane_target.is_some() { AUTOMATION_LANE_HEIGHT } else { 0.0 };
+             row_top += row_height;
              
              // Check visibility
-             if screen_y + self.zoom_y < content_rect.min.y || screen_y > content_rect.max.y {
+             if screen_y + row_height < content_rect.min.y || screen_y > content_rect.max.y {
                  continue;
              }
              
@@ -187,6 +639,197 @@ impl ArrangementUI {
                  egui::pos2(grid_rect.max.x, screen_y + self.zoom_y)
              );
              painter.rect_stroke(row_rect, 0.0, (1.0, crate::ui::theme::THEME.grid_line), egui::StrokeKind::Middle); // Horizontal divider
+
+             // Right-click on the lane: place a session clip as a MIDI part (snapped to the bar)
+             let row_response = ui.interact(row_rect, ui.id().with(("arr_row", i)), egui::Sense::click());
+             if row_response.secondary_clicked() {
+                 if let Some(p) = row_response.interact_pointer_pos() {
+                     self.context_beat = ((p.x - grid_rect.min.x + self.scroll_x) / self.zoom_x).max(0.0) as f64;
+                 }
+             }
+             let context_beat = self.context_beat;
+             let mut show_lane = None;
+             row_response.context_menu(|ui| {
+                 ui.menu_button("Show Automation", |ui| {
+                     let mut choice = |ui: &mut egui::Ui, target: AutomationTarget, label: String| {
+                         if ui.selectable_label(lane_target == Some(target), label).clicked() {
+                             show_lane = Some(Some(target));
+                             ui.close();
+                         }
+                     };
+                     choice(ui, AutomationTarget::Volume, "Volume".to_string());
+                     choice(ui, AutomationTarget::Pan, "Pan".to_string());
+                     // Parameter names are only known for the selected track
+                     if i == selected_track {
+                         ui.separator();
+                         for param in plugin_params {
+                             choice(ui, AutomationTarget::Param { device: None, id: param.id }, param.name.clone());
+                         }
+                         for (position, (device, info)) in tracks[i].effects.iter().zip(effect_devices).enumerate() {
+                             if info.params.is_empty() {
+                                 continue;
+                             }
+                             ui.menu_button(format!("{}: {}", position + 1, device.name), |ui| {
+                                 for param in &info.params {
+                                     choice(ui, AutomationTarget::Param { device: Some(position), id: param.id }, param.name.clone());
+                                 }
+                             });
+                         }
+                     }
+                     for lane in &tracks[i].arrangement.automation {
+                         if matches!(lane.target, AutomationTarget::Param { .. })
+                             && (i != selected_track || param_info(lane.target, plugin_params, effect_devices).is_none())
+                         {
+                             choice(ui, lane.target, target_name(lane.target));
+                         }
+                     }
+                     if lane_target.is_some() {
+                         ui.separator();
+                         if ui.button("Hide").clicked() {
+                             show_lane = Some(None);
+                             ui.close();
+                         }
+                     }
+                 });
+                 ui.separator();
+                 let at_bar = meter.position(context_beat);
+                 let at_beat = at_bar.bar_start_beat;
+                 ui.label(format!("Insert at bar {}", at_bar.bar + 1));
+                 ui.separator();
+                 let mut inserted = None;
+                 for (slot, clip) in tracks[i].clips.iter().enumerate() {
+                     if clip.notes.is_empty() && !clip.use_sequencer {
+                         continue;
+                     }
+                     if ui.button(format!("Scene {} ({} beats)", slot + 1, clip.length)).clicked() {
+                         inserted = Some(omni_shared::project::MidiArrangementClip::from_clip(clip.to_shared(), at_beat));
+                         ui.close();
+                     }
+                 }
+                 if let Some(part) = inserted {
+                     tracks[i].arrangement.midi_clips.push(part.clone());
+                     let _ = sender.send(EngineCommand::AddMidiClip { track_index: i, clip: part });
+                 }
+             });
+             match show_lane {
+                 Some(Some(target)) => {
+                     self.automation_view.insert(i, target);
+                 }
+                 Some(None) => {
+                     self.automation_view.remove(&i);
+                 }
+                 None => {}
+             }
+
+             // --- AUTOMATION LANE (below the clip row) ---
+             if let Some(target) = lane_target {
+                 let lane_rect = egui::Rect::from_min_max(
+                     egui::pos2(grid_rect.min.x, screen_y + self.zoom_y),
+                     egui::pos2(grid_rect.max.x, screen_y + row_height)
+                 );
+                 let range = target_range(target, i == selected_track, plugin_params, effect_devices);
+                 self.show_automation_lane(ui, &painter, lane_rect, i, target, range, tracks, sender);
+             }
+
+             // --- DRAW MIDI PARTS ---
+             let mut remove_part = None;
+             for part_idx in 0..tracks[i].arrangement.midi_clips.len() {
+                 let (part_start, part_len) = {
+                     let p = &tracks[i].arrangement.midi_clips[part_idx];
+                     (p.start, p.length)
+                 };
+                 let part_rect = egui::Rect::from_min_size(
+                     egui::pos2(grid_rect.min.x + (part_start as f32 * self.zoom_x) - self.scroll_x, screen_y + 2.0),
+                     egui::vec2(part_len as f32 * self.zoom_x, self.zoom_y - 4.0)
+                 );
+                 if part_rect.max.x < grid_rect.min.x || part_rect.min.x > grid_rect.max.x {
+                     continue;
+                 }
+
+                 let part_response = ui.interact(part_rect, ui.id().with(("midi_part", i, part_idx)), egui::Sense::click_and_drag());
+                 if part_response.drag_started() {
+                     self.midi_drag = Some(MidiDragState {
+                         track_index: i,
+                         clip_index: part_idx,
+                         original_start_beats: part_start,
+                         start_mouse_x: ui.input(|inp| inp.pointer.interact_pos().unwrap_or(egui::Pos2::ZERO).x),
+                     });
+                 }
+                 if let Some(drag) = self.midi_drag {
+                     if drag.track_index == i && drag.clip_index == part_idx {
+                         if ui.input(|inp| inp.pointer.primary_down()) {
+                             let current_mouse_x = ui.input(|inp| inp.pointer.interact_pos().unwrap_or(egui::Pos2::ZERO).x);
+                             // Snap to 16ths
+                             let delta_beats = ((current_mouse_x - drag.start_mouse_x) / self.zoom_x) as f64;
+                             let new_start = ((drag.original_start_beats + delta_beats) * 4.0).round().max(0.0) / 4.0;
+                             if new_start != tracks[i].arrangement.midi_clips[part_idx].start {
+                                 tracks[i].arrangement.midi_clips[part_idx].start = new_start;
+                                 let _ = sender.send(EngineCommand::MoveMidiClip { track_index: i, clip_index: part_idx, new_start });
+                             }
+                         } else {
+                             self.midi_drag = None;
+                         }
+                     }
+                 }
+
+                 part_response.context_menu(|ui| {
+                     ui.label("MIDI Part");
+                     ui.separator();
+                     let p = &mut tracks[i].arrangement.midi_clips[part_idx];
+                     let mut changed = ui.checkbox(&mut p.looped, "Loop").changed();
+                     ui.horizontal(|ui| {
+                         ui.label("Length:");
+                         changed |= ui.add(egui::DragValue::new(&mut p.length).speed(0.25).range(0.25..=1024.0).suffix(" beats")).changed();
+                     });
+                     if changed {
+                         let _ = sender.send(EngineCommand::SetMidiClipLoop {
+                             track_index: i,
+                             clip_index: part_idx,
+                             looped: p.looped,
+                             length: p.length,
+                         });
+                     }
+                     if ui.button("Delete").clicked() {
+                         remove_part = Some(part_idx);
+                         ui.close();
+                     }
+                 });
+
+                 let part_color = egui::Color32::from_rgb(110, 190, 120);
+                 painter.rect_filled(part_rect, 4.0, part_color.gamma_multiply(0.3));
+                 painter.rect_stroke(part_rect, 4.0, (1.0, egui::Color32::WHITE), egui::StrokeKind::Middle);
+
+                 // Note overview (repeated per loop)
+                 let p = &tracks[i].arrangement.midi_clips[part_idx];
+                 let loop_len = p.clip.length.max(0.25);
+                 let repeats = if p.looped { (p.length / loop_len).ceil() as usize } else { 1 };
+                 let note_h = (part_rect.height() / 128.0).max(1.0);
+                 for rep in 0..repeats {
+                     let origin = rep as f64 * loop_len - p.start_offset;
+                     for note in &p.clip.notes {
+                         let start = origin + note.start;
+                         let end = (start + note.duration).min(p.length);
+                         if start < 0.0 || start >= p.length {
+                             continue;
+                         }
+                         let y = part_rect.max.y - (note.key as f32 / 127.0) * part_rect.height();
+                         let x0 = part_rect.min.x + start as f32 * self.zoom_x;
+                         let x1 = part_rect.min.x + end as f32 * self.zoom_x;
+                         painter.line_segment([egui::pos2(x0, y), egui::pos2(x1.max(x0 + 1.0), y)], (note_h, part_color));
+                     }
+                 }
+                 painter.text(
+                     part_rect.left_top() + egui::vec2(5.0, 8.0),
+                     egui::Align2::LEFT_CENTER,
+                     if p.clip.use_sequencer { "Sequencer" } else { "MIDI" },
+                     egui::FontId::proportional(11.0),
+                     egui::Color32::BLACK
+                 );
+             }
+             if let Some(part_idx) = remove_part {
+                 tracks[i].arrangement.midi_clips.remove(part_idx);
+                 let _ = sender.send(EngineCommand::RemoveMidiClip { track_index: i, clip_index: part_idx });
+             }
              
              // --- DRAW CLIPS ---
              // Use index loop to avoid immutable borrow of tracks[i] preventing mutation later
@@ -195,16 +838,14 @@ impl ArrangementUI {
                  // 1. Read Clip Data (Immutable Scope)
                  let (clip_name, clip_start, clip_len) = {
                      let c = &tracks[i].arrangement.clips[clip_idx];
-                     (c.name.clone(), c.start_time.samples, c.length.samples)
+                     (c.name.clone(), c.start, c.length.samples)
                  };
 
-                // Draw Clip
-                let sample_to_beats = |s: u64| -> f32 {
-                     (s as f64 * (bpm as f64 / (60.0 * sample_rate as f64))) as f32
-                };
-                 
-                let start_beat = sample_to_beats(clip_start);
-                let len_beats = sample_to_beats(clip_len);
+                // Draw Clip: the start is in beats, the length in frames (through the tempo map)
+                let sr = sample_rate as f64;
+                let end_sample = tempo.sample_at(clip_start, sr) + clip_len as f64;
+                let start_beat = clip_start as f32;
+                let len_beats = (tempo.beat_at_sample(end_sample, sr) - clip_start) as f32;
                  
                 let clip_x = grid_rect.min.x + (start_beat * self.zoom_x) - self.scroll_x;
                 let clip_w = len_beats * self.zoom_x;
@@ -227,7 +868,7 @@ impl ArrangementUI {
                     self.drag_state = Some(DragState {
                         track_index: i,
                         clip_index: clip_idx,
-                        original_start_samples: clip_start,
+                        original_start_beat: clip_start,
                         start_mouse_x: ui.input(|inp| inp.pointer.interact_pos().unwrap_or(egui::Pos2::ZERO).x),
                     });
                 }
@@ -239,17 +880,12 @@ impl ArrangementUI {
                             let current_mouse_x = ui.input(|inp| inp.pointer.interact_pos().unwrap_or(egui::Pos2::ZERO).x);
                             let delta_x = current_mouse_x - drag.start_mouse_x;
                             
-                            // Convert delta pixels -> delta samples
-                            let beats_per_sample = bpm as f64 / (60.0 * sample_rate as f64);
-                            let samples_per_pixel = (1.0 / self.zoom_x as f64) / beats_per_sample;
-                            let delta_samples = (delta_x as f64 * samples_per_pixel) as i64;
-                            
-                            // Calculate new start time
-                            let new_start = (drag.original_start_samples as i64 + delta_samples).max(0) as u64;
+                            // Convert delta pixels -> delta beats
+                            let new_start = (drag.original_start_beat + (delta_x / self.zoom_x) as f64).max(0.0);
                             
                             // Apply to UI (Immediate Feedback)
                             if let Some(c) = tracks[i].arrangement.clips.get_mut(clip_idx) {
-                                c.start_time.samples = new_start;
+                                c.start = new_start;
                             }
                             
                             // Sync with Engine (Throttle this? Or send every frame?)
@@ -288,11 +924,71 @@ impl ArrangementUI {
                             }
                         });
                         
+                        ui.separator();
+                        let mut trigger_pitch = false;
+                        ui.horizontal(|ui| {
+                            ui.label("Transpose:");
+                            trigger_pitch |= ui.add(egui::DragValue::new(&mut c.pitch_semitones).speed(0.1).range(-48..=48).suffix(" st")).changed();
+                            trigger_pitch |= ui.add(egui::DragValue::new(&mut c.pitch_cents).speed(0.5).range(-100.0..=100.0).suffix(" ct")).changed();
+                        });
+                        if trigger_pitch {
+                            let _ = sender.send(EngineCommand::SetClipPitch {
+                                track_index: i,
+                                clip_index: clip_idx,
+                                semitones: c.pitch_semitones,
+                                cents: c.pitch_cents,
+                            });
+                        }
+                        
+                        ui.separator();
+                        let mut gain_db = 20.0 * c.gain.max(1e-6).log10();
+                        ui.horizontal(|ui| {
+                            ui.label("Gain:");
+                            if ui.add(egui::DragValue::new(&mut gain_db).speed(0.1).range(-60.0..=24.0).suffix(" dB")).changed() {
+                                c.gain = 10.0_f32.powf(gain_db / 20.0);
+                                let _ = sender.send(EngineCommand::SetClipGain {
+                                    track_index: i,
+                                    clip_index: clip_idx,
+                                    gain: c.gain,
+                                });
+                            }
+                        });
+
+                        // Fades are edited in ms, stored in frames
+                        let mut trigger_fades = false;
+                        let frames_per_ms = sample_rate as f64 / 1000.0;
+                        for (label, fade) in [("Fade In:", &mut c.fade_in), ("Fade Out:", &mut c.fade_out)] {
+                            ui.horizontal(|ui| {
+                                ui.label(label);
+                                let mut ms = fade.length as f64 / frames_per_ms;
+                                if ui.add(egui::DragValue::new(&mut ms).speed(1.0).range(0.0..=10000.0).suffix(" ms")).changed() {
+                                    fade.length = (ms * frames_per_ms) as u64;
+                                    trigger_fades = true;
+                                }
+                                egui::ComboBox::from_id_salt(label)
+                                    .selected_text(fade.curve.name())
+                                    .show_ui(ui, |ui| {
+                                        for curve in FadeCurve::ALL {
+                                            trigger_fades |= ui.selectable_value(&mut fade.curve, curve, curve.name()).changed();
+                                        }
+                                    });
+                            });
+                        }
+                        if trigger_fades {
+                            let _ = sender.send(EngineCommand::SetClipFades {
+                                track_index: i,
+                                clip_index: clip_idx,
+                                fade_in: c.fade_in,
+                                fade_out: c.fade_out,
+                            });
+                        }
+
                         // Send command if changed
                         if trigger_stretch {
                              let _ = sender.send(EngineCommand::StretchClip { 
                                  track_index: i, 
                                  clip_index: clip_idx, 
+                                 stretch: c.stretch,
                                  original_bpm: c.original_bpm 
                              });
                         }
@@ -340,21 +1036,25 @@ impl ArrangementUI {
                             if !cache_valid {
                                 if let Some(asset) = pool.get_asset(asset_id) {
                                     let data = &asset.data;
+                                    let channels = asset.channels.max(1) as usize;
+                                    let frames = asset.frames();
                                     let mut peaks = Vec::new();
                                     let mut idx = 0;
                                     
-                                    while idx < data.len() {
-                                        let chunk_end = (idx + samples_per_pixel).min(data.len());
-                                        let first_sample = data[idx];
+                                    // Peaks span all channels of each frame
+                                    while idx < frames {
+                                        let chunk_end = (idx + samples_per_pixel).min(frames);
+                                        let first_sample = data[idx * channels];
                                         let mut min_v = first_sample;
                                         let mut max_v = first_sample;
                                         
                                         // Stride for very large chunks
                                         let stride = if samples_per_pixel > 100 { samples_per_pixel / 50 } else { 1 };
                                         for k in (idx..chunk_end).step_by(stride.max(1)) {
-                                            let s = data[k];
-                                            if s < min_v { min_v = s; }
-                                            if s > max_v { max_v = s; }
+                                            for &s in &data[k * channels..(k + 1) * channels] {
+                                                if s < min_v { min_v = s; }
+                                                if s > max_v { max_v = s; }
+                                            }
                                         }
                                         peaks.push((min_v, max_v));
                                         idx += samples_per_pixel;
@@ -418,7 +1118,20 @@ impl ArrangementUI {
                         }
                 }
 
-                
+                // Fade / gain envelope (includes automatic crossfades)
+                let envelope = omni_engine::fades::ClipEnvelope::for_clip(&tracks[i].arrangement.clips, clip_idx, &tempo, sr);
+                if envelope.fade_in.length > 0 || envelope.fade_out.length > 0 || envelope.gain != 1.0 {
+                    let points: Vec<egui::Pos2> = (0..=clip_w.max(1.0) as usize)
+                        .step_by(2)
+                        .map(|px| {
+                            let pos = (px as f64 / clip_w.max(1.0) as f64 * clip_len as f64) as u64;
+                            let level = envelope.gain_at(pos.min(clip_len.saturating_sub(1))).min(1.0);
+                            egui::pos2(clip_rect.min.x + px as f32, clip_rect.max.y - level * clip_rect.height())
+                        })
+                        .collect();
+                    painter.add(egui::Shape::line(points, (1.0, egui::Color32::from_rgb(240, 200, 80))));
+                }
+
                 painter.text(
                     clip_rect.left_center() + egui::vec2(5.0, 0.0),
                     egui::Align2::LEFT_CENTER,
@@ -433,7 +1146,7 @@ impl ArrangementUI {
         // Global Sample Pos -> Beats -> X
         // We reuse the calculation:
         let samples_to_beats = |s: u64| -> f32 {
-             (s as f64 * (bpm as f64 / (60.0 * sample_rate as f64))) as f32
+             tempo.beat_at_sample(s as f64, sample_rate as f64) as f32
         };
         let current_beat = samples_to_beats(playback_pos_samples);
         let playhead_x = grid_rect.min.x + (current_beat * self.zoom_x) - self.scroll_x;
@@ -460,3 +1173,43 @@ impl ArrangementUI {
 
     }
 }
+
+fn target_name(target: AutomationTarget) -> String {
+    match target {
+        AutomationTarget::Volume => "Volume".to_string(),
+        AutomationTarget::Pan => "Pan".to_string(),
+        AutomationTarget::Param { device: None, id } => format!("Param {}", id),
+        AutomationTarget::Param { device: Some(device), id } => format!("FX {} Param {}", device + 1, id),
+    }
+}
+
+/// Parameter a lane drives, from the selected track's instrument or effect parameters.
+fn param_info<'a>(
+    target: AutomationTarget,
+    plugin_params: &'a [omni_shared::ParamInfo],
+    effect_devices: &'a [omni_shared::DeviceInfo],
+) -> Option<&'a omni_shared::ParamInfo> {
+    let AutomationTarget::Param { device, id } = target else { return None };
+    let params = match device {
+        None => plugin_params,
+        Some(position) => &effect_devices.get(position)?.params,
+    };
+    params.iter().find(|p| p.id == id)
+}
+
+/// Value range drawn by a lane. Parameter ranges are known for the selected track only.
+fn target_range(
+    target: AutomationTarget,
+    selected: bool,
+    plugin_params: &[omni_shared::ParamInfo],
+    effect_devices: &[omni_shared::DeviceInfo],
+) -> (f32, f32) {
+    match target {
+        AutomationTarget::Volume => (0.0, 1.0),
+        AutomationTarget::Pan => (-1.0, 1.0),
+        AutomationTarget::Param { .. } => param_info(target, plugin_params, effect_devices)
+            .filter(|p| selected && p.max_value > p.min_value)
+            .map(|p| (p.min_value as f32, p.max_value as f32))
+            .unwrap_or((0.0, 1.0)),
+    }
+}
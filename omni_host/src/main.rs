@@ -2,10 +2,13 @@ use anyhow::Result;
 use omni_engine::{AudioEngine, EngineCommand};
 use crossbeam_channel::{unbounded, Sender, Receiver};
 use eframe::egui;
-use omni_shared::project::{Project, StepSequencerData};
+use omni_shared::project::{ClickSound, CountIn, LaunchQuantization, MetronomeSettings, Project, StepSequencerData, TimeRange, TimeSignature};
+use omni_shared::meter::MeterChange;
+use omni_shared::tempo::{TempoEvent, TempoMap};
 mod sequencer_ui;
 mod arrangement_ui;
 mod project_io;
+mod preferences;
 pub mod ui; // New UI module
 
 use project_io::{load_project_file, save_project_file};
@@ -19,6 +22,21 @@ pub struct ClipData {
     pub length: f64,
     pub use_sequencer: bool,
     pub step_sequencer: StepSequencerData,
+    pub follow_actions: omni_shared::project::FollowActions,
+}
+
+impl ClipData {
+    pub fn to_shared(&self) -> omni_shared::project::Clip {
+        omni_shared::project::Clip {
+            name: "Clip".to_string(),
+            notes: self.notes.clone(),
+            length: self.length,
+            color: [self.color.r(), self.color.g(), self.color.b()],
+            use_sequencer: self.use_sequencer,
+            step_sequencer: self.step_sequencer.clone(),
+            follow_actions: self.follow_actions,
+        }
+    }
 }
 
 impl Default for ClipData {
@@ -29,6 +47,7 @@ impl Default for ClipData {
             length: 4.0,
             use_sequencer: false,
             step_sequencer: StepSequencerData::default(),
+            follow_actions: Default::default(),
         }
     }
 }
@@ -36,16 +55,31 @@ impl Default for ClipData {
 pub struct TrackData {
     pub name: String,
     pub mute: bool,
+    pub solo: bool,
+    pub solo_safe: bool, // Returns/groups: keeps playing under solo
     pub volume: f32,
     pub pan: f32,
     pub clips: Vec<ClipData>,
-    pub active_clip: Option<usize>,
+    pub active_clip: Option<usize>, // Mirrors the engine (launches are quantized)
+    pub pending_launch: Option<omni_engine::launcher::Launch>, // Waiting for its boundary
     pub trigger_flash: f32,
     /// Valid MIDI notes for this track's plugin (None = all 128 notes valid)
     pub valid_notes: Option<Vec<i16>>,
     pub arrangement: omni_shared::project::TrackArrangement,
     pub parameters: HashMap<u32, f32>,
     pub plugin_path: String,
+    // Live Input
+    pub input: omni_shared::project::TrackInput,
+    pub record_arm: bool,
+    pub monitor: omni_shared::project::MonitorMode,
+    // Send/Return
+    pub kind: omni_shared::project::TrackKind,
+    pub sends: Vec<omni_shared::project::TrackSend>,
+    pub parent: Option<usize>, // Group track (None: master)
+    // Insert effects after the instrument
+    pub effects: Vec<omni_shared::project::Device>,
+    // Pads when the instrument is the built-in sampler
+    pub sampler_pads: Vec<omni_shared::sampler::SamplerPad>,
 }
 
 impl Default for TrackData {
@@ -53,15 +87,26 @@ impl Default for TrackData {
         Self {
             name: "New Track".to_string(),
             mute: false,
+            solo: false,
+            solo_safe: false,
             volume: 1.0,
             pan: 0.0,
             clips: vec![ClipData::default(); 8], // 8 Scenes
             active_clip: None,
+            pending_launch: None,
             trigger_flash: 0.0,
             valid_notes: None,
             arrangement: omni_shared::project::TrackArrangement::default(),
             parameters: HashMap::new(),
             plugin_path: String::new(),
+            input: omni_shared::project::TrackInput::None,
+            record_arm: false,
+            monitor: omni_shared::project::MonitorMode::Auto,
+            kind: omni_shared::project::TrackKind::Regular,
+            sends: Vec::new(),
+            parent: None,
+            effects: Vec::new(),
+            sampler_pads: Vec::new(),
         }
     }
 }
@@ -75,11 +120,20 @@ pub struct OmniApp {
     engine: Option<AudioEngine>,
     tracks: Vec<TrackData>,
     bpm: f32,
+    tempo_changes: Vec<TempoEvent>,
+    time_signature: TimeSignature,
+    meter_changes: Vec<MeterChange>,
+    loop_region: TimeRange,
+    punch: TimeRange,
+    metronome: MetronomeSettings,
+    launch_quantization: LaunchQuantization,
     last_step: usize,
     
     // Plugin Params (Transient for selected track)
     plugin_params: Vec<omni_shared::ParamInfo>,
     pending_params_rx: Option<(usize, Receiver<Vec<omni_shared::ParamInfo>>)>,
+    effect_devices: Vec<omni_shared::DeviceInfo>, // Per insert effect
+    pending_effect_devices_rx: Option<(usize, Receiver<Vec<omni_shared::DeviceInfo>>)>,
     
     selected_track: usize,
     last_selected_track: usize, // To detect changes
@@ -103,10 +157,15 @@ pub struct OmniApp {
     
     // Deferred Actions (RefCell to mutate from inside UI closures)
     deferred_track_remove: std::cell::RefCell<Option<usize>>,
+    deferred_solo: std::cell::RefCell<Option<(usize, bool)>>, // (track, exclusive)
     
     // Arrangement Logic
     arrangement_ui: ArrangementUI,
     show_arrangement_view: bool,
+
+    // User Preferences (audio device etc.)
+    preferences: preferences::Preferences,
+    audio_settings_ui: ui::audio_settings::AudioSettingsState,
 }
 
 impl OmniApp {
@@ -126,7 +185,13 @@ impl OmniApp {
             })
             .expect("Failed to spawn GC thread");
         
-        let engine = match AudioEngine::new(rx, drop_tx) {
+        // Open the preferred device, fall back to the system default if it is gone
+        let preferences = preferences::Preferences::load();
+        let backend = omni_engine::backend::CpalBackend::new(&preferences.audio).or_else(|e| {
+            eprintln!("[UI] Preferred audio device unavailable ({}), using default", e);
+            omni_engine::backend::CpalBackend::new_default()
+        });
+        let engine = match backend.and_then(|b| AudioEngine::with_backend(Box::new(b), rx, drop_tx)) {
             Ok(e) => Some(e),
             Err(e) => {
                 eprintln!("Failed to init engine: {}", e);
@@ -143,10 +208,19 @@ impl OmniApp {
             engine,
             tracks,
             bpm: 120.0,
+            tempo_changes: Vec::new(),
+            time_signature: TimeSignature::default(),
+            meter_changes: Vec::new(),
+            loop_region: TimeRange::default(),
+            punch: TimeRange::default(),
+            metronome: MetronomeSettings::default(),
+            launch_quantization: LaunchQuantization::default(),
             last_step: 0,
             
             plugin_params: Vec::new(),
             pending_params_rx: None,
+            effect_devices: Vec::new(),
+            pending_effect_devices_rx: None,
             
             selected_track: 0,
             last_selected_track: 9999, // Force initial update
@@ -163,19 +237,74 @@ impl OmniApp {
             last_touched_generation: 0,
             pending_last_touched_rx: None,
             deferred_track_remove: std::cell::RefCell::new(None),
+            deferred_solo: std::cell::RefCell::new(None),
             
             arrangement_ui: ArrangementUI::new(),
             show_arrangement_view: false,
+
+            preferences,
+            audio_settings_ui: ui::audio_settings::AudioSettingsState::default(),
+        }
+    }
+
+    /// Metronome settings popup. Returns true when a setting changed.
+    fn show_metronome_menu(&mut self, ui: &mut egui::Ui) -> bool {
+        let m = &mut self.metronome;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Volume:");
+            changed |= ui.add(egui::Slider::new(&mut m.volume, 0.0..=1.0)).changed();
+        });
+
+        ui.separator();
+        ui.label("Sound");
+        changed |= ui.radio_value(&mut m.sound, ClickSound::Synth, "Synth Click").changed();
+        let sample_text = match m.sound {
+            ClickSound::Sample(id) => format!("Sample (asset {})", id),
+            ClickSound::Synth => "Load Sample...".to_string(),
+        };
+        if ui.radio(matches!(m.sound, ClickSound::Sample(_)), sample_text).clicked() {
+            if let Some(path) = rfd::FileDialog::new().add_filter("WAV", &["wav"]).pick_file() {
+                match import_sample(&self.messenger, &path.to_string_lossy()) {
+                    Ok(id) => {
+                        m.sound = ClickSound::Sample(id);
+                        changed = true;
+                    }
+                    Err(e) => eprintln!("[UI] Failed to load click sample: {}", e),
+                }
+            }
+            ui.close();
         }
+
+        ui.separator();
+        ui.label("Before Recording");
+        changed |= ui.radio_value(&mut m.count_in, CountIn::Off, "Off").changed();
+        changed |= ui.radio_value(&mut m.count_in, CountIn::CountIn, "Count-In").changed();
+        changed |= ui.radio_value(&mut m.count_in, CountIn::PreRoll, "Pre-Roll").changed();
+        ui.add_enabled_ui(m.count_in != CountIn::Off, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Bars:");
+                changed |= ui.radio_value(&mut m.count_in_bars, 1, "1").changed();
+                changed |= ui.radio_value(&mut m.count_in_bars, 2, "2").changed();
+            });
+        });
+        changed
     }
 
     fn load_project(&mut self, path: String) {
         if let Some(ref engine) = self.engine {
-            if let Ok((shared_proj, nodes)) = load_project_file(&path, engine.get_sample_rate() as f64) {
+            if let Ok((shared_proj, nodes)) = load_project_file(&path, engine.get_sample_rate() as f64, &engine.audio_pool) {
                 let _ = self.messenger.send(EngineCommand::LoadProjectState(shared_proj.clone(), nodes));
                     
                 self.tracks.clear();
                 self.bpm = shared_proj.bpm;
+                self.tempo_changes = shared_proj.tempo_changes.clone();
+                self.time_signature = shared_proj.time_signature;
+                self.meter_changes = shared_proj.meter_changes.clone();
+                self.loop_region = shared_proj.loop_region;
+                self.punch = shared_proj.punch;
+                self.metronome = shared_proj.metronome;
+                self.launch_quantization = shared_proj.launch_quantization;
                 let _ = self.messenger.send(EngineCommand::SetBpm(self.bpm));
                 self.selected_track = 0;
                 self.last_selected_track = 9999; // Force refresh
@@ -187,11 +316,24 @@ impl OmniApp {
                         volume: shared_track.volume,
                         pan: shared_track.pan,
                         mute: shared_track.mute,
+                        solo: shared_track.solo,
+                        solo_safe: shared_track.solo_safe,
                         active_clip: shared_track.active_clip_index,
                         arrangement: shared_track.arrangement.clone(),
                         valid_notes: None,
                         parameters: shared_track.parameters.clone(),
                         plugin_path: shared_track.plugin_path.clone(),
+                        input: shared_track.input,
+                        record_arm: shared_track.record_arm,
+                        monitor: shared_track.monitor,
+                        kind: shared_track.kind,
+                        sends: shared_track.sends.clone(),
+                        parent: shared_track.parent,
+                        effects: shared_track.effects.clone(),
+                        sampler_pads: shared_track.plugin_state.as_deref()
+                            .filter(|_| shared_track.plugin_path == omni_engine::sampler::SAMPLER_PATH)
+                            .and_then(|data| omni_engine::sampler::decode_pads(data).ok())
+                            .unwrap_or_default(),
                         ..Default::default()
                     };
                         
@@ -201,6 +343,7 @@ impl OmniApp {
                             local_track.clips[c_idx].length = shared_clip.length;
                             local_track.clips[c_idx].use_sequencer = shared_clip.use_sequencer;
                             local_track.clips[c_idx].step_sequencer = shared_clip.step_sequencer.clone();
+                            local_track.clips[c_idx].follow_actions = shared_clip.follow_actions;
                         }
                     }
                     self.tracks.push(local_track);
@@ -229,6 +372,9 @@ impl eframe::App for OmniApp {
                 response_tx: tx 
             });
             self.pending_params_rx = Some((self.selected_track, rx));
+            let (tx, rx) = unbounded();
+            let _ = self.messenger.send(EngineCommand::GetDeviceParams { track_index: self.selected_track, response_tx: tx });
+            self.pending_effect_devices_rx = Some((self.selected_track, rx));
             
             // Note names handled separately elsewhere, but could trigger here too if logic requires (currently valid_notes is persistent in TrackData).
         }
@@ -243,18 +389,43 @@ impl eframe::App for OmniApp {
                  self.pending_params_rx = None;
              }
         }
+        if let Some((track_idx, ref rx)) = self.pending_effect_devices_rx {
+             if let Ok(devices) = rx.try_recv() {
+                 if track_idx == self.selected_track {
+                     self.effect_devices = devices;
+                 }
+                 self.pending_effect_devices_rx = None;
+             }
+        }
         
         // --- DEFERRED ACTIONS ---
         if let Some(track_idx) = self.deferred_track_remove.borrow_mut().take() {
              if track_idx < self.tracks.len() {
                  let _ = self.messenger.send(EngineCommand::RemoveTrack { track_index: track_idx });
-                 self.tracks.remove(track_idx);
+                 let removed = self.tracks.remove(track_idx);
+                 for track in self.tracks.iter_mut() {
+                     omni_shared::project::reindex_sends(&mut track.sends, track_idx);
+                     omni_shared::project::reindex_sidechains(&mut track.effects, track_idx);
+                     track.parent = omni_shared::project::reindex_parent(track.parent, track_idx, removed.parent);
+                 }
                  if self.selected_track >= self.tracks.len() && !self.tracks.is_empty() {
                      self.selected_track = self.tracks.len() - 1;
                  }
                  eprintln!("[UI] Deleted Track {}", track_idx);
              }
         }
+        if let Some((track_idx, exclusive)) = self.deferred_solo.borrow_mut().take() {
+             if track_idx < self.tracks.len() {
+                 let solo = !self.tracks[track_idx].solo;
+                 if solo && exclusive {
+                     for track in self.tracks.iter_mut() {
+                         track.solo = false;
+                     }
+                 }
+                 self.tracks[track_idx].solo = solo;
+                 let _ = self.messenger.send(EngineCommand::SetSolo { track_index: track_idx, solo, exclusive });
+             }
+        }
 
         let current_step = if let Some(ref engine) = self.engine {
             if self.is_playing {
@@ -271,6 +442,14 @@ impl eframe::App for OmniApp {
         if let Some(ref engine) = self.engine {
             self.current_step = engine.get_current_step();
             self.global_sample_pos = engine.get_sample_position() as u64;
+            // Launched/stopped clips switch on the engine's quantization boundary
+            for (t_idx, track) in self.tracks.iter_mut().enumerate() {
+                track.active_clip = engine.launch_state.playing(t_idx);
+                track.pending_launch = engine.launch_state.pending(t_idx);
+            }
+            if self.tracks.iter().any(|t| t.pending_launch.is_some()) {
+                ctx.request_repaint(); // Keep pending launches blinking
+            }
         } else {
             self.current_step = 0;
             self.global_sample_pos = 0;
@@ -420,6 +599,10 @@ impl eframe::App for OmniApp {
                     self.is_recording = !self.is_recording;
                     if self.is_recording {
                         let _ = self.messenger.send(EngineCommand::StartRecording);
+                        // Count-in / pre-roll starts the transport from the engine side
+                        if self.metronome.count_in != CountIn::Off {
+                            self.is_playing = true;
+                        }
                     } else {
                         let (tx, rx) = unbounded(); 
                         let _ = self.messenger.send(EngineCommand::StopRecording { response_tx: tx });
@@ -447,6 +630,35 @@ impl eframe::App for OmniApp {
                 if ui.add(egui::DragValue::new(&mut self.bpm).range(40.0..=240.0).speed(1.0)).changed() {
                     let _ = self.messenger.send(EngineCommand::SetBpm(self.bpm));
                 }
+
+                ui.add_space(crate::ui::theme::SPACING_MEDIUM);
+                ui.label("Quantize:");
+                let quantization_text = |q: LaunchQuantization| match q {
+                    LaunchQuantization::None => "None".to_string(),
+                    LaunchQuantization::Sixteenth => "1/16".to_string(),
+                    LaunchQuantization::Beat => "Beat".to_string(),
+                    LaunchQuantization::Bar => "1 Bar".to_string(),
+                    LaunchQuantization::Bars(n) => format!("{} Bars", n),
+                };
+                let before = self.launch_quantization;
+                egui::ComboBox::from_id_salt("launch_quantization")
+                    .selected_text(quantization_text(self.launch_quantization))
+                    .show_ui(ui, |ui| {
+                        for q in [
+                            LaunchQuantization::None,
+                            LaunchQuantization::Sixteenth,
+                            LaunchQuantization::Beat,
+                            LaunchQuantization::Bar,
+                            LaunchQuantization::Bars(2),
+                            LaunchQuantization::Bars(4),
+                            LaunchQuantization::Bars(8),
+                        ] {
+                            ui.selectable_value(&mut self.launch_quantization, q, quantization_text(q));
+                        }
+                    });
+                if self.launch_quantization != before {
+                    let _ = self.messenger.send(EngineCommand::SetLaunchQuantization(self.launch_quantization));
+                }
                 
                 ui.add_space(crate::ui::theme::SPACING_MEDIUM);
                 ui.label("Vol:");
@@ -454,6 +666,17 @@ impl eframe::App for OmniApp {
                     let _ = self.messenger.send(EngineCommand::SetVolume(self.master_volume));
                 }
 
+                ui.add_space(crate::ui::theme::SPACING_MEDIUM);
+                let click_resp = ui.toggle_value(&mut self.metronome.enabled, "Click")
+                    .on_hover_text("Metronome (right-click for settings)");
+                let mut metronome_changed = click_resp.changed();
+                click_resp.context_menu(|ui| {
+                    metronome_changed |= self.show_metronome_menu(ui);
+                });
+                if metronome_changed {
+                    let _ = self.messenger.send(EngineCommand::SetMetronome(self.metronome));
+                }
+
                 ui.separator();
 
                 // Project Controls - New
@@ -488,6 +711,13 @@ impl eframe::App for OmniApp {
                      self.last_selected_track = 9999;
                      self.selected_clip = 0;
                      self.bpm = 120.0;
+                     self.tempo_changes.clear();
+                     self.time_signature = TimeSignature::default();
+                     self.meter_changes.clear();
+                     self.loop_region = TimeRange::default();
+                     self.punch = TimeRange::default();
+                     self.metronome = MetronomeSettings::default();
+                     self.launch_quantization = LaunchQuantization::default();
                      let _ = self.messenger.send(EngineCommand::SetBpm(self.bpm));
                 }
                 
@@ -520,6 +750,7 @@ impl eframe::App for OmniApp {
                         }
                         
                         let mut track_plugin_states = Vec::new();
+                        let mut track_effect_states = Vec::new();
                         for (i, _track) in self.tracks.iter().enumerate() {
                             let (tx, rx) = unbounded();
                             let _ = self.messenger.send(EngineCommand::GetPluginState { track_index: i, response_tx: tx });
@@ -528,6 +759,9 @@ impl eframe::App for OmniApp {
                             } else {
                                 track_plugin_states.push(None);
                             }
+                            let (tx, rx) = unbounded();
+                            let _ = self.messenger.send(EngineCommand::GetDeviceStates { track_index: i, response_tx: tx });
+                            track_effect_states.push(rx.recv().unwrap_or_default());
                         }
                         
                         let shared_project = Project {
@@ -540,25 +774,35 @@ impl eframe::App for OmniApp {
                                     volume: t.volume,
                                     pan: t.pan,
                                     mute: t.mute,
-                                    solo: false,
-                                    clips: t.clips.iter().map(|c| omni_shared::project::Clip {
-                                        name: "Clip".to_string(),
-                                        notes: c.notes.clone(),
-                                        length: c.length,
-                                        color: [c.color.r(), c.color.g(), c.color.b()],
-                                        use_sequencer: c.use_sequencer,
-                                        step_sequencer: c.step_sequencer.clone(),
-                                    }).collect(),
+                                    solo: t.solo,
+                                    solo_safe: t.solo_safe,
+                                    clips: t.clips.iter().map(ClipData::to_shared).collect(),
                                     active_clip_index: t.active_clip,
                                     parameters: t.parameters.clone(),
                                     plugin_path: t.plugin_path.clone(),
                                     plugin_state: track_plugin_states[i].clone(),
                                     arrangement: t.arrangement.clone(),
+                                    input: t.input,
+                                    record_arm: t.record_arm,
+                                    monitor: t.monitor,
+                                    kind: t.kind,
+                                    sends: t.sends.clone(),
+                                    parent: t.parent,
+                                    effects: t.effects.iter().enumerate().map(|(e, device)| omni_shared::project::Device {
+                                        state: track_effect_states[i].get(e).cloned().flatten(),
+                                        ..device.clone()
+                                    }).collect(),
                                 }
                             }).collect(),
                             arrangement_mode: false,
-                            time_signature: omni_shared::project::TimeSignature::default(),
+                            time_signature: self.time_signature,
                             groove: omni_shared::project::GrooveTemplate::default(),
+                            tempo_changes: self.tempo_changes.clone(),
+                            meter_changes: self.meter_changes.clone(),
+                            loop_region: self.loop_region,
+                            punch: self.punch,
+                            metronome: self.metronome,
+                            launch_quantization: self.launch_quantization,
                         };
                         if let Err(e) = save_project_file(&shared_project, &path_str) {
                             eprintln!("Failed to save project: {}", e);
@@ -633,9 +877,59 @@ impl eframe::App for OmniApp {
                     self.show_arrangement_view = !self.show_arrangement_view;
                     let _ = self.messenger.send(EngineCommand::SetArrangementMode(self.show_arrangement_view));
                 }
+
+                ui.separator();
+
+                // Audio Settings
+                let (audio_rect, audio_resp) = ui.allocate_exact_size(egui::vec2(crate::ui::theme::BUTTON_WIDTH_SMALL, crate::ui::theme::BUTTON_HEIGHT_SMALL), egui::Sense::click());
+                if audio_resp.hovered() || self.audio_settings_ui.open {
+                    ui.painter().rect_filled(audio_rect, 2.0, crate::ui::theme::THEME.bg_light);
+                }
+                let audio_icon_color = crate::ui::theme::THEME.text_secondary;
+                let ac = audio_rect.center();
+                // Speaker shape
+                let cone_points = vec![
+                    ac + egui::vec2(-6.0, -2.5),
+                    ac + egui::vec2(-3.0, -2.5),
+                    ac + egui::vec2(1.0, -6.0),
+                    ac + egui::vec2(1.0, 6.0),
+                    ac + egui::vec2(-3.0, 2.5),
+                    ac + egui::vec2(-6.0, 2.5),
+                ];
+                ui.painter().add(egui::Shape::closed_line(cone_points, egui::Stroke::new(1.5, audio_icon_color)));
+                ui.painter().line_segment([ac + egui::vec2(4.0, -3.0), ac + egui::vec2(4.0, 3.0)], egui::Stroke::new(1.5, audio_icon_color));
+                ui.painter().line_segment([ac + egui::vec2(7.0, -5.0), ac + egui::vec2(7.0, 5.0)], egui::Stroke::new(1.5, audio_icon_color));
+
+                audio_resp.clone().on_hover_text("Audio Settings");
+                if audio_resp.clicked() {
+                    if self.audio_settings_ui.open {
+                        self.audio_settings_ui.open = false;
+                    } else {
+                        self.audio_settings_ui.open_with(&self.preferences.audio);
+                    }
+                }
             });
         });
 
+        // Audio Settings Window
+        if self.audio_settings_ui.open {
+            let current = self.engine.as_ref().map(|e| e.audio_settings()).unwrap_or_default();
+            if let Some(settings) = ui::audio_settings::show_audio_settings(ctx, &mut self.audio_settings_ui, &current) {
+                if let Some(ref mut engine) = self.engine {
+                    match engine.restart_audio(&settings) {
+                        Ok(()) => {
+                            self.audio_settings_ui.error = None;
+                            self.preferences.audio = settings;
+                            if let Err(e) = self.preferences.save() {
+                                eprintln!("[UI] Failed to save preferences: {}", e);
+                            }
+                        }
+                        Err(e) => self.audio_settings_ui.error = Some(e.to_string()),
+                    }
+                }
+            }
+        }
+
         // 2. BOTTOM PANEL: Details (Piano Roll / Devices) - RESIZABLE
         if !self.show_arrangement_view {
             egui::TopBottomPanel::bottom("detail_view")
@@ -646,15 +940,23 @@ impl eframe::App for OmniApp {
                 .show(ctx, |ui| {
                     // Fixed split: Device collapsible at top, then Piano Roll + Expressions
                     if self.selected_track < self.tracks.len() {
+                        let track_names: Vec<String> = self.tracks.iter().map(|t| t.name.clone()).collect();
                         let track = &mut self.tracks[self.selected_track];
                         
+                        let sample_rate = self.engine.as_ref().map_or(44100.0, |e| e.get_sample_rate() as f64);
+                        let audio_pool = self.engine.as_ref().map(|e| &e.audio_pool);
                         ui.collapsing("Device Parameters", |ui| {
                             ui::device::show_device_view(
                                 ui, 
                                 &self.plugin_params, 
-                                &mut track.parameters, 
+                                track,
+                                &track_names,
+                                &self.effect_devices,
+                                &mut self.pending_effect_devices_rx,
                                 &self.messenger, 
-                                self.selected_track
+                                self.selected_track,
+                                sample_rate,
+                                audio_pool,
                             );
                         });
                     }
@@ -699,7 +1001,7 @@ impl eframe::App for OmniApp {
                                         track.valid_notes.as_ref(),
                                         self.is_playing,
                                         self.global_sample_pos,
-                                        self.bpm,
+                                        &TempoMap::new(self.bpm, &self.tempo_changes),
                                         if let Some(ref e) = self.engine { e.get_sample_rate() as f32 } else { 44100.0 },
                                         &mut self.selected_sequencer_lane,
                                         newly_touched_param,
@@ -724,13 +1026,15 @@ impl eframe::App for OmniApp {
                      ui, 
                      &mut self.tracks, 
                      &self.messenger, 
-                     self.bpm, 
+                     &TempoMap::new(self.bpm, &self.tempo_changes), 
                      self.is_playing, 
                      self.global_sample_pos,
                      if let Some(ref e) = self.engine { e.get_sample_rate() as f32 } else { 44100.0 }, // Fix u32->f32
+                     self.engine.as_ref().map(|e| e.get_input_channels()).unwrap_or(0),
                      &mut self.selected_track,
                      &mut self.selected_clip,
                      &self.deferred_track_remove,
+                     &self.deferred_solo,
                      &mut self.pending_note_names_rx,
                  );
                  
@@ -742,11 +1046,19 @@ impl eframe::App for OmniApp {
                       ui, 
                       &mut self.tracks, 
                       self.bpm, 
+                      &mut self.tempo_changes, 
+                      &mut self.time_signature, 
+                      &mut self.meter_changes, 
+                      &mut self.loop_region, 
+                      &mut self.punch, 
                       &self.messenger, 
                       self.current_step, 
                       self.global_sample_pos, 
                       if let Some(ref e) = self.engine { e.get_sample_rate() as f32 } else { 44100.0 },
                       if let Some(ref e) = self.engine { Some(&e.audio_pool) } else { None },
+                      self.selected_track,
+                      &self.plugin_params,
+                      &self.effect_devices,
                   );
              }
         });
@@ -767,3 +1079,19 @@ fn main() -> Result<()> {
         Box::new(|_cc| Ok(Box::new(OmniApp::new(tx, rx)))),
     ).map_err(|e| anyhow::anyhow!("Eframe error: {}", e))
 }
+
+/// Decodes a WAV and hands it to the engine's pool. Returns the new asset id.
+fn import_sample(messenger: &Sender<EngineCommand>, path: &str) -> Result<u32> {
+    let mut pool = omni_engine::assets::AudioPool::new();
+    let id = pool.load_asset(path)?;
+    let asset = pool.get_asset(id).ok_or_else(|| anyhow::anyhow!("decoded asset missing"))?;
+    let (tx, rx) = unbounded();
+    messenger.send(EngineCommand::AddAsset {
+        name: path.to_string(),
+        data: asset.data.to_vec(),
+        channels: asset.channels,
+        source_sample_rate: asset.sample_rate as f32,
+        response_tx: tx,
+    })?;
+    rx.recv()?.map_err(|e| anyhow::anyhow!(e))
+}
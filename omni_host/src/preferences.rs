@@ -0,0 +1,51 @@
+//! User preferences (audio device selection, ...).
+//! Stored as JSON in the per-user config directory, independent of projects.
+
+use omni_shared::AudioSettings;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Preferences {
+    #[serde(default)]
+    pub audio: AudioSettings,
+}
+
+impl Preferences {
+    /// `<config dir>/omni/preferences.json`
+    pub fn path() -> Option<PathBuf> {
+        let base = if cfg!(target_os = "windows") {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            std::env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        };
+        base.map(|b| b.join("omni").join("preferences.json"))
+    }
+
+    /// Missing or unreadable preferences fall back to defaults.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("[Preferences] Ignoring invalid {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = Self::path().ok_or(anyhow::anyhow!("No config directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
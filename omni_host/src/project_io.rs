@@ -1,38 +1,83 @@
 use omni_shared::project::Project;
+use omni_engine::chain::DeviceChain;
 use omni_engine::nodes::AudioNode;
 use omni_engine::nodes::GainNode;
 use omni_engine::plugin_node::PluginNode;
+use omni_engine::sampler::{Sampler, SAMPLER_PATH};
+use omni_engine::assets::AudioPool;
+use arc_swap::ArcSwap;
+use std::sync::Arc;
 use std::fs::File;
 use std::io::Write;
 
-pub fn load_project_file(path: &str, sample_rate: f64) -> Result<(Project, Vec<Box<dyn AudioNode>>), anyhow::Error> {
+pub fn load_project_file(path: &str, sample_rate: f64, audio_pool: &Arc<ArcSwap<AudioPool>>) -> Result<(Project, Vec<Box<dyn AudioNode>>), anyhow::Error> {
     let content = std::fs::read_to_string(path)?;
-    let project: Project = serde_json::from_str(&content)?;
+    let mut project: Project = serde_json::from_str(&content)?;
+    project.upgrade_clip_positions(sample_rate);
     
     let mut nodes: Vec<Box<dyn AudioNode>> = Vec::new();
     
     eprintln!("[ProjectIO] Loading Plugins for project: {}", project.name);
 
     for track in &project.tracks {
-         if !track.plugin_path.is_empty() {
-             match PluginNode::new(&track.plugin_path, sample_rate) {
-                 Ok(n) => nodes.push(Box::new(n)),
-                 Err(e) => {
-                     eprintln!("[ProjectIO] Plugin Load Error: {}. Using GainNode.", e);
-                     nodes.push(Box::new(GainNode::new(1.0)));
-                 }
-             }
+         // The sampler gets its pads from the plugin state, like a plugin
+         let instrument: Box<dyn AudioNode> = if track.plugin_path == SAMPLER_PATH {
+             Box::new(Sampler::new(audio_pool.clone()))
          } else {
-             nodes.push(Box::new(GainNode::new(1.0)));
+             load_plugin_or_gain(&track.plugin_path, sample_rate)
+         };
+         let mut chain = DeviceChain::new(instrument);
+         for effect in &track.effects {
+             chain = chain.with_effect(load_plugin_or_gain(&effect.plugin_path, sample_rate), effect.bypass);
          }
+         nodes.push(Box::new(chain));
     }
     
     Ok((project, nodes))
 }
 
+/// Plugin at `path` (or a built-in effect), or a pass-through GainNode when there is none or it fails to load.
+fn load_plugin_or_gain(path: &str, sample_rate: f64) -> Box<dyn AudioNode> {
+    if path.is_empty() {
+        return Box::new(GainNode::new(1.0));
+    }
+    if let Some(node) = omni_engine::effects::create(path, sample_rate as f32) {
+        return node;
+    }
+    match PluginNode::new(path, sample_rate) {
+        Ok(n) => Box::new(n),
+        Err(e) => {
+            eprintln!("[ProjectIO] Plugin Load Error: {}. Using GainNode.", e);
+            Box::new(GainNode::new(1.0))
+        }
+    }
+}
+
 pub fn save_project_file(project: &Project, path: &str) -> Result<(), anyhow::Error> {
     let json = serde_json::to_string_pretty(project)?;
     let mut file = File::create(path)?;
     file.write_all(json.as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loads_sample_positioned_clips() {
+        // Saved before clip positions moved to beats: one clip at sample 48000, 90 BPM
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/legacy_arrangement.omni");
+        let pool = Arc::new(ArcSwap::from_pointee(AudioPool::new()));
+        let (project, nodes) = load_project_file(path, 48000.0, &pool).unwrap();
+        assert_eq!(nodes.len(), 1);
+
+        let clip = &project.tracks[0].arrangement.clips[0];
+        assert!((clip.start - 1.5).abs() < 1e-9, "one second at 90 BPM: {}", clip.start);
+        assert_eq!(clip.length.samples, 4800);
+        assert!(clip.legacy_start.is_none());
+
+        let saved = serde_json::to_string(&project).unwrap();
+        assert!(!saved.contains("start_time"), "saved again in beats");
+    }
+}
@@ -0,0 +1,163 @@
+use eframe::egui;
+use omni_engine::backend::{list_hosts, list_input_devices, list_output_devices, AudioDeviceInfo};
+use omni_shared::AudioSettings;
+
+const BUFFER_SIZES: [u32; 8] = [32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+/// Audio device dialog state. `pending` is edited until the user hits Apply.
+#[derive(Default)]
+pub struct AudioSettingsState {
+    pub open: bool,
+    pub pending: AudioSettings,
+    pub error: Option<String>,
+    hosts: Vec<String>,
+    devices: Vec<AudioDeviceInfo>,
+    input_devices: Vec<AudioDeviceInfo>,
+}
+
+impl AudioSettingsState {
+    /// Opens the dialog pre-filled with the saved preferences.
+    pub fn open_with(&mut self, settings: &AudioSettings) {
+        self.pending = settings.clone();
+        self.error = None;
+        self.open = true;
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        self.hosts = list_hosts();
+        self.devices = list_output_devices(self.pending.host.as_deref());
+        self.input_devices = list_input_devices(self.pending.host.as_deref());
+    }
+
+    fn selected_device(&self) -> Option<&AudioDeviceInfo> {
+        match &self.pending.device {
+            Some(name) => self.devices.iter().find(|d| &d.name == name),
+            None => self.devices.iter().find(|d| d.is_default),
+        }
+    }
+}
+
+/// Shows the audio settings window. Returns the settings to apply when the user clicks Apply.
+pub fn show_audio_settings(ctx: &egui::Context, state: &mut AudioSettingsState, current: &AudioSettings) -> Option<AudioSettings> {
+    let mut apply = None;
+    let mut open = state.open;
+    let mut host_changed = false;
+
+    egui::Window::new("Audio Settings")
+        .open(&mut open)
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("audio_settings_grid").num_columns(2).spacing([12.0, 6.0]).show(ui, |ui| {
+                // Host
+                ui.label("Driver:");
+                let host_text = state.pending.host.clone().unwrap_or_else(|| "Default".to_string());
+                egui::ComboBox::from_id_salt("audio_host").selected_text(host_text).show_ui(ui, |ui| {
+                    host_changed |= ui.selectable_value(&mut state.pending.host, None, "Default").changed();
+                    for host in state.hosts.clone() {
+                        host_changed |= ui.selectable_value(&mut state.pending.host, Some(host.clone()), host).changed();
+                    }
+                });
+                ui.end_row();
+
+                // Device
+                ui.label("Output:");
+                let device_text = state.pending.device.clone().unwrap_or_else(|| "Default".to_string());
+                egui::ComboBox::from_id_salt("audio_device").selected_text(device_text).width(220.0).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.pending.device, None, "Default");
+                    for device in &state.devices {
+                        ui.selectable_value(&mut state.pending.device, Some(device.name.clone()), &device.name);
+                    }
+                });
+                ui.end_row();
+
+                // Input Device (opened at the output rate)
+                ui.label("Input:");
+                ui.horizontal(|ui| {
+                    let input_text = if state.pending.disable_input {
+                        "Disabled".to_string()
+                    } else {
+                        state.pending.input_device.clone().unwrap_or_else(|| "Default".to_string())
+                    };
+                    ui.add_enabled_ui(!state.pending.disable_input, |ui| {
+                        egui::ComboBox::from_id_salt("audio_input_device").selected_text(input_text).width(160.0).show_ui(ui, |ui| {
+                            ui.selectable_value(&mut state.pending.input_device, None, "Default");
+                            for device in &state.input_devices {
+                                ui.selectable_value(&mut state.pending.input_device, Some(device.name.clone()), &device.name);
+                            }
+                        });
+                    });
+                    let mut enabled = !state.pending.disable_input;
+                    if ui.checkbox(&mut enabled, "On").changed() {
+                        state.pending.disable_input = !enabled;
+                    }
+                });
+                ui.end_row();
+
+                // Sample Rate (only rates the device reports)
+                let device = state.selected_device().cloned();
+                ui.label("Sample Rate:");
+                let rate_text = match state.pending.sample_rate {
+                    Some(r) => format!("{} Hz", r),
+                    None => "Device Default".to_string(),
+                };
+                egui::ComboBox::from_id_salt("audio_rate").selected_text(rate_text).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.pending.sample_rate, None, "Device Default");
+                    if let Some(ref d) = device {
+                        for &rate in &d.sample_rates {
+                            ui.selectable_value(&mut state.pending.sample_rate, Some(rate), format!("{} Hz", rate));
+                        }
+                    }
+                });
+                ui.end_row();
+
+                // Buffer Size (filtered by the device range if known)
+                ui.label("Buffer Size:");
+                let buffer_text = match state.pending.buffer_size {
+                    Some(b) => format!("{} samples", b),
+                    None => format!("Default ({})", omni_engine::backend::DEFAULT_BUFFER_SIZE),
+                };
+                egui::ComboBox::from_id_salt("audio_buffer").selected_text(buffer_text).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.pending.buffer_size, None, "Default");
+                    let range = device.as_ref().and_then(|d| d.buffer_size_range);
+                    for size in BUFFER_SIZES {
+                        if range.is_none_or(|(min, max)| (min..=max).contains(&size)) {
+                            ui.selectable_value(&mut state.pending.buffer_size, Some(size), format!("{} samples", size));
+                        }
+                    }
+                });
+                ui.end_row();
+            });
+
+            ui.separator();
+            ui.label(egui::RichText::new(format!(
+                "Running: {} @ {} Hz, {} samples",
+                current.device.as_deref().unwrap_or("-"),
+                current.sample_rate.unwrap_or(0),
+                current.buffer_size.map(|b| b.to_string()).unwrap_or_else(|| "default".to_string()),
+            )).small().color(crate::ui::theme::THEME.text_secondary));
+
+            if let Some(ref err) = state.error {
+                ui.colored_label(crate::ui::theme::THEME.accent_warn, err);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Apply").clicked() {
+                    apply = Some(state.pending.clone());
+                }
+                if ui.button("Rescan").clicked() {
+                    state.refresh();
+                }
+            });
+        });
+
+    if host_changed {
+        // Device names are per host
+        state.pending.device = None;
+        state.pending.input_device = None;
+        state.refresh();
+    }
+    state.open = open;
+    apply
+}
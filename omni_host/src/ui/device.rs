@@ -1,70 +1,324 @@
 use eframe::egui;
-use crossbeam_channel::Sender;
+use crossbeam_channel::{Receiver, Sender};
 use omni_engine::EngineCommand;
+use omni_shared::project::{Device, Sidechain};
+use omni_shared::sampler::SamplerPad;
+use omni_engine::sampler::{Sampler, SAMPLER_PATH};
+use crate::TrackData;
+use crate::ui::theme;
 use std::collections::HashMap;
 
+/// The selected track's chain, left to right: instrument, then insert effects.
+#[allow(clippy::too_many_arguments)]
 pub fn show_device_view(
     ui: &mut egui::Ui,
     plugin_params: &[omni_shared::ParamInfo],
-    param_states: &mut HashMap<u32, f32>,
+    track: &mut TrackData,
+    track_names: &[String],
+    effect_devices: &[omni_shared::DeviceInfo],
+    pending_effect_devices: &mut Option<(usize, Receiver<Vec<omni_shared::DeviceInfo>>)>,
     sender: &Sender<EngineCommand>,
     selected_track_idx: usize,
+    engine_sample_rate: f64,
+    audio_pool: Option<&std::sync::Arc<arc_swap::ArcSwap<omni_engine::assets::AudioPool>>>,
 ) {
-    if plugin_params.is_empty() {
-        return;
-    }
-
-    ui.heading("Device View: CLAP Plugin");
     ui.horizontal(|ui| {
         if ui.button(egui::RichText::new("KILL PLUGIN (TEST)").color(egui::Color32::RED)).clicked() {
             let _ = sender.send(EngineCommand::SimulateCrash { track_index: 0 }); // Hardcoded index 0 in original too
         }
     });
 
+    // Chain edits apply after drawing (they shift effect positions)
+    let mut remove = None;
+    let mut swap = None;
+
     egui::ScrollArea::horizontal()
         .id_salt("device_view_scroll")
         .show(ui, |ui| {
-        ui.horizontal(|ui| {
-            // Limit to first 16 params for UI safety in prototype
-            for param in plugin_params.iter().take(16) {
-                ui.push_id(param.id, |ui| {
-                    ui.group(|ui| {
-                        ui.set_width(100.0);
-                        ui.vertical_centered(|ui| {
-                            ui.label(&param.name);
-                            // Simple detection for boolean params: Stepped + Min 0 + Max 1
-                            let is_stepped = (param.flags & 1) != 0;
-                            let is_bool = is_stepped && param.min_value == 0.0 && param.max_value == 1.0;
-
-                            // Get current value from local map, fallback to default
-                            let current_val = param_states.get(&param.id).copied().unwrap_or(param.default_value as f32);
-                            let mut val = current_val;
+        ui.horizontal_top(|ui| {
+            // Instrument
+            ui.group(|ui| {
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new(&track.name).strong());
+                    if track.plugin_path == SAMPLER_PATH {
+                        sampler_pads(ui, track, sender, selected_track_idx);
+                        return;
+                    }
+                    param_controls(ui, plugin_params, &mut track.parameters, |id, value| {
+                        let _ = sender.send(EngineCommand::SetPluginParam { track_index: selected_track_idx, id, value });
+                    });
+                    let use_sampler = ui.small_button("Use Sampler").on_hover_text("Replace the instrument with the built-in sampler").clicked();
+                    if let (true, Some(pool)) = (use_sampler, audio_pool) {
+                        let node = Sampler::new(pool.clone()).with_pads(track.sampler_pads.clone());
+                        let _ = sender.send(EngineCommand::ReplaceTrackNode {
+                            track_index: selected_track_idx,
+                            node: Box::new(node),
+                            name: "Sampler".to_string(),
+                            plugin_path: SAMPLER_PATH.to_string(),
+                        });
+                        track.name = "Sampler".to_string();
+                        track.plugin_path = SAMPLER_PATH.to_string();
+                        track.valid_notes = pad_keys(&track.sampler_pads);
+                    }
+                });
+            });
 
-                            if is_bool {
-                                let mut bool_val = val > 0.5;
-                                if ui.checkbox(&mut bool_val, "").changed() {
-                                    val = if bool_val { 1.0 } else { 0.0 };
-                                    param_states.insert(param.id, val); // Update local state
-                                    let _ = sender.send(EngineCommand::SetPluginParam { 
-                                        track_index: selected_track_idx, 
-                                        id: param.id, 
-                                        value: val 
-                                    });
+            // Insert effects
+            let count = track.effects.len();
+            for (position, device) in track.effects.iter_mut().enumerate() {
+                ui.label("→");
+                ui.push_id(("effect", position), |ui| {
+                    ui.group(|ui| {
+                        ui.vertical(|ui| {
+                            ui.horizontal(|ui| {
+                                let on = !device.bypass;
+                                let fill = if on { theme::THEME.accent_secondary } else { theme::COLOR_MUTE_INACTIVE };
+                                if ui.add(egui::Button::new("⏻").fill(fill)).on_hover_text("Bypass").clicked() {
+                                    device.bypass = on;
+                                    let _ = sender.send(EngineCommand::SetDeviceBypass { track_index: selected_track_idx, position, bypass: device.bypass });
+                                }
+                                ui.label(egui::RichText::new(&device.name).strong());
+                            });
+                            ui.horizontal(|ui| {
+                                // Built-in effects have no editor window
+                                let has_gui = !device.plugin_path.starts_with(omni_engine::effects::NATIVE_PREFIX);
+                                if has_gui && ui.small_button("GUI").clicked() {
+                                    let _ = sender.send(EngineCommand::OpenDeviceEditor { track_index: selected_track_idx, position });
+                                }
+                                if ui.add_enabled(position > 0, egui::Button::new("◀").small()).clicked() {
+                                    swap = Some((position, position - 1));
                                 }
-                            } else {
-                                if ui.add(egui::Slider::new(&mut val, param.min_value as f32..=param.max_value as f32).show_value(false)).changed() {
-                                    param_states.insert(param.id, val); // Update local state
-                                    let _ = sender.send(EngineCommand::SetPluginParam { 
-                                        track_index: selected_track_idx, 
-                                        id: param.id, 
-                                        value: val 
-                                    });
+                                if ui.add_enabled(position + 1 < count, egui::Button::new("▶").small()).clicked() {
+                                    swap = Some((position, position + 1));
                                 }
+                                if ui.small_button("✕").clicked() {
+                                    remove = Some(position);
+                                }
+                            });
+                            let info = effect_devices.get(position);
+                            if info.is_some_and(|info| info.sidechain) {
+                                sidechain_controls(ui, device, track_names, selected_track_idx, position, sender);
                             }
+                            let params = info.map(|info| info.params.as_slice()).unwrap_or_default();
+                            param_controls(ui, params, &mut device.parameters, |id, value| {
+                                let _ = sender.send(EngineCommand::SetDeviceParam { track_index: selected_track_idx, position, id, value });
+                            });
                         });
                     });
                 });
             }
+
+            // Append a built-in or CLAP effect
+            let mut picked = None;
+            ui.menu_button("+ FX", |ui| {
+                for (path, name) in omni_engine::effects::NATIVE_EFFECTS {
+                    if ui.button(name).clicked() {
+                        picked = Some((name.to_string(), path.to_string()));
+                        ui.close();
+                    }
+                }
+                ui.separator();
+                if ui.button("CLAP...").clicked() {
+                    ui.close();
+                    picked = rfd::FileDialog::new().add_filter("CLAP", &["clap"]).pick_file().map(|path| {
+                        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Effect").to_string();
+                        (name, path.to_string_lossy().to_string())
+                    });
+                }
+            });
+            if let Some((name, plugin_path)) = picked {
+                let device = Device { name, plugin_path: plugin_path.clone(), ..Default::default() };
+                track.effects.push(device.clone());
+
+                // Load off the UI thread, then refresh the effect parameters
+                let (tx, rx) = crossbeam_channel::bounded(1);
+                *pending_effect_devices = Some((selected_track_idx, rx));
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let node: Box<dyn omni_engine::nodes::AudioNode> = match omni_engine::effects::create(&plugin_path, engine_sample_rate as f32) {
+                        Some(node) => node,
+                        None => match omni_engine::plugin_node::PluginNode::new(&plugin_path, engine_sample_rate) {
+                            Ok(node) => Box::new(node),
+                            Err(e) => {
+                                eprintln!("[BG] Error loading effect: {}. Fallback to GainNode.", e);
+                                Box::new(omni_engine::nodes::GainNode::new(1.0))
+                            }
+                        },
+                    };
+                    if node.plugin_kind() == omni_shared::PluginKind::Instrument {
+                        eprintln!("[BG] {} is an instrument; it will replace the audio in front of it", plugin_path);
+                    }
+                    let _ = sender.send(EngineCommand::InsertDevice { track_index: selected_track_idx, position: usize::MAX, node, device });
+                    let _ = sender.send(EngineCommand::GetDeviceParams { track_index: selected_track_idx, response_tx: tx });
+                });
+            }
         });
     });
+
+    let refresh = remove.is_some() || swap.is_some();
+    if let Some(position) = remove {
+        track.effects.remove(position);
+        track.arrangement.remap_device_lanes(|p| match p.cmp(&position) {
+            std::cmp::Ordering::Less => Some(p),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(p - 1),
+        });
+        let _ = sender.send(EngineCommand::RemoveDevice { track_index: selected_track_idx, position });
+    }
+    if let Some((from, to)) = swap {
+        track.effects.swap(from, to);
+        track.arrangement.remap_device_lanes(|p| Some(if p == from { to } else if p == to { from } else { p }));
+        let _ = sender.send(EngineCommand::MoveDevice { track_index: selected_track_idx, from, to });
+    }
+    if refresh {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = sender.send(EngineCommand::GetDeviceParams { track_index: selected_track_idx, response_tx: tx });
+        *pending_effect_devices = Some((selected_track_idx, rx));
+    }
+}
+
+/// Pads of the built-in sampler. Edits resend the whole pad list as the plugin state.
+fn sampler_pads(ui: &mut egui::Ui, track: &mut TrackData, sender: &Sender<EngineCommand>, track_idx: usize) {
+    let mut changed = false;
+    let mut remove = None;
+    for (idx, pad) in track.sampler_pads.iter_mut().enumerate() {
+        ui.push_id(("pad", idx), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(&pad.name).small());
+                changed |= ui.add(egui::DragValue::new(&mut pad.key).range(0..=127).prefix("Key ")).changed();
+                changed |= ui.add(egui::DragValue::new(&mut pad.tune).range(-24.0..=24.0).speed(0.1).suffix(" st")).changed();
+                let mut choke = pad.choke_group.unwrap_or(0);
+                if ui.add(egui::DragValue::new(&mut choke).range(0..=16).prefix("Choke ")).on_hover_text("0: none").changed() {
+                    pad.choke_group = (choke > 0).then_some(choke);
+                    changed = true;
+                }
+                changed |= ui.checkbox(&mut pad.one_shot, "One-shot").changed();
+                if ui.small_button("✕").clicked() {
+                    remove = Some(idx);
+                }
+            });
+            ui.horizontal(|ui| {
+                let env = &mut pad.envelope;
+                changed |= ui.add(egui::DragValue::new(&mut env.attack).range(0.0..=5.0).speed(0.001).prefix("A ")).changed();
+                changed |= ui.add(egui::DragValue::new(&mut env.decay).range(0.0..=5.0).speed(0.001).prefix("D ")).changed();
+                changed |= ui.add(egui::DragValue::new(&mut env.sustain).range(0.0..=1.0).speed(0.01).prefix("S ")).changed();
+                changed |= ui.add(egui::DragValue::new(&mut env.release).range(0.0..=10.0).speed(0.001).prefix("R ")).changed();
+            });
+        });
+    }
+
+    // New pad on the key after the highest one
+    let picked = if ui.button("+ Pad").on_hover_text("Add a WAV on the next key").clicked() {
+        rfd::FileDialog::new().add_filter("WAV", &["wav"]).pick_file()
+    } else {
+        None
+    };
+    if let Some(path) = picked {
+        match crate::import_sample(sender, &path.to_string_lossy()) {
+            Ok(asset_id) => {
+                let key = track.sampler_pads.iter().map(|p| p.key.saturating_add(1)).max().unwrap_or(36).min(127);
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Pad").to_string();
+                track.sampler_pads.push(SamplerPad::new(name, key, asset_id));
+                changed = true;
+            }
+            Err(e) => eprintln!("[UI] Failed to load sample: {}", e),
+        }
+    }
+    if let Some(idx) = remove {
+        track.sampler_pads.remove(idx);
+        changed = true;
+    }
+
+    if changed {
+        let data = omni_engine::sampler::encode_pads(&track.sampler_pads);
+        let _ = sender.send(EngineCommand::SetPluginState { track_index: track_idx, data });
+        track.valid_notes = pad_keys(&track.sampler_pads);
+    }
+}
+
+/// Piano roll rows for the sampler: one per pad key.
+fn pad_keys(pads: &[SamplerPad]) -> Option<Vec<i16>> {
+    let mut keys: Vec<i16> = pads.iter().map(|p| p.key as i16).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    (!keys.is_empty()).then_some(keys)
+}
+
+/// Key source for a device with an auxiliary input: any other track, post-fader unless "Pre" is lit.
+fn sidechain_controls(
+    ui: &mut egui::Ui,
+    device: &mut Device,
+    track_names: &[String],
+    track_idx: usize,
+    position: usize,
+    sender: &Sender<EngineCommand>,
+) {
+    ui.horizontal(|ui| {
+        let mut source = device.sidechain.map(|key| key.source);
+        let mut pre_fader = device.sidechain.is_some_and(|key| key.pre_fader);
+        let source_name = source.and_then(|s| track_names.get(s)).map_or("None", String::as_str);
+        egui::ComboBox::from_id_salt("sidechain_source")
+            .selected_text(format!("Key: {}", source_name))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut source, None, "None");
+                for (idx, name) in track_names.iter().enumerate().filter(|&(idx, _)| idx != track_idx) {
+                    ui.selectable_value(&mut source, Some(idx), name);
+                }
+            });
+        if source.is_some() {
+            let pre_fill = if pre_fader { theme::THEME.accent_secondary } else { theme::COLOR_MUTE_INACTIVE };
+            if ui.add(egui::Button::new(egui::RichText::new("Pre").small()).fill(pre_fill))
+                .on_hover_text("Pre-fader key")
+                .clicked()
+            {
+                pre_fader = !pre_fader;
+            }
+        }
+        let sidechain = source.map(|source| Sidechain { source, pre_fader });
+        if sidechain != device.sidechain {
+            device.sidechain = sidechain;
+            let _ = sender.send(EngineCommand::SetDeviceSidechain { track_index: track_idx, position, sidechain });
+        }
+    });
+}
+
+/// Knobs for one device's parameters; `on_change(id, value)` forwards edits.
+fn param_controls(
+    ui: &mut egui::Ui,
+    params: &[omni_shared::ParamInfo],
+    param_states: &mut HashMap<u32, f32>,
+    mut on_change: impl FnMut(u32, f32),
+) {
+    ui.horizontal(|ui| {
+        // Limit to first 16 params for UI safety in prototype
+        for param in params.iter().take(16) {
+            ui.push_id(param.id, |ui| {
+                ui.group(|ui| {
+                    ui.set_width(100.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(&param.name);
+                        // Simple detection for boolean params: Stepped + Min 0 + Max 1
+                        let is_stepped = (param.flags & 1) != 0;
+                        let is_bool = is_stepped && param.min_value == 0.0 && param.max_value == 1.0;
+
+                        // Get current value from local map, fallback to default
+                        let mut val = param_states.get(&param.id).copied().unwrap_or(param.default_value as f32);
+
+                        let changed = if is_bool {
+                            let mut bool_val = val > 0.5;
+                            let changed = ui.checkbox(&mut bool_val, "").changed();
+                            val = if bool_val { 1.0 } else { 0.0 };
+                            changed
+                        } else {
+                            ui.add(egui::Slider::new(&mut val, param.min_value as f32..=param.max_value as f32).show_value(false)).changed()
+                        };
+                        if changed {
+                            param_states.insert(param.id, val); // Update local state
+                            on_change(param.id, val);
+                        }
+                    });
+                });
+            });
+        }
+    });
 }
@@ -2,6 +2,7 @@ use eframe::egui;
 use crossbeam_channel::Sender;
 use omni_engine::EngineCommand;
 use crate::TrackData;
+use omni_shared::project::{MonitorMode, TrackInput, TrackKind, TrackSend};
 use crate::ui::widgets::knob_ui;
 use crate::ui::theme;
 
@@ -89,18 +90,49 @@ pub fn show_track_strip(
     });
 }
 
+/// Where tracks can send to and route their output, gathered once per frame.
+pub struct RouteTargets {
+    pub returns: Vec<(usize, String)>,
+    pub groups: Vec<(usize, String)>,
+    parents: Vec<Option<usize>>,
+    kinds: Vec<TrackKind>,
+}
+
+impl RouteTargets {
+    pub fn new(tracks: &[TrackData]) -> Self {
+        let named = |kind: TrackKind| tracks.iter().enumerate()
+            .filter(|(_, t)| t.kind == kind)
+            .map(|(i, t)| (i, t.name.clone()))
+            .collect();
+        Self {
+            returns: named(TrackKind::Return),
+            groups: named(TrackKind::Group),
+            parents: tracks.iter().map(|t| t.parent).collect(),
+            kinds: tracks.iter().map(|t| t.kind).collect(),
+        }
+    }
+
+    fn can_route(&self, track: usize, group: usize) -> bool {
+        omni_shared::project::can_route_to_group(&self.parents, &self.kinds, track, group)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn show_track_controls(
     ui: &mut egui::Ui,
     track: &mut TrackData,
     track_idx: usize,
     sender: &Sender<EngineCommand>,
     deferred_track_remove: &std::cell::RefCell<Option<usize>>,
+    deferred_solo: &std::cell::RefCell<Option<(usize, bool)>>,
     pending_note_names_state: &mut Option<(usize, crossbeam_channel::Receiver<(String, Vec<omni_shared::NoteNameInfo>)>)>,
     engine_sample_rate: f32,
+    input_channels: u32,
+    targets: &RouteTargets,
 ) {
-     // A. Header Row: Load | GUI | Mute | Stop | Delete
+     // A. Header Row: Load | GUI | Mute | Solo | Stop | Delete
     ui.horizontal(|ui| {
-        let btn_w = (ui.available_width() - 16.0) / 5.0; // 5 buttons now
+        let btn_w = (ui.available_width() - 20.0) / 6.0; // 6 buttons now
         let btn_size = egui::vec2(btn_w, theme::BUTTON_HEIGHT_SMALL);
         
         // Load
@@ -160,9 +192,18 @@ pub fn show_track_controls(
             let _ = sender.send(EngineCommand::SetMute { track_index: track_idx, muted: track.mute });
         }
 
+        // Solo: exclusive, Ctrl/Cmd-click adds to the soloed tracks (applied after drawing)
+        let solo_color = if track.solo { theme::THEME.accent_warn } else { theme::COLOR_MUTE_INACTIVE };
+        if ui.add_sized(btn_size, egui::Button::new("S").fill(solo_color))
+            .on_hover_text("Solo (Ctrl/Cmd: add)")
+            .clicked()
+        {
+            let exclusive = !ui.input(|i| i.modifiers.command);
+            *deferred_solo.borrow_mut() = Some((track_idx, exclusive));
+        }
+
         // Stop
         if ui.add_sized(btn_size, egui::Button::new("■")).clicked() {
-            track.active_clip = None;
             let _ = sender.send(EngineCommand::StopTrack { track_index: track_idx });
         }
 
@@ -207,4 +248,130 @@ pub fn show_track_controls(
     });
 
     ui.add_space(theme::SPACING_MEDIUM);
+
+    // Output: Master or a group track
+    let group_name = |idx: Option<usize>| match idx {
+        Some(g) => targets.groups.iter().find(|(i, _)| *i == g).map_or("?".to_string(), |(_, n)| n.clone()),
+        None => "Master".to_string(),
+    };
+    let mut parent = track.parent;
+    egui::ComboBox::from_id_salt(("track_output", track_idx))
+        .width(ui.available_width() - theme::SPACING_SMALL)
+        .selected_text(format!("→ {}", group_name(parent)))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut parent, None, "Master");
+            for (group_idx, name) in &targets.groups {
+                if targets.can_route(track_idx, *group_idx) {
+                    ui.selectable_value(&mut parent, Some(*group_idx), name);
+                }
+            }
+        });
+    if parent != track.parent {
+        track.parent = parent;
+        let _ = sender.send(EngineCommand::SetTrackParent { track_index: track_idx, parent });
+    }
+
+    // Returns and groups can stay audible while other tracks are soloed
+    if track.kind != TrackKind::Regular {
+        let safe_fill = if track.solo_safe { theme::THEME.accent_secondary } else { theme::COLOR_MUTE_INACTIVE };
+        if ui.add(egui::Button::new(egui::RichText::new("Solo Safe").small()).fill(safe_fill)).clicked() {
+            track.solo_safe = !track.solo_safe;
+            let _ = sender.send(EngineCommand::SetSoloSafe { track_index: track_idx, safe: track.solo_safe });
+        }
+    }
+
+    ui.add_space(theme::SPACING_MEDIUM);
+
+    // Return tracks only take sends: no input, no sends of their own
+    if track.kind == TrackKind::Return {
+        return;
+    }
+
+    // C. Input Row: Arm | Input | Monitor (groups play what's routed into them)
+    if track.kind == TrackKind::Regular {
+        ui.horizontal(|ui| {
+            let arm_color = if track.record_arm { theme::THEME.accent_warn } else { theme::COLOR_MUTE_INACTIVE };
+            if ui.add_sized(egui::vec2(theme::BUTTON_HEIGHT_SMALL, theme::BUTTON_HEIGHT_SMALL), egui::Button::new("●").fill(arm_color))
+                .on_hover_text("Record Arm")
+                .clicked()
+            {
+                track.record_arm = !track.record_arm;
+                let _ = sender.send(EngineCommand::SetRecordArm { track_index: track_idx, armed: track.record_arm });
+            }
+
+            let mut input = track.input;
+            egui::ComboBox::from_id_salt(("track_input", track_idx))
+                .width(ui.available_width() - theme::SPACING_SMALL)
+                .selected_text(input_label(input))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut input, TrackInput::None, "No Input");
+                    for ch in 0..input_channels as u16 {
+                        ui.selectable_value(&mut input, TrackInput::Mono(ch), input_label(TrackInput::Mono(ch)));
+                    }
+                    for ch in (0..input_channels.saturating_sub(1) as u16).step_by(2) {
+                        ui.selectable_value(&mut input, TrackInput::Stereo(ch), input_label(TrackInput::Stereo(ch)));
+                    }
+                });
+            if input != track.input {
+                track.input = input;
+                let _ = sender.send(EngineCommand::SetTrackInput { track_index: track_idx, input });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let btn_w = (ui.available_width() - 8.0) / 3.0;
+            for (mode, label) in [(MonitorMode::In, "In"), (MonitorMode::Auto, "Auto"), (MonitorMode::Off, "Off")] {
+                let fill = if track.monitor == mode { theme::THEME.accent_secondary } else { theme::COLOR_MUTE_INACTIVE };
+                if ui.add_sized(egui::vec2(btn_w, theme::BUTTON_HEIGHT_SMALL), egui::Button::new(egui::RichText::new(label).small()).fill(fill))
+                    .on_hover_text("Monitor")
+                    .clicked()
+                {
+                    track.monitor = mode;
+                    let _ = sender.send(EngineCommand::SetMonitorMode { track_index: track_idx, mode });
+                }
+            }
+        });
+
+        ui.add_space(theme::SPACING_MEDIUM);
+    }
+
+    // D. Sends: one knob per return, post-fader unless "Pre" is lit
+    let mut sends_changed = false;
+    for (return_idx, return_name) in &targets.returns {
+        ui.horizontal(|ui| {
+            let existing = track.sends.iter().position(|s| s.target == *return_idx);
+            let mut send = existing.map(|i| track.sends[i])
+                .unwrap_or(TrackSend { target: *return_idx, level: 0.0, pre_fader: false });
+
+            ui.label(egui::RichText::new(return_name).small().weak());
+            let mut changed = knob_ui(ui, &mut send.level, 0.0..=1.0).changed();
+            let pre_fill = if send.pre_fader { theme::THEME.accent_secondary } else { theme::COLOR_MUTE_INACTIVE };
+            if ui.add(egui::Button::new(egui::RichText::new("Pre").small()).fill(pre_fill))
+                .on_hover_text("Pre-fader send")
+                .clicked()
+            {
+                send.pre_fader = !send.pre_fader;
+                changed = true;
+            }
+            if changed {
+                match existing {
+                    Some(i) => track.sends[i] = send,
+                    None => track.sends.push(send),
+                }
+                sends_changed = true;
+            }
+        });
+    }
+    if sends_changed {
+        let _ = sender.send(EngineCommand::SetTrackSends { track_index: track_idx, sends: track.sends.clone() });
+    }
+}
+
+/// "In 1", "In 1/2" (1-based like the hardware labels).
+fn input_label(input: TrackInput) -> String {
+    match input {
+        TrackInput::None => "No Input".to_string(),
+        TrackInput::Mono(ch) => format!("In {}", ch + 1),
+        TrackInput::Stereo(ch) => format!("In {}/{}", ch + 1, ch + 2),
+    }
 }
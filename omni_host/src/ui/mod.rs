@@ -3,5 +3,6 @@ pub mod widgets;
 pub mod mixer;
 pub mod session;
 pub mod device;
+pub mod audio_settings;
 pub mod piano_roll;
 pub mod note_expressions;
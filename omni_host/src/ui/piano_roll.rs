@@ -2,6 +2,7 @@ use eframe::egui;
 use crossbeam_channel::Sender;
 use omni_engine::EngineCommand;
 use crate::ClipData;
+use omni_shared::tempo::TempoMap;
 
 use crate::sequencer_ui::SequencerUI;
 use lazy_static::lazy_static;
@@ -314,7 +315,7 @@ pub fn show_piano_roll(
     valid_notes: Option<&Vec<i16>>,
     is_playing: bool,
     global_sample_pos: u64,
-    bpm: f32,
+    tempo: &TempoMap,
     sample_rate: f32,
     selected_sequencer_lane: &mut usize,
     newly_touched_param: Option<u32>,
@@ -323,7 +324,7 @@ pub fn show_piano_roll(
 ) {
         ui.heading(format!("Piano Roll: {} - Clip {}", track_name, clip_idx));
         
-        let samples_per_beat = (sample_rate * 60.0) / bpm;
+        let current_beat_global = tempo.beat_at_sample(global_sample_pos as f64, sample_rate as f64);
 
         
         // TOGGLE MODE
@@ -346,10 +347,7 @@ pub fn show_piano_roll(
 
         if clip.use_sequencer {
             
-            let current_beat = if is_playing {
-                    let samples_per_beat = (sample_rate * 60.0) / bpm;
-                    Some(global_sample_pos as f64 / samples_per_beat as f64)
-             } else { None };
+            let current_beat = if is_playing { Some(current_beat_global) } else { None };
 
             if SequencerUI::show(
                 ui, 
@@ -1185,7 +1183,6 @@ pub fn show_piano_roll(
         
         // 7. Draw Playhead
         if is_playing {
-            let current_beat_global = global_sample_pos as f64 / samples_per_beat as f64;
             let loop_beat = current_beat_global % clip.length;
             
             let playhead_x = grid_left + (loop_beat as f32 * beat_width) - state.scroll_x;
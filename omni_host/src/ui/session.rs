@@ -1,7 +1,10 @@
 use eframe::egui;
 use crossbeam_channel::Sender;
 use omni_engine::EngineCommand;
+use omni_engine::launcher::Launch;
 use crate::TrackData;
+use omni_shared::project::{FollowAction, FollowActions, FollowTime, TrackKind};
+use omni_shared::tempo::TempoMap;
 use crate::ui::theme;
 use crate::ui::mixer;
 
@@ -9,13 +12,15 @@ pub fn show_matrix(
     ui: &mut egui::Ui,
     tracks: &mut Vec<TrackData>,
     sender: &Sender<EngineCommand>,
-    bpm: f32,
+    tempo: &TempoMap,
     is_playing: bool,
     global_sample_pos: u64,
     engine_sample_rate: f32,
+    input_channels: u32,
     selected_track_idx: &mut usize,
     selected_clip_idx: &mut usize,
     deferred_track_remove: &std::cell::RefCell<Option<usize>>,
+    deferred_solo: &std::cell::RefCell<Option<(usize, bool)>>,
     pending_note_names_state: &mut Option<(usize, crossbeam_channel::Receiver<(String, Vec<omni_shared::NoteNameInfo>)>)>,
 ) {
     ui.heading("Session Matrix");
@@ -42,25 +47,18 @@ pub fn show_matrix(
                     let btn_size = egui::vec2(theme::TRACK_WIDTH, theme::CLIP_HEIGHT); 
                     let btn = egui::Button::new(format!("Scene {}", scene_idx + 1));
                     
-                    // Scene Button
+                    // Scene Button: this clip index on ALL tracks, on one boundary
                     if ui.add_sized(btn_size, btn).clicked() {
-                        // Trigger this clip index on ALL tracks
-                        for (track_idx, track) in tracks.iter_mut().enumerate() {
-                            let _ = sender.send(EngineCommand::TriggerClip { 
-                                track_index: track_idx, 
-                                clip_index: scene_idx 
-                            });
-                            // Update local UI state immediately to show feedback
-                            if scene_idx < track.clips.len() {
-                                track.active_clip = Some(scene_idx);
-                            }
-                        }
+                        let _ = sender.send(EngineCommand::LaunchScene { scene_index: scene_idx });
                     }
                 }
             });
 
             ui.separator();
 
+            // Send and output targets for the track controls
+            let targets = mixer::RouteTargets::new(tracks);
+
             // TRACK COLUMNS
             for (track_idx, track) in tracks.iter_mut().enumerate() {
                 ui.push_id(track_idx, |ui| {
@@ -91,9 +89,22 @@ pub fn show_matrix(
                         }
                         ui.add_space(theme::SPACING_SMALL);
                         
-                        // 2. Clips
-                        for (clip_idx, clip) in track.clips.iter_mut().enumerate() {
+                        // 2. Clips (return and group tracks have none; keep the controls aligned)
+                        if track.kind != TrackKind::Regular {
+                            let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), theme::CLIP_HEIGHT * track.clips.len() as f32), egui::Sense::hover());
+                            let label = if track.kind == TrackKind::Group { "Group" } else { "Return" };
+                            ui.painter().text(rect.center(), egui::Align2::CENTER_CENTER, label, egui::FontId::proportional(14.0), theme::THEME.text_secondary);
+                        }
+                        // Pending launches/stops blink until the engine reaches the boundary
+                        let blink_on = (ui.input(|i| i.time) * 4.0) as i64 % 2 == 0;
+                        let clip_slots = if track.kind == TrackKind::Regular { track.clips.len() } else { 0 };
+                        for (clip_idx, clip) in track.clips.iter_mut().enumerate().take(clip_slots) {
                             let is_active = track.active_clip == Some(clip_idx);
+                            let is_pending = match track.pending_launch {
+                                Some(Launch::Clip(pending)) => pending == clip_idx,
+                                Some(Launch::Stop) => is_active,
+                                None => false,
+                            };
                             let is_selected = *selected_track_idx == track_idx && *selected_clip_idx == clip_idx;
                             
                             let (rect, response) = ui.allocate_exact_size(egui::vec2(ui.available_width(), theme::CLIP_HEIGHT), egui::Sense::click());
@@ -101,12 +112,21 @@ pub fn show_matrix(
                             if response.clicked() {
                                 *selected_track_idx = track_idx;
                                 *selected_clip_idx = clip_idx;
-                                track.active_clip = Some(clip_idx);
                                 let _ = sender.send(EngineCommand::TriggerClip { track_index: track_idx, clip_index: clip_idx });
                             }
+                            response.context_menu(|ui| {
+                                if follow_actions_menu(ui, &mut clip.follow_actions) {
+                                    let _ = sender.send(EngineCommand::SetClipFollowActions {
+                                        track_index: track_idx,
+                                        clip_index: clip_idx,
+                                        follow_actions: clip.follow_actions,
+                                    });
+                                }
+                            });
 
                             // Colors
-                            let base_color = if is_active { clip.color } else { theme::THEME.clip_inactive };
+                            let lit = if is_pending { is_active != blink_on } else { is_active };
+                            let base_color = if lit { clip.color } else { theme::THEME.clip_inactive };
                             
                             let final_color = if is_selected {
                                 egui::Color32::from_rgb(
@@ -128,9 +148,9 @@ pub fn show_matrix(
 
                             // Playback Progress
                             if is_active && is_playing {
-                                let samples_per_beat = (engine_sample_rate as f64 * 60.0) / bpm as f64;
-                                let loop_len_samples = (clip.length * samples_per_beat).max(1024.0); 
-                                let phase = (global_sample_pos as f64 % loop_len_samples) / loop_len_samples;
+                                let beat = tempo.beat_at_sample(global_sample_pos as f64, engine_sample_rate as f64);
+                                let loop_len = clip.length.max(0.0625);
+                                let phase = (beat % loop_len) / loop_len;
                                 
                                 let progress_width = rect.width() * phase as f32;
                                 let progress_rect = egui::Rect::from_min_size(
@@ -161,8 +181,11 @@ pub fn show_matrix(
                             track_idx, 
                             sender, 
                             deferred_track_remove, 
+                            deferred_solo,
                             pending_note_names_state, 
-                            engine_sample_rate
+                            engine_sample_rate,
+                            input_channels,
+                            &targets,
                         );
                         
                         ui.add_space(theme::SPACING_MEDIUM);
@@ -204,7 +227,96 @@ pub fn show_matrix(
                         ..Default::default()
                     });
                 }
+
+                ui.add_space(theme::SPACING_SMALL);
+                if ui.add_sized(egui::vec2(theme::TRACK_WIDTH, theme::BUTTON_HEIGHT_SMALL), egui::Button::new("+ Return")).clicked() {
+                    let name = format!("Return {}", (b'A' + targets.returns.len() as u8 % 26) as char);
+                    let node = Box::new(omni_engine::nodes::GainNode::new(1.0));
+                    let _ = sender.send(EngineCommand::AddReturnTrack { node, name: name.clone(), plugin_path: None });
+                    tracks.push(TrackData {
+                        name,
+                        kind: TrackKind::Return,
+                        solo_safe: true,
+                        ..Default::default()
+                    });
+                }
+
+                // New group around the selected track
+                if ui.add_sized(egui::vec2(theme::TRACK_WIDTH, theme::BUTTON_HEIGHT_SMALL), egui::Button::new("+ Group")).clicked() {
+                    let name = format!("Group {}", targets.groups.len() + 1);
+                    let children: Vec<usize> = (*selected_track_idx < tracks.len()).then_some(*selected_track_idx).into_iter().collect();
+                    let node = Box::new(omni_engine::nodes::GainNode::new(1.0));
+                    let _ = sender.send(EngineCommand::AddGroupTrack { node, name: name.clone(), children: children.clone() });
+                    // Mirror the engine: the group takes its child's place in the routing
+                    let group = tracks.len();
+                    let parent = children.first().and_then(|&c| tracks[c].parent);
+                    for &child in &children {
+                        tracks[child].parent = Some(group);
+                    }
+                    tracks.push(TrackData {
+                        name,
+                        kind: TrackKind::Group,
+                        parent,
+                        ..Default::default()
+                    });
+                }
             });
         }); 
     });
 }
+
+/// Follow action editor for a clip slot. Returns true when something changed.
+fn follow_actions_menu(ui: &mut egui::Ui, follow: &mut FollowActions) -> bool {
+    let mut changed = ui.checkbox(&mut follow.enabled, "Follow Actions").changed();
+    ui.add_enabled_ui(follow.enabled, |ui| {
+        let (mut count, mut loops) = match follow.time {
+            FollowTime::Bars(n) => (n, false),
+            FollowTime::Loops(n) => (n, true),
+        };
+        ui.horizontal(|ui| {
+            ui.label("After");
+            changed |= ui.add(egui::DragValue::new(&mut count).range(1..=64)).changed();
+            changed |= ui.selectable_value(&mut loops, false, "Bars").changed();
+            changed |= ui.selectable_value(&mut loops, true, "Loops").changed();
+        });
+        follow.time = if loops { FollowTime::Loops(count) } else { FollowTime::Bars(count) };
+
+        for (label, action, weight) in [
+            ("A", &mut follow.action_a, &mut follow.weight_a),
+            ("B", &mut follow.action_b, &mut follow.weight_b),
+        ] {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                egui::ComboBox::from_id_salt(("follow_action", label))
+                    .selected_text(format!("{:?}", action))
+                    .show_ui(ui, |ui| {
+                        for option in [
+                            FollowAction::None,
+                            FollowAction::Stop,
+                            FollowAction::Again,
+                            FollowAction::Next,
+                            FollowAction::Previous,
+                            FollowAction::First,
+                            FollowAction::Last,
+                            FollowAction::Any,
+                            FollowAction::Other,
+                        ] {
+                            changed |= ui.selectable_value(action, option, format!("{:?}", option)).changed();
+                        }
+                    });
+                ui.label("Weight");
+                changed |= ui.add(egui::DragValue::new(weight).range(0..=10)).changed();
+            });
+        }
+
+        let mut chance = follow.chance * 100.0;
+        ui.horizontal(|ui| {
+            ui.label("Chance");
+            if ui.add(egui::Slider::new(&mut chance, 0.0..=100.0).suffix("%")).changed() {
+                follow.chance = chance / 100.0;
+                changed = true;
+            }
+        });
+    });
+    changed
+}
@@ -12,7 +12,7 @@ use clap_sys::events::{
     clap_event_param_value, CLAP_EVENT_PARAM_VALUE,
     clap_event_transport, CLAP_EVENT_TRANSPORT,
     CLAP_TRANSPORT_HAS_TEMPO, CLAP_TRANSPORT_HAS_BEATS_TIMELINE,
-    CLAP_TRANSPORT_HAS_TIME_SIGNATURE, CLAP_TRANSPORT_IS_PLAYING,
+    CLAP_TRANSPORT_HAS_TIME_SIGNATURE, CLAP_TRANSPORT_IS_PLAYING, CLAP_TRANSPORT_IS_LOOP_ACTIVE,
     clap_event_note_expression, CLAP_EVENT_NOTE_EXPRESSION, CLAP_NOTE_EXPRESSION_TUNING,
 };
 use clap_sys::fixedpoint::CLAP_BEATTIME_FACTOR;
@@ -22,6 +22,7 @@ use clap_sys::ext::timer_support::{clap_plugin_timer_support, clap_host_timer_su
 use clap_sys::ext::note_name::{clap_plugin_note_name, clap_note_name, CLAP_EXT_NOTE_NAME};
 use clap_sys::ext::latency::{clap_plugin_latency, CLAP_EXT_LATENCY};
 use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::ext::audio_ports::{clap_plugin_audio_ports, clap_audio_port_info, CLAP_EXT_AUDIO_PORTS};
 use clap_sys::stream::{clap_istream, clap_ostream};
 use winit::window::Window;
 use raw_window_handle::{HasWindowHandle, RawWindowHandle};
@@ -46,7 +47,7 @@ lazy_static::lazy_static! {
     static ref ACTIVE_TIMERS: Mutex<TimerMap> = Mutex::new(HashMap::new());
 }
 
-use omni_shared::MidiNoteEvent;
+use omni_shared::{MidiNoteEvent, PluginKind};
 
 /// Transport information passed from the audio engine to plugins
 #[derive(Clone, Copy, Debug, Default)]
@@ -58,11 +59,20 @@ pub struct TransportInfo {
     pub bar_number: i32,
     pub time_sig_num: u16,
     pub time_sig_denom: u16,
+    pub loop_active: bool,
+    pub loop_start_beats: f64,
+    pub loop_end_beats: f64,
 }
 
 struct AudioBuffers {
     left: Vec<f32>,
     right: Vec<f32>,
+    // Deinterleaved copy of the incoming block for the main input port
+    in_left: Vec<f32>,
+    in_right: Vec<f32>,
+    // Key audio for the auxiliary input port
+    sc_left: Vec<f32>,
+    sc_right: Vec<f32>,
     input_events: Vec<clap_event_note>,
     expression_events: Vec<clap_event_note_expression>,
     param_events: Vec<clap_event_param_value>,
@@ -78,6 +88,11 @@ pub struct ClapPlugin {
 
     // Plugin metadata
     pub clap_id: String,
+    pub kind: PluginKind,
+    /// Channels of the main audio input port (0: the plugin takes no audio)
+    input_channels: u32,
+    /// Channels of the auxiliary (sidechain) input port, 0 if it has none
+    pub sidechain_channels: u32,
 
     // Interior Mutability for Audio Thread exclusive access
     // This Mutex is ONLY locked by process_audio, so it is uncontended by GUI
@@ -203,6 +218,37 @@ unsafe extern "C" fn output_events_try_push(list: *const clap_output_events, eve
     true 
 }
 
+/// Feature strings of a plugin descriptor ("instrument", "audio-effect", ...).
+unsafe fn descriptor_features(desc: *const clap_sys::plugin::clap_plugin_descriptor) -> Vec<String> {
+    let mut features = Vec::new();
+    let mut feature = (*desc).features;
+    if feature.is_null() {
+        return features;
+    }
+    while !(*feature).is_null() {
+        features.push(CStr::from_ptr(*feature).to_string_lossy().into_owned());
+        feature = feature.add(1);
+    }
+    features
+}
+
+/// Channel counts of the main (first) and auxiliary (second) audio input
+/// ports, 0 where a port is missing. None without the audio-ports extension.
+/// Further inputs are not connected.
+unsafe fn input_port_channels(plugin: *const clap_plugin) -> Option<(u32, u32)> {
+    let get_ext = (*plugin).get_extension?;
+    let ports = get_ext(plugin, CLAP_EXT_AUDIO_PORTS.as_ptr()) as *const clap_plugin_audio_ports;
+    if ports.is_null() {
+        return None;
+    }
+    let (count, get) = ((*ports).count?, (*ports).get?);
+    let channels = |index: u32| {
+        let mut info = std::mem::zeroed::<clap_audio_port_info>();
+        if index < count(plugin, true) && get(plugin, index, true, &mut info) { info.channel_count } else { 0 }
+    };
+    Some((channels(0), channels(1)))
+}
+
 // --- CLAP STREAM IMPLEMENTATION ---
 
 struct InputMemoryStream<'a> {
@@ -282,7 +328,8 @@ impl ClapPlugin {
         let plugin_id = (*desc).id;
         
         let name = CStr::from_ptr((*desc).name).to_string_lossy();
-        eprintln!("[CLAP] Loading: {}", name);
+        let kind = PluginKind::from_features(descriptor_features(desc).iter().map(String::as_str));
+        eprintln!("[CLAP] Loading: {} ({:?})", name, kind);
 
         let host_name = CString::new("OmniHost")?;
         let host_vendor = CString::new("Id3at")?;
@@ -324,6 +371,15 @@ impl ClapPlugin {
             ptr::null()
         };
 
+        // Port layout is fixed while inactive, so read it before activating
+        let (input_channels, sidechain_channels) = input_port_channels(plugin).unwrap_or(match kind {
+            PluginKind::Effect => (2, 0), // No audio-ports extension: assume stereo in
+            PluginKind::Instrument => (0, 0),
+        });
+        if sidechain_channels > 0 {
+            eprintln!("[CLAP] Sidechain input: {} channels", sidechain_channels);
+        }
+
         if let Some(activate) = (*plugin).activate {
              if !activate(plugin, sample_rate, 32, 4096) {
                  eprintln!("[CLAP] Warning: activate failed");
@@ -345,9 +401,16 @@ impl ClapPlugin {
             params,
             pending_params: Arc::new(Mutex::new(Vec::new())),
             clap_id: CStr::from_ptr(plugin_id).to_string_lossy().into_owned(),
+            kind,
+            input_channels,
+            sidechain_channels,
             audio_buffers: Mutex::new(AudioBuffers {
                 left: vec![0.0; max_buf],
                 right: vec![0.0; max_buf],
+                in_left: vec![0.0; max_buf],
+                in_right: vec![0.0; max_buf],
+                sc_left: vec![0.0; max_buf],
+                sc_right: vec![0.0; max_buf],
                 input_events: Vec::with_capacity(128),
                 expression_events: Vec::with_capacity(128),
                 param_events: Vec::with_capacity(32),
@@ -356,9 +419,36 @@ impl ClapPlugin {
         })
     }
 
+    /// Stop, deactivate and re-activate at a new sample rate (main thread).
+    /// The caller must make sure `process_audio` is not running concurrently.
+    pub unsafe fn reactivate(&self, sample_rate: f64) {
+        let plugin = self.plugin;
+        if let Some(stop_processing) = (*plugin).stop_processing {
+            stop_processing(plugin);
+        }
+        if let Some(deactivate) = (*plugin).deactivate {
+            deactivate(plugin);
+        }
+        if let Some(activate) = (*plugin).activate {
+            if !activate(plugin, sample_rate, 32, 4096) {
+                eprintln!("[CLAP] Warning: re-activate at {}Hz failed", sample_rate);
+            }
+        }
+        if let Some(start_processing) = (*plugin).start_processing {
+            if !start_processing(plugin) {
+                eprintln!("[CLAP] Warning: start_processing failed");
+            }
+        }
+    }
+
+    /// `output_buffer` holds the incoming interleaved audio, which feeds the
+    /// plugin's main input port, and is overwritten with the plugin output.
+    /// `sidechain` (interleaved, empty = silence) feeds the auxiliary port.
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn process_audio(
         &self, 
         output_buffer: &mut [f32], 
+        sidechain: &[f32],
         midi_events: &[MidiNoteEvent],
         param_events: &[omni_shared::ParameterEvent],
         expression_events: &[omni_shared::ExpressionEvent],
@@ -366,13 +456,30 @@ impl ClapPlugin {
         transport: &TransportInfo,
     ) {
         let mut bufs = self.audio_buffers.lock().unwrap();
-        let AudioBuffers { left, right, input_events: clap_input_events, expression_events: clap_expr_events, param_events: clap_param_events } = &mut *bufs;
+        let AudioBuffers { left, right, in_left, in_right, sc_left, sc_right, input_events: clap_input_events, expression_events: clap_expr_events, param_events: clap_param_events } = &mut *bufs;
         
         let frames = output_buffer.len() / 2;
         
         if left.len() < frames {
             left.resize(frames, 0.0);
             right.resize(frames, 0.0);
+            in_left.resize(frames, 0.0);
+            in_right.resize(frames, 0.0);
+            sc_left.resize(frames, 0.0);
+            sc_right.resize(frames, 0.0);
+        }
+
+        // Mono inputs get the downmix
+        if self.input_channels > 0 {
+            for i in 0..frames {
+                let (l, r) = (output_buffer[i * 2], output_buffer[i * 2 + 1]);
+                if self.input_channels == 1 {
+                    in_left[i] = (l + r) * 0.5;
+                } else {
+                    in_left[i] = l;
+                    in_right[i] = r;
+                }
+            }
         }
         
         clap_input_events.clear();
@@ -506,6 +613,52 @@ impl ClapPlugin {
             right.as_mut_ptr()
         ];
         
+        // Key: same downmix rule; a block without one is flagged constant silence
+        let key_silent = sidechain.len() < frames * 2;
+        if self.sidechain_channels > 0 {
+            for i in 0..frames {
+                let (l, r) = if key_silent { (0.0, 0.0) } else { (sidechain[i * 2], sidechain[i * 2 + 1]) };
+                if self.sidechain_channels == 1 {
+                    sc_left[i] = (l + r) * 0.5;
+                } else {
+                    sc_left[i] = l;
+                    sc_right[i] = r;
+                }
+            }
+        }
+
+        let mut input_channel_pointers = [
+            in_left.as_mut_ptr(),
+            in_right.as_mut_ptr()
+        ];
+        let mut sidechain_channel_pointers = [
+            sc_left.as_mut_ptr(),
+            sc_right.as_mut_ptr()
+        ];
+
+        // Port order: main (0), then the auxiliary input (1)
+        let audio_inputs = [
+            clap_audio_buffer {
+                data32: input_channel_pointers.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: self.input_channels.min(2),
+                latency: 0,
+                constant_mask: 0,
+            },
+            clap_audio_buffer {
+                data32: sidechain_channel_pointers.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: self.sidechain_channels.min(2),
+                latency: 0,
+                constant_mask: if key_silent { u64::MAX } else { 0 },
+            },
+        ];
+        let audio_inputs_count = match (self.input_channels, self.sidechain_channels) {
+            (0, _) => 0,
+            (_, 0) => 1,
+            _ => 2,
+        };
+
         let mut audio_outputs = clap_audio_buffer {
             data32: output_channel_pointers.as_mut_ptr(),
             data64: ptr::null_mut(),
@@ -521,6 +674,9 @@ impl ClapPlugin {
         if transport.is_playing {
             transport_flags |= CLAP_TRANSPORT_IS_PLAYING;
         }
+        if transport.loop_active {
+            transport_flags |= CLAP_TRANSPORT_IS_LOOP_ACTIVE;
+        }
 
         let song_pos_beats_fixed = (transport.song_pos_beats * CLAP_BEATTIME_FACTOR as f64) as i64;
         let bar_start_fixed = (transport.bar_start_beats * CLAP_BEATTIME_FACTOR as f64) as i64;
@@ -538,8 +694,8 @@ impl ClapPlugin {
             song_pos_seconds: 0,
             tempo: transport.tempo,
             tempo_inc: 0.0,
-            loop_start_beats: 0,
-            loop_end_beats: 0,
+            loop_start_beats: (transport.loop_start_beats * CLAP_BEATTIME_FACTOR as f64) as i64,
+            loop_end_beats: (transport.loop_end_beats * CLAP_BEATTIME_FACTOR as f64) as i64,
             loop_start_seconds: 0,
             loop_end_seconds: 0,
             bar_start: bar_start_fixed,
@@ -552,9 +708,9 @@ impl ClapPlugin {
             steady_time: -1, 
             frames_count: frames as u32,
             transport: &transport_event,
-            audio_inputs: ptr::null(), 
+            audio_inputs: if audio_inputs_count > 0 { audio_inputs.as_ptr() } else { ptr::null() },
             audio_outputs: &mut audio_outputs,
-            audio_inputs_count: 0,
+            audio_inputs_count,
             audio_outputs_count: 1,
             in_events: &input_events,
             out_events: &output_events,
@@ -31,6 +31,7 @@ enum CustomEvent {
     OpenEditor,
     Initialize(Uuid, omni_shared::ShmemConfig),
     LoadPlugin(String, f64),
+    Reactivate(f64),
     Shutdown, 
 }
 
@@ -142,7 +143,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 let transport = clap_wrapper::TransportInfo::default();
                                 let guard = plugin_for_ipc.read().unwrap();
                                 if let Some(ref p) = *guard {
-                                    p.process_audio(slice, &[], &[], &[], header, &transport);
+                                    p.process_audio(slice, &[], &[], &[], &[], header, &transport);
                                     
                                     // Update Latency
                                     let latency = p.get_latency();
@@ -170,7 +171,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 let transport = clap_wrapper::TransportInfo::default();
                                 let guard = plugin_for_ipc.read().unwrap();
                                 if let Some(ref p) = *guard {
-                                    p.process_audio(slice, &events, &[], &[], header, &transport);
+                                    p.process_audio(slice, &[], &events, &[], &[], header, &transport);
                                     
                                     // Update Latency
                                     let latency = p.get_latency();
@@ -255,6 +256,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                 }
+                HostCommand::Reactivate { sample_rate } => {
+                    let mut f = log_file.lock().unwrap();
+                    let _ = writeln!(f, "[CMD] Reactivate (Dispatching to Main Thread) @ {}Hz", sample_rate);
+                    let _ = event_loop_proxy.send_event(CustomEvent::Reactivate(sample_rate));
+                    // Main thread sends reply.
+                }
                 HostCommand::Shutdown => {
                     std::process::exit(0);
                 }
@@ -340,11 +347,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             bar_number: header.transport_bar_number,
                             time_sig_num: header.transport_time_sig_num,
                             time_sig_denom: header.transport_time_sig_denom,
+                            loop_active: header.transport_loop_active != 0,
+                            loop_start_beats: header.transport_loop_start_beats,
+                            loop_end_beats: header.transport_loop_end_beats,
+                        };
+                        
+                        let sidechain_count = header.sidechain_sample_count;
+                        let sidechain_offset = header.sidechain_offset;
+                        let sidechain_slice = if sidechain_count > 0 {
+                            let sidechain_ptr = base_ptr.add(sidechain_offset as usize) as *const f32;
+                            std::slice::from_raw_parts(sidechain_ptr, sidechain_count as usize)
+                        } else {
+                            &[]
                         };
                         
                         let guard = plugin_for_audio.read().unwrap();
                         if let Some(ref p) = *guard {
-                            p.process_audio(slice, midi_slice, param_slice, expr_slice, header, &transport);
+                            p.process_audio(slice, sidechain_slice, midi_slice, param_slice, expr_slice, header, &transport);
 
                             // Update Latency
                             let latency = p.get_latency();
@@ -435,12 +454,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                  let _ = writeln!(f, "[Main] Processing LoadPlugin: {} @ {}Hz", path, sample_rate);
                  match unsafe { ClapPlugin::load(&path, sample_rate) } {
                     Ok(p) => {
+                        let (kind, sidechain) = (p.kind, p.sidechain_channels > 0);
                         let mut guard = plugin.write().unwrap();
                         *guard = Some(p);
                         
                         // Send Reply
                         let mut out = stdout.lock().unwrap();
-                        if let Ok(serialized) = bincode::serialize(&PluginEvent::PluginLoaded) {
+                        if let Ok(serialized) = bincode::serialize(&PluginEvent::PluginLoaded { kind, sidechain }) {
                              let _ = writeln!(out, "{}", BASE64.encode(serialized));
                              let _ = out.flush();
                              let _ = writeln!(f, "[Main] PluginLoaded Sent.");
@@ -456,6 +476,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            Event::UserEvent(CustomEvent::Reactivate(sample_rate)) => {
+                 let mut f = log_file.lock().unwrap();
+                 let _ = writeln!(f, "[Main] Processing Reactivate @ {}Hz", sample_rate);
+                 // Write lock keeps the audio thread out while the plugin is inactive
+                 let reply = match *plugin.write().unwrap() {
+                     Some(ref p) => {
+                         unsafe { p.reactivate(sample_rate); }
+                         PluginEvent::Reactivated
+                     }
+                     None => PluginEvent::Error("No plugin loaded".into()),
+                 };
+                 let mut out = stdout.lock().unwrap();
+                 if let Ok(serialized) = bincode::serialize(&reply) {
+                     let _ = writeln!(out, "{}", BASE64.encode(serialized));
+                     let _ = out.flush();
+                 }
+            }
             Event::UserEvent(CustomEvent::OpenEditor) => {
                  let mut f = log_file.lock().unwrap();
                  let _ = writeln!(f, "[GUI] OpenEditor received.");
@@ -1,6 +1,9 @@
 pub mod scale;
 pub mod project;
 pub mod performance;
+pub mod tempo;
+pub mod meter;
+pub mod sampler;
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -31,6 +34,24 @@ pub struct ExpressionEvent {
     pub sample_offset: u32,
 }
 
+/// Audio device selection. `None` means "use the system default".
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AudioSettings {
+    /// Audio host/API name (e.g. "ALSA", "JACK", "WASAPI")
+    pub host: Option<String>,
+    /// Output device name as reported by the host
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// Frames per device callback
+    pub buffer_size: Option<u32>,
+    /// Input device name (`None` = host default input)
+    #[serde(default)]
+    pub input_device: Option<String>,
+    /// Don't open any input stream
+    #[serde(default)]
+    pub disable_input: bool,
+}
+
 /// Commands sent from Host to Plugin Process via IPC (e.g., Stdin/Pipe)
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum HostCommand {
@@ -72,6 +93,8 @@ pub enum HostCommand {
         /// We use Vec<u8> in Rust struct, bincode handles it.
         data: Vec<u8>,
     },
+    /// Deactivate and re-activate the plugin at a new sample rate
+    Reactivate { sample_rate: f64 },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -84,6 +107,39 @@ pub struct ParamInfo {
     pub flags: u32,
 }
 
+/// What a plugin does with audio, from its CLAP descriptor features
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PluginKind {
+    /// Makes sound from notes; audio input (if any) is ignored
+    #[default]
+    Instrument,
+    /// Processes the audio fed into it (EQ, compressor, reverb...)
+    Effect,
+}
+
+impl PluginKind {
+    /// "instrument" wins when a plugin claims both; unknown plugins are instruments.
+    pub fn from_features<'a>(features: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut kind = Self::Instrument;
+        for feature in features {
+            match feature {
+                "instrument" => return Self::Instrument,
+                "audio-effect" | "analyzer" => kind = Self::Effect,
+                _ => {}
+            }
+        }
+        kind
+    }
+}
+
+/// A chain device as the UI sees it
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeviceInfo {
+    pub params: Vec<ParamInfo>,
+    /// Has an auxiliary (key) input that a sidechain can feed
+    pub sidechain: bool,
+}
+
 /// Note name information from CLAP plugin's note_name extension
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NoteNameInfo {
@@ -100,8 +156,8 @@ pub struct NoteNameInfo {
 pub enum PluginEvent {
     /// Initialization successful
     Initialized,
-    /// Plugin loaded successfully
-    PluginLoaded,
+    /// Plugin loaded successfully; `sidechain`: it has an auxiliary audio input
+    PluginLoaded { kind: PluginKind, sidechain: bool },
     /// Heartbeat signal
     Heartbeat,
     /// Error occurred
@@ -117,6 +173,8 @@ pub enum PluginEvent {
     NoteNameList { clap_id: String, names: Vec<NoteNameInfo> },
     /// Return of the plugin State data
     StateData(Vec<u8>),
+    /// Plugin re-activated at the requested sample rate
+    Reactivated,
 }
 
 /// Configuration for Shared Memory Region
@@ -169,6 +227,11 @@ pub struct OmniShmemHeader {
     /// Offset to the Note Expression Event Buffer
     pub expression_event_offset: u32,
 
+    /// Interleaved stereo samples of sidechain (key) audio, 0 = none this block
+    pub sidechain_sample_count: u32,
+    /// Offset to the Sidechain Audio Buffer
+    pub sidechain_offset: u32,
+
     // Parameter Learn / Touch Feedback
     pub last_touched_param: u32,
     pub last_touched_value: f32,
@@ -182,6 +245,9 @@ pub struct OmniShmemHeader {
     pub transport_bar_number: i32,   // Current bar number
     pub transport_time_sig_num: u16, // Time signature numerator
     pub transport_time_sig_denom: u16, // Time signature denominator
+    pub transport_loop_active: u32,    // 0 = off, 1 = loop brace active
+    pub transport_loop_start_beats: f64,
+    pub transport_loop_end_beats: f64,
     pub latency: u32, // Plugin Latency in samples
 }
 
@@ -0,0 +1,106 @@
+//! Meter map: time-signature changes at bar positions and beat <-> bar conversion.
+//! Beats are quarter notes throughout (7/8 is 3.5 beats per bar).
+
+use crate::project::TimeSignature;
+use serde::{Deserialize, Serialize};
+
+/// A time-signature change at the start of `bar` (0-based).
+/// The signature before the first change is `Project::time_signature`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MeterChange {
+    pub bar: u32,
+    pub signature: TimeSignature,
+}
+
+/// Position of a beat within its bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarPosition {
+    pub bar: i32,            // 0-based
+    pub bar_start_beat: f64, // Timeline beat of the bar start
+    pub signature: TimeSignature,
+}
+
+impl BarPosition {
+    /// Length of one signature beat (an eighth in 7/8) in quarter beats.
+    pub fn beat_unit(&self) -> f64 {
+        4.0 / self.signature.denominator.max(1) as f64
+    }
+}
+
+/// Run of bars sharing a signature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MeterSection {
+    bar: u32,
+    beat: f64,
+    signature: TimeSignature,
+}
+
+impl MeterSection {
+    fn beats_per_bar(&self) -> f64 {
+        self.signature.beats_per_bar().max(1e-6)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterMap {
+    sections: Vec<MeterSection>,
+}
+
+impl MeterMap {
+    pub fn constant(signature: TimeSignature) -> Self {
+        Self::new(signature, &[])
+    }
+
+    /// `signature` holds from bar 0 until the first change.
+    pub fn new(signature: TimeSignature, changes: &[MeterChange]) -> Self {
+        let mut changes: Vec<MeterChange> = changes
+            .iter()
+            .filter(|c| c.bar > 0 && c.signature.numerator > 0 && c.signature.denominator > 0)
+            .copied()
+            .collect();
+        changes.sort_by_key(|c| c.bar);
+        // Last change wins when two share a bar
+        changes.dedup_by(|later, earlier| {
+            let same = later.bar == earlier.bar;
+            if same {
+                earlier.signature = later.signature;
+            }
+            same
+        });
+
+        let mut sections = vec![MeterSection { bar: 0, beat: 0.0, signature }];
+        for change in changes {
+            let last = sections.last().unwrap();
+            let beat = last.beat + (change.bar - last.bar) as f64 * last.beats_per_bar();
+            sections.push(MeterSection { bar: change.bar, beat, signature: change.signature });
+        }
+        Self { sections }
+    }
+
+    pub fn signature_at(&self, beat: f64) -> TimeSignature {
+        self.section_at_beat(beat).signature
+    }
+
+    fn section_at_beat(&self, beat: f64) -> &MeterSection {
+        let idx = self.sections.partition_point(|s| s.beat <= beat);
+        &self.sections[idx.saturating_sub(1)]
+    }
+
+    /// Bar containing `beat` (bars before 0 repeat the first signature).
+    pub fn position(&self, beat: f64) -> BarPosition {
+        let section = self.section_at_beat(beat);
+        let bars_in = ((beat - section.beat) / section.beats_per_bar()).floor();
+        BarPosition {
+            bar: section.bar as i32 + bars_in as i32,
+            bar_start_beat: section.beat + bars_in * section.beats_per_bar(),
+            signature: section.signature,
+        }
+    }
+
+    /// Timeline beat at which `bar` starts.
+    pub fn bar_start(&self, bar: u32) -> f64 {
+        let idx = self.sections.partition_point(|s| s.bar <= bar);
+        let section = &self.sections[idx.saturating_sub(1)];
+        section.beat + (bar - section.bar) as f64 * section.beats_per_bar()
+    }
+}
@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::HashMap;
 use crate::scale::ScaleType;
+use crate::meter::{MeterChange, MeterMap};
+use crate::tempo::{TempoEvent, TempoMap};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum NoteCondition {
@@ -41,6 +43,22 @@ pub struct Note {
 
 fn default_probability() -> f64 { 1.0 }
 
+impl Note {
+    /// A note that always plays, at a fixed velocity.
+    pub fn new(start: f64, duration: f64, key: u8, velocity: u8) -> Self {
+        Self {
+            start,
+            duration,
+            key,
+            velocity,
+            probability: default_probability(),
+            velocity_deviation: 0,
+            condition: NoteCondition::default(),
+            selected: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SequencerDirection {
     Forward,
@@ -330,6 +348,9 @@ pub struct Clip {
     
     #[serde(default)]
     pub step_sequencer: StepSequencerData,
+
+    #[serde(default)]
+    pub follow_actions: FollowActions,
 }
 
 impl Default for Clip {
@@ -341,6 +362,67 @@ impl Default for Clip {
             color: [100, 100, 100],
             use_sequencer: false,
             step_sequencer: StepSequencerData::default(),
+            follow_actions: FollowActions::default(),
+        }
+    }
+}
+
+impl Clip {
+    /// Has notes or a step pattern (follow actions skip empty slots)
+    pub fn has_content(&self) -> bool {
+        self.use_sequencer || !self.notes.is_empty()
+    }
+}
+
+/// What a session clip does once its follow time is up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum FollowAction {
+    /// Keep looping
+    #[default]
+    None,
+    Stop,
+    /// Relaunch this clip
+    Again,
+    Next,
+    Previous,
+    First,
+    Last,
+    /// Random clip, this one included
+    Any,
+    /// Random clip other than this one
+    Other,
+}
+
+/// When follow actions are evaluated, counted from the clip's launch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FollowTime {
+    Bars(u16),
+    Loops(u16),
+}
+
+/// Two weighted follow actions: after `time`, with probability `chance`, one of
+/// them runs (A with `weight_a / (weight_a + weight_b)`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FollowActions {
+    pub enabled: bool,
+    pub time: FollowTime,
+    pub action_a: FollowAction,
+    pub action_b: FollowAction,
+    pub weight_a: u8,
+    pub weight_b: u8,
+    pub chance: f32, // 0.0 - 1.0; a miss keeps looping until the next follow time
+}
+
+impl Default for FollowActions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            time: FollowTime::Bars(1),
+            action_a: FollowAction::Next,
+            action_b: FollowAction::None,
+            weight_a: 1,
+            weight_b: 0,
+            chance: 1.0,
         }
     }
 }
@@ -378,15 +460,63 @@ impl Timestamp {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Pins a source position to a beat; audio between markers is stretched to fit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WarpMarker {
-    pub source_sample: u64,
-    pub timeline_beat: f64,
+    pub source_sample: u64, // Frame in the source file (at its own rate)
+    pub timeline_beat: f64, // Beats from the clip start
+}
+
+/// Shape of a clip fade, given as the fade-in ramp (fade-outs play it mirrored).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Constant power, used for automatic crossfades
+    EqualPower,
+    Exponential,
+    SCurve,
+}
+
+impl FadeCurve {
+    pub const ALL: [FadeCurve; 4] = [FadeCurve::Linear, FadeCurve::EqualPower, FadeCurve::Exponential, FadeCurve::SCurve];
+
+    /// Gain at `t` (0.0 = silent edge, 1.0 = full level).
+    pub fn gain(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower => (t * std::f32::consts::FRAC_PI_2).sin(),
+            FadeCurve::Exponential => t * t,
+            FadeCurve::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "Linear",
+            FadeCurve::EqualPower => "Equal Power",
+            FadeCurve::Exponential => "Exponential",
+            FadeCurve::SCurve => "S-Curve",
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub struct ClipFade {
+    pub length: u64, // Frames from the clip edge
+    pub curve: FadeCurve,
+}
+
+/// An audio clip on the arrangement timeline. The position is in beats so clips follow
+/// the tempo map; length and offset are frames of the played asset.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArrangementClip {
-    pub start_time: Timestamp, 
+    #[serde(default)]
+    pub start: f64, // Timeline position (beats)
+    /// Sample position saved by projects from before beat positions; `Project::upgrade_clip_positions` converts it
+    #[serde(default, rename = "start_time", skip_serializing)]
+    pub legacy_start: Option<Timestamp>,
     pub length: Timestamp,     
     pub start_offset: Timestamp, 
     pub source_id: u32,        // ID in Audio Pool (0 = none/midi?)
@@ -401,15 +531,320 @@ pub struct ArrangementClip {
     pub stretch_ratio: f32, // 1.0 = normal, 0.5 = half speed, 2.0 = double speed
     #[serde(default = "default_bpm")]
     pub original_bpm: f32,
+
+    // Pitch Shifting (length preserved)
+    #[serde(default)]
+    pub pitch_semitones: i32,
+    #[serde(default)]
+    pub pitch_cents: f32,
+
+    // Gain & Fades
+    #[serde(default = "default_gain")]
+    pub gain: f32, // Linear
+    #[serde(default)]
+    pub fade_in: ClipFade,
+    #[serde(default)]
+    pub fade_out: ClipFade,
         
     #[serde(skip)]
-    pub cached_id: Option<u32>, // Runtime ID of the stretched asset
+    pub cached_id: Option<u32>, // Runtime ID of the stretched/pitched asset
+    #[serde(skip)]
+    pub cached_span: Option<(u64, u64)>, // Offset and length of the clip in that asset (frames)
+}
+
+/// A MIDI part on the arrangement timeline. Positions are in beats so parts follow the tempo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MidiArrangementClip {
+    pub start: f64,  // Timeline position (beats)
+    pub length: f64, // Length on the timeline (beats)
+    #[serde(default)]
+    pub start_offset: f64, // Beats into the content where playback starts
+    #[serde(default)]
+    pub looped: bool, // Repeat the content (clip.length) to fill the part
+    pub clip: Clip,   // Embedded notes or step sequencer
+    #[serde(skip)]
+    pub selected: bool,
+}
+
+impl ArrangementClip {
+    /// `length` frames of pool asset `source_id` from beat `start`, unstretched and at unity gain.
+    pub fn new(name: String, source_id: u32, start: f64, length: u64) -> Self {
+        Self {
+            start,
+            legacy_start: None,
+            length: Timestamp { samples: length, fractional: 0.0 },
+            start_offset: Timestamp::default(),
+            source_id,
+            name,
+            selected: false,
+            warp_markers: Vec::new(),
+            stretch: false,
+            stretch_ratio: 1.0,
+            original_bpm: default_bpm(),
+            pitch_semitones: 0,
+            pitch_cents: 0.0,
+            gain: default_gain(),
+            fade_in: ClipFade::default(),
+            fade_out: ClipFade::default(),
+            cached_id: None,
+            cached_span: None,
+        }
+    }
+
+    /// Offset and length (frames) of what the clip plays: its span of the stretched variant
+    /// once rendered, of the source otherwise.
+    pub fn played_span(&self) -> (u64, u64) {
+        self.cached_span.unwrap_or((self.start_offset.samples, self.length.samples))
+    }
+
+    /// Timeline position in samples at `sample_rate`.
+    pub fn start_sample(&self, tempo: &TempoMap, sample_rate: f64) -> u64 {
+        tempo.sample_at(self.start, sample_rate).round() as u64
+    }
+}
+
+impl MidiArrangementClip {
+    pub fn from_clip(clip: Clip, start: f64) -> Self {
+        Self { start, length: clip.length, start_offset: 0.0, looped: true, clip, selected: false }
+    }
+
+    pub fn end(&self) -> f64 {
+        self.start + self.length
+    }
+}
+
+/// What an automation lane drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AutomationTarget {
+    Volume, // Linear gain, like `Track::volume`
+    Pan,    // -1.0 .. 1.0
+    /// Plugin parameter id (`ParamInfo::id`), plain value, of the instrument
+    /// (`device: None`) or of the insert effect at position `device`
+    Param {
+        #[serde(default)]
+        device: Option<usize>,
+        id: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AutomationPoint {
+    pub beat: f64, // Timeline position
+    pub value: f32,
+    /// Shape of the segment to the next point: 0.0 = linear,
+    /// > 0 rises early (convex), < 0 rises late (concave). Range -1.0 .. 1.0
+    #[serde(default)]
+    pub curve: f32,
+}
+
+/// Breakpoint automation. Points are kept sorted by beat; the value holds
+/// before the first and after the last point.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutomationLane {
+    pub target: AutomationTarget,
+    pub points: Vec<AutomationPoint>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl AutomationLane {
+    pub fn new(target: AutomationTarget) -> Self {
+        Self { target, points: Vec::new(), enabled: true }
+    }
+
+    /// Inserts a point keeping the order; returns its index.
+    pub fn insert_point(&mut self, point: AutomationPoint) -> usize {
+        let idx = self.points.partition_point(|p| p.beat <= point.beat);
+        self.points.insert(idx, point);
+        idx
+    }
+
+    /// Value at `beat`, or None if the lane is off or empty.
+    pub fn value_at(&self, beat: f64) -> Option<f32> {
+        if !self.enabled {
+            return None;
+        }
+        let first = self.points.first()?;
+        let idx = self.points.partition_point(|p| p.beat <= beat);
+        if idx == 0 {
+            return Some(first.value);
+        }
+        let a = &self.points[idx - 1];
+        let Some(b) = self.points.get(idx) else {
+            return Some(a.value);
+        };
+        let span = b.beat - a.beat;
+        if span <= 0.0 {
+            return Some(b.value);
+        }
+        let t = ((beat - a.beat) / span) as f32;
+        Some(a.value + (b.value - a.value) * shape_curve(t, a.curve))
+    }
+}
+
+/// Bends a 0..1 ramp: exponent 4^-curve (curve 0 = linear).
+fn shape_curve(t: f32, curve: f32) -> f32 {
+    if curve.abs() < 1e-3 {
+        t
+    } else {
+        t.powf(4.0_f32.powf(-curve.clamp(-1.0, 1.0)))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct TrackArrangement {
     pub clips: Vec<ArrangementClip>,
-    // Automation curves will go here later
+    #[serde(default)]
+    pub midi_clips: Vec<MidiArrangementClip>,
+    #[serde(default)]
+    pub automation: Vec<AutomationLane>,
+}
+
+impl TrackArrangement {
+    pub fn lane(&self, target: AutomationTarget) -> Option<&AutomationLane> {
+        self.automation.iter().find(|l| l.target == target)
+    }
+
+    /// Keeps effect lanes on their device when the chain changes: the device at
+    /// position `p` is now at `position(p)`; lanes of removed devices (`None`) are dropped.
+    pub fn remap_device_lanes(&mut self, position: impl Fn(usize) -> Option<usize>) {
+        self.automation.retain_mut(|lane| match lane.target {
+            AutomationTarget::Param { device: Some(device), id } => match position(device) {
+                Some(device) => {
+                    lane.target = AutomationTarget::Param { device: Some(device), id };
+                    true
+                }
+                None => false,
+            },
+            _ => true,
+        });
+    }
+}
+
+/// Hardware input feeding a track. Channel indices are 0-based device inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TrackInput {
+    #[default]
+    None,
+    Mono(u16),
+    /// Channels `n` and `n + 1`
+    Stereo(u16),
+}
+
+/// When a track plays its live input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum MonitorMode {
+    /// Monitor while record-armed
+    #[default]
+    Auto,
+    /// Always monitor
+    In,
+    Off,
+}
+
+impl MonitorMode {
+    pub fn is_monitoring(self, armed: bool) -> bool {
+        match self {
+            MonitorMode::Auto => armed,
+            MonitorMode::In => true,
+            MonitorMode::Off => false,
+        }
+    }
+}
+
+/// Role of a track in the mix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TrackKind {
+    /// Plays clips and input into its device
+    #[default]
+    Regular,
+    /// Aux bus: its device processes the sends of other tracks
+    Return,
+    /// Submix: sums the tracks routed into it (after their faders) through its device
+    Group,
+}
+
+/// Send from a track into a return track.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrackSend {
+    pub target: usize, // Track index of the return
+    pub level: f32,    // Linear
+    /// Taken before the track's volume (and its automation)
+    #[serde(default)]
+    pub pre_fader: bool,
+}
+
+/// Removing track `removed` drops sends into it and shifts later targets down.
+pub fn reindex_sends(sends: &mut Vec<TrackSend>, removed: usize) {
+    sends.retain(|s| s.target != removed);
+    for send in sends.iter_mut() {
+        if send.target > removed {
+            send.target -= 1;
+        }
+    }
+}
+
+/// Parent after removing track `removed`, whose own parent was `removed_parent`:
+/// children of a removed group move up to that group's parent.
+pub fn reindex_parent(parent: Option<usize>, removed: usize, removed_parent: Option<usize>) -> Option<usize> {
+    let shift = |p: usize| if p > removed { p - 1 } else { p };
+    match parent {
+        Some(p) if p == removed => removed_parent.map(shift),
+        p => p.map(shift),
+    }
+}
+
+/// Whether `track` may be routed into `group`: it must be a group track and
+/// not `track` itself or one of the groups nested inside it.
+pub fn can_route_to_group(parents: &[Option<usize>], kinds: &[TrackKind], track: usize, group: usize) -> bool {
+    if kinds.get(group) != Some(&TrackKind::Group) {
+        return false;
+    }
+    // Walk up from the group; reaching `track` would close a loop
+    let mut current = Some(group);
+    for _ in 0..=parents.len() {
+        match current {
+            Some(c) if c == track => return false,
+            Some(c) => current = parents.get(c).copied().flatten(),
+            None => return true,
+        }
+    }
+    false // Already cyclic
+}
+
+/// Another track's signal feeding a device's auxiliary (key) input.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sidechain {
+    pub source: usize, // Track index
+    /// Taken before the source's volume (and its automation)
+    #[serde(default)]
+    pub pre_fader: bool,
+}
+
+/// Insert effect on a track, after its instrument.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Device {
+    pub name: String,
+    pub plugin_path: String,
+    #[serde(default)]
+    pub bypass: bool,
+    #[serde(default)]
+    pub parameters: HashMap<u32, f32>,
+    #[serde(default)]
+    pub state: Option<Vec<u8>>,
+    #[serde(default)]
+    pub sidechain: Option<Sidechain>,
+}
+
+/// Removing track `removed` disconnects keys taken from it and shifts later sources down.
+pub fn reindex_sidechains(effects: &mut [Device], removed: usize) {
+    for device in effects {
+        device.sidechain = match device.sidechain {
+            Some(key) if key.source == removed => None,
+            Some(key) if key.source > removed => Some(Sidechain { source: key.source - 1, ..key }),
+            key => key,
+        };
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -428,6 +863,28 @@ pub struct Track {
     
     #[serde(default)]
     pub plugin_state: Option<Vec<u8>>,
+
+    // Live Input
+    #[serde(default)]
+    pub input: TrackInput,
+    #[serde(default)]
+    pub record_arm: bool,
+    #[serde(default)]
+    pub monitor: MonitorMode,
+
+    #[serde(default)]
+    pub kind: TrackKind,
+    #[serde(default)]
+    pub sends: Vec<TrackSend>,
+    /// Group track this one's output feeds (None: master)
+    #[serde(default)]
+    pub parent: Option<usize>,
+    /// Insert effects after the instrument (`plugin_path`), in signal order
+    #[serde(default)]
+    pub effects: Vec<Device>,
+    /// Return/group keeps playing while other tracks are soloed
+    #[serde(default)]
+    pub solo_safe: bool,
 }
 
 impl Default for Track {
@@ -445,6 +902,14 @@ impl Default for Track {
             parameters: HashMap::new(),
             arrangement: TrackArrangement::default(),
             plugin_state: None,
+            input: TrackInput::None,
+            record_arm: false,
+            monitor: MonitorMode::Auto,
+            kind: TrackKind::Regular,
+            sends: Vec::new(),
+            parent: None,
+            effects: Vec::new(),
+            solo_safe: false,
         }
     }
 }
@@ -469,6 +934,72 @@ impl TimeSignature {
     }
 }
 
+/// Span of the arrangement timeline in beats (loop brace, punch range).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub struct TimeRange {
+    pub enabled: bool,
+    pub start: f64,
+    pub end: f64,
+}
+
+impl TimeRange {
+    /// Enabled and not empty
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.end > self.start
+    }
+}
+
+/// What the metronome plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ClickSound {
+    #[default]
+    Synth,
+    /// AudioPool asset; the accent plays it louder
+    Sample(u32),
+}
+
+/// How recording starts from stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum CountIn {
+    #[default]
+    Off,
+    /// Click alone for the count-in bars, then play and record
+    CountIn,
+    /// Play from the count-in bars earlier, record from the original position
+    PreRoll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetronomeSettings {
+    pub enabled: bool,
+    pub volume: f32,
+    #[serde(default)]
+    pub sound: ClickSound,
+    #[serde(default)]
+    pub count_in: CountIn,
+    pub count_in_bars: u8, // 1 or 2
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self { enabled: false, volume: 0.5, sound: ClickSound::Synth, count_in: CountIn::Off, count_in_bars: 1 }
+    }
+}
+
+/// Grid that session clip launches and stops wait for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum LaunchQuantization {
+    /// Launch immediately
+    None,
+    Sixteenth,
+    /// Signature beat (an eighth in 7/8)
+    Beat,
+    #[default]
+    Bar,
+    /// Every N bars, counted from bar 0
+    Bars(u8),
+}
+
 /// Swing/groove template — applies micro-timing offsets to subdivisions.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrooveTemplate {
@@ -546,6 +1077,43 @@ pub struct Project {
     pub time_signature: TimeSignature,
     #[serde(default)]
     pub groove: GrooveTemplate,
+    /// Tempo changes after beat 0 (`bpm` is the starting tempo)
+    #[serde(default)]
+    pub tempo_changes: Vec<TempoEvent>,
+    /// Time-signature changes after bar 0 (`time_signature` is the starting meter)
+    #[serde(default)]
+    pub meter_changes: Vec<MeterChange>,
+    /// Arrangement playback wraps from `end` back to `start`
+    #[serde(default)]
+    pub loop_region: TimeRange,
+    /// Arrangement recording only captures inside this range
+    #[serde(default)]
+    pub punch: TimeRange,
+    #[serde(default)]
+    pub metronome: MetronomeSettings,
+    #[serde(default)]
+    pub launch_quantization: LaunchQuantization,
+}
+
+impl Project {
+    pub fn tempo_map(&self) -> TempoMap {
+        TempoMap::new(self.bpm, &self.tempo_changes)
+    }
+
+    pub fn meter_map(&self) -> MeterMap {
+        MeterMap::new(self.time_signature, &self.meter_changes)
+    }
+
+    /// Moves audio clips loaded from sample positions onto beats, through the
+    /// saved tempo. `sample_rate` is the rate the positions were recorded at.
+    pub fn upgrade_clip_positions(&mut self, sample_rate: f64) {
+        let tempo = self.tempo_map();
+        for clip in self.tracks.iter_mut().flat_map(|t| &mut t.arrangement.clips) {
+            if let Some(legacy) = clip.legacy_start.take() {
+                clip.start = tempo.beat_at_sample(legacy.samples as f64, sample_rate);
+            }
+        }
+    }
 }
 
 impl Default for Project {
@@ -557,8 +1125,16 @@ impl Default for Project {
             arrangement_mode: false,
             time_signature: TimeSignature::default(),
             groove: GrooveTemplate::default(),
+            tempo_changes: Vec::new(),
+            meter_changes: Vec::new(),
+            loop_region: TimeRange::default(),
+            punch: TimeRange::default(),
+            metronome: MetronomeSettings::default(),
+            launch_quantization: LaunchQuantization::default(),
         }
     }
 }
 
 fn default_bpm() -> f32 { 120.0 }
+fn default_gain() -> f32 { 1.0 }
+fn default_true() -> bool { true }
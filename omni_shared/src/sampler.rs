@@ -0,0 +1,95 @@
+//! Sampler / drum rack configuration: pads triggered by MIDI keys, each
+//! playing one of its velocity layers (AudioPool assets).
+
+use serde::{Deserialize, Serialize};
+
+/// Envelope times in seconds; `sustain` is a level (0..1).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self { attack: 0.001, decay: 0.0, sustain: 1.0, release: 0.05 }
+    }
+}
+
+/// One sample of a pad, played for velocities `velocity_min..=velocity_max`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleLayer {
+    pub asset_id: u32,
+    #[serde(default = "default_velocity_min")]
+    pub velocity_min: u8,
+    #[serde(default = "default_velocity_max")]
+    pub velocity_max: u8,
+    /// Playback region in sample frames; `end` 0 plays to the end of the asset
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default)]
+    pub end: u64,
+    /// Loop region (frames) repeated until the note is released
+    #[serde(default)]
+    pub loop_region: Option<(u64, u64)>,
+}
+
+impl SampleLayer {
+    pub fn new(asset_id: u32) -> Self {
+        Self {
+            asset_id,
+            velocity_min: default_velocity_min(),
+            velocity_max: default_velocity_max(),
+            start: 0,
+            end: 0,
+            loop_region: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplerPad {
+    pub name: String,
+    pub key: u8,
+    pub layers: Vec<SampleLayer>,
+    #[serde(default)]
+    pub envelope: Adsr,
+    /// Pitch in semitones (fractional for cents)
+    #[serde(default)]
+    pub tune: f32,
+    #[serde(default = "default_gain")]
+    pub gain: f32, // Linear
+    /// Starting a pad cuts the other voices in its group (open/closed hi-hat)
+    #[serde(default)]
+    pub choke_group: Option<u8>,
+    /// Plays to the end, ignoring note off (drums)
+    #[serde(default)]
+    pub one_shot: bool,
+}
+
+impl SamplerPad {
+    /// A one-shot pad playing `asset_id` at every velocity.
+    pub fn new(name: String, key: u8, asset_id: u32) -> Self {
+        Self {
+            name,
+            key,
+            layers: vec![SampleLayer::new(asset_id)],
+            envelope: Adsr::default(),
+            tune: 0.0,
+            gain: 1.0,
+            choke_group: None,
+            one_shot: true,
+        }
+    }
+
+    /// Layer for a note-on velocity: the first whose range holds it.
+    pub fn layer_for(&self, velocity: u8) -> Option<&SampleLayer> {
+        self.layers.iter().find(|l| (l.velocity_min..=l.velocity_max).contains(&velocity))
+    }
+}
+
+fn default_velocity_min() -> u8 { 1 }
+fn default_velocity_max() -> u8 { 127 }
+fn default_gain() -> f32 { 1.0 }
@@ -0,0 +1,154 @@
+//! Tempo map: tempo changes over the timeline and beat <-> time conversion.
+
+use serde::{Deserialize, Serialize};
+
+/// How the tempo reaches a tempo event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TempoRamp {
+    /// Jump to the new tempo at the event
+    #[default]
+    Instant,
+    /// Glide linearly (per beat) from the previous tempo to the event's tempo
+    Linear,
+}
+
+/// A tempo change. The tempo before the first change is `Project::bpm`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TempoEvent {
+    pub beat: f64,
+    pub bpm: f32,
+    #[serde(default)]
+    pub ramp: TempoRamp,
+}
+
+/// Span of constant or linearly changing tempo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoSegment {
+    beat: f64,    // Start
+    seconds: f64, // Time at the start
+    bpm: f64,     // Tempo at the start
+    end_bpm: f64, // Tempo at the end (== bpm when constant)
+    length: f64,  // Beats (infinite for the last segment)
+}
+
+impl TempoSegment {
+    fn bpm_at(&self, beat: f64) -> f64 {
+        if self.end_bpm == self.bpm || !self.length.is_finite() {
+            return self.bpm;
+        }
+        let t = ((beat - self.beat) / self.length).clamp(0.0, 1.0);
+        self.bpm + (self.end_bpm - self.bpm) * t
+    }
+
+    /// Seconds from the segment start to `beats` into it.
+    fn seconds_into(&self, beats: f64) -> f64 {
+        let slope = self.slope();
+        if slope.abs() < 1e-12 {
+            beats * 60.0 / self.bpm
+        } else {
+            // Integral of 60 / (bpm + slope * b) db
+            60.0 / slope * ((self.bpm + slope * beats) / self.bpm).ln()
+        }
+    }
+
+    /// Beats from the segment start after `seconds` (inverse of `seconds_into`).
+    fn beats_into(&self, seconds: f64) -> f64 {
+        let slope = self.slope();
+        if slope.abs() < 1e-12 {
+            seconds * self.bpm / 60.0
+        } else {
+            self.bpm * ((seconds * slope / 60.0).exp() - 1.0) / slope
+        }
+    }
+
+    /// Tempo change per beat
+    fn slope(&self) -> f64 {
+        if self.length.is_finite() && self.length > 0.0 {
+            (self.end_bpm - self.bpm) / self.length
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Precomputed tempo segments; conversions are a binary search plus a closed form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    segments: Vec<TempoSegment>,
+}
+
+impl TempoMap {
+    pub fn constant(bpm: f32) -> Self {
+        Self::new(bpm, &[])
+    }
+
+    /// `bpm` holds from beat 0 until the first change.
+    pub fn new(bpm: f32, changes: &[TempoEvent]) -> Self {
+        let mut changes: Vec<TempoEvent> = changes
+            .iter()
+            .filter(|e| e.beat > 0.0 && e.bpm > 0.0)
+            .copied()
+            .collect();
+        changes.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+
+        let mut segments = vec![TempoSegment {
+            beat: 0.0,
+            seconds: 0.0,
+            bpm: bpm.max(1.0) as f64,
+            end_bpm: bpm.max(1.0) as f64,
+            length: f64::INFINITY,
+        }];
+        for change in changes {
+            let last = segments.last_mut().unwrap();
+            last.length = change.beat - last.beat;
+            if change.ramp == TempoRamp::Linear {
+                last.end_bpm = change.bpm as f64;
+            }
+            let seconds = last.seconds + last.seconds_into(last.length);
+            segments.push(TempoSegment {
+                beat: change.beat,
+                seconds,
+                bpm: change.bpm as f64,
+                end_bpm: change.bpm as f64,
+                length: f64::INFINITY,
+            });
+        }
+        Self { segments }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.segments.len() == 1
+    }
+
+    fn segment_at_beat(&self, beat: f64) -> &TempoSegment {
+        let idx = self.segments.partition_point(|s| s.beat <= beat);
+        &self.segments[idx.saturating_sub(1)]
+    }
+
+    fn segment_at_seconds(&self, seconds: f64) -> &TempoSegment {
+        let idx = self.segments.partition_point(|s| s.seconds <= seconds);
+        &self.segments[idx.saturating_sub(1)]
+    }
+
+    pub fn bpm_at(&self, beat: f64) -> f64 {
+        self.segment_at_beat(beat).bpm_at(beat)
+    }
+
+    pub fn seconds_at(&self, beat: f64) -> f64 {
+        let seg = self.segment_at_beat(beat);
+        seg.seconds + seg.seconds_into(beat - seg.beat)
+    }
+
+    pub fn beat_at(&self, seconds: f64) -> f64 {
+        let seg = self.segment_at_seconds(seconds);
+        seg.beat + seg.beats_into(seconds - seg.seconds)
+    }
+
+    pub fn sample_at(&self, beat: f64, sample_rate: f64) -> f64 {
+        self.seconds_at(beat) * sample_rate
+    }
+
+    pub fn beat_at_sample(&self, sample: f64, sample_rate: f64) -> f64 {
+        self.beat_at(sample / sample_rate)
+    }
+}